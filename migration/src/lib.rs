@@ -2,6 +2,21 @@ pub use sea_orm_migration::prelude::*;
 
 mod m20220101_000001_create_table;
 mod m20251117_073031_create_users_table;
+mod m20251120_090000_create_revoked_tokens_table;
+mod m20251124_090000_create_refresh_tokens_table;
+mod m20251201_090000_create_rbac_tables;
+mod m20251208_090000_seed_policy_permissions;
+mod m20251215_090000_create_organizations;
+mod m20251222_090000_create_invitations;
+mod m20251229_090000_add_user_email;
+mod m20260105_090000_create_password_resets;
+mod m20260112_090000_add_username_canonical;
+mod m20260119_090000_create_login_throttles;
+mod m20260126_090000_create_mfa_tables;
+mod m20260202_090000_create_webauthn_tables;
+mod m20260209_090000_create_magic_links;
+mod m20260216_090000_create_sessions;
+mod m20260223_090000_create_signing_key_states;
 
 pub struct Migrator;
 
@@ -11,6 +26,21 @@ impl MigratorTrait for Migrator {
         vec![
             Box::new(m20220101_000001_create_table::Migration),
             Box::new(m20251117_073031_create_users_table::Migration),
+            Box::new(m20251120_090000_create_revoked_tokens_table::Migration),
+            Box::new(m20251124_090000_create_refresh_tokens_table::Migration),
+            Box::new(m20251201_090000_create_rbac_tables::Migration),
+            Box::new(m20251208_090000_seed_policy_permissions::Migration),
+            Box::new(m20251215_090000_create_organizations::Migration),
+            Box::new(m20251222_090000_create_invitations::Migration),
+            Box::new(m20251229_090000_add_user_email::Migration),
+            Box::new(m20260105_090000_create_password_resets::Migration),
+            Box::new(m20260112_090000_add_username_canonical::Migration),
+            Box::new(m20260119_090000_create_login_throttles::Migration),
+            Box::new(m20260126_090000_create_mfa_tables::Migration),
+            Box::new(m20260202_090000_create_webauthn_tables::Migration),
+            Box::new(m20260209_090000_create_magic_links::Migration),
+            Box::new(m20260216_090000_create_sessions::Migration),
+            Box::new(m20260223_090000_create_signing_key_states::Migration),
         ]
     }
 }
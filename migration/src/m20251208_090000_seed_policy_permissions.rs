@@ -0,0 +1,54 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .exec_stmt(
+                Query::insert()
+                    .into_table(Permissions::Table)
+                    .columns([Permissions::Name, Permissions::Description])
+                    .values_panic([
+                        "policies:read".into(),
+                        "Explain access policy decisions".into(),
+                    ])
+                    .values_panic(["policies:write".into(), "Reload the access policy".into()])
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "INSERT INTO role_permissions (role_id, permission_id) \
+                 SELECT roles.id, permissions.id FROM roles CROSS JOIN permissions \
+                 WHERE roles.name = 'admin' AND permissions.name LIKE 'policies:%'",
+            )
+            .await
+            .map(|_| ())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Grants go with the rows through the role_permissions cascade.
+        manager
+            .exec_stmt(
+                Query::delete()
+                    .from_table(Permissions::Table)
+                    .and_where(
+                        Expr::col(Permissions::Name).is_in(["policies:read", "policies:write"]),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Permissions {
+    Table,
+    Name,
+    Description,
+}
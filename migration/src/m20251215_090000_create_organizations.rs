@@ -0,0 +1,210 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Organizations::Table)
+                    .if_not_exists()
+                    .col(pk_auto(Organizations::Id))
+                    .col(string(Organizations::Name))
+                    .col(
+                        timestamp_with_time_zone(Organizations::CreatedAt)
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Memberships::Table)
+                    .if_not_exists()
+                    .col(integer(Memberships::OrganizationId))
+                    .col(integer(Memberships::UserId))
+                    .col(integer(Memberships::RoleId))
+                    .col(
+                        timestamp_with_time_zone(Memberships::CreatedAt)
+                            .default(Expr::current_timestamp()),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(Memberships::OrganizationId)
+                            .col(Memberships::UserId),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_memberships_organization_id")
+                            .from(Memberships::Table, Memberships::OrganizationId)
+                            .to(Organizations::Table, Organizations::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_memberships_user_id")
+                            .from(Memberships::Table, Memberships::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_memberships_role_id")
+                            .from(Memberships::Table, Memberships::RoleId)
+                            .to(Roles::Table, Roles::Id)
+                            .on_delete(ForeignKeyAction::Restrict),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // The primary key serves lookups by organization; this serves lookups by user.
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_memberships_user_id")
+                    .table(Memberships::Table)
+                    .col(Memberships::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(integer_null(Users::ActiveOrganizationId))
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name("fk_users_active_organization_id")
+                            .from_tbl(Users::Table)
+                            .from_col(Users::ActiveOrganizationId)
+                            .to_tbl(Organizations::Table)
+                            .to_col(Organizations::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Organization roles: whoever creates an org owns it; `member` is read-only.
+        manager
+            .exec_stmt(
+                Query::insert()
+                    .into_table(Roles::Table)
+                    .columns([Roles::Name, Roles::Description])
+                    .values_panic(["owner".into(), "Manages an organization".into()])
+                    .values_panic(["member".into(), "Belongs to an organization".into()])
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .exec_stmt(
+                Query::insert()
+                    .into_table(Permissions::Table)
+                    .columns([Permissions::Name, Permissions::Description])
+                    .values_panic([
+                        "members:read".into(),
+                        "List the members of the active organization".into(),
+                    ])
+                    .values_panic([
+                        "members:write".into(),
+                        "Manage the members of the active organization".into(),
+                    ])
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "INSERT INTO role_permissions (role_id, permission_id) \
+                 SELECT roles.id, permissions.id FROM roles CROSS JOIN permissions \
+                 WHERE (roles.name = 'owner' AND permissions.name LIKE 'members:%') \
+                 OR (roles.name = 'member' AND permissions.name = 'members:read')",
+            )
+            .await
+            .map(|_| ())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .drop_foreign_key(Alias::new("fk_users_active_organization_id"))
+                    .drop_column(Users::ActiveOrganizationId)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Memberships::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Organizations::Table).to_owned())
+            .await?;
+        manager
+            .exec_stmt(
+                Query::delete()
+                    .from_table(Permissions::Table)
+                    .and_where(
+                        Expr::col(Permissions::Name).is_in(["members:read", "members:write"]),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .exec_stmt(
+                Query::delete()
+                    .from_table(Roles::Table)
+                    .and_where(Expr::col(Roles::Name).is_in(["owner", "member"]))
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Organizations {
+    Table,
+    Id,
+    Name,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Memberships {
+    Table,
+    OrganizationId,
+    UserId,
+    RoleId,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+    ActiveOrganizationId,
+}
+
+#[derive(DeriveIden)]
+enum Roles {
+    Table,
+    Id,
+    Name,
+    Description,
+}
+
+#[derive(DeriveIden)]
+enum Permissions {
+    Table,
+    Name,
+    Description,
+}
@@ -0,0 +1,165 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Invitations::Table)
+                    .if_not_exists()
+                    .col(pk_auto(Invitations::Id))
+                    .col(integer(Invitations::OrganizationId))
+                    .col(string(Invitations::Invitee))
+                    .col(integer(Invitations::RoleId))
+                    .col(integer_null(Invitations::InvitedBy))
+                    .col(string_uniq(Invitations::TokenHash))
+                    .col(timestamp_with_time_zone(Invitations::ExpiresAt))
+                    .col(
+                        timestamp_with_time_zone(Invitations::CreatedAt)
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(timestamp_with_time_zone_null(Invitations::AcceptedAt))
+                    .col(integer_null(Invitations::AcceptedBy))
+                    .col(timestamp_with_time_zone_null(Invitations::RevokedAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_invitations_organization_id")
+                            .from(Invitations::Table, Invitations::OrganizationId)
+                            .to(Organizations::Table, Organizations::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_invitations_role_id")
+                            .from(Invitations::Table, Invitations::RoleId)
+                            .to(Roles::Table, Roles::Id)
+                            .on_delete(ForeignKeyAction::Restrict),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_invitations_invited_by")
+                            .from(Invitations::Table, Invitations::InvitedBy)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_invitations_accepted_by")
+                            .from(Invitations::Table, Invitations::AcceptedBy)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_invitations_organization_id")
+                    .table(Invitations::Table)
+                    .col(Invitations::OrganizationId)
+                    .to_owned(),
+            )
+            .await?;
+
+        // Append-only audit trail; rows outlive the users that caused them.
+        manager
+            .create_table(
+                Table::create()
+                    .table(InvitationEvents::Table)
+                    .if_not_exists()
+                    .col(pk_auto(InvitationEvents::Id))
+                    .col(integer(InvitationEvents::InvitationId))
+                    .col(string(InvitationEvents::Event))
+                    .col(integer_null(InvitationEvents::ActorId))
+                    .col(
+                        timestamp_with_time_zone(InvitationEvents::CreatedAt)
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_invitation_events_invitation_id")
+                            .from(InvitationEvents::Table, InvitationEvents::InvitationId)
+                            .to(Invitations::Table, Invitations::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_invitation_events_actor_id")
+                            .from(InvitationEvents::Table, InvitationEvents::ActorId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_invitation_events_invitation_id")
+                    .table(InvitationEvents::Table)
+                    .col(InvitationEvents::InvitationId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(InvitationEvents::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Invitations::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Invitations {
+    Table,
+    Id,
+    OrganizationId,
+    Invitee,
+    RoleId,
+    InvitedBy,
+    TokenHash,
+    ExpiresAt,
+    CreatedAt,
+    AcceptedAt,
+    AcceptedBy,
+    RevokedAt,
+}
+
+#[derive(DeriveIden)]
+enum InvitationEvents {
+    Table,
+    Id,
+    InvitationId,
+    Event,
+    ActorId,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Organizations {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Roles {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+}
@@ -0,0 +1,101 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Existing accounts have no address yet; they can add one from `/me/email`.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(string_null(Users::Email))
+                    .add_column(timestamp_with_time_zone_null(Users::EmailVerifiedAt))
+                    .to_owned(),
+            )
+            .await?;
+
+        // Addresses are stored lowercased, so a plain unique index is enough.
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_users_email")
+                    .table(Users::Table)
+                    .col(Users::Email)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(EmailVerifications::Table)
+                    .if_not_exists()
+                    .col(pk_auto(EmailVerifications::Id))
+                    .col(integer(EmailVerifications::UserId))
+                    .col(string(EmailVerifications::Email))
+                    .col(string_uniq(EmailVerifications::TokenHash))
+                    .col(timestamp_with_time_zone(EmailVerifications::ExpiresAt))
+                    .col(
+                        timestamp_with_time_zone(EmailVerifications::CreatedAt)
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(timestamp_with_time_zone_null(EmailVerifications::UsedAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_email_verifications_user_id")
+                            .from(EmailVerifications::Table, EmailVerifications::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(EmailVerifications::Table).to_owned())
+            .await?;
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_users_email")
+                    .table(Users::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .drop_column(Users::EmailVerifiedAt)
+                    .drop_column(Users::Email)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+    Email,
+    EmailVerifiedAt,
+}
+
+#[derive(DeriveIden)]
+enum EmailVerifications {
+    Table,
+    Id,
+    UserId,
+    Email,
+    TokenHash,
+    ExpiresAt,
+    CreatedAt,
+    UsedAt,
+}
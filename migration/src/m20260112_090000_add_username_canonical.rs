@@ -0,0 +1,105 @@
+use sea_orm_migration::{
+    prelude::*,
+    schema::*,
+    sea_orm::{ConnectionTrait, Statement},
+};
+
+/// SQL twin of `canonical_username` in the app: NFKC, lowercase, NFKC again, trimmed.
+/// Needs Postgres 13+ and a UTF-8 database for `normalize`.
+const CANONICAL: &str =
+    "btrim(normalize(lower(normalize(username, NFKC)), NFKC), E' \\t\\r\\n\\u3000')";
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        // Accounts that only differ in case, width or padding can't both keep their name, and
+        // picking a loser is an operator decision. Refuse to continue and list them instead.
+        let collisions = db
+            .query_all_raw(Statement::from_string(
+                manager.get_database_backend(),
+                format!(
+                    "SELECT {canonical} AS canonical, \
+                     string_agg(id::text || ' ' || quote_literal(username), ', ' ORDER BY id) AS users \
+                     FROM users GROUP BY 1 HAVING count(*) > 1 ORDER BY 1",
+                    canonical = CANONICAL
+                ),
+            ))
+            .await?;
+        if !collisions.is_empty() {
+            let mut report = Vec::new();
+            for row in collisions {
+                let canonical: String = row.try_get("", "canonical")?;
+                let users: String = row.try_get("", "users")?;
+                report.push(format!("{:?}: {}", canonical, users));
+            }
+            return Err(DbErr::Migration(format!(
+                "usernames collide once canonicalized; rename all but one account of each \
+                 group and rerun the migration: {}",
+                report.join("; ")
+            )));
+        }
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(string_null(Users::UsernameCanonical))
+                    .to_owned(),
+            )
+            .await?;
+        db.execute_unprepared(&format!(
+            "UPDATE users SET username_canonical = {}",
+            CANONICAL
+        ))
+        .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .modify_column(string(Users::UsernameCanonical))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_users_username_canonical")
+                    .table(Users::Table)
+                    .col(Users::UsernameCanonical)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_users_username_canonical")
+                    .table(Users::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .drop_column(Users::UsernameCanonical)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    UsernameCanonical,
+}
@@ -0,0 +1,49 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Keyed by `user:<canonical username>` or `ip:<address>` rather than a user ID, so
+        // guesses against unknown usernames are throttled the same way.
+        manager
+            .create_table(
+                Table::create()
+                    .table(LoginThrottles::Table)
+                    .if_not_exists()
+                    .col(string(LoginThrottles::Key).primary_key())
+                    .col(integer(LoginThrottles::Failures))
+                    .col(timestamp_with_time_zone(LoginThrottles::LastFailureAt))
+                    .col(timestamp_with_time_zone_null(LoginThrottles::LockedUntil))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_login_throttles_last_failure_at")
+                    .table(LoginThrottles::Table)
+                    .col(LoginThrottles::LastFailureAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(LoginThrottles::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum LoginThrottles {
+    Table,
+    Key,
+    Failures,
+    LastFailureAt,
+    LockedUntil,
+}
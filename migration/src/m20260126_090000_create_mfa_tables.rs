@@ -0,0 +1,154 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(TotpCredentials::Table)
+                    .if_not_exists()
+                    .col(integer(TotpCredentials::UserId).primary_key())
+                    .col(string(TotpCredentials::Secret))
+                    .col(timestamp_with_time_zone_null(TotpCredentials::ConfirmedAt))
+                    .col(big_integer_null(TotpCredentials::LastUsedStep))
+                    .col(
+                        timestamp_with_time_zone(TotpCredentials::CreatedAt)
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_totp_credentials_user_id")
+                            .from(TotpCredentials::Table, TotpCredentials::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(RecoveryCodes::Table)
+                    .if_not_exists()
+                    .col(pk_auto(RecoveryCodes::Id))
+                    .col(integer(RecoveryCodes::UserId))
+                    .col(string(RecoveryCodes::CodeHash))
+                    .col(
+                        timestamp_with_time_zone(RecoveryCodes::CreatedAt)
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(timestamp_with_time_zone_null(RecoveryCodes::UsedAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_recovery_codes_user_id")
+                            .from(RecoveryCodes::Table, RecoveryCodes::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_recovery_codes_user_id_code_hash")
+                    .table(RecoveryCodes::Table)
+                    .col(RecoveryCodes::UserId)
+                    .col(RecoveryCodes::CodeHash)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(MfaChallenges::Table)
+                    .if_not_exists()
+                    .col(pk_auto(MfaChallenges::Id))
+                    .col(integer(MfaChallenges::UserId))
+                    .col(string_uniq(MfaChallenges::TokenHash))
+                    .col(integer(MfaChallenges::FailedAttempts).default(0))
+                    .col(timestamp_with_time_zone(MfaChallenges::ExpiresAt))
+                    .col(
+                        timestamp_with_time_zone(MfaChallenges::CreatedAt)
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(timestamp_with_time_zone_null(MfaChallenges::UsedAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_mfa_challenges_user_id")
+                            .from(MfaChallenges::Table, MfaChallenges::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_mfa_challenges_user_id")
+                    .table(MfaChallenges::Table)
+                    .col(MfaChallenges::UserId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(MfaChallenges::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(RecoveryCodes::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(TotpCredentials::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum TotpCredentials {
+    Table,
+    UserId,
+    Secret,
+    ConfirmedAt,
+    LastUsedStep,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum RecoveryCodes {
+    Table,
+    Id,
+    UserId,
+    CodeHash,
+    CreatedAt,
+    UsedAt,
+}
+
+#[derive(DeriveIden)]
+enum MfaChallenges {
+    Table,
+    Id,
+    UserId,
+    TokenHash,
+    FailedAttempts,
+    ExpiresAt,
+    CreatedAt,
+    UsedAt,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+}
@@ -0,0 +1,132 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(WebauthnCredentials::Table)
+                    .if_not_exists()
+                    .col(pk_auto(WebauthnCredentials::Id))
+                    .col(integer(WebauthnCredentials::UserId))
+                    .col(string_uniq(WebauthnCredentials::CredentialId))
+                    .col(blob(WebauthnCredentials::PublicKey))
+                    .col(big_integer(WebauthnCredentials::SignCount).default(0))
+                    .col(string(WebauthnCredentials::UserHandle))
+                    .col(string(WebauthnCredentials::Name))
+                    .col(
+                        timestamp_with_time_zone(WebauthnCredentials::CreatedAt)
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(timestamp_with_time_zone_null(
+                        WebauthnCredentials::LastUsedAt,
+                    ))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_webauthn_credentials_user_id")
+                            .from(WebauthnCredentials::Table, WebauthnCredentials::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_webauthn_credentials_user_id")
+                    .table(WebauthnCredentials::Table)
+                    .col(WebauthnCredentials::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(WebauthnChallenges::Table)
+                    .if_not_exists()
+                    .col(pk_auto(WebauthnChallenges::Id))
+                    .col(string_uniq(WebauthnChallenges::ChallengeHash))
+                    .col(string(WebauthnChallenges::Ceremony))
+                    .col(integer_null(WebauthnChallenges::UserId))
+                    .col(string_null(WebauthnChallenges::Username))
+                    .col(string_null(WebauthnChallenges::Email))
+                    .col(string_null(WebauthnChallenges::UserHandle))
+                    .col(timestamp_with_time_zone(WebauthnChallenges::ExpiresAt))
+                    .col(
+                        timestamp_with_time_zone(WebauthnChallenges::CreatedAt)
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(timestamp_with_time_zone_null(WebauthnChallenges::UsedAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_webauthn_challenges_user_id")
+                            .from(WebauthnChallenges::Table, WebauthnChallenges::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_webauthn_challenges_expires_at")
+                    .table(WebauthnChallenges::Table)
+                    .col(WebauthnChallenges::ExpiresAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(WebauthnChallenges::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(WebauthnCredentials::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum WebauthnCredentials {
+    Table,
+    Id,
+    UserId,
+    CredentialId,
+    PublicKey,
+    SignCount,
+    UserHandle,
+    Name,
+    CreatedAt,
+    LastUsedAt,
+}
+
+#[derive(DeriveIden)]
+enum WebauthnChallenges {
+    Table,
+    Id,
+    ChallengeHash,
+    Ceremony,
+    UserId,
+    Username,
+    Email,
+    UserHandle,
+    ExpiresAt,
+    CreatedAt,
+    UsedAt,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+}
@@ -0,0 +1,56 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(MagicLinks::Table)
+                    .if_not_exists()
+                    .col(pk_auto(MagicLinks::Id))
+                    .col(string(MagicLinks::Email))
+                    .col(string_uniq(MagicLinks::TokenHash))
+                    .col(timestamp_with_time_zone(MagicLinks::ExpiresAt))
+                    .col(
+                        timestamp_with_time_zone(MagicLinks::CreatedAt)
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(timestamp_with_time_zone_null(MagicLinks::UsedAt))
+                    .to_owned(),
+            )
+            .await?;
+
+        // Serves the per-address rate limit.
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_magic_links_email_created_at")
+                    .table(MagicLinks::Table)
+                    .col(MagicLinks::Email)
+                    .col(MagicLinks::CreatedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(MagicLinks::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum MagicLinks {
+    Table,
+    Id,
+    Email,
+    TokenHash,
+    ExpiresAt,
+    CreatedAt,
+    UsedAt,
+}
@@ -0,0 +1,77 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Key material stays in the configuration; only promotions and retirements done
+        // through the API are kept here so they survive a restart.
+        manager
+            .create_table(
+                Table::create()
+                    .table(SigningKeyStates::Table)
+                    .if_not_exists()
+                    .col(string(SigningKeyStates::Kid).primary_key())
+                    .col(string(SigningKeyStates::State))
+                    .col(timestamp_with_time_zone(SigningKeyStates::UpdatedAt))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .exec_stmt(
+                Query::insert()
+                    .into_table(Permissions::Table)
+                    .columns([Permissions::Name, Permissions::Description])
+                    .values_panic(["keys:read".into(), "List JWT signing keys".into()])
+                    .values_panic([
+                        "keys:write".into(),
+                        "Promote and retire JWT signing keys".into(),
+                    ])
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "INSERT INTO role_permissions (role_id, permission_id) \
+                 SELECT roles.id, permissions.id FROM roles CROSS JOIN permissions \
+                 WHERE roles.name = 'admin' AND permissions.name LIKE 'keys:%'",
+            )
+            .await
+            .map(|_| ())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .exec_stmt(
+                Query::delete()
+                    .from_table(Permissions::Table)
+                    .and_where(Expr::col(Permissions::Name).is_in(["keys:read", "keys:write"]))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(SigningKeyStates::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum SigningKeyStates {
+    Table,
+    Kid,
+    State,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Permissions {
+    Table,
+    Name,
+    Description,
+}
@@ -1,26 +1,1694 @@
-#[derive(Clone)]
+use std::fmt::{self, Display};
+use std::io::ErrorKind;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+use jsonwebtoken::Algorithm;
+use serde::Deserialize;
+
+use crate::utils::jwt_keys::KeyState;
+
+/// Secret used when `JWT_SECRET` is unset; only accepted in development.
+const DEFAULT_JWT_SECRET: &str = "change-me";
+/// Shortest shared secret accepted outside development (256 bits for HS256).
+const MIN_SECRET_LEN: usize = 32;
+/// Config file read when `CONFIG_FILE` is unset; it is optional.
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+/// Application settings, layered as built-in defaults < TOML file < environment variables.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct AppConfig {
-    /// Database connection string, typically fetched from `.env`.
-    pub database_url: String,
+    /// Deployment profile from `APP_ENV`; everything but development is validated strictly.
+    pub environment: Environment,
+    /// HTTP listener settings.
+    pub server: ServerConfig,
+    /// Postgres connection and pool settings.
+    pub database: DatabaseConfig,
+    /// Signing secret and claim validation settings for JWTs.
+    pub jwt: JwtConfig,
+    /// Argon2id cost parameters used when hashing passwords.
+    pub password_hashing: PasswordHashingConfig,
+    /// Log filter and access log toggle.
+    pub logging: LoggingConfig,
+    /// Where the access policy rules are read from and how often they are reloaded.
+    pub policy: PolicyConfig,
+    /// Lifetime and link format of organization invitations.
+    pub invitations: InvitationConfig,
+    /// How outgoing mail is delivered.
+    pub mail: MailConfig,
+    /// Whether accounts need a verified email address, and the verification link format.
+    pub email_verification: EmailVerificationConfig,
+    /// Lifetime and link format of password reset tokens.
+    pub password_reset: PasswordResetConfig,
+    /// Rules new passwords must satisfy.
+    pub password_policy: PasswordPolicyConfig,
+    /// Delays and lockouts after failed logins.
+    pub login_throttle: LoginThrottleConfig,
+    /// Two-factor authentication settings.
+    pub mfa: MfaConfig,
+    /// Relying party settings for passkeys.
+    pub webauthn: WebauthnConfig,
+    /// Lifetime, link format and rate limit of magic sign-in links.
+    pub magic_link: MagicLinkConfig,
+    /// Attributes of the cookies that carry tokens for browser clients.
+    pub auth_cookie: AuthCookieConfig,
+    /// How often expired entries are purged from `revoked_tokens`, defaults to 5 minutes.
+    pub revoked_token_sweep_interval_secs: u64,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            environment: Environment::default(),
+            server: ServerConfig::default(),
+            database: DatabaseConfig::default(),
+            jwt: JwtConfig::default(),
+            password_hashing: PasswordHashingConfig::default(),
+            logging: LoggingConfig::default(),
+            policy: PolicyConfig::default(),
+            invitations: InvitationConfig::default(),
+            mail: MailConfig::default(),
+            email_verification: EmailVerificationConfig::default(),
+            password_reset: PasswordResetConfig::default(),
+            password_policy: PasswordPolicyConfig::default(),
+            login_throttle: LoginThrottleConfig::default(),
+            mfa: MfaConfig::default(),
+            webauthn: WebauthnConfig::default(),
+            magic_link: MagicLinkConfig::default(),
+            auth_cookie: AuthCookieConfig::default(),
+            revoked_token_sweep_interval_secs: 300,
+        }
+    }
+}
+
+/// Settings for the Actix HTTP server.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
     /// Address that Actix should bind to, defaults to `127.0.0.1:8080`.
     pub bind_address: String,
-    /// Secret used to sign JWTs.
-    pub jwt_secret: String,
+    /// Allows binding to all interfaces (`0.0.0.0`/`[::]`) in production.
+    pub allow_public_bind: bool,
+    /// Number of worker threads; Actix uses one per physical core when unset.
+    pub workers: Option<usize>,
+    /// Reverse proxies whose `Forwarded`/`X-Forwarded-For` headers name the client. Requests
+    /// from any other peer are attributed to the peer itself.
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: "127.0.0.1:8080".to_string(),
+            allow_public_bind: false,
+            workers: None,
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+/// Postgres connection string and pool sizing.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// Database connection string, required.
+    pub url: String,
+    /// Upper bound on pooled connections.
+    pub max_connections: u32,
+    /// Connections kept open while idle.
+    pub min_connections: u32,
+    /// How long to wait for a new connection before failing.
+    pub connect_timeout_secs: u64,
+    /// How long an unused connection stays in the pool.
+    pub idle_timeout_secs: u64,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            max_connections: 10,
+            min_connections: 1,
+            connect_timeout_secs: 8,
+            idle_timeout_secs: 300,
+        }
+    }
+}
+
+/// Settings used to mint and validate JWTs.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JwtConfig {
+    /// Signing algorithm: `HS256` (shared secret) or `RS256`/`ES256`/`EdDSA` (keypair).
+    pub algorithm: Algorithm,
+    /// Secret used to sign JWTs when `algorithm` is `HS256`.
+    pub secret: String,
+    /// `kid` header written into tokens and published in the JWKS.
+    pub key_id: String,
+    /// PKCS#8 PEM private key, required for asymmetric algorithms.
+    pub private_key_path: Option<String>,
+    /// SPKI PEM public key, required for asymmetric algorithms.
+    pub public_key_path: Option<String>,
+    /// Further keys in the ring, e.g. the previous key kept as verify-only after a rotation.
+    pub keys: Vec<JwtKeyConfig>,
+    /// `iss` claim written into tokens and required on decode.
+    pub issuer: String,
+    /// `aud` claim written into tokens and required on decode.
+    pub audience: String,
+    /// Allowed clock skew in seconds when checking `exp` and `nbf`.
+    pub leeway_secs: u64,
+    /// Lifetime of access tokens, defaults to 30 minutes.
+    pub access_token_ttl_secs: i64,
+    /// Lifetime of refresh tokens, defaults to 30 days.
+    pub refresh_token_ttl_secs: i64,
+    /// How often key states saved by other replicas are picked up, `0` only loads them at
+    /// startup.
+    pub key_state_reload_interval_secs: u64,
+}
+
+impl Default for JwtConfig {
+    fn default() -> Self {
+        Self {
+            algorithm: Algorithm::HS256,
+            secret: DEFAULT_JWT_SECRET.to_string(),
+            key_id: "default".to_string(),
+            private_key_path: None,
+            public_key_path: None,
+            keys: Vec::new(),
+            issuer: "backend".to_string(),
+            audience: "backend-api".to_string(),
+            leeway_secs: 30,
+            access_token_ttl_secs: 30 * 60,
+            refresh_token_ttl_secs: 30 * 24 * 60 * 60,
+            key_state_reload_interval_secs: 30,
+        }
+    }
+}
+
+/// An extra entry in the JWT key ring, from `[[jwt.keys]]` or the `JWT_KEYS` JSON array.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JwtKeyConfig {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    pub state: KeyState,
+    /// Shared secret for `HS256` keys.
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// Optional for asymmetric keys that only verify.
+    #[serde(default)]
+    pub private_key_path: Option<String>,
+    #[serde(default)]
+    pub public_key_path: Option<String>,
+}
+
+impl JwtConfig {
+    fn apply_env(&mut self, env: &mut EnvLayer) {
+        env.set("JWT_ALGORITHM", &mut self.algorithm);
+        if let Some(secret) = env.secret("JWT_SECRET") {
+            self.secret = secret;
+        }
+        env.set("JWT_KEY_ID", &mut self.key_id);
+        env.set_some("JWT_PRIVATE_KEY_PATH", &mut self.private_key_path);
+        env.set_some("JWT_PUBLIC_KEY_PATH", &mut self.public_key_path);
+        if let Some(raw) = env.secret("JWT_KEYS") {
+            match serde_json::from_str(&raw) {
+                Ok(keys) => self.keys = keys,
+                Err(e) => env.invalid("JWT_KEYS", format!("expected a JSON array of keys: {}", e)),
+            }
+        }
+        env.set("JWT_ISSUER", &mut self.issuer);
+        env.set("JWT_AUDIENCE", &mut self.audience);
+        env.set("JWT_LEEWAY_SECS", &mut self.leeway_secs);
+        env.set("JWT_ACCESS_TOKEN_TTL_SECS", &mut self.access_token_ttl_secs);
+        env.set("REFRESH_TOKEN_TTL_SECS", &mut self.refresh_token_ttl_secs);
+        env.set(
+            "JWT_KEY_STATE_RELOAD_INTERVAL_SECS",
+            &mut self.key_state_reload_interval_secs,
+        );
+    }
+}
+
+/// Argon2id cost parameters, defaulting to the OWASP-recommended baseline.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PasswordHashingConfig {
+    /// Memory cost in KiB.
+    pub memory_kib: u32,
+    /// Number of passes over the memory.
+    pub iterations: u32,
+    /// Degree of parallelism (lanes).
+    pub parallelism: u32,
+}
+
+impl Default for PasswordHashingConfig {
+    fn default() -> Self {
+        Self {
+            memory_kib: argon2::Params::DEFAULT_M_COST,
+            iterations: argon2::Params::DEFAULT_T_COST,
+            parallelism: argon2::Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+/// Logging settings handed to `env_logger` and the Actix access logger.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// `env_logger` filter directives, e.g. `info` or `backend=debug,sqlx=warn`.
+    pub level: String,
+    /// Whether every request is logged by the Actix `Logger` middleware.
+    pub access_log: bool,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            access_log: true,
+        }
+    }
+}
+
+/// Location of the access policy file, see [`crate::policy`].
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyConfig {
+    /// TOML rule file; without one every policy check is denied.
+    pub path: Option<String>,
+    /// How often the file is checked for changes, `0` disables hot reloading.
+    pub reload_interval_secs: u64,
+}
+
+impl Default for PolicyConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            reload_interval_secs: 30,
+        }
+    }
+}
+
+/// Organization invitation settings.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InvitationConfig {
+    /// How long an invite link stays valid, defaults to 7 days.
+    pub ttl_secs: i64,
+    /// Page the invite link points at; the token is appended as `?token=...`.
+    pub accept_url: String,
+}
+
+impl Default for InvitationConfig {
+    fn default() -> Self {
+        Self {
+            ttl_secs: 7 * 24 * 60 * 60,
+            accept_url: "http://127.0.0.1:8080/invitations/accept".to_string(),
+        }
+    }
+}
+
+/// Outgoing mail settings, see [`crate::mail`].
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MailConfig {
+    /// Where messages go, defaults to `stdout`.
+    pub transport: MailTransport,
+    /// Sender address of every message.
+    pub from: String,
+    /// File the `file` transport appends messages to.
+    pub file_path: String,
+    /// Server used by the `smtp` transport.
+    pub smtp: SmtpConfig,
+}
+
+impl Default for MailConfig {
+    fn default() -> Self {
+        Self {
+            transport: MailTransport::default(),
+            from: "noreply@localhost".to_string(),
+            file_path: "mail.log".to_string(),
+            smtp: SmtpConfig::default(),
+        }
+    }
+}
+
+/// Mail transport selected with `MAIL_TRANSPORT`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MailTransport {
+    /// Print messages instead of sending them.
+    #[default]
+    Stdout,
+    /// Append messages to `file_path`.
+    File,
+    /// Deliver through an SMTP server.
+    Smtp,
+}
+
+impl FromStr for MailTransport {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "stdout" => Ok(MailTransport::Stdout),
+            "file" => Ok(MailTransport::File),
+            "smtp" => Ok(MailTransport::Smtp),
+            _ => Err(format!(
+                "unknown mail transport `{}`, expected stdout, file or smtp",
+                value
+            )),
+        }
+    }
+}
+
+/// SMTP submission server settings.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SmtpConfig {
+    pub host: String,
+    /// Defaults to the submission port, 587.
+    pub port: u16,
+    /// Credentials for `AUTH PLAIN`; no authentication when unset.
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Upgrade the connection with STARTTLS before authenticating, on by default.
+    pub starttls: bool,
+    /// Name sent with `EHLO`.
+    pub helo_name: String,
+    /// Connect, read and write timeout.
+    pub timeout_secs: u64,
+}
+
+impl Default for SmtpConfig {
+    fn default() -> Self {
+        Self {
+            host: String::new(),
+            port: 587,
+            username: None,
+            password: None,
+            starttls: true,
+            helo_name: "localhost".to_string(),
+            timeout_secs: 10,
+        }
+    }
+}
+
+/// Email address verification settings.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmailVerificationConfig {
+    /// Registration requires an email address and login waits until it is verified.
+    pub required: bool,
+    /// How long a verification link stays valid, defaults to 24 hours.
+    pub ttl_secs: i64,
+    /// Page the verification link points at; the token is appended as `?token=...`.
+    pub verify_url: String,
+}
+
+impl Default for EmailVerificationConfig {
+    fn default() -> Self {
+        Self {
+            required: false,
+            ttl_secs: 24 * 60 * 60,
+            verify_url: "http://127.0.0.1:8080/auth/verify-email".to_string(),
+        }
+    }
+}
+
+/// Password reset settings.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PasswordResetConfig {
+    /// How long a reset link stays valid, defaults to 30 minutes.
+    pub ttl_secs: i64,
+    /// Page the reset link points at; the token is appended as `?token=...`.
+    pub reset_url: String,
+}
+
+impl Default for PasswordResetConfig {
+    fn default() -> Self {
+        Self {
+            ttl_secs: 30 * 60,
+            reset_url: "http://127.0.0.1:8080/auth/password/reset".to_string(),
+        }
+    }
+}
+
+/// Rules for new passwords, checked on registration, reset and change.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PasswordPolicyConfig {
+    /// Fewest characters accepted, defaults to 8.
+    pub min_length: usize,
+    /// Most characters accepted, defaults to 128; bounds the work spent hashing.
+    pub max_length: usize,
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    /// Anything that is neither a letter nor a digit counts as a symbol.
+    pub require_symbol: bool,
+    /// Rejects passwords containing the username, ignoring case. On by default.
+    pub disallow_username: bool,
+    /// Directory of breached-password range files named by the first five hex digits of the
+    /// SHA-1 hash (`21BD1.txt`), each listing `SUFFIX:COUNT` lines. Disabled when unset.
+    pub breached_passwords_dir: Option<String>,
+}
+
+impl Default for PasswordPolicyConfig {
+    fn default() -> Self {
+        Self {
+            min_length: 8,
+            max_length: 128,
+            require_lowercase: false,
+            require_uppercase: false,
+            require_digit: false,
+            require_symbol: false,
+            disallow_username: true,
+            breached_passwords_dir: None,
+        }
+    }
+}
+
+/// Failed login throttling, tracked per username and per client IP.
+///
+/// After each failure the next attempt has to wait `base_delay_secs`, doubling with every
+/// further failure up to `max_delay_secs`. Reaching the failure limit locks the key for
+/// `lockout_secs`. Failures older than `failure_window_secs` are forgotten.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoginThrottleConfig {
+    /// Failures that lock a username, defaults to 5.
+    pub user_max_failures: i32,
+    /// Failures that lock a client IP, defaults to 50; higher since addresses are shared.
+    pub ip_max_failures: i32,
+    /// Wait after the first failure, defaults to 1 second.
+    pub base_delay_secs: i64,
+    /// Longest wait between attempts, defaults to 60 seconds.
+    pub max_delay_secs: i64,
+    /// How long a lockout lasts, defaults to 15 minutes.
+    pub lockout_secs: i64,
+    /// How long failures count towards the limits, defaults to 15 minutes.
+    pub failure_window_secs: i64,
+    /// How often forgotten failures and lapsed lockouts are purged, defaults to 5 minutes.
+    pub sweep_interval_secs: u64,
+}
+
+impl Default for LoginThrottleConfig {
+    fn default() -> Self {
+        Self {
+            user_max_failures: 5,
+            ip_max_failures: 50,
+            base_delay_secs: 1,
+            max_delay_secs: 60,
+            lockout_secs: 15 * 60,
+            failure_window_secs: 15 * 60,
+            sweep_interval_secs: 300,
+        }
+    }
+}
+
+/// TOTP two-factor authentication settings.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MfaConfig {
+    /// Issuer shown next to the account in authenticator apps, defaults to `backend`.
+    pub issuer: String,
+    /// How long the challenge token from a password login can be exchanged, defaults to
+    /// 5 minutes.
+    pub challenge_ttl_secs: i64,
+    /// Wrong codes a challenge token survives, defaults to 5.
+    pub max_challenge_attempts: i32,
+    /// Recovery codes handed out per batch, defaults to 10.
+    pub recovery_code_count: usize,
+}
+
+impl Default for MfaConfig {
+    fn default() -> Self {
+        Self {
+            issuer: "backend".to_string(),
+            challenge_ttl_secs: 5 * 60,
+            max_challenge_attempts: 5,
+            recovery_code_count: 10,
+        }
+    }
+}
+
+/// WebAuthn relying party settings.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebauthnConfig {
+    /// Relying party ID: the domain passkeys are bound to, defaults to `localhost`. Every
+    /// origin must be on it or one of its subdomains.
+    pub rp_id: String,
+    /// Name authenticators show for the site, defaults to `backend`.
+    pub rp_name: String,
+    /// Page origins allowed to run ceremonies, defaults to `http://localhost:8080`.
+    pub origins: Vec<String>,
+    /// How long a ceremony may take, defaults to 5 minutes.
+    pub challenge_ttl_secs: i64,
+    /// Requires a PIN or biometric check on every use, not just a touch. On by default, which
+    /// lets a passkey stand in for both the password and the second factor.
+    pub require_user_verification: bool,
+}
+
+impl Default for WebauthnConfig {
+    fn default() -> Self {
+        Self {
+            rp_id: "localhost".to_string(),
+            rp_name: "backend".to_string(),
+            origins: vec!["http://localhost:8080".to_string()],
+            challenge_ttl_secs: 5 * 60,
+            require_user_verification: true,
+        }
+    }
+}
+
+/// Passwordless sign-in link settings.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MagicLinkConfig {
+    /// How long a sign-in link stays valid, defaults to 15 minutes.
+    pub ttl_secs: i64,
+    /// Page the sign-in link points at; the token is appended as `?token=...`. The page posts
+    /// it to `POST /auth/magic-link/callback`, since opening a link must not spend it.
+    pub callback_url: String,
+    /// Links one address can request per window, defaults to 5.
+    pub max_per_address: usize,
+    /// Window the per-address limit applies to, defaults to 1 hour.
+    pub window_secs: i64,
+}
+
+impl Default for MagicLinkConfig {
+    fn default() -> Self {
+        Self {
+            ttl_secs: 15 * 60,
+            callback_url: "http://127.0.0.1:8080/auth/magic-link/callback".to_string(),
+            max_per_address: 5,
+            window_secs: 60 * 60,
+        }
+    }
+}
+
+/// Settings for the cookies a login can hand its tokens out in.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthCookieConfig {
+    /// Only send the cookies over HTTPS, on by default. Turning it off is meant for local
+    /// development over plain HTTP.
+    pub secure: bool,
+    /// `SameSite` attribute of every cookie, defaults to `strict`.
+    pub same_site: SameSitePolicy,
+    /// Domain the cookies are scoped to; the responding host only when unset.
+    pub domain: Option<String>,
+}
+
+impl Default for AuthCookieConfig {
+    fn default() -> Self {
+        Self {
+            secure: true,
+            same_site: SameSitePolicy::default(),
+            domain: None,
+        }
+    }
+}
+
+/// `SameSite` cookie attribute selected with `AUTH_COOKIE_SAME_SITE`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SameSitePolicy {
+    /// Never sent with cross-site requests.
+    #[default]
+    Strict,
+    /// Sent with top-level cross-site navigations.
+    Lax,
+    /// Sent with every request; browsers require `secure` for it.
+    None,
+}
+
+impl FromStr for SameSitePolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "strict" => Ok(SameSitePolicy::Strict),
+            "lax" => Ok(SameSitePolicy::Lax),
+            "none" => Ok(SameSitePolicy::None),
+            _ => Err(format!(
+                "unknown SameSite policy `{}`, expected strict, lax or none",
+                value
+            )),
+        }
+    }
+}
+
+/// Host of an origin such as `https://app.example.com:8443`.
+fn origin_host(origin: &str) -> Option<&str> {
+    let (_, rest) = origin.split_once("://")?;
+    let host = rest.split(['/', ':']).next()?;
+
+    (!host.is_empty()).then_some(host)
 }
 
 impl AppConfig {
-    pub fn from_env() -> Self {
+    /// Loads the config file and environment overrides, then validates the result.
+    ///
+    /// Every problem found is reported at once so a broken deployment can be fixed in one go.
+    pub fn load() -> Result<Self, ConfigErrors> {
         dotenvy::dotenv().ok();
-        let database_url =
-            std::env::var("DATABASE_URL").expect("DATABASE_URL must be set for the application");
-        let bind_address =
-            std::env::var("BIND_ADDRESS").unwrap_or_else(|_| "127.0.0.1:8080".to_string());
-        let jwt_secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "change-me".to_string());
+        let mut problems = Vec::new();
+
+        let mut config = Self::from_file(&mut problems).unwrap_or_default();
+        let lookup = |key: &str| std::env::var(key).ok();
+        let mut env = EnvLayer::new(&lookup);
+        config.apply_env(&mut env);
+        problems.append(&mut env.problems);
+
+        if let Err(ConfigErrors(invalid)) = config.validate() {
+            problems.extend(invalid);
+        }
+
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigErrors(problems))
+        }
+    }
+
+    /// Reads `CONFIG_FILE`, or `config.toml` when it exists.
+    fn from_file(problems: &mut Vec<ConfigProblem>) -> Option<Self> {
+        let (path, required) = match std::env::var("CONFIG_FILE") {
+            Ok(path) => (path, true),
+            Err(_) => (DEFAULT_CONFIG_FILE.to_string(), false),
+        };
+
+        let raw = match std::fs::read_to_string(&path) {
+            Ok(raw) => raw,
+            Err(e) if !required && e.kind() == ErrorKind::NotFound => return None,
+            Err(e) => {
+                problems.push(ConfigProblem::Invalid {
+                    key: "CONFIG_FILE".to_string(),
+                    reason: format!("cannot read {}: {}", path, e),
+                });
+                return None;
+            }
+        };
+
+        Self::from_toml(&raw)
+            .map_err(|e| {
+                problems.push(ConfigProblem::Invalid {
+                    key: path,
+                    reason: e.to_string(),
+                })
+            })
+            .ok()
+    }
+
+    fn from_toml(raw: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(raw)
+    }
+
+    /// Overrides file values with any environment variables that are set.
+    fn apply_env(&mut self, env: &mut EnvLayer) {
+        if !env.set("APP_ENV", &mut self.environment) {
+            // Validate the rest as strictly as possible.
+            self.environment = Environment::Production;
+        }
+
+        env.set("BIND_ADDRESS", &mut self.server.bind_address);
+        env.set("ALLOW_PUBLIC_BIND", &mut self.server.allow_public_bind);
+        env.set_some("HTTP_WORKERS", &mut self.server.workers);
+        env.set_list("TRUSTED_PROXIES", &mut self.server.trusted_proxies);
+
+        if let Some(url) = env.secret("DATABASE_URL") {
+            self.database.url = url;
+        }
+        env.set(
+            "DATABASE_MAX_CONNECTIONS",
+            &mut self.database.max_connections,
+        );
+        env.set(
+            "DATABASE_MIN_CONNECTIONS",
+            &mut self.database.min_connections,
+        );
+        env.set(
+            "DATABASE_CONNECT_TIMEOUT_SECS",
+            &mut self.database.connect_timeout_secs,
+        );
+        env.set(
+            "DATABASE_IDLE_TIMEOUT_SECS",
+            &mut self.database.idle_timeout_secs,
+        );
+
+        self.jwt.apply_env(env);
+
+        env.set("ARGON2_MEMORY_KIB", &mut self.password_hashing.memory_kib);
+        env.set("ARGON2_ITERATIONS", &mut self.password_hashing.iterations);
+        env.set("ARGON2_PARALLELISM", &mut self.password_hashing.parallelism);
+
+        env.set("LOG_LEVEL", &mut self.logging.level);
+        env.set("LOG_ACCESS", &mut self.logging.access_log);
+
+        env.set_some("POLICY_FILE", &mut self.policy.path);
+        env.set(
+            "POLICY_RELOAD_INTERVAL_SECS",
+            &mut self.policy.reload_interval_secs,
+        );
+
+        env.set("INVITATION_TTL_SECS", &mut self.invitations.ttl_secs);
+        env.set("INVITATION_ACCEPT_URL", &mut self.invitations.accept_url);
 
+        env.set("MAIL_TRANSPORT", &mut self.mail.transport);
+        env.set("MAIL_FROM", &mut self.mail.from);
+        env.set("MAIL_FILE", &mut self.mail.file_path);
+        env.set("SMTP_HOST", &mut self.mail.smtp.host);
+        env.set("SMTP_PORT", &mut self.mail.smtp.port);
+        env.set_some("SMTP_USERNAME", &mut self.mail.smtp.username);
+        if let Some(password) = env.secret("SMTP_PASSWORD") {
+            self.mail.smtp.password = Some(password);
+        }
+        env.set("SMTP_STARTTLS", &mut self.mail.smtp.starttls);
+        env.set("SMTP_HELO_NAME", &mut self.mail.smtp.helo_name);
+        env.set("SMTP_TIMEOUT_SECS", &mut self.mail.smtp.timeout_secs);
+
+        env.set(
+            "EMAIL_VERIFICATION_REQUIRED",
+            &mut self.email_verification.required,
+        );
+        env.set(
+            "EMAIL_VERIFICATION_TTL_SECS",
+            &mut self.email_verification.ttl_secs,
+        );
+        env.set(
+            "EMAIL_VERIFICATION_URL",
+            &mut self.email_verification.verify_url,
+        );
+
+        env.set("PASSWORD_RESET_TTL_SECS", &mut self.password_reset.ttl_secs);
+        env.set("PASSWORD_RESET_URL", &mut self.password_reset.reset_url);
+
+        let policy = &mut self.password_policy;
+        env.set("PASSWORD_MIN_LENGTH", &mut policy.min_length);
+        env.set("PASSWORD_MAX_LENGTH", &mut policy.max_length);
+        env.set("PASSWORD_REQUIRE_LOWERCASE", &mut policy.require_lowercase);
+        env.set("PASSWORD_REQUIRE_UPPERCASE", &mut policy.require_uppercase);
+        env.set("PASSWORD_REQUIRE_DIGIT", &mut policy.require_digit);
+        env.set("PASSWORD_REQUIRE_SYMBOL", &mut policy.require_symbol);
+        env.set("PASSWORD_DISALLOW_USERNAME", &mut policy.disallow_username);
+        env.set_some("PASSWORD_BREACHED_DIR", &mut policy.breached_passwords_dir);
+
+        let throttle = &mut self.login_throttle;
+        env.set("LOGIN_USER_MAX_FAILURES", &mut throttle.user_max_failures);
+        env.set("LOGIN_IP_MAX_FAILURES", &mut throttle.ip_max_failures);
+        env.set("LOGIN_BASE_DELAY_SECS", &mut throttle.base_delay_secs);
+        env.set("LOGIN_MAX_DELAY_SECS", &mut throttle.max_delay_secs);
+        env.set("LOGIN_LOCKOUT_SECS", &mut throttle.lockout_secs);
+        env.set(
+            "LOGIN_FAILURE_WINDOW_SECS",
+            &mut throttle.failure_window_secs,
+        );
+        env.set(
+            "LOGIN_THROTTLE_SWEEP_INTERVAL_SECS",
+            &mut throttle.sweep_interval_secs,
+        );
+
+        env.set("MFA_ISSUER", &mut self.mfa.issuer);
+        env.set("MFA_CHALLENGE_TTL_SECS", &mut self.mfa.challenge_ttl_secs);
+        env.set(
+            "MFA_MAX_CHALLENGE_ATTEMPTS",
+            &mut self.mfa.max_challenge_attempts,
+        );
+        env.set("MFA_RECOVERY_CODE_COUNT", &mut self.mfa.recovery_code_count);
+
+        let webauthn = &mut self.webauthn;
+        env.set("WEBAUTHN_RP_ID", &mut webauthn.rp_id);
+        env.set("WEBAUTHN_RP_NAME", &mut webauthn.rp_name);
+        env.set_list("WEBAUTHN_ORIGINS", &mut webauthn.origins);
+        env.set(
+            "WEBAUTHN_CHALLENGE_TTL_SECS",
+            &mut webauthn.challenge_ttl_secs,
+        );
+        env.set(
+            "WEBAUTHN_REQUIRE_USER_VERIFICATION",
+            &mut webauthn.require_user_verification,
+        );
+
+        env.set("MAGIC_LINK_TTL_SECS", &mut self.magic_link.ttl_secs);
+        env.set("MAGIC_LINK_URL", &mut self.magic_link.callback_url);
+        env.set(
+            "MAGIC_LINK_MAX_PER_ADDRESS",
+            &mut self.magic_link.max_per_address,
+        );
+        env.set("MAGIC_LINK_WINDOW_SECS", &mut self.magic_link.window_secs);
+
+        env.set("AUTH_COOKIE_SECURE", &mut self.auth_cookie.secure);
+        env.set("AUTH_COOKIE_SAME_SITE", &mut self.auth_cookie.same_site);
+        env.set_some("AUTH_COOKIE_DOMAIN", &mut self.auth_cookie.domain);
+
+        env.set(
+            "REVOKED_TOKEN_SWEEP_INTERVAL_SECS",
+            &mut self.revoked_token_sweep_interval_secs,
+        );
+    }
+
+    /// Checks the configuration against the rules of its environment.
+    pub fn validate(&self) -> Result<(), ConfigErrors> {
+        let problems = self.problems();
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigErrors(problems))
+        }
+    }
+
+    fn problems(&self) -> Vec<ConfigProblem> {
+        let mut problems = Vec::new();
+
+        if self.database.url.trim().is_empty() {
+            problems.push(ConfigProblem::Missing("DATABASE_URL"));
+        }
+        if self.database.max_connections == 0
+            || self.database.min_connections > self.database.max_connections
+        {
+            problems.push(ConfigProblem::Invalid {
+                key: "DATABASE_MAX_CONNECTIONS".to_string(),
+                reason: format!(
+                    "must be at least 1 and no less than DATABASE_MIN_CONNECTIONS ({})",
+                    self.database.min_connections
+                ),
+            });
+        }
+        if self.revoked_token_sweep_interval_secs == 0 {
+            problems.push(ConfigProblem::Invalid {
+                key: "REVOKED_TOKEN_SWEEP_INTERVAL_SECS".to_string(),
+                reason: "must be at least 1".to_string(),
+            });
+        }
+        if self.invitations.ttl_secs <= 0 {
+            problems.push(ConfigProblem::Invalid {
+                key: "INVITATION_TTL_SECS".to_string(),
+                reason: "must be at least 1".to_string(),
+            });
+        }
+        if self.email_verification.ttl_secs <= 0 {
+            problems.push(ConfigProblem::Invalid {
+                key: "EMAIL_VERIFICATION_TTL_SECS".to_string(),
+                reason: "must be at least 1".to_string(),
+            });
+        }
+        if self.password_reset.ttl_secs <= 0 {
+            problems.push(ConfigProblem::Invalid {
+                key: "PASSWORD_RESET_TTL_SECS".to_string(),
+                reason: "must be at least 1".to_string(),
+            });
+        }
+        let policy = &self.password_policy;
+        if policy.min_length == 0 || policy.min_length > policy.max_length {
+            problems.push(ConfigProblem::Invalid {
+                key: "PASSWORD_MIN_LENGTH".to_string(),
+                reason: format!(
+                    "must be at least 1 and no more than PASSWORD_MAX_LENGTH ({})",
+                    policy.max_length
+                ),
+            });
+        }
+        if let Some(dir) = &policy.breached_passwords_dir
+            && !std::path::Path::new(dir).is_dir()
+        {
+            problems.push(ConfigProblem::Invalid {
+                key: "PASSWORD_BREACHED_DIR".to_string(),
+                reason: format!("{} is not a directory", dir),
+            });
+        }
+        let throttle = &self.login_throttle;
+        for (key, value) in [
+            (
+                "LOGIN_USER_MAX_FAILURES",
+                i64::from(throttle.user_max_failures),
+            ),
+            ("LOGIN_IP_MAX_FAILURES", i64::from(throttle.ip_max_failures)),
+            ("LOGIN_LOCKOUT_SECS", throttle.lockout_secs),
+            ("LOGIN_FAILURE_WINDOW_SECS", throttle.failure_window_secs),
+            (
+                "LOGIN_THROTTLE_SWEEP_INTERVAL_SECS",
+                throttle.sweep_interval_secs as i64,
+            ),
+            ("MFA_CHALLENGE_TTL_SECS", self.mfa.challenge_ttl_secs),
+            (
+                "MFA_MAX_CHALLENGE_ATTEMPTS",
+                i64::from(self.mfa.max_challenge_attempts),
+            ),
+            (
+                "MFA_RECOVERY_CODE_COUNT",
+                self.mfa.recovery_code_count as i64,
+            ),
+            (
+                "WEBAUTHN_CHALLENGE_TTL_SECS",
+                self.webauthn.challenge_ttl_secs,
+            ),
+            ("MAGIC_LINK_TTL_SECS", self.magic_link.ttl_secs),
+            (
+                "MAGIC_LINK_MAX_PER_ADDRESS",
+                self.magic_link.max_per_address as i64,
+            ),
+            ("MAGIC_LINK_WINDOW_SECS", self.magic_link.window_secs),
+        ] {
+            if value <= 0 {
+                problems.push(ConfigProblem::Invalid {
+                    key: key.to_string(),
+                    reason: "must be at least 1".to_string(),
+                });
+            }
+        }
+        if throttle.base_delay_secs < 0 || throttle.max_delay_secs < throttle.base_delay_secs {
+            problems.push(ConfigProblem::Invalid {
+                key: "LOGIN_MAX_DELAY_SECS".to_string(),
+                reason: format!(
+                    "must be no less than LOGIN_BASE_DELAY_SECS ({}), which can't be negative",
+                    throttle.base_delay_secs
+                ),
+            });
+        }
+        let webauthn = &self.webauthn;
+        if webauthn.origins.is_empty() {
+            problems.push(ConfigProblem::Missing("WEBAUTHN_ORIGINS"));
+        }
+        for origin in &webauthn.origins {
+            let on_rp_id = origin_host(origin).is_some_and(|host| {
+                host == webauthn.rp_id || host.ends_with(&format!(".{}", webauthn.rp_id))
+            });
+            if !on_rp_id {
+                problems.push(ConfigProblem::Invalid {
+                    key: "WEBAUTHN_ORIGINS".to_string(),
+                    reason: format!(
+                        "{} is not on WEBAUTHN_RP_ID ({}) or a subdomain of it",
+                        origin, webauthn.rp_id
+                    ),
+                });
+            }
+        }
+        if self.auth_cookie.same_site == SameSitePolicy::None && !self.auth_cookie.secure {
+            problems.push(ConfigProblem::Invalid {
+                key: "AUTH_COOKIE_SAME_SITE".to_string(),
+                reason: "none requires AUTH_COOKIE_SECURE".to_string(),
+            });
+        }
+        if self.mail.transport == MailTransport::Smtp {
+            let smtp = &self.mail.smtp;
+            if smtp.host.trim().is_empty() {
+                problems.push(ConfigProblem::Missing("SMTP_HOST"));
+            }
+            if smtp.username.is_some() && smtp.password.is_none() {
+                problems.push(ConfigProblem::Missing("SMTP_PASSWORD"));
+            }
+        }
+        if self.server.workers == Some(0) {
+            problems.push(ConfigProblem::Invalid {
+                key: "HTTP_WORKERS".to_string(),
+                reason: "must be at least 1".to_string(),
+            });
+        }
+
+        match parse_bind_address(&self.server.bind_address) {
+            Ok(Some(addr))
+                if addr.ip().is_unspecified()
+                    && self.environment == Environment::Production
+                    && !self.server.allow_public_bind =>
+            {
+                problems.push(ConfigProblem::UnsafeBindAddress(
+                    self.server.bind_address.clone(),
+                ));
+            }
+            Ok(_) => {}
+            Err(reason) => problems.push(ConfigProblem::Invalid {
+                key: "BIND_ADDRESS".to_string(),
+                reason,
+            }),
+        }
+
+        if self.environment.is_development() {
+            return problems;
+        }
+
+        if is_hmac(self.jwt.algorithm) {
+            check_secret(&mut problems, "JWT_SECRET", &self.jwt.secret);
+        }
+        for key in &self.jwt.keys {
+            if let Some(secret) = &key.secret {
+                check_secret(
+                    &mut problems,
+                    &format!("JWT_KEYS[{}].secret", key.kid),
+                    secret,
+                );
+            }
+        }
+        if self.mail.transport == MailTransport::Smtp
+            && self.mail.smtp.username.is_some()
+            && !self.mail.smtp.starttls
+        {
+            problems.push(ConfigProblem::Invalid {
+                key: "SMTP_STARTTLS".to_string(),
+                reason: "SMTP credentials would be sent unencrypted".to_string(),
+            });
+        }
+        if !self.auth_cookie.secure {
+            problems.push(ConfigProblem::Invalid {
+                key: "AUTH_COOKIE_SECURE".to_string(),
+                reason: "token cookies would be sent over plain HTTP".to_string(),
+            });
+        }
+
+        problems
+    }
+}
+
+/// Deployment profile selected with `APP_ENV`.
+///
+/// Defaults to production so a deployment that forgets to set it gets the strictest checks;
+/// local runs opt into development explicitly.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Environment {
+    /// Local development: default secrets and any bind address are tolerated.
+    #[serde(alias = "dev")]
+    Development,
+    /// Pre-production: secrets are checked like production.
+    Staging,
+    /// Secrets are checked and the server must not listen on every interface by accident.
+    #[default]
+    #[serde(alias = "prod")]
+    Production,
+}
+
+impl Environment {
+    pub fn is_development(self) -> bool {
+        self == Environment::Development
+    }
+}
+
+impl FromStr for Environment {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "development" | "dev" => Ok(Environment::Development),
+            "staging" => Ok(Environment::Staging),
+            "production" | "prod" => Ok(Environment::Production),
+            _ => Err(format!(
+                "unknown environment `{}`, expected development, staging or production",
+                value
+            )),
+        }
+    }
+}
+
+/// A single reason the configuration was rejected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConfigProblem {
+    /// A required setting is unset or empty.
+    Missing(&'static str),
+    /// A setting is present but could not be used.
+    Invalid { key: String, reason: String },
+    /// A secret still has its built-in default value.
+    DefaultSecret(String),
+    /// A secret is shorter than [`MIN_SECRET_LEN`] bytes.
+    WeakSecret(String),
+    /// The server would listen on every interface in production.
+    UnsafeBindAddress(String),
+}
+
+impl Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigProblem::Missing(key) => write!(f, "{} must be set", key),
+            ConfigProblem::Invalid { key, reason } => write!(f, "{} is invalid: {}", key, reason),
+            ConfigProblem::DefaultSecret(key) => {
+                write!(f, "{} uses the built-in default secret", key)
+            }
+            ConfigProblem::WeakSecret(key) => {
+                write!(f, "{} must be at least {} bytes long", key, MIN_SECRET_LEN)
+            }
+            ConfigProblem::UnsafeBindAddress(addr) => write!(
+                f,
+                "BIND_ADDRESS {} listens on every interface; bind a specific address or set ALLOW_PUBLIC_BIND=true",
+                addr
+            ),
+        }
+    }
+}
+
+/// Every problem found while loading the configuration.
+#[derive(Debug)]
+pub struct ConfigErrors(pub Vec<ConfigProblem>);
+
+impl Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid configuration:")?;
+        for problem in &self.0 {
+            write!(f, "\n  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+/// Environment variable overrides, collecting a problem for every value that fails to parse.
+struct EnvLayer<'a> {
+    lookup: &'a dyn Fn(&str) -> Option<String>,
+    problems: Vec<ConfigProblem>,
+}
+
+impl<'a> EnvLayer<'a> {
+    fn new(lookup: &'a dyn Fn(&str) -> Option<String>) -> Self {
         Self {
-            database_url,
-            bind_address,
-            jwt_secret,
+            lookup,
+            problems: Vec::new(),
+        }
+    }
+
+    fn invalid(&mut self, key: &str, reason: impl Into<String>) {
+        self.problems.push(ConfigProblem::Invalid {
+            key: key.to_string(),
+            reason: reason.into(),
+        });
+    }
+
+    /// Parses `key` if it is set, recording a problem when it does not parse.
+    fn parse<T>(&mut self, key: &str) -> Option<Option<T>>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let raw = (self.lookup)(key)?;
+
+        match raw.trim().parse() {
+            Ok(value) => Some(Some(value)),
+            Err(e) => {
+                self.invalid(key, e.to_string());
+                Some(None)
+            }
+        }
+    }
+
+    /// Overwrites `target` when `key` is set. Returns `false` if the value was invalid.
+    fn set<T>(&mut self, key: &str, target: &mut T) -> bool
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match self.parse(key) {
+            Some(Some(value)) => *target = value,
+            Some(None) => return false,
+            None => {}
+        }
+        true
+    }
+
+    /// Overwrites `target` with the comma-separated values of `key` when it is set.
+    fn set_list<T>(&mut self, key: &str, target: &mut Vec<T>)
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let Some(raw) = (self.lookup)(key) else {
+            return;
+        };
+        let values: Result<Vec<T>, _> = raw
+            .split(',')
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(|value| {
+                value
+                    .parse::<T>()
+                    .map_err(|e| format!("`{}`: {}", value, e))
+            })
+            .collect();
+        match values {
+            Ok(values) => *target = values,
+            Err(reason) => self.invalid(key, reason),
+        }
+    }
+
+    fn set_some<T>(&mut self, key: &str, target: &mut Option<T>)
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        if let Some(Some(value)) = self.parse(key) {
+            *target = Some(value);
+        }
+    }
+
+    /// Reads a secret from `key`, or from the file named by `{key}_FILE`.
+    fn secret(&mut self, key: &str) -> Option<String> {
+        let file_key = format!("{}_FILE", key);
+
+        match ((self.lookup)(key), (self.lookup)(&file_key)) {
+            (Some(_), Some(_)) => {
+                self.invalid(key, format!("set either {} or {}, not both", key, file_key));
+                None
+            }
+            (Some(value), None) => Some(value),
+            (None, Some(path)) => match std::fs::read_to_string(path.trim()) {
+                Ok(contents) => Some(contents.trim_end_matches(['\r', '\n']).to_string()),
+                Err(e) => {
+                    self.invalid(&file_key, format!("cannot read {}: {}", path, e));
+                    None
+                }
+            },
+            (None, None) => None,
+        }
+    }
+}
+
+fn is_hmac(algorithm: Algorithm) -> bool {
+    matches!(
+        algorithm,
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+    )
+}
+
+fn check_secret(problems: &mut Vec<ConfigProblem>, key: &str, secret: &str) {
+    if secret == DEFAULT_JWT_SECRET {
+        problems.push(ConfigProblem::DefaultSecret(key.to_string()));
+    } else if secret.len() < MIN_SECRET_LEN {
+        problems.push(ConfigProblem::WeakSecret(key.to_string()));
+    }
+}
+
+/// Parses `host:port`, returning the socket address when the host is an IP literal.
+fn parse_bind_address(value: &str) -> Result<Option<SocketAddr>, String> {
+    if let Ok(addr) = value.parse::<SocketAddr>() {
+        return Ok(Some(addr));
+    }
+
+    match value.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(None),
+        _ => Err(format!("expected host:port, got `{}`", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::state::tests::test_config;
+
+    use super::*;
+
+    fn production() -> AppConfig {
+        AppConfig {
+            environment: Environment::Production,
+            ..test_config()
         }
     }
+
+    fn apply(config: &mut AppConfig, vars: &[(&str, &str)]) -> Vec<ConfigProblem> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        let lookup = |key: &str| vars.get(key).cloned();
+        let mut env = EnvLayer::new(&lookup);
+
+        config.apply_env(&mut env);
+        env.problems
+    }
+
+    #[test]
+    fn development_tolerates_default_secret() {
+        let mut config = test_config();
+        config.jwt.secret = DEFAULT_JWT_SECRET.to_string();
+        config.server.bind_address = "0.0.0.0:8080".to_string();
+
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn production_reports_every_problem() {
+        let mut config = production();
+        config.database.url = String::new();
+        config.server.bind_address = "0.0.0.0:8080".to_string();
+        config.jwt.secret = DEFAULT_JWT_SECRET.to_string();
+
+        let ConfigErrors(problems) = config.validate().unwrap_err();
+
+        assert_eq!(
+            problems,
+            vec![
+                ConfigProblem::Missing("DATABASE_URL"),
+                ConfigProblem::UnsafeBindAddress("0.0.0.0:8080".to_string()),
+                ConfigProblem::DefaultSecret("JWT_SECRET".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn staging_checks_secrets_but_not_bind_address() {
+        let mut config = AppConfig {
+            environment: Environment::Staging,
+            ..test_config()
+        };
+        config.server.bind_address = "[::]:8080".to_string();
+        config.jwt.secret = "x".repeat(MIN_SECRET_LEN);
+        assert!(config.validate().is_ok());
+
+        config.jwt.secret = "too-short".to_string();
+        let ConfigErrors(problems) = config.validate().unwrap_err();
+        assert_eq!(
+            problems,
+            vec![ConfigProblem::WeakSecret("JWT_SECRET".to_string())]
+        );
+    }
+
+    #[test]
+    fn production_allows_public_bind_when_opted_in() {
+        let mut config = production();
+        config.server.bind_address = "0.0.0.0:8080".to_string();
+        config.server.allow_public_bind = true;
+        config.jwt.secret = "x".repeat(MIN_SECRET_LEN);
+
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn asymmetric_algorithms_ignore_jwt_secret() {
+        let mut config = production();
+        config.jwt.algorithm = Algorithm::EdDSA;
+        config.jwt.secret = DEFAULT_JWT_SECRET.to_string();
+
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_malformed_bind_address() {
+        let mut config = test_config();
+        config.server.bind_address = "8080".to_string();
+
+        let ConfigErrors(problems) = config.validate().unwrap_err();
+        assert!(matches!(
+            &problems[..],
+            [ConfigProblem::Invalid { key, .. }] if key == "BIND_ADDRESS"
+        ));
+        assert!(parse_bind_address("localhost:8080").unwrap().is_none());
+    }
+
+    #[test]
+    fn parses_environment_names() {
+        assert_eq!("prod".parse(), Ok(Environment::Production));
+        assert_eq!("Staging".parse(), Ok(Environment::Staging));
+        assert_eq!("development".parse(), Ok(Environment::Development));
+        assert!("qa".parse::<Environment>().is_err());
+    }
+
+    #[test]
+    fn environment_defaults_to_production() {
+        let mut config = AppConfig::from_toml("").unwrap();
+        assert_eq!(config.environment, Environment::Production);
+
+        let problems = apply(&mut config, &[]);
+        assert!(problems.is_empty());
+        assert_eq!(config.environment, Environment::Production);
+    }
+
+    #[test]
+    fn reads_typed_sections_from_toml() {
+        let config = AppConfig::from_toml(
+            r#"
+            environment = "prod"
+
+            [server]
+            bind_address = "10.0.0.5:9000"
+            workers = 4
+
+            [database]
+            url = "postgres://db/app"
+            max_connections = 32
+
+            [jwt]
+            algorithm = "EdDSA"
+            issuer = "auth.example.com"
+
+            [[jwt.keys]]
+            kid = "2025-01"
+            algorithm = "ES256"
+            state = "verify_only"
+            public_key_path = "/keys/2025-01.pem"
+
+            [logging]
+            level = "backend=debug"
+            access_log = false
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.environment, Environment::Production);
+        assert_eq!(config.server.bind_address, "10.0.0.5:9000");
+        assert_eq!(config.server.workers, Some(4));
+        assert_eq!(config.database.url, "postgres://db/app");
+        assert_eq!(config.database.max_connections, 32);
+        assert_eq!(config.database.min_connections, 1);
+        assert_eq!(config.jwt.algorithm, Algorithm::EdDSA);
+        assert_eq!(config.jwt.issuer, "auth.example.com");
+        assert_eq!(config.jwt.audience, "backend-api");
+        assert_eq!(config.jwt.keys[0].state, KeyState::VerifyOnly);
+        assert_eq!(config.logging.level, "backend=debug");
+        assert!(!config.logging.access_log);
+    }
+
+    #[test]
+    fn toml_rejects_unknown_keys() {
+        assert!(AppConfig::from_toml("[server]\nbind_adress = \"127.0.0.1:80\"").is_err());
+    }
+
+    #[test]
+    fn env_overrides_file_values() {
+        let mut config = AppConfig::from_toml(
+            "[server]\nbind_address = \"10.0.0.5:9000\"\n[jwt]\nissuer = \"file\"",
+        )
+        .unwrap();
+
+        let problems = apply(
+            &mut config,
+            &[
+                ("BIND_ADDRESS", "127.0.0.1:7000"),
+                ("DATABASE_MAX_CONNECTIONS", "5"),
+                ("LOG_ACCESS", "false"),
+                ("POLICY_FILE", "/etc/backend/policy.toml"),
+                ("TRUSTED_PROXIES", "10.0.0.1, ::1"),
+            ],
+        );
+
+        assert!(problems.is_empty());
+        assert_eq!(
+            config.server.trusted_proxies,
+            [
+                "10.0.0.1".parse::<IpAddr>().unwrap(),
+                "::1".parse().unwrap()
+            ]
+        );
+        assert_eq!(
+            config.policy.path.as_deref(),
+            Some("/etc/backend/policy.toml")
+        );
+        assert_eq!(config.server.bind_address, "127.0.0.1:7000");
+        assert_eq!(config.database.max_connections, 5);
+        assert_eq!(config.jwt.issuer, "file");
+        assert!(!config.logging.access_log);
+    }
+
+    #[test]
+    fn smtp_transport_needs_a_host_and_tls_for_credentials() {
+        let mut config = production();
+        config.jwt.secret = "x".repeat(MIN_SECRET_LEN);
+
+        let problems = apply(
+            &mut config,
+            &[
+                ("MAIL_TRANSPORT", "smtp"),
+                ("SMTP_USERNAME", "mailer"),
+                ("SMTP_PASSWORD", "secret"),
+                ("SMTP_STARTTLS", "false"),
+            ],
+        );
+        assert!(problems.is_empty());
+
+        let ConfigErrors(problems) = config.validate().unwrap_err();
+        assert_eq!(
+            problems,
+            vec![
+                ConfigProblem::Missing("SMTP_HOST"),
+                ConfigProblem::Invalid {
+                    key: "SMTP_STARTTLS".to_string(),
+                    reason: "SMTP credentials would be sent unencrypted".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn password_policy_bounds_must_be_consistent() {
+        let mut config = test_config();
+
+        let problems = apply(
+            &mut config,
+            &[
+                ("PASSWORD_MIN_LENGTH", "20"),
+                ("PASSWORD_MAX_LENGTH", "16"),
+                ("PASSWORD_BREACHED_DIR", "/nonexistent/breaches"),
+            ],
+        );
+        assert!(problems.is_empty());
+
+        let ConfigErrors(problems) = config.validate().unwrap_err();
+        let keys: Vec<_> = problems
+            .iter()
+            .map(|problem| match problem {
+                ConfigProblem::Invalid { key, .. } => key.as_str(),
+                other => panic!("unexpected problem {:?}", other),
+            })
+            .collect();
+        assert_eq!(keys, ["PASSWORD_MIN_LENGTH", "PASSWORD_BREACHED_DIR"]);
+    }
+
+    #[test]
+    fn sweep_intervals_are_set_separately() {
+        let mut config = test_config();
+
+        let problems = apply(
+            &mut config,
+            &[
+                ("REVOKED_TOKEN_SWEEP_INTERVAL_SECS", "60"),
+                ("LOGIN_THROTTLE_SWEEP_INTERVAL_SECS", "0"),
+            ],
+        );
+        assert!(problems.is_empty());
+        assert_eq!(config.revoked_token_sweep_interval_secs, 60);
+
+        let ConfigErrors(problems) = config.validate().unwrap_err();
+        assert!(matches!(
+            problems.as_slice(),
+            [ConfigProblem::Invalid { key, .. }] if key == "LOGIN_THROTTLE_SWEEP_INTERVAL_SECS"
+        ));
+    }
+
+    #[test]
+    fn env_reports_unparsable_values() {
+        let mut config = AppConfig::default();
+
+        let problems = apply(
+            &mut config,
+            &[
+                ("APP_ENV", "qa"),
+                ("HTTP_WORKERS", "many"),
+                ("TRUSTED_PROXIES", "10.0.0.0/8"),
+                ("JWT_KEYS", "not json"),
+            ],
+        );
+
+        let keys: Vec<_> = problems
+            .iter()
+            .map(|problem| match problem {
+                ConfigProblem::Invalid { key, .. } => key.as_str(),
+                other => panic!("unexpected problem {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            keys,
+            ["APP_ENV", "HTTP_WORKERS", "TRUSTED_PROXIES", "JWT_KEYS"]
+        );
+        assert_eq!(config.environment, Environment::Production);
+    }
+
+    #[test]
+    fn webauthn_origins_must_be_on_the_rp_id() {
+        let mut config = test_config();
+        apply(
+            &mut config,
+            &[
+                ("WEBAUTHN_RP_ID", "example.com"),
+                (
+                    "WEBAUTHN_ORIGINS",
+                    "https://example.com, https://app.example.com:8443,https://notexample.com",
+                ),
+            ],
+        );
+
+        let ConfigErrors(problems) = config.validate().unwrap_err();
+
+        assert_eq!(config.webauthn.origins.len(), 3);
+        assert!(matches!(
+            &problems[..],
+            [ConfigProblem::Invalid { key, reason }]
+                if key == "WEBAUTHN_ORIGINS" && reason.starts_with("https://notexample.com ")
+        ));
+    }
+
+    #[test]
+    fn insecure_auth_cookies_are_development_only() {
+        let mut config = test_config();
+        let problems = apply(
+            &mut config,
+            &[
+                ("AUTH_COOKIE_SECURE", "false"),
+                ("AUTH_COOKIE_SAME_SITE", "Lax"),
+            ],
+        );
+        assert!(problems.is_empty());
+        assert_eq!(config.auth_cookie.same_site, SameSitePolicy::Lax);
+        assert!(config.validate().is_ok());
+
+        config.auth_cookie.same_site = SameSitePolicy::None;
+        let ConfigErrors(problems) = config.validate().unwrap_err();
+        assert_eq!(
+            problems,
+            vec![ConfigProblem::Invalid {
+                key: "AUTH_COOKIE_SAME_SITE".to_string(),
+                reason: "none requires AUTH_COOKIE_SECURE".to_string(),
+            }]
+        );
+
+        let mut config = production();
+        config.jwt.secret = "x".repeat(MIN_SECRET_LEN);
+        config.auth_cookie.secure = false;
+        let ConfigErrors(problems) = config.validate().unwrap_err();
+        assert_eq!(
+            problems,
+            vec![ConfigProblem::Invalid {
+                key: "AUTH_COOKIE_SECURE".to_string(),
+                reason: "token cookies would be sent over plain HTTP".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn secrets_can_be_read_from_files() {
+        let path = std::env::temp_dir().join(format!("jwt-secret-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "from-a-mounted-secret\n").unwrap();
+        let mut config = AppConfig::default();
+
+        let problems = apply(
+            &mut config,
+            &[
+                ("JWT_SECRET_FILE", path.to_str().unwrap()),
+                ("SMTP_PASSWORD", "inline"),
+                ("SMTP_PASSWORD_FILE", "/also/set"),
+                ("DATABASE_URL_FILE", "/does/not/exist"),
+            ],
+        );
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.jwt.secret, "from-a-mounted-secret");
+        assert_eq!(config.mail.smtp.password, None);
+        assert!(matches!(
+            &problems[..],
+            [
+                ConfigProblem::Invalid { key: unreadable, .. },
+                ConfigProblem::Invalid { key: conflict, .. },
+            ] if unreadable == "DATABASE_URL_FILE" && conflict == "SMTP_PASSWORD"
+        ));
+    }
 }
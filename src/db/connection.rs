@@ -1,6 +1,17 @@
-use sea_orm::{Database, DatabaseConnection, DbErr};
+use std::time::Duration;
 
-/// Establishes a Postgres connection using `DATABASE_URL`.
-pub async fn establish_connection(database_url: &str) -> Result<DatabaseConnection, DbErr> {
-    Database::connect(database_url).await
+use sea_orm::{ConnectOptions, Database, DatabaseConnection, DbErr};
+
+use crate::config::DatabaseConfig;
+
+/// Opens the Postgres connection pool described by the `[database]` settings.
+pub async fn establish_connection(config: &DatabaseConfig) -> Result<DatabaseConnection, DbErr> {
+    let mut options = ConnectOptions::new(&config.url);
+    options
+        .max_connections(config.max_connections)
+        .min_connections(config.min_connections)
+        .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+        .idle_timeout(Duration::from_secs(config.idle_timeout_secs));
+
+    Database::connect(options).await
 }
@@ -0,0 +1,325 @@
+use actix_web::{HttpResponse, web};
+use chrono::Utc;
+use serde_json::json;
+
+use crate::middleware::AuthenticatedUser;
+use crate::models::user::Model as UserModel;
+use crate::services::login_throttle_service::{
+    Throttle, clear_login_throttle, find_login_throttle, throttle_of, user_key,
+};
+use crate::services::organization_service::ORG_ROLES;
+use crate::services::role_service::{assign_role, remove_role};
+use crate::services::user_service::{Tenant, find_user_by_id, list_users as list_all_users};
+use crate::state::AppState;
+
+// These handlers carry no route attributes: `routes::configure` mounts them on resources
+// wrapped with the `require_permission` guard they need. An administrator acting in an
+// organization only sees and manages that organization's members.
+
+/// Lists every account. Requires `users:read`.
+pub async fn list_users(state: web::Data<AppState>, user: AuthenticatedUser) -> HttpResponse {
+    match list_all_users(&state.db, Tenant::of(&user.claims)).await {
+        Ok(users) => HttpResponse::Ok().json(json!({
+            "users": users
+                .into_iter()
+                .map(|user| json!({ "id": user.id, "username": user.username }))
+                .collect::<Vec<_>>(),
+        })),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("DB error when listing users: {}", e))
+        }
+    }
+}
+
+/// Grants a role to a user. Requires `users:write`; takes effect on their next token.
+///
+/// Organization roles only come with a membership, so they can't be granted here.
+pub async fn grant_role(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<(i32, String)>,
+) -> HttpResponse {
+    let (user_id, role) = path.into_inner();
+
+    if ORG_ROLES.contains(&role.as_str()) {
+        return HttpResponse::BadRequest().body(format!(
+            "{} is an organization role; invite the user to the organization instead",
+            role
+        ));
+    }
+    if let Err(response) = ensure_visible(&state, &user, user_id).await {
+        return response;
+    }
+
+    match assign_role(&state.db, user_id, &role).await {
+        Ok(Some(_)) => HttpResponse::NoContent().finish(),
+        Ok(None) => HttpResponse::NotFound().body(format!("Unknown role {}", role)),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("DB error when granting role: {}", e))
+        }
+    }
+}
+
+/// Takes a role away from a user. Requires `users:write`.
+pub async fn revoke_role(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<(i32, String)>,
+) -> HttpResponse {
+    let (user_id, role) = path.into_inner();
+
+    if let Err(response) = ensure_visible(&state, &user, user_id).await {
+        return response;
+    }
+
+    match remove_role(&state.db, user_id, &role).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => HttpResponse::NotFound().body("User does not have that role"),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("DB error when revoking role: {}", e))
+        }
+    }
+}
+
+/// Shows whether failed logins currently delay or lock the user's login. Requires `users:read`.
+pub async fn lock_status(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> HttpResponse {
+    let target = match ensure_visible(&state, &user, path.into_inner()).await {
+        Ok(target) => target,
+        Err(response) => return response,
+    };
+
+    let entry = match find_login_throttle(&state.db, &user_key(&target.username)).await {
+        Ok(entry) => entry,
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error when loading lock status: {}", e));
+        }
+    };
+    let now = Utc::now();
+    let throttle = entry.as_ref().map_or(Throttle::Open, |entry| {
+        throttle_of(&state.config.login_throttle, entry, now)
+    });
+
+    HttpResponse::Ok().json(json!({
+        "user_id": target.id,
+        "locked": matches!(throttle, Throttle::Locked { .. }),
+        "locked_until": match throttle {
+            Throttle::Locked { until } => Some(until),
+            _ => None,
+        },
+        "retry_after_secs": throttle.retry_after_secs(now),
+        "failed_attempts": entry.as_ref().map_or(0, |entry| entry.failures),
+        "last_failure_at": entry.map(|entry| entry.last_failure_at),
+    }))
+}
+
+/// Lifts a login lockout and forgets the user's failed logins. Requires `users:write`.
+pub async fn unlock(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> HttpResponse {
+    let target = match ensure_visible(&state, &user, path.into_inner()).await {
+        Ok(target) => target,
+        Err(response) => return response,
+    };
+
+    match clear_login_throttle(&state.db, &user_key(&target.username)).await {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("DB error when unlocking user: {}", e))
+        }
+    }
+}
+
+/// Answers `404` for users outside the caller's tenant, as if they did not exist.
+async fn ensure_visible(
+    state: &AppState,
+    caller: &AuthenticatedUser,
+    user_id: i32,
+) -> Result<UserModel, HttpResponse> {
+    match find_user_by_id(&state.db, Tenant::of(&caller.claims), user_id).await {
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => Err(HttpResponse::NotFound().body("User not found")),
+        Err(e) => {
+            Err(HttpResponse::InternalServerError()
+                .body(format!("DB error when loading user: {}", e)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, http::StatusCode, test};
+    use sea_orm::{DatabaseBackend, MockDatabase};
+
+    use crate::models::login_throttle::Model as LoginThrottleModel;
+    use crate::models::revoked_token::Model as RevokedTokenModel;
+    use crate::models::role::Model as RoleModel;
+    use crate::models::user::Model as UserModel;
+    use crate::routes;
+    use crate::state::tests::{exec, test_state};
+    use crate::utils::UserGrants;
+
+    use super::*;
+
+    fn admin_grants() -> UserGrants {
+        UserGrants::new(
+            ["admin".to_string()],
+            ["users:read".to_string(), "users:write".to_string()],
+        )
+    }
+
+    #[actix_web::test]
+    async fn grant_role_requires_users_write() {
+        let state = web::Data::new(test_state(
+            MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<RevokedTokenModel>::new()])
+                .into_connection(),
+        ));
+        let token = state
+            .issue_access_token(1, &UserGrants::new([], ["users:read".to_string()]))
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::put()
+            .uri("/admin/users/2/roles/admin")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn organization_roles_cannot_be_granted_globally() {
+        let state = web::Data::new(test_state(
+            MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<RevokedTokenModel>::new()])
+                .into_connection(),
+        ));
+        let token = state.issue_access_token(1, &admin_grants()).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::put()
+            .uri("/admin/users/2/roles/owner")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn admin_can_grant_role() {
+        let state = web::Data::new(test_state(
+            MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<RevokedTokenModel>::new()])
+                .append_query_results([vec![UserModel {
+                    id: 2,
+                    username: "bob".into(),
+                    username_canonical: "bob".into(),
+                    password: String::new(),
+                    active_organization_id: None,
+                    email: None,
+                    email_verified_at: None,
+                }]])
+                .append_query_results([vec![RoleModel {
+                    id: 1,
+                    name: "admin".into(),
+                    description: None,
+                }]])
+                .append_exec_results([exec(1)])
+                .into_connection(),
+        ));
+        let token = state.issue_access_token(1, &admin_grants()).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::put()
+            .uri("/admin/users/2/roles/admin")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[actix_web::test]
+    async fn lock_status_reports_a_locked_account() {
+        let locked_until = Utc::now() + chrono::Duration::minutes(5);
+        let state = web::Data::new(test_state(
+            MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<RevokedTokenModel>::new()])
+                .append_query_results([vec![UserModel {
+                    id: 2,
+                    username: "bob".into(),
+                    username_canonical: "bob".into(),
+                    password: String::new(),
+                    active_organization_id: None,
+                    email: None,
+                    email_verified_at: None,
+                }]])
+                .append_query_results([vec![LoginThrottleModel {
+                    key: "user:bob".into(),
+                    failures: 5,
+                    last_failure_at: Utc::now(),
+                    locked_until: Some(locked_until),
+                }]])
+                .into_connection(),
+        ));
+        let token = state.issue_access_token(1, &admin_grants()).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/admin/users/2/lock")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+        assert_eq!(body["locked"], true);
+        assert_eq!(body["failed_attempts"], 5);
+        assert!(body["retry_after_secs"].as_i64().unwrap() > 290);
+    }
+
+    #[actix_web::test]
+    async fn list_users_requires_a_token() {
+        let state = web::Data::new(test_state(
+            MockDatabase::new(DatabaseBackend::Postgres).into_connection(),
+        ));
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/admin/users").to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+}
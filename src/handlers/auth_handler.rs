@@ -1,95 +1,571 @@
-use actix_web::{HttpRequest, HttpResponse, ResponseError, post, web};
-use serde::Deserialize;
-use serde_json::json;
+use actix_web::{HttpRequest, HttpResponse, ResponseError, http::header, post, web};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use uuid::Uuid;
 
-use crate::services::user_service::{create_user, find_user_by_username};
-use crate::state::{self, AppState};
-use crate::utils::encode_token;
+use crate::handlers::email_handler::{parse_email, send_verification};
+use crate::handlers::mfa_handler::confirmed_credential;
+use crate::handlers::password_handler::enforce_password_policy;
+use crate::middleware::{AuthenticatedUser, ClientInfo, client_ip};
+use crate::models::user::Model as UserModel;
+use crate::services::login_throttle_service::{
+    Throttle, check_login_throttle, clear_login_throttle, ip_key, record_login_failure, user_key,
+};
+use crate::services::mfa_service::issue_mfa_challenge;
+use crate::services::refresh_token_service::{
+    RefreshOutcome, issue_refresh_token, rotate_refresh_token,
+};
+use crate::services::role_service::{DEFAULT_ROLE, assign_role, load_user_grants};
+use crate::services::session_service::{
+    end_session, mark_session_revoked, start_session, touch_session,
+};
+use crate::services::token_service::revoke_session_tokens;
+use crate::services::user_service::{
+    Tenant, create_user, find_user_by_email, find_user_by_id, find_user_by_username,
+    release_unverified_email, update_user_password,
+};
+use crate::state::AppState;
+use crate::utils::auth_cookie::{
+    ACCESS_TOKEN_COOKIE, REFRESH_TOKEN_COOKIE, cleared_session_cookies, session_cookies,
+};
+use crate::utils::{
+    PasswordVerification, TokenTransport, generate_opaque_token, hash_password, validate_username,
+    verify_dummy_password, verify_password,
+};
 
 #[derive(Deserialize)]
 pub struct LoginRequest {
     username: String,
     password: String,
+    /// `cookie` hands the tokens out in HttpOnly cookies instead of the body.
+    #[serde(default)]
+    transport: TokenTransport,
 }
 
 #[derive(Deserialize)]
 pub struct RegisterRequest {
     username: String,
     password: String,
+    /// Optional unless `EMAIL_VERIFICATION_REQUIRED` is set.
+    email: Option<String>,
+    /// As for `POST /auth/login`.
+    #[serde(default)]
+    transport: TokenTransport,
 }
 
+#[derive(Deserialize)]
+pub struct RefreshRequest {
+    refresh_token: String,
+}
+
+/// Access token plus the refresh token that can renew it.
+#[derive(Serialize)]
+pub struct TokenPair {
+    pub token: String,
+    pub refresh_token: String,
+}
+
+/// Mints an access token carrying the user's current roles and permissions, scoped to
+/// `organization_id` when they are a member of it and tied to `session_id`.
+pub(crate) async fn mint_access_token(
+    state: &AppState,
+    user_id: i32,
+    organization_id: Option<i32>,
+    session_id: Option<&str>,
+) -> Result<String, HttpResponse> {
+    let grants = load_user_grants(&state.db, user_id, organization_id)
+        .await
+        .map_err(|e| {
+            HttpResponse::InternalServerError().body(format!("DB error on loading roles: {}", e))
+        })?
+        .in_session(session_id);
+
+    state.issue_access_token(user_id, &grants).map_err(|e| {
+        HttpResponse::InternalServerError().body(format!("JWT encoding failed: {}", e))
+    })
+}
+
+/// Starts a session for the user: a new refresh token family, recorded with the client it
+/// was issued to, and an access token tied to it.
+pub(crate) async fn issue_token_pair(
+    state: &AppState,
+    client: &ClientInfo,
+    user_id: i32,
+    organization_id: Option<i32>,
+) -> Result<TokenPair, HttpResponse> {
+    let session_id = Uuid::new_v4().to_string();
+    let token = mint_access_token(state, user_id, organization_id, Some(&session_id)).await?;
+    let refresh_token = issue_refresh_token(
+        &state.db,
+        user_id,
+        Some(session_id.clone()),
+        state.config.jwt.refresh_token_ttl_secs,
+    )
+    .await
+    .map_err(|e| {
+        HttpResponse::InternalServerError()
+            .body(format!("DB error on issuing refresh token: {}", e))
+    })?;
+    start_session(&state.db, user_id, &session_id, client)
+        .await
+        .map_err(|e| {
+            HttpResponse::InternalServerError().body(format!("DB error on starting session: {}", e))
+        })?;
+
+    Ok(TokenPair {
+        token,
+        refresh_token,
+    })
+}
+
+/// Hands a new token pair to the client along with the fields of `details`: in the body, or
+/// in cookies with a fresh CSRF token for `X-CSRF-Token` in its place.
+pub(crate) fn token_pair_response(
+    state: &AppState,
+    tokens: TokenPair,
+    transport: TokenTransport,
+    details: Value,
+) -> HttpResponse {
+    let mut body = match details {
+        Value::Object(fields) => fields,
+        _ => Map::new(),
+    };
+    match transport {
+        TokenTransport::Body => {
+            body.insert("token".to_string(), tokens.token.into());
+            body.insert("refresh_token".to_string(), tokens.refresh_token.into());
+            HttpResponse::Ok().json(body)
+        }
+        TokenTransport::Cookie => {
+            let csrf_token = generate_opaque_token();
+            let mut response = HttpResponse::Ok();
+            for cookie in session_cookies(
+                &state.config.auth_cookie,
+                &state.config.jwt,
+                tokens.token,
+                tokens.refresh_token,
+                csrf_token.clone(),
+            ) {
+                response.cookie(cookie);
+            }
+            body.insert("csrf_token".to_string(), csrf_token.into());
+            response.json(body)
+        }
+    }
+}
+
+/// Answers `429` with `Retry-After` while the username or client IP is throttled.
+pub(crate) fn throttled_response(throttle: Throttle) -> Option<HttpResponse> {
+    let message = match throttle {
+        Throttle::Open => return None,
+        Throttle::Delayed { .. } => "Too many failed logins; try again shortly.",
+        Throttle::Locked { .. } => {
+            "Too many failed logins; login is locked for now. Resetting the password unlocks it."
+        }
+    };
+
+    Some(
+        HttpResponse::TooManyRequests()
+            .insert_header((
+                header::RETRY_AFTER,
+                throttle.retry_after_secs(Utc::now()).to_string(),
+            ))
+            .body(message),
+    )
+}
+
+/// Counts a failed login against the username and the client IP.
+async fn record_failed_login(state: &AppState, keys: &[String]) -> Result<(), HttpResponse> {
+    let config = &state.config.login_throttle;
+
+    for key in keys {
+        let max_failures = if key.starts_with("ip:") {
+            config.ip_max_failures
+        } else {
+            config.user_max_failures
+        };
+        let entry = record_login_failure(&state.db, config, key, max_failures)
+            .await
+            .map_err(|e| {
+                HttpResponse::InternalServerError()
+                    .body(format!("DB error on recording failed login: {}", e))
+            })?;
+        if entry.failures == max_failures {
+            log::warn!("login locked for {} after {} failures", key, entry.failures);
+        }
+    }
+    Ok(())
+}
+
+/// Checks the password, throttling repeated failures per username and per client IP.
+///
+/// Users with a second factor get a short-lived `mfa_token` for `POST /auth/mfa/verify`
+/// instead of a token pair.
 #[post("/auth/login")]
 pub async fn login(
+    req: HttpRequest,
+    client: ClientInfo,
     state: web::Data<AppState>,
     login_payload: web::Json<LoginRequest>,
 ) -> HttpResponse {
-    match find_user_by_username(&state.db, &login_payload.username).await {
+    let mut throttle_keys = vec![user_key(&login_payload.username)];
+    throttle_keys.extend(client_ip(&req).map(ip_key));
+
+    match check_login_throttle(&state.db, &state.config.login_throttle, &throttle_keys).await {
+        Ok(throttle) => {
+            if let Some(response) = throttled_response(throttle) {
+                return response;
+            }
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on checking login throttle: {}", e));
+        }
+    }
+
+    match find_user_by_username(&state.db, Tenant::Global, &login_payload.username).await {
         Ok(Some(user)) => {
-            if user.password != login_payload.password {
-                HttpResponse::Unauthorized().body("Invalid password.")
-            } else {
-                match encode_token(&state.config.jwt_secret, user.id) {
-                    Ok(token) => HttpResponse::Ok().json(json!({ "token": token })),
-                    Err(e) => HttpResponse::InternalServerError()
-                        .body(format!("JWT encoding failed: {}", e)),
-                }
+            if user.password.is_empty() {
+                // Passkey-only accounts would otherwise fail faster than a wrong password.
+                verify_dummy_password(&state.config.password_hashing, &login_payload.password);
+            }
+            let verification = verify_password(
+                &state.config.password_hashing,
+                &login_payload.password,
+                &user.password,
+            );
+
+            if !verification.is_match() {
+                return reject_login(&state, &throttle_keys).await;
             }
+            if verification == PasswordVerification::NeedsRehash {
+                upgrade_password_hash(&state, user.id, &login_payload.password).await;
+            }
+
+            complete_login(
+                &state,
+                &client,
+                &user,
+                &throttle_keys[0],
+                login_payload.transport,
+            )
+            .await
+        }
+        Ok(None) => {
+            verify_dummy_password(&state.config.password_hashing, &login_payload.password);
+            reject_login(&state, &throttle_keys).await
         }
-        Ok(None) => HttpResponse::Unauthorized().body("Invalid username or password."),
         Err(e) => {
             HttpResponse::InternalServerError().body(format!("DB error on fetching user: {}", e))
         }
     }
 }
 
+/// Answers a failed login the same way whether or not the username exists.
+async fn reject_login(state: &AppState, throttle_keys: &[String]) -> HttpResponse {
+    match record_failed_login(state, throttle_keys).await {
+        Ok(()) => HttpResponse::Unauthorized().body("Invalid username or password."),
+        Err(response) => response,
+    }
+}
+
+/// Finishes a login whose first factor checked out, answering like `POST /auth/login`.
+///
+/// Users with a second factor get a short-lived `mfa_token` instead of a token pair.
+pub(crate) async fn complete_login(
+    state: &AppState,
+    client: &ClientInfo,
+    user: &UserModel,
+    throttle_key: &str,
+    transport: TokenTransport,
+) -> HttpResponse {
+    let totp = match confirmed_credential(state, user.id).await {
+        Ok(totp) => totp,
+        Err(response) => return response,
+    };
+    // Only the username's count is reset; one good account doesn't clear an IP. With a second
+    // factor that waits until `POST /auth/mfa/verify` succeeds.
+    if totp.is_none()
+        && let Err(e) = clear_login_throttle(&state.db, throttle_key).await
+    {
+        return HttpResponse::InternalServerError()
+            .body(format!("DB error on clearing login throttle: {}", e));
+    }
+
+    if state.config.email_verification.required && user.email_verified_at.is_none() {
+        return HttpResponse::Forbidden().body("Email address not verified.");
+    }
+
+    if totp.is_some() {
+        let ttl_secs = state.config.mfa.challenge_ttl_secs;
+        return match issue_mfa_challenge(&state.db, user.id, ttl_secs).await {
+            Ok(mfa_token) => HttpResponse::Ok().json(json!({
+                "mfa_required": true,
+                "mfa_token": mfa_token,
+                "expires_in": ttl_secs,
+            })),
+            Err(e) => HttpResponse::InternalServerError()
+                .body(format!("DB error on issuing MFA challenge: {}", e)),
+        };
+    }
+    match issue_token_pair(state, client, user.id, user.active_organization_id).await {
+        Ok(tokens) => token_pair_response(state, tokens, transport, json!({})),
+        Err(response) => response,
+    }
+}
+
+/// Re-hashes a verified password with the current Argon2id parameters.
+///
+/// Best effort: a failed upgrade leaves the old value in place and is retried on
+/// the next successful login.
+async fn upgrade_password_hash(state: &AppState, user_id: i32, password: &str) {
+    if let Ok(password_hash) = hash_password(&state.config.password_hashing, password) {
+        let _ = update_user_password(&state.db, user_id, password_hash).await;
+    }
+}
+
+/// The `register` path: claims the username (and normalized `email`, taking it over from an
+/// account that never verified it), hashes the password,
+/// creates the user with [`DEFAULT_ROLE`] and mails a verification link to the address.
+///
+/// Passkey-only accounts pass no password and store an empty one, which never verifies.
+pub(crate) async fn create_account(
+    state: &AppState,
+    username: &str,
+    password: Option<&str>,
+    email: Option<String>,
+) -> Result<UserModel, HttpResponse> {
+    let username = validate_username(username)
+        .map_err(|reason| HttpResponse::BadRequest().body(reason.to_string()))?;
+    match find_user_by_username(&state.db, Tenant::Global, &username).await {
+        Ok(Some(_)) => return Err(HttpResponse::BadRequest().body("Username already exists.")),
+        Ok(None) => {}
+        Err(e) => {
+            return Err(HttpResponse::InternalServerError()
+                .body(format!("DB error on checking username: {}", e)));
+        }
+    }
+    if let Some(email) = &email {
+        match find_user_by_email(&state.db, Tenant::Global, email).await {
+            Ok(Some(holder)) if holder.email_verified_at.is_some() => {
+                return Err(HttpResponse::BadRequest().body("Email address is already in use."));
+            }
+            Ok(Some(_)) => {
+                release_unverified_email(&state.db, email)
+                    .await
+                    .map_err(|e| {
+                        HttpResponse::InternalServerError()
+                            .body(format!("DB error on releasing email: {}", e))
+                    })?;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                return Err(HttpResponse::InternalServerError()
+                    .body(format!("DB error on checking email: {}", e)));
+            }
+        }
+    }
+
+    let password_hash = match password {
+        Some(password) => {
+            enforce_password_policy(state, password, &username).await?;
+            hash_password(&state.config.password_hashing, password).map_err(|e| {
+                HttpResponse::InternalServerError().body(format!("Password hashing failed: {}", e))
+            })?
+        }
+        None => String::new(),
+    };
+
+    let created_user = create_user(&state.db, username, password_hash, email)
+        .await
+        .map_err(|e| {
+            HttpResponse::InternalServerError().body(format!("DB error on insert user: {}", e))
+        })?;
+
+    assign_role(&state.db, created_user.id, DEFAULT_ROLE)
+        .await
+        .map_err(|e| {
+            HttpResponse::InternalServerError()
+                .body(format!("DB error on assigning default role: {}", e))
+        })?;
+
+    if let Some(email) = &created_user.email {
+        send_verification(state, created_user.id, email).await?;
+    }
+
+    Ok(created_user)
+}
+
 #[post("/auth/register")]
 pub async fn register(
+    client: ClientInfo,
     state: web::Data<AppState>,
     register_payload: web::Json<RegisterRequest>,
 ) -> HttpResponse {
-    match find_user_by_username(&state.db, &register_payload.username).await {
-        Ok(Some(_)) => return HttpResponse::BadRequest().body("Username already exists."),
-        Ok(None) => {}
-        Err(e) => {
-            return HttpResponse::InternalServerError()
-                .body(format!("DB error on checking username: {}", e));
-        }
+    let email = match parse_email(&state, register_payload.email.as_deref()) {
+        Ok(email) => email,
+        Err(reason) => return HttpResponse::BadRequest().body(reason),
+    };
+    let created_user = match create_account(
+        &state,
+        &register_payload.username,
+        Some(&register_payload.password),
+        email,
+    )
+    .await
+    {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let user = json!({
+        "id": created_user.id,
+        "username": created_user.username,
+        "email": created_user.email,
+    });
+
+    // Tokens are only handed out once the address is confirmed.
+    if state.config.email_verification.required {
+        return HttpResponse::Accepted().json(json!({
+            "user": user,
+            "email_verification": "pending",
+        }));
+    }
+
+    match issue_token_pair(&state, &client, created_user.id, None).await {
+        Ok(tokens) => token_pair_response(
+            &state,
+            tokens,
+            register_payload.transport,
+            json!({ "user": user }),
+        ),
+        Err(response) => response,
     }
+}
 
-    match create_user(
+/// Rotates a refresh token from the body, or from the refresh cookie of a cookie-mode session,
+/// which then gets its cookies renewed.
+#[post("/auth/refresh")]
+pub async fn refresh(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    refresh_payload: Option<web::Json<RefreshRequest>>,
+) -> HttpResponse {
+    let (presented, transport) = match (refresh_payload, req.cookie(REFRESH_TOKEN_COOKIE)) {
+        (Some(payload), _) => (payload.into_inner().refresh_token, TokenTransport::Body),
+        (None, Some(cookie)) => (cookie.value().to_string(), TokenTransport::Cookie),
+        (None, None) => return HttpResponse::BadRequest().body("Missing refresh token."),
+    };
+    let outcome = match rotate_refresh_token(
         &state.db,
-        register_payload.username.clone(),
-        register_payload.password.clone(),
+        &presented,
+        state.config.jwt.refresh_token_ttl_secs,
     )
     .await
     {
-        Ok(created_user) => match encode_token(&state.config.jwt_secret, created_user.id) {
-            Ok(token) => HttpResponse::Ok().json(json!({
-                "token": token,
-                "user": {
-                    "id": created_user.id,
-                    "username": created_user.username,
+        Ok(outcome) => outcome,
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on refreshing token: {}", e));
+        }
+    };
+
+    match outcome {
+        RefreshOutcome::Rotated {
+            user_id,
+            family_id,
+            refresh_token,
+        } => {
+            // The active organization may have been switched since the last token.
+            let user = match find_user_by_id(&state.db, Tenant::Global, user_id).await {
+                Ok(Some(user)) => user,
+                Ok(None) => return HttpResponse::Unauthorized().body("Invalid refresh token."),
+                Err(e) => {
+                    return HttpResponse::InternalServerError()
+                        .body(format!("DB error on fetching user: {}", e));
                 }
-            })),
-            Err(e) => {
-                HttpResponse::InternalServerError().body(format!("JWT encoding failed: {}", e))
+            };
+
+            if let Err(e) = touch_session(&state.db, &family_id).await {
+                return HttpResponse::InternalServerError()
+                    .body(format!("DB error on updating session: {}", e));
+            }
+
+            match mint_access_token(
+                &state,
+                user_id,
+                user.active_organization_id,
+                Some(&family_id),
+            )
+            .await
+            {
+                Ok(token) => token_pair_response(
+                    &state,
+                    TokenPair {
+                        token,
+                        refresh_token,
+                    },
+                    transport,
+                    json!({}),
+                ),
+                Err(response) => response,
+            }
+        }
+        RefreshOutcome::Invalid => HttpResponse::Unauthorized().body("Invalid refresh token."),
+        RefreshOutcome::Expired => HttpResponse::Unauthorized().body("Refresh token has expired."),
+        RefreshOutcome::Reused { user_id, family_id } => {
+            // Likely stolen: whoever holds the session's access tokens loses them as well.
+            let ended = async {
+                mark_session_revoked(&state.db, &family_id).await?;
+                revoke_session_tokens(
+                    &state.db,
+                    user_id,
+                    &family_id,
+                    state.config.jwt.access_token_ttl_secs,
+                )
+                .await
+            };
+            match ended.await {
+                Ok(()) => HttpResponse::Unauthorized()
+                    .body("Refresh token was already used; please log in again."),
+                Err(e) => HttpResponse::InternalServerError()
+                    .body(format!("DB error on ending session: {}", e)),
             }
-        },
-        Err(e) => {
-            HttpResponse::InternalServerError().body(format!("DB error on insert user: {}", e))
         }
     }
 }
 
+/// Revokes the access token and ends its session, so the session's refresh tokens stop
+/// working too; a cookie-mode session also has its cookies cleared.
 #[post("/auth/logout")]
-pub async fn logout(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
-    let token = match state::bearer_token(&req) {
-        Ok(token) => token,
-        Err(err) => return err.error_response(),
-    };
+pub async fn logout(
+    req: HttpRequest,
+    user: AuthenticatedUser,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    match state.revoke_token(&user.claims).await {
+        Ok(true) => {
+            if let Some(session_id) = &user.claims.sid
+                && let Err(e) = end_session(
+                    &state.db,
+                    user.user_id,
+                    session_id,
+                    state.config.jwt.access_token_ttl_secs,
+                )
+                .await
+            {
+                return HttpResponse::InternalServerError()
+                    .body(format!("DB error on ending session: {}", e));
+            }
 
-    match state.revoke_token(&token) {
-        Ok(true) => HttpResponse::Ok().body("Logged out successfully."),
+            let mut response = HttpResponse::Ok();
+            if req.cookie(ACCESS_TOKEN_COOKIE).is_some() {
+                for cookie in cleared_session_cookies(&state.config.auth_cookie) {
+                    response.cookie(cookie);
+                }
+            }
+            response.body("Logged out successfully.")
+        }
         Ok(false) => HttpResponse::BadRequest().body("Token already revoked"),
         Err(err) => err.error_response(),
     }
@@ -97,25 +573,32 @@ pub async fn logout(req: HttpRequest, state: web::Data<AppState>) -> HttpRespons
 
 #[cfg(test)]
 mod tests {
-    use actix_web::{App, http::StatusCode, test, web};
+    use actix_web::{App, cookie::Cookie, http::StatusCode, test, web};
+    use chrono::{Duration, Utc};
     use sea_orm::{DatabaseBackend, MockDatabase, MockExecResult};
     use serde_json::Value;
 
     use crate::{
-        config::AppConfig,
-        models::user::Model as UserModel,
-        state::AppState,
-        utils::encode_token,
+        models::{
+            login_throttle::Model as LoginThrottleModel, membership::Model as MembershipModel,
+            refresh_token::Model as RefreshTokenModel, revoked_token::Model as RevokedTokenModel,
+            role::Model as RoleModel, role_permission::Model as RolePermissionModel,
+            totp_credential::Model as TotpCredentialModel, user::Model as UserModel,
+            user_role::Model as UserRoleModel,
+        },
+        state::{
+            AppState,
+            tests::{exec, test_config, test_state},
+        },
+        utils::{
+            UserGrants, auth_cookie::CSRF_COOKIE, decode_token, hash_opaque_token, hash_password,
+        },
     };
 
     use super::*;
 
-    fn test_config() -> AppConfig {
-        AppConfig {
-            database_url: "postgres://localhost:5432/postgres".to_string(),
-            bind_address: "127.0.0.1:8080".to_string(),
-            jwt_secret: "test-secret".to_string(),
-        }
+    fn hashed(password: &str) -> String {
+        hash_password(&test_config().password_hashing, password).expect("password should hash")
     }
 
     fn mock_state(
@@ -127,7 +610,14 @@ mod tests {
             .append_exec_results(exec_results)
             .into_connection();
 
-        web::Data::new(AppState::new(db, test_config()))
+        web::Data::new(test_state(db))
+    }
+
+    /// A login's first queries: the throttle check (nothing recorded) and the user lookup.
+    fn login_db(user: Option<UserModel>) -> MockDatabase {
+        MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<LoginThrottleModel>::new()])
+            .append_query_results([user.into_iter().collect::<Vec<_>>()])
     }
 
     #[actix_web::test]
@@ -135,14 +625,25 @@ mod tests {
         let user = UserModel {
             id: 1,
             username: "alice".into(),
-            password: "secret".into(),
+            username_canonical: "alice".into(),
+            password: hashed("secret"),
+            active_organization_id: None,
+            email: None,
+            email_verified_at: None,
         };
-        let state = mock_state(vec![vec![user]], vec![]);
+        // The throttle is cleared, the refresh token and session stored; the user has no roles.
+        let state = web::Data::new(test_state(
+            login_db(Some(user))
+                .append_query_results([Vec::<TotpCredentialModel>::new()])
+                .append_query_results([Vec::<UserRoleModel>::new()])
+                .append_exec_results([exec(1), exec(1), exec(1)])
+                .into_connection(),
+        ));
 
         let app = test::init_service(App::new().app_data(state.clone()).service(login)).await;
         let req = test::TestRequest::post()
             .uri("/auth/login")
-            .set_json(&serde_json::json!({"username": "alice", "password": "secret"}))
+            .set_json(serde_json::json!({"username": "alice", "password": "secret"}))
             .to_request();
 
         let resp = test::call_service(&app, req).await;
@@ -150,63 +651,266 @@ mod tests {
 
         let body: Value = test::read_body_json(resp).await;
         assert!(body.get("token").and_then(Value::as_str).is_some());
+        assert!(body.get("refresh_token").and_then(Value::as_str).is_some());
     }
 
     #[actix_web::test]
-    async fn login_rejects_wrong_password() {
+    async fn login_can_hand_tokens_out_in_cookies() {
+        let user = UserModel {
+            id: 1,
+            username: "alice".into(),
+            username_canonical: "alice".into(),
+            password: hashed("secret"),
+            active_organization_id: None,
+            email: None,
+            email_verified_at: None,
+        };
+        let state = web::Data::new(test_state(
+            login_db(Some(user))
+                .append_query_results([Vec::<TotpCredentialModel>::new()])
+                .append_query_results([Vec::<UserRoleModel>::new()])
+                .append_exec_results([exec(1), exec(1), exec(1)])
+                .into_connection(),
+        ));
+
+        let app = test::init_service(App::new().app_data(state.clone()).service(login)).await;
+        let req = test::TestRequest::post()
+            .uri("/auth/login")
+            .set_json(serde_json::json!({
+                "username": "alice",
+                "password": "secret",
+                "transport": "cookie",
+            }))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let cookies: Vec<_> = resp.response().cookies().collect();
+        let access = cookies
+            .iter()
+            .find(|cookie| cookie.name() == ACCESS_TOKEN_COOKIE)
+            .expect("access token cookie");
+        assert_eq!(access.http_only(), Some(true));
+        assert_eq!(access.secure(), Some(true));
+        assert!(
+            cookies
+                .iter()
+                .any(|cookie| cookie.name() == REFRESH_TOKEN_COOKIE)
+        );
+        let csrf = cookies
+            .iter()
+            .find(|cookie| cookie.name() == CSRF_COOKIE)
+            .expect("CSRF cookie")
+            .value()
+            .to_string();
+
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body, serde_json::json!({ "csrf_token": csrf }));
+    }
+
+    #[actix_web::test]
+    async fn login_upgrades_legacy_plaintext_password() {
         let user = UserModel {
             id: 1,
             username: "alice".into(),
+            username_canonical: "alice".into(),
             password: "secret".into(),
+            active_organization_id: None,
+            email: None,
+            email_verified_at: None,
         };
-        let state = mock_state(vec![vec![user]], vec![]);
+        // Throttle cleared, hash upgraded, refresh token and session stored.
+        let state = web::Data::new(test_state(
+            login_db(Some(user))
+                .append_query_results([Vec::<TotpCredentialModel>::new()])
+                .append_query_results([Vec::<UserRoleModel>::new()])
+                .append_exec_results([exec(1), exec(1), exec(1), exec(1)])
+                .into_connection(),
+        ));
 
         let app = test::init_service(App::new().app_data(state.clone()).service(login)).await;
         let req = test::TestRequest::post()
             .uri("/auth/login")
-            .set_json(&serde_json::json!({"username": "alice", "password": "wrong"}))
+            .set_json(serde_json::json!({"username": "alice", "password": "secret"}))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn login_accepts_legacy_bcrypt_hash() {
+        let user = UserModel {
+            id: 1,
+            username: "alice".into(),
+            username_canonical: "alice".into(),
+            password: bcrypt::hash("secret", 4).expect("bcrypt should hash"),
+            active_organization_id: None,
+            email: None,
+            email_verified_at: None,
+        };
+        // Throttle cleared, hash upgraded, refresh token and session stored.
+        let state = web::Data::new(test_state(
+            login_db(Some(user))
+                .append_query_results([Vec::<TotpCredentialModel>::new()])
+                .append_query_results([Vec::<UserRoleModel>::new()])
+                .append_exec_results([exec(1), exec(1), exec(1), exec(1)])
+                .into_connection(),
+        ));
+
+        let app = test::init_service(App::new().app_data(state.clone()).service(login)).await;
+        let req = test::TestRequest::post()
+            .uri("/auth/login")
+            .set_json(serde_json::json!({"username": "alice", "password": "secret"}))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn login_rejects_wrong_password() {
+        let user = UserModel {
+            id: 1,
+            username: "alice".into(),
+            username_canonical: "alice".into(),
+            password: hashed("secret"),
+            active_organization_id: None,
+            email: None,
+            email_verified_at: None,
+        };
+        let state = web::Data::new(test_state(
+            login_db(Some(user))
+                .append_query_results([
+                    vec![throttle_entry(1, None)],
+                    vec![throttle_entry(1, None)],
+                ])
+                .into_connection(),
+        ));
+
+        let app = test::init_service(App::new().app_data(state.clone()).service(login)).await;
+        let req = test::TestRequest::post()
+            .uri("/auth/login")
+            .peer_addr("10.0.0.1:4711".parse().unwrap())
+            .set_json(serde_json::json!({"username": "alice", "password": "wrong"}))
             .to_request();
 
         let resp = test::call_service(&app, req).await;
         assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(test::read_body(resp).await, "Invalid username or password.");
+
+        // The failure counts against both the username and the client IP.
+        let log = state.db.clone().into_transaction_log();
+        let keys: Vec<_> = log
+            .iter()
+            .flat_map(|transaction| transaction.statements())
+            .filter(|statement| statement.sql.starts_with("INSERT INTO login_throttles"))
+            .map(|statement| statement.values.as_ref().unwrap().0[0].clone())
+            .collect();
+        assert_eq!(keys, ["user:alice".into(), "ip:10.0.0.1".into()]);
+    }
+
+    fn throttle_entry(
+        failures: i32,
+        locked_until: Option<chrono::DateTime<Utc>>,
+    ) -> LoginThrottleModel {
+        LoginThrottleModel {
+            key: "user:alice".into(),
+            failures,
+            last_failure_at: Utc::now(),
+            locked_until,
+        }
+    }
+
+    #[actix_web::test]
+    async fn login_refuses_locked_usernames_before_checking_the_password() {
+        let state = web::Data::new(test_state(
+            MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([vec![throttle_entry(
+                    5,
+                    Some(Utc::now() + Duration::minutes(10)),
+                )]])
+                .into_connection(),
+        ));
+
+        let app = test::init_service(App::new().app_data(state.clone()).service(login)).await;
+        let req = test::TestRequest::post()
+            .uri("/auth/login")
+            .set_json(serde_json::json!({"username": "Alice", "password": "secret"}))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers().get(header::RETRY_AFTER).unwrap(), "600");
     }
 
     #[actix_web::test]
     async fn login_rejects_unknown_user() {
-        let state = mock_state(vec![vec![]], vec![]);
+        let state = web::Data::new(test_state(
+            login_db(None)
+                .append_query_results([vec![throttle_entry(1, None)]])
+                .into_connection(),
+        ));
 
         let app = test::init_service(App::new().app_data(state.clone()).service(login)).await;
         let req = test::TestRequest::post()
             .uri("/auth/login")
-            .set_json(&serde_json::json!({"username": "ghost", "password": "whatever"}))
+            .set_json(serde_json::json!({"username": "ghost", "password": "whatever"}))
             .to_request();
 
+        // Indistinguishable from a wrong password for an existing user.
         let resp = test::call_service(&app, req).await;
         assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(test::read_body(resp).await, "Invalid username or password.");
     }
 
-    #[actix_web::test]
-    async fn register_creates_user_when_username_free() {
+    /// Registration of the free username `newuser`, who gets the default `user` role.
+    fn register_db() -> MockDatabase {
         let created = UserModel {
             id: 10,
             username: "newuser".into(),
+            username_canonical: "newuser".into(),
             password: "pw".into(),
+            active_organization_id: None,
+            email: None,
+            email_verified_at: None,
         };
-        let state = mock_state(
-            vec![
-                vec![],          // check for existing username
+        let user_role = RoleModel {
+            id: 2,
+            name: "user".into(),
+            description: None,
+        };
+        MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([
+                vec![],                // check for existing username
                 vec![created.clone()], // insert returning created row
-            ],
-            vec![MockExecResult {
-                last_insert_id: 10,
-                rows_affected: 1,
-            }],
-        );
+            ])
+            .append_query_results([vec![user_role.clone()]]) // default role lookup
+            .append_query_results([vec![UserRoleModel {
+                user_id: 10,
+                role_id: 2,
+            }]])
+            .append_query_results([vec![user_role]])
+            .append_query_results([Vec::<RolePermissionModel>::new()])
+            .append_exec_results([
+                exec(1),
+                MockExecResult {
+                    last_insert_id: 10,
+                    rows_affected: 1,
+                },
+                exec(1),
+            ])
+    }
+
+    #[actix_web::test]
+    async fn register_creates_user_when_username_free() {
+        let state = web::Data::new(test_state(register_db().into_connection()));
 
         let app = test::init_service(App::new().app_data(state.clone()).service(register)).await;
         let req = test::TestRequest::post()
             .uri("/auth/register")
-            .set_json(&serde_json::json!({"username": "newuser", "password": "pw"}))
+            .set_json(serde_json::json!({"username": "newuser", "password": "correct horse"}))
             .to_request();
 
         let resp = test::call_service(&app, req).await;
@@ -215,6 +919,39 @@ mod tests {
         let body: Value = test::read_body_json(resp).await;
         assert_eq!(body["user"]["id"], 10);
         assert_eq!(body["user"]["username"], "newuser");
+
+        let token = body["token"].as_str().expect("access token in body");
+        let claims =
+            decode_token(&state.config.jwt, &state.key_ring.active().unwrap(), token).unwrap();
+        assert_eq!(claims.roles, ["user"]);
+    }
+
+    #[actix_web::test]
+    async fn register_can_hand_tokens_out_in_cookies() {
+        let state = web::Data::new(test_state(register_db().into_connection()));
+
+        let app = test::init_service(App::new().app_data(state.clone()).service(register)).await;
+        let req = test::TestRequest::post()
+            .uri("/auth/register")
+            .set_json(serde_json::json!({
+                "username": "newuser",
+                "password": "correct horse",
+                "transport": "cookie",
+            }))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(
+            resp.response()
+                .cookies()
+                .any(|cookie| cookie.name() == ACCESS_TOKEN_COOKIE)
+        );
+
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["user"]["id"], 10);
+        assert!(body.get("token").is_none());
+        assert!(body["csrf_token"].as_str().is_some());
     }
 
     #[actix_web::test]
@@ -222,14 +959,18 @@ mod tests {
         let existing = UserModel {
             id: 1,
             username: "taken".into(),
+            username_canonical: "taken".into(),
             password: "pw".into(),
+            active_organization_id: None,
+            email: None,
+            email_verified_at: None,
         };
         let state = mock_state(vec![vec![existing]], vec![]);
 
         let app = test::init_service(App::new().app_data(state.clone()).service(register)).await;
         let req = test::TestRequest::post()
             .uri("/auth/register")
-            .set_json(&serde_json::json!({"username": "taken", "password": "pw"}))
+            .set_json(serde_json::json!({"username": " TAKEN ", "password": "correct horse"}))
             .to_request();
 
         let resp = test::call_service(&app, req).await;
@@ -237,10 +978,176 @@ mod tests {
     }
 
     #[actix_web::test]
-    async fn logout_revokes_token() {
+    async fn register_rejects_reserved_usernames() {
         let state = mock_state(vec![], vec![]);
-        let token =
-            encode_token(&state.config.jwt_secret, 3).expect("should encode test token successfully");
+
+        let app = test::init_service(App::new().app_data(state.clone()).service(register)).await;
+        let req = test::TestRequest::post()
+            .uri("/auth/register")
+            .set_json(serde_json::json!({"username": "Admin", "password": "correct horse"}))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(test::read_body(resp).await, "Username is reserved.");
+    }
+
+    #[actix_web::test]
+    async fn login_looks_up_the_canonical_username() {
+        let state = web::Data::new(test_state(
+            login_db(None)
+                .append_query_results([vec![throttle_entry(1, None)]])
+                .into_connection(),
+        ));
+
+        let app = test::init_service(App::new().app_data(state.clone()).service(login)).await;
+        let req = test::TestRequest::post()
+            .uri("/auth/login")
+            .set_json(serde_json::json!({"username": " ＡＬＩＣＥ ", "password": "secret"}))
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let log = state.db.clone().into_transaction_log();
+        let statement = &log[1].statements()[0];
+        assert!(
+            statement
+                .sql
+                .contains(r#""users"."username_canonical" = $1"#)
+        );
+        assert_eq!(statement.values.as_ref().unwrap().0[0], "alice".into());
+    }
+
+    fn stored_refresh_token(token: &str, used: bool) -> RefreshTokenModel {
+        RefreshTokenModel {
+            id: 1,
+            user_id: 4,
+            family_id: "family".into(),
+            token_hash: hash_opaque_token(token),
+            expires_at: Utc::now() + Duration::days(1),
+            created_at: Utc::now(),
+            used_at: used.then(Utc::now),
+            revoked_at: None,
+        }
+    }
+
+    #[actix_web::test]
+    async fn refresh_rotates_token_pair() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![stored_refresh_token("rt-1", false)]])
+            .append_query_results([vec![UserModel {
+                id: 4,
+                username: "alice".into(),
+                username_canonical: "alice".into(),
+                password: String::new(),
+                // No longer a member there, so the new token carries no organization.
+                active_organization_id: Some(6),
+                email: None,
+                email_verified_at: None,
+            }]])
+            .append_query_results([Vec::<UserRoleModel>::new()])
+            .append_query_results([Vec::<MembershipModel>::new()])
+            .append_exec_results([exec(1), exec(1), exec(1)])
+            .into_connection();
+        let state = web::Data::new(test_state(db));
+
+        let app = test::init_service(App::new().app_data(state.clone()).service(refresh)).await;
+        let req = test::TestRequest::post()
+            .uri("/auth/refresh")
+            .set_json(serde_json::json!({"refresh_token": "rt-1"}))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let body: Value = test::read_body_json(resp).await;
+        let token = body["token"].as_str().expect("access token in body");
+        let claims = decode_token(&state.config.jwt, &state.key_ring.active().unwrap(), token)
+            .expect("access token should decode");
+        assert_eq!(claims.sub, 4);
+        assert_eq!(claims.org_id, None);
+        assert_ne!(body["refresh_token"], "rt-1");
+    }
+
+    #[actix_web::test]
+    async fn refresh_reads_and_renews_the_cookies() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![stored_refresh_token("rt-1", false)]])
+            .append_query_results([vec![UserModel {
+                id: 4,
+                username: "alice".into(),
+                username_canonical: "alice".into(),
+                password: String::new(),
+                active_organization_id: None,
+                email: None,
+                email_verified_at: None,
+            }]])
+            .append_query_results([Vec::<UserRoleModel>::new()])
+            .append_exec_results([exec(1), exec(1), exec(1)])
+            .into_connection();
+        let state = web::Data::new(test_state(db));
+
+        let app = test::init_service(App::new().app_data(state.clone()).service(refresh)).await;
+        let req = test::TestRequest::post()
+            .uri("/auth/refresh")
+            .cookie(Cookie::new(REFRESH_TOKEN_COOKIE, "rt-1"))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let renewed: Vec<_> = resp
+            .response()
+            .cookies()
+            .map(|cookie| cookie.name().to_string())
+            .collect();
+        assert_eq!(
+            renewed,
+            [ACCESS_TOKEN_COOKIE, REFRESH_TOKEN_COOKIE, CSRF_COOKIE]
+        );
+        let body: Value = test::read_body_json(resp).await;
+        assert!(body.get("token").is_none());
+    }
+
+    #[actix_web::test]
+    async fn refresh_rejects_replayed_token() {
+        // The family is revoked, then the session and the access tokens minted for it.
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![stored_refresh_token("rt-1", true)]])
+            .append_exec_results([exec(1), exec(1), exec(1)])
+            .into_connection();
+        let state = web::Data::new(test_state(db));
+
+        let app = test::init_service(App::new().app_data(state.clone()).service(refresh)).await;
+        let req = test::TestRequest::post()
+            .uri("/auth/refresh")
+            .set_json(serde_json::json!({"refresh_token": "rt-1"}))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let log = state.db.clone().into_transaction_log();
+        let statements: Vec<_> = log
+            .iter()
+            .flat_map(|transaction| transaction.statements())
+            .collect();
+        assert!(statements.iter().any(|statement| {
+            statement
+                .sql
+                .starts_with(r#"UPDATE "sessions" SET "revoked_at""#)
+        }));
+        assert!(statements.iter().any(|statement| {
+            statement.sql.starts_with(r#"INSERT INTO "revoked_tokens""#)
+                && statement.values.as_ref().unwrap().0[0] == "session:family".into()
+        }));
+    }
+
+    #[actix_web::test]
+    async fn logout_revokes_token() {
+        let state = mock_state(vec![vec![]], vec![exec(1)]);
+        let token = state
+            .issue_access_token(3, &UserGrants::default())
+            .expect("should encode test token successfully");
 
         let app = test::init_service(App::new().app_data(state.clone()).service(logout)).await;
         let req = test::TestRequest::post()
@@ -252,6 +1159,80 @@ mod tests {
         assert_eq!(resp.status(), StatusCode::OK);
     }
 
+    #[actix_web::test]
+    async fn refresh_fails_after_logout() {
+        // Logout revokes the token, the session, its refresh family and its access tokens;
+        // the refresh token then comes back revoked, which ends the session again.
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<RevokedTokenModel>::new()])
+            .append_exec_results([exec(1), exec(1), exec(1), exec(1)])
+            .append_query_results([vec![RefreshTokenModel {
+                revoked_at: Some(Utc::now()),
+                ..stored_refresh_token("rt-1", false)
+            }]])
+            .append_exec_results([exec(1), exec(1), exec(1)])
+            .into_connection();
+        let state = web::Data::new(test_state(db));
+        let token = state
+            .issue_access_token(4, &UserGrants::default().in_session(Some("family")))
+            .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .service(logout)
+                .service(refresh),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/auth/logout")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+        let req = test::TestRequest::post()
+            .uri("/auth/refresh")
+            .set_json(serde_json::json!({"refresh_token": "rt-1"}))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        let log = state.db.clone().into_transaction_log();
+        let statements: Vec<_> = log
+            .iter()
+            .flat_map(|transaction| transaction.statements())
+            .map(|statement| statement.sql.clone())
+            .collect();
+        assert!(
+            statements
+                .iter()
+                .any(|sql| sql.starts_with(r#"UPDATE "sessions" SET "revoked_at""#))
+        );
+        assert!(
+            statements
+                .iter()
+                .any(|sql| sql.starts_with(r#"UPDATE "refresh_tokens" SET "revoked_at""#))
+        );
+    }
+
+    #[actix_web::test]
+    async fn logout_rejects_already_revoked_token() {
+        // Revoked concurrently between validation and the revocation insert.
+        let state = mock_state(vec![vec![]], vec![exec(0)]);
+        let token = state.issue_access_token(3, &UserGrants::default()).unwrap();
+
+        let app = test::init_service(App::new().app_data(state.clone()).service(logout)).await;
+        let req = test::TestRequest::post()
+            .uri("/auth/logout")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
     #[actix_web::test]
     async fn logout_requires_authorization_header() {
         let state = mock_state(vec![], vec![]);
@@ -260,6 +1241,6 @@ mod tests {
         let req = test::TestRequest::post().uri("/auth/logout").to_request();
 
         let resp = test::call_service(&app, req).await;
-        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
     }
 }
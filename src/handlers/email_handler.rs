@@ -0,0 +1,389 @@
+use actix_web::{HttpResponse, post, put, web};
+use serde::Deserialize;
+
+use crate::mail::{Email, deliver};
+use crate::middleware::AuthenticatedUser;
+use crate::services::email_verification_service::{
+    VerificationOutcome, issue_email_verification, verify_email as verify,
+};
+use crate::services::user_service::{
+    Tenant, find_user_by_email, release_unverified_email, set_email,
+};
+use crate::state::AppState;
+use crate::utils::normalize_email;
+
+#[derive(Deserialize)]
+pub struct VerifyEmailRequest {
+    token: String,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateEmailRequest {
+    email: String,
+}
+
+/// Normalizes an optional email address from a sign-up payload, insisting on one when
+/// `EMAIL_VERIFICATION_REQUIRED` is set. Errors are meant for a `400` body.
+pub(crate) fn parse_email(
+    state: &AppState,
+    raw: Option<&str>,
+) -> Result<Option<String>, &'static str> {
+    match raw.map(normalize_email) {
+        Some(Some(email)) => Ok(Some(email)),
+        Some(None) => Err("Invalid email address."),
+        None if state.config.email_verification.required => Err("Email address is required."),
+        None => Ok(None),
+    }
+}
+
+/// Issues a verification token for `email` and mails the link to it.
+///
+/// Returns whether the mail went out; delivery failures are logged, not fatal, since a new
+/// link can be requested from `PUT /me/email`.
+pub(crate) async fn send_verification(
+    state: &AppState,
+    user_id: i32,
+    email: &str,
+) -> Result<bool, HttpResponse> {
+    let config = &state.config.email_verification;
+    let token = issue_email_verification(&state.db, user_id, email.to_string(), config.ttl_secs)
+        .await
+        .map_err(|e| {
+            HttpResponse::InternalServerError()
+                .body(format!("DB error on issuing verification token: {}", e))
+        })?;
+
+    let message = Email {
+        to: email.to_string(),
+        subject: "Verify your email address".to_string(),
+        body: format!(
+            "Confirm this address by opening the link below within {} hours:\n\n{}?token={}\n\n\
+             If you did not sign up, you can ignore this message.",
+            config.ttl_secs / 3600,
+            config.verify_url,
+            token
+        ),
+    };
+
+    match deliver(&state.mailer, message).await {
+        Ok(()) => Ok(true),
+        Err(e) => {
+            log::warn!(
+                "sending verification email to user {} failed: {}",
+                user_id,
+                e
+            );
+            Ok(false)
+        }
+    }
+}
+
+/// Confirms an email address with the token from a verification link.
+#[post("/auth/verify-email")]
+pub async fn verify_email(
+    state: web::Data<AppState>,
+    payload: web::Json<VerifyEmailRequest>,
+) -> HttpResponse {
+    match verify(&state.db, &payload.token).await {
+        Ok(VerificationOutcome::Verified { .. }) => {
+            HttpResponse::Ok().body("Email address verified.")
+        }
+        Ok(VerificationOutcome::Invalid) => {
+            HttpResponse::NotFound().body("Invalid verification token.")
+        }
+        Ok(VerificationOutcome::Expired) => {
+            HttpResponse::Gone().body("Verification link has expired.")
+        }
+        Ok(VerificationOutcome::Used) => {
+            HttpResponse::Gone().body("Verification link was already used.")
+        }
+        Ok(VerificationOutcome::Superseded) => {
+            HttpResponse::Gone().body("Email address has changed since this link was sent.")
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("DB error on verifying email: {}", e))
+        }
+    }
+}
+
+/// Sets the caller's email address and sends a verification link to it. Mounted inside the
+/// `/me` scope; sending the current address again just issues a fresh link. An address
+/// another account holds but never verified is taken over from it.
+#[put("/email")]
+pub async fn update_email(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    payload: web::Json<UpdateEmailRequest>,
+) -> HttpResponse {
+    let Some(email) = normalize_email(&payload.email) else {
+        return HttpResponse::BadRequest().body("Invalid email address.");
+    };
+
+    match find_user_by_email(&state.db, Tenant::Global, &email).await {
+        Ok(Some(owner)) if owner.id != user.user_id => {
+            if owner.email_verified_at.is_some() {
+                return HttpResponse::BadRequest().body("Email address is already in use.");
+            }
+            if let Err(e) = release_unverified_email(&state.db, &email).await {
+                return HttpResponse::InternalServerError()
+                    .body(format!("DB error on releasing email: {}", e));
+            }
+        }
+        Ok(Some(owner)) if owner.email_verified_at.is_some() => {
+            return HttpResponse::Ok().body("Email address is already verified.");
+        }
+        Ok(_) => {}
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on checking email: {}", e));
+        }
+    }
+
+    if let Err(e) = set_email(&state.db, user.user_id, email.clone()).await {
+        return HttpResponse::InternalServerError()
+            .body(format!("DB error on updating email: {}", e));
+    }
+
+    match send_verification(&state, user.user_id, &email).await {
+        Ok(true) => HttpResponse::Accepted().body("Verification email sent."),
+        Ok(false) => HttpResponse::BadGateway().body("Could not send the verification email."),
+        Err(response) => response,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{App, http::StatusCode, test};
+    use chrono::{Duration, Utc};
+    use sea_orm::{DatabaseBackend, DatabaseConnection, MockDatabase};
+    use serde_json::json;
+
+    use crate::mail::tests::Outbox;
+    use crate::models::email_verification::Model as EmailVerificationModel;
+    use crate::models::login_throttle::Model as LoginThrottleModel;
+    use crate::models::role::Model as RoleModel;
+    use crate::models::role_permission::Model as RolePermissionModel;
+    use crate::models::totp_credential::Model as TotpCredentialModel;
+    use crate::models::user::Model as UserModel;
+    use crate::models::user_role::Model as UserRoleModel;
+    use crate::routes;
+    use crate::state::tests::{exec, test_config, test_state};
+    use crate::utils::{hash_opaque_token, hash_password};
+
+    use super::*;
+
+    fn user(email_verified: bool) -> UserModel {
+        UserModel {
+            id: 10,
+            username: "bob".into(),
+            username_canonical: "bob".into(),
+            password: hash_password(&test_config().password_hashing, "pw").unwrap(),
+            active_organization_id: None,
+            email: Some("bob@example.com".into()),
+            email_verified_at: email_verified.then(Utc::now),
+        }
+    }
+
+    fn required_state(db: DatabaseConnection) -> web::Data<AppState> {
+        let mut state = test_state(db);
+        state.config.email_verification.required = true;
+        web::Data::new(state)
+    }
+
+    #[actix_web::test]
+    async fn register_mails_a_verification_link() {
+        let role = RoleModel {
+            id: 2,
+            name: "user".into(),
+            description: None,
+        };
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![], vec![], vec![user(false)]])
+            .append_query_results([vec![role.clone()]])
+            .append_query_results([vec![UserRoleModel {
+                user_id: 10,
+                role_id: 2,
+            }]])
+            .append_query_results([vec![role]])
+            .append_query_results([Vec::<RolePermissionModel>::new()])
+            .append_exec_results([exec(1), exec(1), exec(1), exec(1)])
+            .into_connection();
+        let outbox = Arc::new(Outbox::default());
+        let state = web::Data::new(AppState {
+            mailer: outbox.clone(),
+            ..test_state(db)
+        });
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/auth/register")
+            .set_json(json!({"username": "bob", "password": "correct horse", "email": " Bob@Example.com"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let sent = outbox.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to, "bob@example.com");
+        assert!(sent[0].body.contains(&format!(
+            "{}?token=",
+            state.config.email_verification.verify_url
+        )));
+    }
+
+    /// Registers bob with bob@example.com, which `holder` already uses.
+    async fn register_over(holder: UserModel) -> (StatusCode, Vec<String>) {
+        let role = RoleModel {
+            id: 2,
+            name: "user".into(),
+            description: None,
+        };
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![], vec![holder], vec![user(false)]])
+            .append_query_results([vec![role.clone()]])
+            .append_query_results([vec![UserRoleModel {
+                user_id: 10,
+                role_id: 2,
+            }]])
+            .append_query_results([vec![role]])
+            .append_query_results([Vec::<RolePermissionModel>::new()])
+            .append_exec_results([exec(1), exec(1), exec(1), exec(1), exec(1)])
+            .into_connection();
+        let state = web::Data::new(test_state(db));
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/auth/register")
+            .set_json(
+                json!({"username": "bob", "password": "correct horse", "email": "bob@example.com"}),
+            )
+            .to_request();
+        let status = test::call_service(&app, req).await.status();
+        let statements = state
+            .db
+            .clone()
+            .into_transaction_log()
+            .iter()
+            .flat_map(|transaction| transaction.statements())
+            .map(|statement| statement.sql.clone())
+            .collect();
+        (status, statements)
+    }
+
+    fn squatter(email_verified: bool) -> UserModel {
+        UserModel {
+            id: 3,
+            username: "mallory".into(),
+            username_canonical: "mallory".into(),
+            ..user(email_verified)
+        }
+    }
+
+    #[actix_web::test]
+    async fn register_takes_over_an_unverified_address() {
+        let (status, statements) = register_over(squatter(false)).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(statements.iter().any(|sql| {
+            sql.starts_with("UPDATE \"users\" SET \"email\"")
+                && sql.contains("\"email_verified_at\" IS NULL")
+        }));
+    }
+
+    #[actix_web::test]
+    async fn register_rejects_a_verified_address() {
+        let (status, statements) = register_over(squatter(true)).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(!statements.iter().any(|sql| sql.starts_with("UPDATE")));
+    }
+
+    #[actix_web::test]
+    async fn register_requires_an_email_when_verification_is_required() {
+        let state = required_state(MockDatabase::new(DatabaseBackend::Postgres).into_connection());
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/auth/register")
+            .set_json(json!({"username": "bob", "password": "correct horse"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn unverified_users_cannot_log_in_when_verification_is_required() {
+        let state = required_state(
+            MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<LoginThrottleModel>::new()])
+                .append_query_results([vec![user(false)]])
+                .append_query_results([Vec::<TotpCredentialModel>::new()])
+                .append_exec_results([exec(1)])
+                .into_connection(),
+        );
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/auth/login")
+            .set_json(json!({"username": "bob", "password": "pw"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn verify_email_spends_the_token() {
+        let state = web::Data::new(test_state(
+            MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([vec![EmailVerificationModel {
+                    id: 1,
+                    user_id: 10,
+                    email: "bob@example.com".into(),
+                    token_hash: hash_opaque_token("token"),
+                    expires_at: Utc::now() + Duration::hours(1),
+                    created_at: Utc::now(),
+                    used_at: None,
+                }]])
+                .append_exec_results([exec(1), exec(1)])
+                .into_connection(),
+        ));
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/auth/verify-email")
+            .set_json(json!({"token": "token"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+}
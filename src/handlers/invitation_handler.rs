@@ -0,0 +1,662 @@
+use actix_web::{HttpResponse, delete, post, web};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::handlers::auth_handler::{create_account, issue_token_pair, token_pair_response};
+use crate::handlers::email_handler::parse_email;
+use crate::handlers::organization_handler::org_permission_denied;
+use crate::middleware::{AuthenticatedUser, ClientInfo};
+use crate::services::invitation_service::{
+    InvitationLookup, RevokeOutcome, accept_invitation as accept, claim_invitation,
+    complete_invitation, create_invitation as invite, find_invitation, release_invitation,
+    revoke_invitation as revoke,
+};
+use crate::services::organization_service::{MEMBER_ROLE, ORG_ROLES};
+use crate::services::role_service::find_role_by_name;
+use crate::services::user_service::{Tenant, find_user_by_id, set_active_organization};
+use crate::state::AppState;
+use crate::utils::{TokenTransport, canonical_username, normalize_email};
+
+#[derive(Deserialize)]
+pub struct InviteRequest {
+    /// Username, or an email address for people without an account yet.
+    invitee: String,
+    role: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct AcceptInvitationRequest {
+    token: String,
+    /// Credentials for a new account, when accepting without being logged in.
+    username: Option<String>,
+    password: Option<String>,
+    /// Email address for the new account. Invitations sent by email always use the invited
+    /// address, which this may only repeat.
+    email: Option<String>,
+    /// As for `POST /auth/login`, for new accounts.
+    #[serde(default)]
+    transport: TokenTransport,
+}
+
+/// Invitees containing an `@` are treated as email addresses, anything else as a username.
+fn is_username(invitee: &str) -> bool {
+    !invitee.contains('@')
+}
+
+/// Invites someone into the caller's active organization. Requires `members:write` there.
+///
+/// The response carries the only copy of the invite link; the token is stored hashed.
+#[post("/{id}/invitations")]
+pub async fn create_invitation(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+    payload: web::Json<InviteRequest>,
+) -> HttpResponse {
+    let organization_id = path.into_inner();
+    if let Some(response) = org_permission_denied(&user, organization_id, "members:write") {
+        return response;
+    }
+
+    let invitee = payload.invitee.trim();
+    if invitee.is_empty() {
+        return HttpResponse::BadRequest().body("Invitee must not be empty.");
+    }
+    let role_name = payload.role.as_deref().unwrap_or(MEMBER_ROLE);
+    if !ORG_ROLES.contains(&role_name) {
+        return HttpResponse::BadRequest()
+            .body(format!("Unknown organization role: {}", role_name));
+    }
+
+    let role = match find_role_by_name(&state.db, role_name).await {
+        Ok(Some(role)) => role,
+        Ok(None) => {
+            return HttpResponse::BadRequest()
+                .body(format!("Unknown organization role: {}", role_name));
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on loading role: {}", e));
+        }
+    };
+
+    match invite(
+        &state.db,
+        organization_id,
+        user.user_id,
+        invitee.to_string(),
+        role.id,
+        state.config.invitations.ttl_secs,
+    )
+    .await
+    {
+        Ok((invitation, token)) => HttpResponse::Created().json(json!({
+            "id": invitation.id,
+            "invitee": invitation.invitee,
+            "role": role.name,
+            "expires_at": invitation.expires_at,
+            "token": token,
+            "link": format!("{}?token={}", state.config.invitations.accept_url, token),
+        })),
+        Err(e) => HttpResponse::InternalServerError()
+            .body(format!("DB error on creating invitation: {}", e)),
+    }
+}
+
+/// Revokes a pending invitation of the caller's active organization. Requires `members:write`.
+#[delete("/{id}/invitations/{invitation_id}")]
+pub async fn revoke_invitation(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<(i32, i32)>,
+) -> HttpResponse {
+    let (organization_id, invitation_id) = path.into_inner();
+    if let Some(response) = org_permission_denied(&user, organization_id, "members:write") {
+        return response;
+    }
+
+    match revoke(&state.db, organization_id, invitation_id, user.user_id).await {
+        Ok(RevokeOutcome::Revoked) => HttpResponse::Ok().body("Invitation revoked."),
+        Ok(RevokeOutcome::NotFound) => HttpResponse::NotFound().body("Invitation not found."),
+        Ok(RevokeOutcome::AlreadyAccepted) => {
+            HttpResponse::Conflict().body("Invitation was already accepted.")
+        }
+        Err(e) => HttpResponse::InternalServerError()
+            .body(format!("DB error on revoking invitation: {}", e)),
+    }
+}
+
+/// Accepts an invitation.
+///
+/// Logged-in callers join the organization with their account. Everyone else signs up through
+/// the `register` path with the supplied credentials and gets a token pair scoped to the
+/// organization. Invitations addressed to a username can only be accepted as that user, and
+/// those sent by email only by an account that verified the address or a new one created
+/// with it.
+#[post("/invitations/accept")]
+pub async fn accept_invitation(
+    client: ClientInfo,
+    state: web::Data<AppState>,
+    user: Option<AuthenticatedUser>,
+    payload: web::Json<AcceptInvitationRequest>,
+) -> HttpResponse {
+    let invitation = match find_invitation(&state.db, &payload.token).await {
+        Ok(InvitationLookup::Pending(invitation)) => invitation,
+        Ok(InvitationLookup::Invalid) => {
+            return HttpResponse::NotFound().body("Invalid invitation.");
+        }
+        Ok(InvitationLookup::Expired) => {
+            return HttpResponse::Gone().body("Invitation has expired.");
+        }
+        Ok(InvitationLookup::Used) => {
+            return HttpResponse::Gone().body("Invitation was already accepted.");
+        }
+        Ok(InvitationLookup::Revoked) => {
+            return HttpResponse::Gone().body("Invitation was revoked.");
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on loading invitation: {}", e));
+        }
+    };
+    let invited_username =
+        is_username(&invitation.invitee).then(|| canonical_username(&invitation.invitee));
+    let invited_email = (!is_username(&invitation.invitee))
+        .then(|| normalize_email(&invitation.invitee).unwrap_or_default());
+
+    let (account, new_account) = match user {
+        Some(user) => {
+            let account = match find_user_by_id(&state.db, Tenant::Global, user.user_id).await {
+                Ok(Some(account)) => account,
+                Ok(None) => return HttpResponse::Unauthorized().body("Unknown user."),
+                Err(e) => {
+                    return HttpResponse::InternalServerError()
+                        .body(format!("DB error on fetching user: {}", e));
+                }
+            };
+            if invited_username
+                .as_ref()
+                .is_some_and(|invitee| *invitee != account.username_canonical)
+                || invited_email
+                    .as_ref()
+                    .is_some_and(|invitee| account.email.as_ref() != Some(invitee))
+            {
+                return HttpResponse::Forbidden().body("Invitation is for another user.");
+            }
+            if invited_email.is_some() && account.email_verified_at.is_none() {
+                return HttpResponse::Forbidden()
+                    .body("Verify your email address before accepting this invitation.");
+            }
+            (account, false)
+        }
+        None => {
+            let (Some(username), Some(password)) = (&payload.username, &payload.password) else {
+                return HttpResponse::BadRequest()
+                    .body("Log in, or provide a username and password to sign up.");
+            };
+            if invited_username
+                .as_ref()
+                .is_some_and(|invitee| *invitee != canonical_username(username))
+            {
+                return HttpResponse::Forbidden().body("Invitation is for another user.");
+            }
+            // Invitations sent by email sign up with the invited address.
+            if let (Some(invitee), Some(requested)) = (&invited_email, &payload.email)
+                && normalize_email(requested).as_ref() != Some(invitee)
+            {
+                return HttpResponse::Forbidden().body("Invitation is for another user.");
+            }
+            let email = match parse_email(
+                &state,
+                invited_email.as_deref().or(payload.email.as_deref()),
+            ) {
+                Ok(email) => email,
+                Err(reason) => return HttpResponse::BadRequest().body(reason),
+            };
+
+            // Claimed first so a second acceptance can't race us, and handed back if the
+            // account can't be created, so no account is left without its membership.
+            match claim_invitation(&state.db, &invitation, None).await {
+                Ok(true) => {}
+                Ok(false) => return HttpResponse::Gone().body("Invitation is no longer valid."),
+                Err(e) => {
+                    return HttpResponse::InternalServerError()
+                        .body(format!("DB error on claiming invitation: {}", e));
+                }
+            }
+            match create_account(&state, username, Some(password), email).await {
+                Ok(account) => (account, true),
+                Err(response) => {
+                    if let Err(e) = release_invitation(&state.db, &invitation).await {
+                        log::error!("failed to release invitation {}: {}", invitation.id, e);
+                    }
+                    return response;
+                }
+            }
+        }
+    };
+
+    let accepted = if new_account {
+        complete_invitation(&state.db, &invitation, account.id)
+            .await
+            .map(|()| true)
+    } else {
+        accept(&state.db, &invitation, account.id).await
+    };
+    match accepted {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Gone().body("Invitation is no longer valid."),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on accepting invitation: {}", e));
+        }
+    }
+
+    if !new_account {
+        return HttpResponse::Ok().json(json!({
+            "organization_id": invitation.organization_id,
+        }));
+    }
+
+    if let Err(e) =
+        set_active_organization(&state.db, account.id, Some(invitation.organization_id)).await
+    {
+        return HttpResponse::InternalServerError()
+            .body(format!("DB error on switching organization: {}", e));
+    }
+    let user = json!({
+        "id": account.id,
+        "username": account.username,
+        "email": account.email,
+    });
+
+    // Like `register`, tokens wait for the address to be confirmed.
+    if state.config.email_verification.required {
+        return HttpResponse::Accepted().json(json!({
+            "organization_id": invitation.organization_id,
+            "user": user,
+            "email_verification": "pending",
+        }));
+    }
+
+    match issue_token_pair(
+        &state,
+        &client,
+        account.id,
+        Some(invitation.organization_id),
+    )
+    .await
+    {
+        Ok(tokens) => token_pair_response(
+            &state,
+            tokens,
+            payload.transport,
+            json!({
+                "organization_id": invitation.organization_id,
+                "user": user,
+            }),
+        ),
+        Err(response) => response,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, http::StatusCode, test};
+    use chrono::{Duration, Utc};
+    use sea_orm::{DatabaseBackend, MockDatabase};
+    use serde_json::Value;
+
+    use crate::models::invitation::Model as InvitationModel;
+    use crate::models::revoked_token::Model as RevokedTokenModel;
+    use crate::models::role::Model as RoleModel;
+    use crate::models::user::Model as UserModel;
+    use crate::routes;
+    use crate::state::tests::{exec, test_state};
+    use crate::utils::{UserGrants, hash_opaque_token};
+
+    use super::*;
+
+    fn invitation(invitee: &str) -> InvitationModel {
+        InvitationModel {
+            id: 7,
+            organization_id: 4,
+            invitee: invitee.into(),
+            role_id: 5,
+            invited_by: Some(1),
+            token_hash: hash_opaque_token("invite-token"),
+            expires_at: Utc::now() + Duration::days(1),
+            created_at: Utc::now(),
+            accepted_at: None,
+            accepted_by: None,
+            revoked_at: None,
+        }
+    }
+
+    #[actix_web::test]
+    async fn invite_returns_a_link_with_the_token() {
+        let state = web::Data::new(test_state(
+            MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<RevokedTokenModel>::new()])
+                .append_query_results([vec![RoleModel {
+                    id: 5,
+                    name: "member".into(),
+                    description: None,
+                }]])
+                .append_query_results([vec![invitation("bob")]])
+                .append_exec_results([exec(1)])
+                .into_connection(),
+        ));
+        let grants =
+            UserGrants::default().in_organization(4, "owner".into(), ["members:write".to_string()]);
+        let token = state.issue_access_token(1, &grants).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/orgs/4/invitations")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(json!({"invitee": "bob"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["role"], "member");
+        assert_eq!(
+            body["link"],
+            format!(
+                "{}?token={}",
+                state.config.invitations.accept_url,
+                body["token"].as_str().unwrap()
+            )
+        );
+    }
+
+    #[actix_web::test]
+    async fn global_permissions_do_not_count_within_an_organization() {
+        let state = web::Data::new(test_state(
+            MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<RevokedTokenModel>::new()])
+                .into_connection(),
+        ));
+        // A plain member whose global roles happen to carry `members:write`.
+        let grants = UserGrants::new([], ["members:write".to_string()]).in_organization(
+            4,
+            "member".into(),
+            ["members:read".to_string()],
+        );
+        let token = state.issue_access_token(1, &grants).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/orgs/4/invitations")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(json!({"invitee": "bob", "role": "owner"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn invite_rejects_global_roles() {
+        let state = web::Data::new(test_state(
+            MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<RevokedTokenModel>::new()])
+                .into_connection(),
+        ));
+        let grants =
+            UserGrants::default().in_organization(4, "owner".into(), ["members:write".to_string()]);
+        let token = state.issue_access_token(1, &grants).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/orgs/4/invitations")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(json!({"invitee": "bob", "role": "admin"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn logged_in_invitee_joins_the_organization() {
+        let state = web::Data::new(test_state(
+            MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<RevokedTokenModel>::new()])
+                .append_query_results([vec![invitation("bob")]])
+                .append_query_results([vec![UserModel {
+                    id: 2,
+                    username: "bob".into(),
+                    username_canonical: "bob".into(),
+                    password: "hash".into(),
+                    active_organization_id: None,
+                    email: None,
+                    email_verified_at: None,
+                }]])
+                .append_exec_results([exec(1), exec(1), exec(1)])
+                .into_connection(),
+        ));
+        let token = state.issue_access_token(2, &UserGrants::default()).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/invitations/accept")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(json!({"token": "invite-token"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["organization_id"], 4);
+    }
+
+    #[actix_web::test]
+    async fn signing_up_under_another_username_is_forbidden() {
+        let state = web::Data::new(test_state(
+            MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([vec![invitation("bob")]])
+                .into_connection(),
+        ));
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/invitations/accept")
+            .set_json(json!({"token": "invite-token", "username": "mallory", "password": "correct horse"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    fn bob(email: Option<&str>, verified: bool) -> UserModel {
+        UserModel {
+            id: 2,
+            username: "bob".into(),
+            username_canonical: "bob".into(),
+            password: "hash".into(),
+            active_organization_id: None,
+            email: email.map(str::to_string),
+            email_verified_at: verified.then(Utc::now),
+        }
+    }
+
+    async fn accept_as(account: UserModel) -> StatusCode {
+        let state = web::Data::new(test_state(
+            MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<RevokedTokenModel>::new()])
+                .append_query_results([vec![invitation("Bob@Example.com")]])
+                .append_query_results([vec![account]])
+                .append_exec_results([exec(1), exec(1), exec(1)])
+                .into_connection(),
+        ));
+        let token = state.issue_access_token(2, &UserGrants::default()).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/invitations/accept")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(json!({"token": "invite-token"}))
+            .to_request();
+        test::call_service(&app, req).await.status()
+    }
+
+    #[actix_web::test]
+    async fn email_invitations_need_the_verified_address() {
+        assert_eq!(
+            accept_as(bob(Some("bob@example.com"), true)).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            accept_as(bob(Some("bob@example.com"), false)).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            accept_as(bob(Some("mallory@example.com"), true)).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(accept_as(bob(None, false)).await, StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn signing_up_with_another_address_is_forbidden() {
+        let state = web::Data::new(test_state(
+            MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([vec![invitation("bob@example.com")]])
+                .into_connection(),
+        ));
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/invitations/accept")
+            .set_json(json!({
+                "token": "invite-token",
+                "username": "mallory",
+                "password": "correct horse",
+                "email": "mallory@example.com",
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn failed_sign_up_hands_the_invitation_back() {
+        let state = web::Data::new(test_state(
+            MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([vec![invitation("bob")]])
+                .append_query_results([vec![bob(None, false)]])
+                .append_exec_results([exec(1), exec(1)])
+                .into_connection(),
+        ));
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/invitations/accept")
+            .set_json(
+                json!({"token": "invite-token", "username": "bob", "password": "correct horse"}),
+            )
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        // Claimed before the account is created, released once that fails.
+        let log = state.db.clone().into_transaction_log();
+        let statements: Vec<_> = log
+            .iter()
+            .flat_map(|transaction| transaction.statements())
+            .map(|statement| {
+                statement
+                    .sql
+                    .split_whitespace()
+                    .take(3)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect();
+        assert_eq!(
+            statements,
+            [
+                r#"SELECT "invitations"."id", "invitations"."organization_id","#,
+                r#"UPDATE "invitations" SET"#,
+                r#"SELECT "users"."id", "users"."username","#,
+                r#"UPDATE "invitations" SET"#,
+            ]
+        );
+        let release = &log[3].statements()[0];
+        assert!(
+            release.sql.contains(r#""accepted_by" IS NULL"#),
+            "{}",
+            release.sql
+        );
+    }
+
+    #[actix_web::test]
+    async fn expired_invitations_are_gone() {
+        let mut expired = invitation("bob@example.com");
+        expired.expires_at = Utc::now() - Duration::minutes(1);
+        let state = web::Data::new(test_state(
+            MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([vec![expired]])
+                .into_connection(),
+        ));
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/invitations/accept")
+            .set_json(
+                json!({"token": "invite-token", "username": "bob", "password": "correct horse"}),
+            )
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::GONE);
+    }
+}
@@ -0,0 +1,280 @@
+use actix_web::{HttpResponse, get, web};
+use serde_json::json;
+
+use crate::services::key_service::save_key_states;
+use crate::state::AppState;
+use crate::utils::KeyRing;
+use crate::utils::jwt_keys::KeyRingError;
+
+/// Publishes the public signing keys so other services can verify our tokens.
+#[get("/.well-known/jwks.json")]
+pub async fn jwks(state: web::Data<AppState>) -> HttpResponse {
+    match state.key_ring.jwk_set() {
+        Ok(set) => HttpResponse::Ok().json(set),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+// The key endpoints below carry no route attributes: `routes::configure` mounts them behind
+// `require_permission("keys:read")` and `require_permission("keys:write")`.
+
+/// Lists the keys of the ring and their states. Requires `keys:read`.
+pub async fn list_keys(state: web::Data<AppState>) -> HttpResponse {
+    match state.key_ring.statuses() {
+        Ok(keys) => HttpResponse::Ok().json(json!({ "keys": keys })),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+/// Makes a key the active signer; the previous one keeps verifying until retired.
+/// Requires `keys:write`.
+pub async fn promote_key(state: web::Data<AppState>, kid: web::Path<String>) -> HttpResponse {
+    rotate(&state, |ring| ring.promote(&kid)).await
+}
+
+/// Stops trusting a key that is no longer active. Requires `keys:write`.
+pub async fn retire_key(state: web::Data<AppState>, kid: web::Path<String>) -> HttpResponse {
+    rotate(&state, |ring| ring.retire(&kid)).await
+}
+
+/// Applies a promotion or retirement and saves the new states, undoing the change when they
+/// can't be saved so the ring never differs from what the next start restores.
+///
+/// The whole sequence holds the ring's rotation lock, so the undo can't overwrite a
+/// concurrent rotation.
+async fn rotate(
+    state: &AppState,
+    change: impl FnOnce(&KeyRing) -> Result<(), KeyRingError>,
+) -> HttpResponse {
+    let _rotation = state.key_ring.lock_rotation().await;
+    let previous = match state.key_ring.statuses() {
+        Ok(previous) => previous,
+        Err(e) => return key_ring_response(Err(e)),
+    };
+    if let Err(e) = change(&state.key_ring) {
+        return key_ring_response(Err(e));
+    }
+    let current = match state.key_ring.statuses() {
+        Ok(current) => current,
+        Err(e) => return key_ring_response(Err(e)),
+    };
+
+    match save_key_states(&state.db, &current).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            let previous: Vec<_> = previous
+                .into_iter()
+                .map(|key| (key.kid, key.state))
+                .collect();
+            if let Err(restore) = state.key_ring.restore_states(&previous) {
+                log::error!("failed to undo unsaved key rotation: {}", restore);
+            }
+            HttpResponse::InternalServerError()
+                .body(format!("DB error on saving key states: {}", e))
+        }
+    }
+}
+
+fn key_ring_response(result: Result<(), KeyRingError>) -> HttpResponse {
+    match result {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(KeyRingError::UnknownKey(kid)) => {
+            HttpResponse::NotFound().body(format!("Unknown key {}", kid))
+        }
+        Err(e @ (KeyRingError::CannotSign(_) | KeyRingError::RetiringActiveKey(_))) => {
+            HttpResponse::Conflict().body(e.to_string())
+        }
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{App, http::StatusCode, test};
+    use futures_util::FutureExt;
+    use jsonwebtoken::{Algorithm, jwk::JwkSet};
+    use sea_orm::{DatabaseBackend, DbErr, MockDatabase};
+    use serde_json::Value;
+
+    use crate::mail::tests::Outbox;
+    use crate::models::revoked_token::Model as RevokedTokenModel;
+    use crate::routes;
+    use crate::state::tests::{exec, test_config};
+    use crate::utils::UserGrants;
+    use crate::utils::jwt_keys::{KeyState, tests::fixture_key};
+
+    use super::*;
+
+    fn ring_state(db: MockDatabase) -> web::Data<AppState> {
+        let ring = KeyRing::new(vec![
+            (fixture_key(Algorithm::EdDSA), KeyState::Active),
+            (fixture_key(Algorithm::ES256), KeyState::VerifyOnly),
+        ])
+        .unwrap();
+
+        web::Data::new(AppState::new(
+            db.into_connection(),
+            test_config(),
+            Arc::new(ring),
+            Default::default(),
+            Arc::new(Outbox::default()),
+        ))
+    }
+
+    fn not_revoked() -> MockDatabase {
+        MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<RevokedTokenModel>::new()])
+    }
+
+    fn bearer(state: &AppState, permissions: &[&str]) -> (&'static str, String) {
+        let grants = UserGrants::new(
+            ["admin".to_string()],
+            permissions.iter().map(|p| p.to_string()),
+        );
+        let token = state.issue_access_token(1, &grants).unwrap();
+        ("Authorization", format!("Bearer {}", token))
+    }
+
+    #[actix_web::test]
+    async fn jwks_publishes_verifying_keys() {
+        let state = ring_state(MockDatabase::new(DatabaseBackend::Postgres));
+
+        let app = test::init_service(App::new().app_data(state.clone()).service(jwks)).await;
+        let req = test::TestRequest::get()
+            .uri("/.well-known/jwks.json")
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let body: JwkSet = test::read_body_json(resp).await;
+        assert_eq!(body.keys.len(), 2);
+        assert!(body.find("EdDSA-key").is_some());
+        assert!(body.find("ES256-key").is_some());
+    }
+
+    #[actix_web::test]
+    async fn promote_requires_keys_write() {
+        let state = ring_state(not_revoked());
+
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/admin/keys/ES256-key/promote")
+            .insert_header(bearer(&state, &["keys:read"]))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        assert_eq!(state.key_ring.active().unwrap().kid, "EdDSA-key");
+    }
+
+    #[actix_web::test]
+    async fn promote_saves_the_states_and_list_keys_shows_them() {
+        let state = ring_state(
+            not_revoked()
+                .append_exec_results([exec(1)])
+                .append_query_results([Vec::<RevokedTokenModel>::new()]),
+        );
+        let auth = bearer(&state, &["keys:read", "keys:write"]);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/admin/keys/ES256-key/promote")
+            .insert_header(auth.clone())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        let req = test::TestRequest::get()
+            .uri("/admin/keys")
+            .insert_header(auth)
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["keys"][0]["state"], "verify_only");
+        assert_eq!(body["keys"][1]["state"], "active");
+
+        let log = state.db.clone().into_transaction_log();
+        let save = log
+            .iter()
+            .flat_map(|transaction| transaction.statements())
+            .find(|statement| {
+                statement
+                    .sql
+                    .starts_with(r#"INSERT INTO "signing_key_states""#)
+            })
+            .expect("key states saved");
+        let values = &save.values.as_ref().unwrap().0;
+        assert_eq!(values[0], "EdDSA-key".into());
+        assert_eq!(values[1], "verify_only".into());
+        assert_eq!(values[3], "ES256-key".into());
+        assert_eq!(values[4], "active".into());
+    }
+
+    #[actix_web::test]
+    async fn promote_is_undone_when_the_states_cannot_be_saved() {
+        let state =
+            ring_state(not_revoked().append_exec_errors([DbErr::Custom("connection lost".into())]));
+
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/admin/keys/ES256-key/promote")
+            .insert_header(bearer(&state, &["keys:write"]))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(state.key_ring.active().unwrap().kid, "EdDSA-key");
+    }
+
+    #[actix_web::test]
+    async fn rotations_wait_for_the_one_in_progress() {
+        let state =
+            ring_state(MockDatabase::new(DatabaseBackend::Postgres).append_exec_results([exec(1)]));
+
+        let in_progress = state.key_ring.lock_rotation().await;
+        let mut promotion = Box::pin(rotate(&state, |ring| ring.promote("ES256-key")));
+        assert!((&mut promotion).now_or_never().is_none());
+        assert_eq!(state.key_ring.active().unwrap().kid, "EdDSA-key");
+
+        drop(in_progress);
+        let resp = promotion.await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        assert_eq!(state.key_ring.active().unwrap().kid, "ES256-key");
+    }
+
+    #[actix_web::test]
+    async fn retire_rejects_active_key() {
+        let state = ring_state(not_revoked());
+
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri("/admin/keys/EdDSA-key/retire")
+            .insert_header(bearer(&state, &["keys:write"]))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+    }
+}
@@ -0,0 +1,496 @@
+use actix_web::{
+    Either, HttpRequest, HttpResponse,
+    cookie::time::Duration,
+    get,
+    http::header::{self, ContentType},
+    post, web,
+};
+use chrono::Utc;
+use sea_orm::DbErr;
+use serde::Deserialize;
+use subtle::ConstantTimeEq;
+
+use crate::handlers::auth_handler::complete_login;
+use crate::mail::{Email, deliver};
+use crate::middleware::ClientInfo;
+use crate::models::user::Model as UserModel;
+use crate::services::login_throttle_service::user_key;
+use crate::services::magic_link_service::{
+    MagicLinkIssue, find_magic_link, issue_magic_link, redeem_magic_link,
+};
+use crate::services::user_service::{Tenant, find_user_by_email};
+use crate::state::AppState;
+use crate::utils::auth_cookie::{MAGIC_LINK_NONCE_COOKIE, magic_link_nonce_cookie};
+use crate::utils::{TokenTransport, generate_opaque_token, normalize_email};
+
+#[derive(Deserialize)]
+pub struct MagicLinkRequest {
+    email: String,
+}
+
+#[derive(Deserialize)]
+pub struct MagicLinkPageQuery {
+    token: String,
+    /// As for `POST /auth/login`; a browser following the link gets cookies unless it asks
+    /// for the tokens in the body.
+    transport: Option<TokenTransport>,
+}
+
+#[derive(Deserialize)]
+pub struct MagicLinkRedemption {
+    token: String,
+    /// As for `POST /auth/login`.
+    #[serde(default)]
+    transport: TokenTransport,
+    /// Echo of the [`MAGIC_LINK_NONCE_COOKIE`]; required for form posts.
+    #[serde(default)]
+    nonce: String,
+}
+
+/// Looks up the account that verified `email` and mails it the sign-in link, if there is one.
+async fn send_magic_link(state: &AppState, email: &str, token: &str) -> Result<(), String> {
+    let Some(user) = verified_owner(state, email)
+        .await
+        .map_err(|e| format!("DB error on fetching user: {}", e))?
+    else {
+        return Ok(());
+    };
+
+    let config = &state.config.magic_link;
+    let message = Email {
+        to: email.to_string(),
+        subject: "Your sign-in link".to_string(),
+        body: format!(
+            "Someone asked to sign in as {}. Use this link within {} minutes; it works once:\n\n\
+             {}?token={}\n\nIf that wasn't you, ignore this message.",
+            user.username,
+            config.ttl_secs / 60,
+            config.callback_url,
+            token
+        ),
+    };
+
+    deliver(&state.mailer, message)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// The account holding `email`, if it has verified it.
+///
+/// An unverified address may have been typed in by someone else, so a link mailed to it
+/// must not sign its owner into that account.
+async fn verified_owner(state: &AppState, email: &str) -> Result<Option<UserModel>, DbErr> {
+    Ok(find_user_by_email(&state.db, Tenant::Global, email)
+        .await?
+        .filter(|user| user.email_verified_at.is_some()))
+}
+
+/// Mails a single-use sign-in link to the account with this address.
+///
+/// Every request counts towards the address's limit, whether or not an account uses it, and
+/// the mail goes out in the background, so neither the response nor its timing reveals
+/// whether the account exists.
+#[post("/auth/magic-link")]
+pub async fn request_magic_link(
+    state: web::Data<AppState>,
+    payload: web::Json<MagicLinkRequest>,
+) -> HttpResponse {
+    let accepted = || {
+        HttpResponse::Accepted()
+            .body("If an account uses this address, a sign-in link is on its way.")
+    };
+    let Some(email) = normalize_email(&payload.email) else {
+        return accepted();
+    };
+
+    let config = &state.config.magic_link;
+    let token = match issue_magic_link(
+        &state.db,
+        &email,
+        config.ttl_secs,
+        config.window_secs,
+        config.max_per_address,
+    )
+    .await
+    {
+        Ok(MagicLinkIssue::Issued(token)) => token,
+        Ok(MagicLinkIssue::Limited(retry_at)) => {
+            let retry_after = (retry_at - Utc::now()).num_seconds().max(1);
+            return HttpResponse::TooManyRequests()
+                .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+                .body("Too many sign-in links requested for this address; try again later.");
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on issuing sign-in link: {}", e));
+        }
+    };
+    let state = state.clone();
+    actix_web::rt::spawn(async move {
+        if let Err(e) = send_magic_link(&state, &email, &token).await {
+            log::warn!("magic link request failed: {}", e);
+        }
+    });
+
+    accepted()
+}
+
+/// The page a sign-in link opens: a button that posts the token to the configured callback
+/// URL, i.e. `POST /auth/magic-link/callback` when the link points here.
+///
+/// Mail scanners and link previews fetch links on their own, so opening one must not spend it.
+/// The form carries a nonce that must match the cookie set alongside it, so another site can't
+/// post its own token from the victim's browser and sign them into the wrong account.
+#[get("/auth/magic-link/callback")]
+pub async fn magic_link_page(
+    state: web::Data<AppState>,
+    query: web::Query<MagicLinkPageQuery>,
+) -> HttpResponse {
+    let transport = match query.transport.unwrap_or(TokenTransport::Cookie) {
+        TokenTransport::Body => "body",
+        TokenTransport::Cookie => "cookie",
+    };
+    let nonce = generate_opaque_token();
+    let config = &state.config.magic_link;
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .insert_header((header::REFERRER_POLICY, "no-referrer"))
+        .cookie(magic_link_nonce_cookie(
+            &state.config.auth_cookie,
+            nonce.clone(),
+            Duration::seconds(config.ttl_secs),
+        ))
+        .body(format!(
+            "<!doctype html>\n<title>Sign in</title>\n\
+             <form method=\"post\" action=\"{}\">\n\
+             <input type=\"hidden\" name=\"token\" value=\"{}\">\n\
+             <input type=\"hidden\" name=\"transport\" value=\"{}\">\n\
+             <input type=\"hidden\" name=\"nonce\" value=\"{}\">\n\
+             <button type=\"submit\">Sign in</button>\n</form>\n",
+            escape_html(&config.callback_url),
+            escape_html(&query.token),
+            transport,
+            nonce
+        ))
+}
+
+/// Whether a form post repeats the nonce of the page that served it.
+fn nonce_matches(req: &HttpRequest, nonce: &str) -> bool {
+    req.cookie(MAGIC_LINK_NONCE_COOKIE).is_some_and(|cookie| {
+        !nonce.is_empty() && bool::from(cookie.value().as_bytes().ct_eq(nonce.as_bytes()))
+    })
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Exchanges a sign-in token, sent as JSON or as the form [`magic_link_page`] serves, for what
+/// `POST /auth/login` returns. Following the link proves control of the address, so it also
+/// counts as verifying it.
+///
+/// Form posts need the page's nonce; plain forms on other sites can't send JSON.
+#[post("/auth/magic-link/callback")]
+pub async fn magic_link_callback(
+    req: HttpRequest,
+    client: ClientInfo,
+    state: web::Data<AppState>,
+    payload: Either<web::Json<MagicLinkRedemption>, web::Form<MagicLinkRedemption>>,
+) -> HttpResponse {
+    let payload = match payload {
+        Either::Left(json) => json.into_inner(),
+        Either::Right(form) if nonce_matches(&req, &form.nonce) => form.into_inner(),
+        Either::Right(_) => {
+            return HttpResponse::Forbidden()
+                .body("Open the sign-in link again and use the page it shows.");
+        }
+    };
+    let link = match find_magic_link(&state.db, &payload.token).await {
+        Ok(Some(link)) => link,
+        Ok(None) => return HttpResponse::Unauthorized().body("Invalid or expired sign-in link."),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on fetching sign-in link: {}", e));
+        }
+    };
+    let user = match verified_owner(&state, &link.email).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::Unauthorized().body("Invalid or expired sign-in link."),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on fetching user: {}", e));
+        }
+    };
+    match redeem_magic_link(&state.db, &link).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Unauthorized().body("Invalid or expired sign-in link."),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on redeeming sign-in link: {}", e));
+        }
+    }
+
+    complete_login(
+        &state,
+        &client,
+        &user,
+        &user_key(&user.username),
+        payload.transport,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{App, cookie::Cookie, http::StatusCode, test};
+    use chrono::Duration;
+    use sea_orm::{DatabaseBackend, MockDatabase};
+    use serde_json::{Value, json};
+
+    use crate::mail::tests::Outbox;
+    use crate::models::magic_link::Model as MagicLinkModel;
+    use crate::models::totp_credential::Model as TotpCredentialModel;
+    use crate::models::user_role::Model as UserRoleModel;
+    use crate::routes;
+    use crate::state::tests::{exec, test_state};
+    use crate::utils::hash_opaque_token;
+
+    use super::*;
+
+    fn bob() -> UserModel {
+        UserModel {
+            id: 4,
+            username: "bob".into(),
+            username_canonical: "bob".into(),
+            password: String::new(),
+            active_organization_id: None,
+            email: Some("bob@example.com".into()),
+            email_verified_at: Some(Utc::now()),
+        }
+    }
+
+    fn link(token: &str, created_at: chrono::DateTime<Utc>) -> MagicLinkModel {
+        MagicLinkModel {
+            id: 1,
+            email: "bob@example.com".into(),
+            token_hash: hash_opaque_token(token),
+            expires_at: created_at + Duration::minutes(15),
+            created_at,
+            used_at: None,
+        }
+    }
+
+    async fn call(
+        db: MockDatabase,
+        req: test::TestRequest,
+    ) -> (StatusCode, Option<String>, Vec<u8>, Arc<Outbox>) {
+        let outbox = Arc::new(Outbox::default());
+        let state = web::Data::new(AppState {
+            mailer: outbox.clone(),
+            ..test_state(db.into_connection())
+        });
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let resp = test::call_service(&app, req.to_request()).await;
+        let status = resp.status();
+        let retry_after = resp
+            .headers()
+            .get(header::RETRY_AFTER)
+            .map(|value| value.to_str().unwrap().to_string());
+        let body = test::read_body(resp).await.to_vec();
+
+        // Let the background task run.
+        for _ in 0..50 {
+            if !outbox.sent().is_empty() {
+                break;
+            }
+            actix_web::rt::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        (status, retry_after, body, outbox)
+    }
+
+    #[actix_web::test]
+    async fn request_mails_a_link_to_the_account() {
+        // The address is locked and its recent links counted, stale links are pruned and the
+        // new one stored; the account lookup follows in the background.
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<MagicLinkModel>::new()])
+            .append_query_results([vec![bob()]])
+            .append_exec_results([exec(1), exec(1), exec(1)]);
+
+        let (status, _, _, outbox) = call(
+            db,
+            test::TestRequest::post()
+                .uri("/auth/magic-link")
+                .set_json(json!({"email": " Bob@Example.com "})),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let sent = outbox.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to, "bob@example.com");
+        assert!(sent[0].body.contains("/auth/magic-link/callback?token="));
+    }
+
+    #[actix_web::test]
+    async fn unverified_addresses_get_no_link() {
+        let unverified = UserModel {
+            email_verified_at: None,
+            ..bob()
+        };
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<MagicLinkModel>::new()])
+            .append_query_results([vec![unverified]])
+            .append_exec_results([exec(1), exec(1), exec(1)]);
+
+        let (status, _, _, outbox) = call(
+            db,
+            test::TestRequest::post()
+                .uri("/auth/magic-link")
+                .set_json(json!({"email": "bob@example.com"})),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert!(outbox.sent().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn requests_are_limited_per_address() {
+        let now = Utc::now();
+        let recent: Vec<_> = (0..5)
+            .map(|i| link("old", now - Duration::minutes(50 - i)))
+            .collect();
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_exec_results([exec(1)])
+            .append_query_results([recent]);
+
+        let (status, retry_after, _, outbox) = call(
+            db,
+            test::TestRequest::post()
+                .uri("/auth/magic-link")
+                .set_json(json!({"email": "bob@example.com"})),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        // The oldest request leaves the one-hour window in ten minutes.
+        let retry_after: i64 = retry_after.unwrap().parse().unwrap();
+        assert!((590..=600).contains(&retry_after), "{}", retry_after);
+        assert!(outbox.sent().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn opening_a_link_does_not_spend_it() {
+        let state = web::Data::new(test_state(
+            MockDatabase::new(DatabaseBackend::Postgres).into_connection(),
+        ));
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/auth/magic-link/callback?token=a%22b")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        let nonce = resp
+            .response()
+            .cookies()
+            .find(|cookie| cookie.name() == MAGIC_LINK_NONCE_COOKIE)
+            .unwrap()
+            .value()
+            .to_string();
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert!(body.contains(&format!(
+            r#"<form method="post" action="{}">"#,
+            state.config.magic_link.callback_url
+        )));
+        assert!(
+            body.contains(r#"name="token" value="a&quot;b""#),
+            "{}",
+            body
+        );
+        // Browsers get cookies rather than a page of tokens.
+        assert!(body.contains(r#"name="transport" value="cookie""#));
+        assert!(body.contains(&format!(r#"name="nonce" value="{}""#, nonce)));
+    }
+
+    #[actix_web::test]
+    async fn callback_answers_like_login() {
+        // The link is spent, the throttle cleared and a refresh token and session stored.
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![link("token", Utc::now())]])
+            .append_query_results([vec![bob()]])
+            .append_query_results([Vec::<TotpCredentialModel>::new()])
+            .append_query_results([Vec::<UserRoleModel>::new()])
+            .append_exec_results([exec(1), exec(1), exec(1), exec(1)]);
+
+        let (status, _, body, _) = call(
+            db,
+            test::TestRequest::post()
+                .uri("/auth/magic-link/callback")
+                .cookie(Cookie::new(MAGIC_LINK_NONCE_COOKIE, "nonce"))
+                .set_form([("token", "token"), ("nonce", "nonce")]),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert!(body["token"].is_string());
+        assert!(body["refresh_token"].is_string());
+    }
+
+    #[actix_web::test]
+    async fn form_posts_need_the_nonce_of_the_page() {
+        // E.g. another site posting its own token to sign the victim into its account.
+        let (status, _, _, _) = call(
+            MockDatabase::new(DatabaseBackend::Postgres),
+            test::TestRequest::post()
+                .uri("/auth/magic-link/callback")
+                .set_form([
+                    ("token", "token"),
+                    ("transport", "cookie"),
+                    ("nonce", "guess"),
+                ]),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn callback_rejects_unknown_links() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<MagicLinkModel>::new()]);
+
+        let (status, _, _, _) = call(
+            db,
+            test::TestRequest::post()
+                .uri("/auth/magic-link/callback")
+                .set_json(json!({"token": "nope"})),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,694 @@
+use actix_web::{HttpResponse, delete, post, web};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::handlers::auth_handler::{issue_token_pair, throttled_response, token_pair_response};
+use crate::middleware::{AuthenticatedUser, ClientInfo};
+use crate::models::totp_credential::Model as TotpCredentialModel;
+use crate::services::login_throttle_service::{
+    check_login_throttle, clear_login_throttle, record_login_failure, user_key,
+};
+use crate::services::mfa_service::{
+    complete_mfa_challenge, confirm_totp_enrollment, disable_totp as remove_totp,
+    find_mfa_challenge, find_totp_credential, record_mfa_challenge_failure, redeem_recovery_code,
+    redeem_totp_code, replace_recovery_codes, start_totp_enrollment,
+};
+use crate::services::user_service::{Tenant, find_user_by_id};
+use crate::state::AppState;
+use crate::utils::{
+    TokenTransport, base32_encode, generate_totp_secret, otpauth_uri, verify_password,
+};
+
+#[derive(Deserialize)]
+pub struct TotpCodeRequest {
+    code: String,
+}
+
+/// A current TOTP or recovery code, plus the password on accounts that have one.
+#[derive(Deserialize)]
+pub struct DisableTotpRequest {
+    password: Option<String>,
+    code: Option<String>,
+    recovery_code: Option<String>,
+}
+
+/// Second step of a login: the challenge token plus either a TOTP or a recovery code.
+#[derive(Deserialize)]
+pub struct VerifyMfaRequest {
+    mfa_token: String,
+    code: Option<String>,
+    recovery_code: Option<String>,
+    /// As for `POST /auth/login`.
+    #[serde(default)]
+    transport: TokenTransport,
+}
+
+/// Loads the caller's TOTP credential once they have confirmed it.
+pub(crate) async fn confirmed_credential(
+    state: &AppState,
+    user_id: i32,
+) -> Result<Option<TotpCredentialModel>, HttpResponse> {
+    find_totp_credential(&state.db, user_id)
+        .await
+        .map(|credential| credential.filter(|credential| credential.confirmed_at.is_some()))
+        .map_err(|e| {
+            HttpResponse::InternalServerError()
+                .body(format!("DB error on loading TOTP credential: {}", e))
+        })
+}
+
+/// Refuses the request while the user's login is throttled; otherwise returns the throttle key
+/// wrong codes and passwords count against.
+///
+/// Checking a second factor from inside a session is throttled like logging in, so a stolen
+/// session can't be used to guess codes.
+async fn check_throttle(state: &AppState, username: &str) -> Result<String, HttpResponse> {
+    let throttle_key = user_key(username);
+    let throttle = check_login_throttle(
+        &state.db,
+        &state.config.login_throttle,
+        std::slice::from_ref(&throttle_key),
+    )
+    .await
+    .map_err(|e| {
+        HttpResponse::InternalServerError()
+            .body(format!("DB error on checking login throttle: {}", e))
+    })?;
+    match throttled_response(throttle) {
+        Some(response) => Err(response),
+        None => Ok(throttle_key),
+    }
+}
+
+/// Counts a wrong code or password against the throttle key from [`check_throttle`].
+async fn record_failure(state: &AppState, throttle_key: &str) -> Result<(), HttpResponse> {
+    let config = &state.config.login_throttle;
+    record_login_failure(&state.db, config, throttle_key, config.user_max_failures)
+        .await
+        .map(|_| ())
+        .map_err(|e| {
+            HttpResponse::InternalServerError()
+                .body(format!("DB error on recording failed login: {}", e))
+        })
+}
+
+/// Starts TOTP enrollment with a new key. Mounted inside the `/me` scope; the second factor
+/// is only required once a code confirms the app was set up.
+#[post("/mfa/totp")]
+pub async fn enroll_totp(state: web::Data<AppState>, user: AuthenticatedUser) -> HttpResponse {
+    let account = match find_user_by_id(&state.db, Tenant::Global, user.user_id).await {
+        Ok(Some(account)) => account,
+        Ok(None) => return HttpResponse::NotFound().body("User not found"),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on fetching user: {}", e));
+        }
+    };
+    match confirmed_credential(&state, account.id).await {
+        Ok(Some(_)) => {
+            return HttpResponse::Conflict().body("Two-factor authentication is already enabled.");
+        }
+        Ok(None) => {}
+        Err(response) => return response,
+    }
+
+    let secret = generate_totp_secret();
+    if let Err(e) = start_totp_enrollment(&state.db, account.id, &secret).await {
+        return HttpResponse::InternalServerError()
+            .body(format!("DB error on starting TOTP enrollment: {}", e));
+    }
+
+    let uri = otpauth_uri(&state.config.mfa.issuer, &account.username, &secret);
+    HttpResponse::Ok().json(json!({
+        "secret": base32_encode(&secret),
+        "otpauth_uri": uri,
+    }))
+}
+
+/// Finishes enrollment with a code from the app and returns the first recovery codes.
+#[post("/mfa/totp/confirm")]
+pub async fn confirm_totp(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    payload: web::Json<TotpCodeRequest>,
+) -> HttpResponse {
+    let credential = match find_totp_credential(&state.db, user.user_id).await {
+        Ok(Some(credential)) if credential.confirmed_at.is_some() => {
+            return HttpResponse::Conflict().body("Two-factor authentication is already enabled.");
+        }
+        Ok(Some(credential)) => credential,
+        Ok(None) => return HttpResponse::NotFound().body("No TOTP enrollment in progress."),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on loading TOTP credential: {}", e));
+        }
+    };
+
+    match redeem_totp_code(&state.db, &credential, &payload.code).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::BadRequest().body("Invalid code."),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on checking code: {}", e));
+        }
+    }
+
+    match confirm_totp_enrollment(
+        &state.db,
+        user.user_id,
+        state.config.mfa.recovery_code_count,
+    )
+    .await
+    {
+        Ok(codes) => HttpResponse::Ok().json(json!({ "recovery_codes": codes })),
+        Err(e) => HttpResponse::InternalServerError()
+            .body(format!("DB error on confirming TOTP enrollment: {}", e)),
+    }
+}
+
+/// Turns the second factor off. Needs a current TOTP or recovery code, so a stolen session
+/// can't strip it, and the password too unless the account signs in with passkeys only.
+#[delete("/mfa/totp")]
+pub async fn disable_totp(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    payload: web::Json<DisableTotpRequest>,
+) -> HttpResponse {
+    let account = match find_user_by_id(&state.db, Tenant::Global, user.user_id).await {
+        Ok(Some(account)) => account,
+        Ok(None) => return HttpResponse::NotFound().body("User not found"),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on fetching user: {}", e));
+        }
+    };
+    let throttle_key = match check_throttle(&state, &account.username).await {
+        Ok(throttle_key) => throttle_key,
+        Err(response) => return response,
+    };
+    if !account.password.is_empty()
+        && !payload.password.as_deref().is_some_and(|password| {
+            verify_password(&state.config.password_hashing, password, &account.password).is_match()
+        })
+    {
+        if let Err(response) = record_failure(&state, &throttle_key).await {
+            return response;
+        }
+        return HttpResponse::Forbidden().body("Password is incorrect.");
+    }
+
+    let credential = match confirmed_credential(&state, account.id).await {
+        Ok(Some(credential)) => credential,
+        Ok(None) => {
+            return HttpResponse::NotFound().body("Two-factor authentication is not enabled.");
+        }
+        Err(response) => return response,
+    };
+    let verified = match (&payload.code, &payload.recovery_code) {
+        (Some(code), None) => redeem_totp_code(&state.db, &credential, code).await,
+        (None, Some(recovery_code)) => {
+            redeem_recovery_code(&state.db, account.id, recovery_code).await
+        }
+        _ => {
+            return HttpResponse::BadRequest().body("Send either a code or a recovery_code.");
+        }
+    };
+    match verified {
+        Ok(true) => {}
+        Ok(false) => {
+            if let Err(response) = record_failure(&state, &throttle_key).await {
+                return response;
+            }
+            return HttpResponse::BadRequest().body("Invalid code.");
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on checking code: {}", e));
+        }
+    }
+
+    match remove_totp(&state.db, account.id).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("DB error on disabling TOTP: {}", e))
+        }
+    }
+}
+
+/// Replaces the recovery codes, e.g. after using some; needs a current TOTP code. Wrong codes
+/// count towards the login throttle.
+#[post("/mfa/recovery-codes")]
+pub async fn regenerate_recovery_codes(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    payload: web::Json<TotpCodeRequest>,
+) -> HttpResponse {
+    let account = match find_user_by_id(&state.db, Tenant::Global, user.user_id).await {
+        Ok(Some(account)) => account,
+        Ok(None) => return HttpResponse::NotFound().body("User not found"),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on fetching user: {}", e));
+        }
+    };
+    let throttle_key = match check_throttle(&state, &account.username).await {
+        Ok(throttle_key) => throttle_key,
+        Err(response) => return response,
+    };
+    let credential = match confirmed_credential(&state, account.id).await {
+        Ok(Some(credential)) => credential,
+        Ok(None) => {
+            return HttpResponse::NotFound().body("Two-factor authentication is not enabled.");
+        }
+        Err(response) => return response,
+    };
+
+    match redeem_totp_code(&state.db, &credential, &payload.code).await {
+        Ok(true) => {}
+        Ok(false) => {
+            if let Err(response) = record_failure(&state, &throttle_key).await {
+                return response;
+            }
+            return HttpResponse::BadRequest().body("Invalid code.");
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on checking code: {}", e));
+        }
+    }
+
+    match replace_recovery_codes(&state.db, account.id, state.config.mfa.recovery_code_count).await
+    {
+        Ok(codes) => HttpResponse::Ok().json(json!({ "recovery_codes": codes })),
+        Err(e) => HttpResponse::InternalServerError()
+            .body(format!("DB error on replacing recovery codes: {}", e)),
+    }
+}
+
+/// Exchanges the challenge token from `POST /auth/login` and a second factor for a token
+/// pair. Wrong codes count towards both the challenge's attempts and the login throttle.
+#[post("/auth/mfa/verify")]
+pub async fn verify_mfa(
+    client: ClientInfo,
+    state: web::Data<AppState>,
+    payload: web::Json<VerifyMfaRequest>,
+) -> HttpResponse {
+    let config = &state.config;
+    let challenge = match find_mfa_challenge(
+        &state.db,
+        &payload.mfa_token,
+        config.mfa.max_challenge_attempts,
+    )
+    .await
+    {
+        Ok(Some(challenge)) => challenge,
+        Ok(None) => return HttpResponse::Unauthorized().body("Invalid or expired MFA token."),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on loading MFA challenge: {}", e));
+        }
+    };
+    let user = match find_user_by_id(&state.db, Tenant::Global, challenge.user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::Unauthorized().body("Invalid or expired MFA token."),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on fetching user: {}", e));
+        }
+    };
+
+    let throttle_key = match check_throttle(&state, &user.username).await {
+        Ok(throttle_key) => throttle_key,
+        Err(response) => return response,
+    };
+
+    let verified = match (&payload.code, &payload.recovery_code) {
+        (Some(code), None) => match confirmed_credential(&state, user.id).await {
+            Ok(Some(credential)) => redeem_totp_code(&state.db, &credential, code).await,
+            // Turned off since the password step; nothing to check the code against.
+            Ok(None) => Ok(false),
+            Err(response) => return response,
+        },
+        (None, Some(recovery_code)) => {
+            redeem_recovery_code(&state.db, user.id, recovery_code).await
+        }
+        _ => {
+            return HttpResponse::BadRequest().body("Send either a code or a recovery_code.");
+        }
+    };
+    match verified {
+        Ok(true) => {}
+        Ok(false) => {
+            if let Err(e) = record_mfa_challenge_failure(&state.db, challenge.id).await {
+                return HttpResponse::InternalServerError()
+                    .body(format!("DB error on recording MFA failure: {}", e));
+            }
+            if let Err(response) = record_failure(&state, &throttle_key).await {
+                return response;
+            }
+            return HttpResponse::Unauthorized().body("Invalid code.");
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on checking code: {}", e));
+        }
+    }
+
+    match complete_mfa_challenge(&state.db, &challenge).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Unauthorized().body("Invalid or expired MFA token."),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on completing MFA challenge: {}", e));
+        }
+    }
+    if let Err(e) = clear_login_throttle(&state.db, &throttle_key).await {
+        return HttpResponse::InternalServerError()
+            .body(format!("DB error on clearing login throttle: {}", e));
+    }
+
+    match issue_token_pair(&state, &client, user.id, user.active_organization_id).await {
+        Ok(tokens) => token_pair_response(&state, tokens, payload.transport, json!({})),
+        Err(response) => response,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, http::StatusCode, test};
+    use chrono::{Duration, Utc};
+    use sea_orm::{DatabaseBackend, MockDatabase};
+    use serde_json::Value;
+
+    use crate::models::login_throttle::Model as LoginThrottleModel;
+    use crate::models::mfa_challenge::Model as MfaChallengeModel;
+    use crate::models::revoked_token::Model as RevokedTokenModel;
+    use crate::models::user::Model as UserModel;
+    use crate::models::user_role::Model as UserRoleModel;
+    use crate::routes;
+    use crate::state::tests::{exec, test_config, test_state};
+    use crate::utils::totp::{hotp, totp_step};
+    use crate::utils::{UserGrants, hash_opaque_token, hash_password};
+
+    use super::*;
+
+    const SECRET: &[u8] = b"12345678901234567890";
+
+    fn alice() -> UserModel {
+        UserModel {
+            id: 7,
+            username: "alice".into(),
+            username_canonical: "alice".into(),
+            password: hash_password(&test_config().password_hashing, "secret").unwrap(),
+            active_organization_id: None,
+            email: None,
+            email_verified_at: None,
+        }
+    }
+
+    fn credential() -> TotpCredentialModel {
+        TotpCredentialModel {
+            user_id: 7,
+            secret: base32_encode(SECRET),
+            confirmed_at: Some(Utc::now()),
+            last_used_step: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    fn challenge() -> MfaChallengeModel {
+        MfaChallengeModel {
+            id: 3,
+            user_id: 7,
+            token_hash: hash_opaque_token("challenge"),
+            failed_attempts: 0,
+            expires_at: Utc::now() + Duration::minutes(5),
+            created_at: Utc::now(),
+            used_at: None,
+        }
+    }
+
+    async fn post(db: MockDatabase, uri: &str, body: Value) -> (StatusCode, Value) {
+        let state = web::Data::new(test_state(db.into_connection()));
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri(uri)
+            .set_json(body)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let status = resp.status();
+        let body = test::read_body(resp).await;
+
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    #[actix_web::test]
+    async fn login_asks_for_the_second_factor() {
+        // Throttle check, user, TOTP credential; the challenge is then pruned and stored.
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<LoginThrottleModel>::new()])
+            .append_query_results([vec![alice()]])
+            .append_query_results([vec![credential()]])
+            .append_exec_results([exec(1), exec(1)]);
+
+        let (status, body) = post(
+            db,
+            "/auth/login",
+            json!({"username": "alice", "password": "secret"}),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["mfa_required"], true);
+        assert!(body["mfa_token"].is_string());
+        assert!(body.get("token").is_none());
+    }
+
+    #[actix_web::test]
+    async fn verify_exchanges_a_valid_code_for_tokens() {
+        let code = format!("{:06}", hotp(SECRET, totp_step(Utc::now()) as u64));
+        // The code's step, the challenge, and the throttle are updated, then a refresh
+        // token is stored.
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![challenge()]])
+            .append_query_results([vec![alice()]])
+            .append_query_results([Vec::<LoginThrottleModel>::new()])
+            .append_query_results([vec![credential()]])
+            .append_query_results([Vec::<UserRoleModel>::new()])
+            .append_exec_results([exec(1), exec(1), exec(1), exec(1), exec(1)]);
+
+        let (status, body) = post(
+            db,
+            "/auth/mfa/verify",
+            json!({"mfa_token": "challenge", "code": code}),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(body["token"].is_string());
+        assert!(body["refresh_token"].is_string());
+    }
+
+    #[actix_web::test]
+    async fn verify_counts_wrong_codes() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![challenge()]])
+            .append_query_results([vec![alice()]])
+            .append_query_results([Vec::<LoginThrottleModel>::new()])
+            .append_query_results([vec![credential()]])
+            .append_query_results([vec![LoginThrottleModel {
+                key: "user:alice".into(),
+                failures: 1,
+                last_failure_at: Utc::now(),
+                locked_until: None,
+            }]])
+            .append_exec_results([exec(1)]);
+
+        let (status, _) = post(
+            db,
+            "/auth/mfa/verify",
+            json!({"mfa_token": "challenge", "code": "000000x"}),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn verify_rejects_unknown_challenges() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<MfaChallengeModel>::new()]);
+
+        let (status, _) = post(
+            db,
+            "/auth/mfa/verify",
+            json!({"mfa_token": "nope", "recovery_code": "abcde-fghij"}),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    /// Calls a `/me/mfa` endpoint as Alice, returning the status and the SQL that ran.
+    async fn as_alice(db: MockDatabase, req: test::TestRequest) -> (StatusCode, Vec<String>) {
+        let state = web::Data::new(test_state(
+            db.append_query_results([Vec::<RevokedTokenModel>::new()])
+                .into_connection(),
+        ));
+        let token = state.issue_access_token(7, &UserGrants::default()).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = req
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        let status = test::call_service(&app, req).await.status();
+        let log = state.db.clone().into_transaction_log();
+        let statements = log
+            .iter()
+            .flat_map(|transaction| transaction.statements())
+            .map(|statement| statement.sql.clone())
+            .collect();
+        (status, statements)
+    }
+
+    /// The revoked-token check comes first, then the account and its login throttle.
+    fn account_db(account: UserModel) -> MockDatabase {
+        MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<RevokedTokenModel>::new()])
+            .append_query_results([vec![account]])
+            .append_query_results([Vec::<LoginThrottleModel>::new()])
+    }
+
+    fn failure() -> LoginThrottleModel {
+        LoginThrottleModel {
+            key: "user:alice".into(),
+            failures: 1,
+            last_failure_at: Utc::now(),
+            locked_until: None,
+        }
+    }
+
+    fn counts_a_failure(statements: &[String]) -> bool {
+        statements
+            .iter()
+            .any(|sql| sql.starts_with("INSERT INTO login_throttles"))
+    }
+
+    fn disable(body: Value) -> test::TestRequest {
+        test::TestRequest::delete()
+            .uri("/me/mfa/totp")
+            .set_json(body)
+    }
+
+    #[actix_web::test]
+    async fn disabling_needs_a_second_factor_besides_the_password() {
+        let db = account_db(alice()).append_query_results([vec![credential()]]);
+        let (status, _) = as_alice(db, disable(json!({"password": "secret"}))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let db = account_db(alice()).append_query_results([vec![failure()]]);
+        let (status, statements) =
+            as_alice(db, disable(json!({"password": "wrong", "code": "123456"}))).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(counts_a_failure(&statements));
+    }
+
+    #[actix_web::test]
+    async fn disabling_counts_wrong_codes() {
+        let db = account_db(alice())
+            .append_query_results([vec![credential()]])
+            .append_query_results([vec![failure()]]);
+
+        let (status, statements) = as_alice(
+            db,
+            disable(json!({"password": "secret", "code": "000000x"})),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(counts_a_failure(&statements));
+    }
+
+    #[actix_web::test]
+    async fn disabling_accepts_a_recovery_code() {
+        // The code is spent, then the recovery codes and the key are dropped.
+        let db = account_db(alice())
+            .append_query_results([vec![credential()]])
+            .append_exec_results([exec(1), exec(1), exec(1)]);
+
+        let (status, _) = as_alice(
+            db,
+            disable(json!({"password": "secret", "recovery_code": "abcde-fghij"})),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::NO_CONTENT);
+    }
+
+    #[actix_web::test]
+    async fn passkey_only_accounts_disable_with_a_code_alone() {
+        let code = format!("{:06}", hotp(SECRET, totp_step(Utc::now()) as u64));
+        let passkey_only = UserModel {
+            password: String::new(),
+            ..alice()
+        };
+        let db = account_db(passkey_only)
+            .append_query_results([vec![credential()]])
+            .append_exec_results([exec(1), exec(1), exec(1)]);
+
+        let (status, _) = as_alice(db, disable(json!({"code": code}))).await;
+
+        assert_eq!(status, StatusCode::NO_CONTENT);
+    }
+
+    #[actix_web::test]
+    async fn regenerating_recovery_codes_counts_wrong_codes() {
+        let db = account_db(alice())
+            .append_query_results([vec![credential()]])
+            .append_query_results([vec![failure()]]);
+
+        let (status, statements) = as_alice(
+            db,
+            test::TestRequest::post()
+                .uri("/me/mfa/recovery-codes")
+                .set_json(json!({"code": "000000x"})),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(counts_a_failure(&statements));
+    }
+
+    #[actix_web::test]
+    async fn regenerating_recovery_codes_is_refused_while_locked() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<RevokedTokenModel>::new()])
+            .append_query_results([vec![alice()]])
+            .append_query_results([vec![LoginThrottleModel {
+                failures: 5,
+                locked_until: Some(Utc::now() + Duration::minutes(10)),
+                ..failure()
+            }]]);
+
+        let (status, _) = as_alice(
+            db,
+            test::TestRequest::post()
+                .uri("/me/mfa/recovery-codes")
+                .set_json(json!({"code": "123456"})),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    }
+}
@@ -1,2 +1,13 @@
+pub mod admin_handler;
 pub mod auth_handler;
+pub mod email_handler;
+pub mod invitation_handler;
+pub mod key_handler;
+pub mod magic_link_handler;
+pub mod mfa_handler;
+pub mod organization_handler;
+pub mod password_handler;
+pub mod policy_handler;
+pub mod session_handler;
 pub mod user_handler;
+pub mod webauthn_handler;
@@ -0,0 +1,309 @@
+use actix_web::{HttpRequest, HttpResponse, get, post, web};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::handlers::auth_handler::mint_access_token;
+use crate::middleware::AuthenticatedUser;
+use crate::services::organization_service::{
+    OWNER_ROLE, create_organization as create_org, find_membership,
+    list_members as list_org_members,
+};
+use crate::services::user_service::set_active_organization;
+use crate::state::{AppState, authenticated_by_cookie};
+use crate::utils::auth_cookie::access_token_cookie;
+
+// Mounted inside the `/orgs` scope, which is wrapped in [`crate::middleware::JwtAuth`].
+
+/// Returns the `403` to send unless the caller's token is scoped to `organization_id` and grants
+/// `permission` there.
+///
+/// Only the organization role's permissions count; global roles grant nothing within an
+/// organization.
+pub(crate) fn org_permission_denied(
+    user: &AuthenticatedUser,
+    organization_id: i32,
+    permission: &str,
+) -> Option<HttpResponse> {
+    if user.claims.org_id != Some(organization_id) {
+        return Some(HttpResponse::Forbidden().body("Switch to this organization first."));
+    }
+    if !user.claims.has_org_permission(permission) {
+        return Some(HttpResponse::Forbidden().body("Forbidden"));
+    }
+    None
+}
+
+#[derive(Deserialize)]
+pub struct CreateOrganizationRequest {
+    name: String,
+}
+
+/// Creates an organization owned by the caller. Switch to it to act within it.
+#[post("")]
+pub async fn create_organization(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    payload: web::Json<CreateOrganizationRequest>,
+) -> HttpResponse {
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return HttpResponse::BadRequest().body("Organization name must not be empty.");
+    }
+
+    match create_org(&state.db, user.user_id, name.to_string()).await {
+        Ok(organization) => HttpResponse::Created().json(json!({
+            "id": organization.id,
+            "name": organization.name,
+            "role": OWNER_ROLE,
+        })),
+        Err(e) => HttpResponse::InternalServerError()
+            .body(format!("DB error on creating organization: {}", e)),
+    }
+}
+
+/// Lists the members of the caller's active organization. Requires `members:read` there.
+#[get("/{id}/members")]
+pub async fn list_members(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> HttpResponse {
+    let organization_id = path.into_inner();
+
+    if let Some(response) = org_permission_denied(&user, organization_id, "members:read") {
+        return response;
+    }
+
+    match list_org_members(&state.db, organization_id).await {
+        Ok(members) => HttpResponse::Ok().json(json!({
+            "members": members
+                .into_iter()
+                .map(|member| json!({
+                    "id": member.user.id,
+                    "username": member.user.username,
+                    "role": member.role,
+                }))
+                .collect::<Vec<_>>(),
+        })),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("DB error on listing members: {}", e))
+        }
+    }
+}
+
+/// Makes an organization the caller is a member of their active one and returns an access
+/// token scoped to it, or replaces the access token cookie of a cookie-mode session. Later
+/// logins and refreshes keep using it.
+#[post("/{id}/switch")]
+pub async fn switch_organization(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> HttpResponse {
+    let organization_id = path.into_inner();
+
+    match find_membership(&state.db, organization_id, user.user_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().body("Not a member of this organization."),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on loading membership: {}", e));
+        }
+    }
+
+    if let Err(e) = set_active_organization(&state.db, user.user_id, Some(organization_id)).await {
+        return HttpResponse::InternalServerError()
+            .body(format!("DB error on switching organization: {}", e));
+    }
+
+    match mint_access_token(
+        &state,
+        user.user_id,
+        Some(organization_id),
+        user.claims.sid.as_deref(),
+    )
+    .await
+    {
+        // A cookie-mode session keeps the token out of reach of scripts.
+        Ok(token) if authenticated_by_cookie(&req) => HttpResponse::Ok()
+            .cookie(access_token_cookie(
+                &state.config.auth_cookie,
+                &state.config.jwt,
+                token,
+            ))
+            .json(json!({ "organization_id": organization_id })),
+        Ok(token) => HttpResponse::Ok().json(json!({ "token": token })),
+        Err(response) => response,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, cookie::Cookie, http::StatusCode, test};
+    use chrono::Utc;
+    use sea_orm::{DatabaseBackend, MockDatabase};
+
+    use crate::models::membership::Model as MembershipModel;
+    use crate::models::permission::Model as PermissionModel;
+    use crate::models::revoked_token::Model as RevokedTokenModel;
+    use crate::models::role::Model as RoleModel;
+    use crate::models::role_permission::Model as RolePermissionModel;
+    use crate::models::user_role::Model as UserRoleModel;
+    use crate::routes;
+    use crate::state::tests::{exec, test_state};
+    use crate::utils::auth_cookie::ACCESS_TOKEN_COOKIE;
+    use crate::utils::{UserGrants, decode_token};
+
+    use super::*;
+
+    fn membership() -> MembershipModel {
+        MembershipModel {
+            organization_id: 4,
+            user_id: 1,
+            role_id: 5,
+            created_at: Utc::now(),
+        }
+    }
+
+    /// A switch to organization 4, where user 1 is a `member` with `members:read`.
+    fn switch_db() -> MockDatabase {
+        MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<RevokedTokenModel>::new()])
+            .append_query_results([vec![membership()]])
+            .append_exec_results([exec(1)])
+            .append_query_results([Vec::<UserRoleModel>::new()])
+            .append_query_results([vec![membership()]])
+            .append_query_results([vec![RoleModel {
+                id: 5,
+                name: "member".into(),
+                description: None,
+            }]])
+            .append_query_results([vec![RolePermissionModel {
+                role_id: 5,
+                permission_id: 20,
+            }]])
+            .append_query_results([vec![PermissionModel {
+                id: 20,
+                name: "members:read".into(),
+                description: None,
+            }]])
+    }
+
+    #[actix_web::test]
+    async fn switch_issues_a_token_scoped_to_the_organization() {
+        let state = web::Data::new(test_state(switch_db().into_connection()));
+        let token = state.issue_access_token(1, &UserGrants::default()).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/orgs/4/switch")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        let claims = decode_token(
+            &state.config.jwt,
+            &state.key_ring.active().unwrap(),
+            body["token"].as_str().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(claims.org_id, Some(4));
+        assert_eq!(claims.org_role.as_deref(), Some("member"));
+        assert!(claims.has_org_permission("members:read"));
+    }
+
+    #[actix_web::test]
+    async fn switch_replaces_the_access_token_cookie_of_a_cookie_session() {
+        let state = web::Data::new(test_state(switch_db().into_connection()));
+        let token = state.issue_access_token(1, &UserGrants::default()).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/orgs/4/switch")
+            .cookie(Cookie::new(ACCESS_TOKEN_COOKIE, token))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let cookie = resp
+            .response()
+            .cookies()
+            .find(|cookie| cookie.name() == ACCESS_TOKEN_COOKIE)
+            .unwrap()
+            .into_owned();
+        let claims = decode_token(
+            &state.config.jwt,
+            &state.key_ring.active().unwrap(),
+            cookie.value(),
+        )
+        .unwrap();
+        assert_eq!(claims.org_id, Some(4));
+
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body, serde_json::json!({"organization_id": 4}));
+    }
+
+    #[actix_web::test]
+    async fn members_require_the_organization_to_be_active() {
+        let state = web::Data::new(test_state(
+            MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<RevokedTokenModel>::new()])
+                .into_connection(),
+        ));
+        let grants =
+            UserGrants::default().in_organization(4, "member".into(), ["members:read".to_string()]);
+        let token = state.issue_access_token(1, &grants).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/orgs/5/members")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn switching_to_a_foreign_organization_is_not_found() {
+        let state = web::Data::new(test_state(
+            MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<RevokedTokenModel>::new()])
+                .append_query_results([Vec::<MembershipModel>::new()])
+                .into_connection(),
+        ));
+        let token = state.issue_access_token(1, &UserGrants::default()).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/orgs/4/switch")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+}
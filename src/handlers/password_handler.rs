@@ -0,0 +1,713 @@
+use std::path::Path;
+
+use actix_web::{HttpRequest, HttpResponse, post, web};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::handlers::auth_handler::{issue_token_pair, throttled_response, token_pair_response};
+use crate::handlers::session_handler::sign_out_other_sessions;
+use crate::mail::{Email, deliver};
+use crate::middleware::{AuthenticatedUser, ClientInfo};
+use crate::services::login_throttle_service::{
+    check_login_throttle, clear_login_throttle, record_login_failure, user_key,
+};
+use crate::services::password_reset_service::{
+    find_password_reset, issue_password_reset, redeem_password_reset,
+};
+use crate::services::refresh_token_service::revoke_user_refresh_tokens;
+use crate::services::session_service::mark_user_sessions_revoked;
+use crate::services::token_service::revoke_user_tokens;
+use crate::services::user_service::{
+    Tenant, find_user_by_email, find_user_by_id, update_user_password,
+};
+use crate::state::{AppState, authenticated_by_cookie};
+use crate::utils::password_policy::PasswordRule;
+use crate::utils::{
+    PasswordViolation, TokenTransport, check_password, hash_password, is_breached, normalize_email,
+    verify_password,
+};
+
+#[derive(Deserialize)]
+pub struct ForgotPasswordRequest {
+    email: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResetPasswordRequest {
+    token: String,
+    password: String,
+}
+
+#[derive(Deserialize)]
+pub struct ChangePasswordRequest {
+    /// Required unless the account has no password yet, e.g. one that signs in with passkeys.
+    #[serde(default)]
+    current_password: String,
+    new_password: String,
+}
+
+/// Checks a new password against the configured policy, answering `400` with every violated
+/// rule when it falls short.
+///
+/// The breach list is best effort: if it can't be read the password is let through and the
+/// error logged, so a broken mount doesn't stop everyone from signing up.
+pub(crate) async fn enforce_password_policy(
+    state: &AppState,
+    password: &str,
+    username: &str,
+) -> Result<(), HttpResponse> {
+    let policy = &state.config.password_policy;
+    let mut violations = check_password(policy, password, Some(username));
+
+    // Skip the lookup for passwords that are out anyway, e.g. megabytes long.
+    if let Some(dir) = policy.breached_passwords_dir.clone()
+        && violations.is_empty()
+    {
+        let candidate = password.to_string();
+        match web::block(move || is_breached(Path::new(&dir), &candidate)).await {
+            Ok(Ok(true)) => violations.push(PasswordViolation {
+                rule: PasswordRule::Breached,
+                message: "Appears in a list of breached passwords; choose another.".to_string(),
+            }),
+            Ok(Ok(false)) => {}
+            Ok(Err(e)) => log::warn!("breached password lookup failed: {}", e),
+            Err(e) => log::warn!("breached password lookup failed: {}", e),
+        }
+    }
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(HttpResponse::BadRequest().json(json!({
+            "error": "Password does not meet the password policy.",
+            "violations": violations,
+        })))
+    }
+}
+
+/// Ends every session of the user: refresh tokens and the access tokens issued so far.
+async fn revoke_sessions(state: &AppState, user_id: i32) -> Result<(), HttpResponse> {
+    mark_user_sessions_revoked(&state.db, user_id)
+        .await
+        .map_err(|e| {
+            HttpResponse::InternalServerError().body(format!("DB error on ending sessions: {}", e))
+        })?;
+    revoke_user_refresh_tokens(&state.db, user_id)
+        .await
+        .map_err(|e| {
+            HttpResponse::InternalServerError()
+                .body(format!("DB error on revoking refresh tokens: {}", e))
+        })?;
+    revoke_user_tokens(&state.db, user_id, state.config.jwt.access_token_ttl_secs)
+        .await
+        .map_err(|e| {
+            HttpResponse::InternalServerError().body(format!("DB error on revoking tokens: {}", e))
+        })
+}
+
+/// Looks up the account behind `email` and mails it a reset link, if there is one.
+async fn send_password_reset(state: &AppState, email: &str) -> Result<(), String> {
+    let Some(user) = find_user_by_email(&state.db, Tenant::Global, email)
+        .await
+        .map_err(|e| format!("DB error on fetching user: {}", e))?
+    else {
+        return Ok(());
+    };
+
+    let config = &state.config.password_reset;
+    let token = issue_password_reset(&state.db, user.id, config.ttl_secs)
+        .await
+        .map_err(|e| format!("DB error on issuing reset token: {}", e))?;
+    let message = Email {
+        to: email.to_string(),
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Someone asked to reset the password of {}. Choose a new one within {} minutes:\n\n\
+             {}?token={}\n\nIf that wasn't you, ignore this message; your password stays the same.",
+            user.username,
+            config.ttl_secs / 60,
+            config.reset_url,
+            token
+        ),
+    };
+
+    deliver(&state.mailer, message)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Mails a reset link to the account with this address.
+///
+/// Always answers `202` and does the work in the background, so neither the response nor its
+/// timing reveals whether the account exists.
+#[post("/auth/password/forgot")]
+pub async fn forgot_password(
+    state: web::Data<AppState>,
+    payload: web::Json<ForgotPasswordRequest>,
+) -> HttpResponse {
+    if let Some(email) = normalize_email(&payload.email) {
+        let state = state.clone();
+        actix_web::rt::spawn(async move {
+            if let Err(e) = send_password_reset(&state, &email).await {
+                log::warn!("password reset request failed: {}", e);
+            }
+        });
+    }
+
+    HttpResponse::Accepted().body("If an account uses this address, a reset link is on its way.")
+}
+
+/// Sets a new password with a reset token and ends every session of the account.
+#[post("/auth/password/reset")]
+pub async fn reset_password(
+    state: web::Data<AppState>,
+    payload: web::Json<ResetPasswordRequest>,
+) -> HttpResponse {
+    let reset = match find_password_reset(&state.db, &payload.token).await {
+        Ok(Some(reset)) => reset,
+        Ok(None) => return HttpResponse::BadRequest().body("Invalid or expired reset token."),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on fetching reset token: {}", e));
+        }
+    };
+    let user = match find_user_by_id(&state.db, Tenant::Global, reset.user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::BadRequest().body("Invalid or expired reset token."),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on fetching user: {}", e));
+        }
+    };
+
+    // Checked before the token is spent so a rejected password can be retried.
+    if let Err(response) = enforce_password_policy(&state, &payload.password, &user.username).await
+    {
+        return response;
+    }
+    match redeem_password_reset(&state.db, &reset).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::BadRequest().body("Invalid or expired reset token."),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on redeeming reset token: {}", e));
+        }
+    }
+    let user_id = user.id;
+
+    let password_hash = match hash_password(&state.config.password_hashing, &payload.password) {
+        Ok(hash) => hash,
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Password hashing failed: {}", e));
+        }
+    };
+    if let Err(e) = update_user_password(&state.db, user_id, password_hash).await {
+        return HttpResponse::InternalServerError()
+            .body(format!("DB error on updating password: {}", e));
+    }
+
+    // Whoever knew the old password must not stay logged in.
+    if let Err(response) = revoke_sessions(&state, user_id).await {
+        return response;
+    }
+    // Proving control of the mailbox lifts a lockout from failed logins.
+    if let Err(e) = clear_login_throttle(&state.db, &user_key(&user.username)).await {
+        return HttpResponse::InternalServerError()
+            .body(format!("DB error on clearing login throttle: {}", e));
+    }
+
+    HttpResponse::Ok().body("Password has been reset. Please log in again.")
+}
+
+/// Changes the caller's password and signs out every other session; the caller's stays.
+///
+/// Wrong current passwords count towards the username's login throttle, so a stolen session
+/// can't be used to guess it. Accounts without a password, such as passkey-only ones, set
+/// their first one here. Mounted inside the `/me` scope.
+#[post("/password")]
+pub async fn change_password(
+    req: HttpRequest,
+    client: ClientInfo,
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    payload: web::Json<ChangePasswordRequest>,
+) -> HttpResponse {
+    let account = match find_user_by_id(&state.db, Tenant::Global, user.user_id).await {
+        Ok(Some(account)) => account,
+        Ok(None) => return HttpResponse::NotFound().body("User not found"),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on fetching user: {}", e));
+        }
+    };
+
+    if !account.password.is_empty() {
+        let throttle_config = &state.config.login_throttle;
+        let throttle_key = user_key(&account.username);
+        match check_login_throttle(
+            &state.db,
+            throttle_config,
+            std::slice::from_ref(&throttle_key),
+        )
+        .await
+        {
+            Ok(throttle) => {
+                if let Some(response) = throttled_response(throttle) {
+                    return response;
+                }
+            }
+            Err(e) => {
+                return HttpResponse::InternalServerError()
+                    .body(format!("DB error on checking login throttle: {}", e));
+            }
+        }
+
+        let verification = verify_password(
+            &state.config.password_hashing,
+            &payload.current_password,
+            &account.password,
+        );
+        if !verification.is_match() {
+            if let Err(e) = record_login_failure(
+                &state.db,
+                throttle_config,
+                &throttle_key,
+                throttle_config.user_max_failures,
+            )
+            .await
+            {
+                return HttpResponse::InternalServerError()
+                    .body(format!("DB error on recording failed login: {}", e));
+            }
+            return HttpResponse::Forbidden().body("Current password is incorrect.");
+        }
+    }
+    if let Err(response) =
+        enforce_password_policy(&state, &payload.new_password, &account.username).await
+    {
+        return response;
+    }
+
+    let password_hash = match hash_password(&state.config.password_hashing, &payload.new_password) {
+        Ok(hash) => hash,
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Password hashing failed: {}", e));
+        }
+    };
+    if let Err(e) = update_user_password(&state.db, account.id, password_hash).await {
+        return HttpResponse::InternalServerError()
+            .body(format!("DB error on updating password: {}", e));
+    }
+
+    if let Some(current) = user.claims.sid.as_deref() {
+        return match sign_out_other_sessions(&state, account.id, current).await {
+            Ok(revoked) => HttpResponse::Ok().json(json!({ "revoked": revoked })),
+            Err(response) => response,
+        };
+    }
+
+    // A token outside any session can't be told apart from the others, so everything goes
+    // and the caller starts a new session, in cookies if that's how they came.
+    if let Err(response) = revoke_sessions(&state, account.id).await {
+        return response;
+    }
+    let transport = if authenticated_by_cookie(&req) {
+        TokenTransport::Cookie
+    } else {
+        TokenTransport::Body
+    };
+    match issue_token_pair(&state, &client, account.id, account.active_organization_id).await {
+        Ok(tokens) => token_pair_response(&state, tokens, transport, json!({})),
+        Err(response) => response,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use actix_web::{App, cookie::Cookie, http::StatusCode, http::header, test};
+    use chrono::Utc;
+    use sea_orm::{DatabaseBackend, MockDatabase};
+    use serde_json::json;
+
+    use crate::mail::tests::Outbox;
+    use crate::models::login_throttle::Model as LoginThrottleModel;
+    use crate::models::password_reset::Model as PasswordResetModel;
+    use crate::models::revoked_token::Model as RevokedTokenModel;
+    use crate::models::session::Model as SessionModel;
+    use crate::models::user::Model as UserModel;
+    use crate::routes;
+    use crate::state::tests::{exec, test_config, test_state};
+    use crate::utils::auth_cookie::ACCESS_TOKEN_COOKIE;
+    use crate::utils::{UserGrants, hash_opaque_token};
+
+    use super::*;
+
+    async fn forgot(db: MockDatabase, email: &str) -> (StatusCode, String, Arc<Outbox>) {
+        let outbox = Arc::new(Outbox::default());
+        let state = web::Data::new(AppState {
+            mailer: outbox.clone(),
+            ..test_state(db.into_connection())
+        });
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/auth/password/forgot")
+            .set_json(json!({ "email": email }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let status = resp.status();
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+
+        // Let the background task run.
+        for _ in 0..50 {
+            if !outbox.sent().is_empty() {
+                break;
+            }
+            actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+        }
+        (status, body, outbox)
+    }
+
+    #[actix_web::test]
+    async fn forgot_password_answers_the_same_for_unknown_addresses() {
+        let user = UserModel {
+            id: 4,
+            username: "bob".into(),
+            username_canonical: "bob".into(),
+            password: "hash".into(),
+            active_organization_id: None,
+            email: Some("bob@example.com".into()),
+            email_verified_at: None,
+        };
+        let (known_status, known_body, outbox) = forgot(
+            MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([vec![user]])
+                .append_exec_results([exec(1)]),
+            "bob@example.com",
+        )
+        .await;
+        let (unknown_status, unknown_body, _) = forgot(
+            MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<UserModel>::new()]),
+            "nobody@example.com",
+        )
+        .await;
+
+        assert_eq!(known_status, StatusCode::ACCEPTED);
+        assert_eq!((known_status, known_body), (unknown_status, unknown_body));
+        let sent = outbox.sent();
+        assert_eq!(sent.len(), 1);
+        assert!(sent[0].body.contains("/auth/password/reset?token="));
+    }
+
+    #[actix_web::test]
+    async fn reset_password_revokes_every_session() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![PasswordResetModel {
+                id: 1,
+                user_id: 4,
+                token_hash: hash_opaque_token("token"),
+                expires_at: Utc::now() + chrono::Duration::minutes(5),
+                created_at: Utc::now(),
+                used_at: None,
+            }]])
+            .append_query_results([vec![alice("old password")]])
+            .append_exec_results([
+                exec(1),
+                exec(1),
+                exec(1),
+                exec(1),
+                exec(1),
+                exec(1),
+                exec(1),
+            ])
+            .into_connection();
+        let state = web::Data::new(test_state(db));
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/auth/password/reset")
+            .set_json(json!({"token": "token", "password": "new password"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let log = state.db.clone().into_transaction_log();
+        let statements: Vec<_> = log
+            .iter()
+            .flat_map(|transaction| transaction.statements())
+            .map(|statement| statement.sql.clone())
+            .collect();
+        assert!(
+            statements
+                .iter()
+                .any(|sql| sql.starts_with(r#"UPDATE "refresh_tokens" SET "revoked_at""#))
+        );
+        assert!(
+            statements
+                .iter()
+                .any(|sql| sql.starts_with(r#"INSERT INTO "revoked_tokens""#)
+                    && sql.contains("ON CONFLICT"))
+        );
+    }
+
+    fn session(family_id: &str) -> SessionModel {
+        SessionModel {
+            id: family_id.len() as i32,
+            user_id: 1,
+            family_id: family_id.into(),
+            user_agent: None,
+            ip_address: None,
+            created_at: Utc::now(),
+            last_seen_at: Utc::now(),
+            revoked_at: None,
+        }
+    }
+
+    fn alice(password: &str) -> UserModel {
+        UserModel {
+            id: 1,
+            username: "alice".into(),
+            username_canonical: "alice".into(),
+            password: hash_password(&test_config().password_hashing, password).unwrap(),
+            active_organization_id: None,
+            email: None,
+            email_verified_at: None,
+        }
+    }
+
+    /// Changes Alice's password, signed in through the session `sid` by bearer token or, with
+    /// `by_cookie`, by the access token cookie.
+    async fn change(
+        db: MockDatabase,
+        sid: Option<&str>,
+        by_cookie: bool,
+        body: serde_json::Value,
+    ) -> (StatusCode, bool, serde_json::Value, web::Data<AppState>) {
+        let state = web::Data::new(test_state(db.into_connection()));
+        let token = state
+            .issue_access_token(1, &UserGrants::default().in_session(sid))
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::post().uri("/me/password").set_json(body);
+        let req = if by_cookie {
+            req.cookie(Cookie::new(ACCESS_TOKEN_COOKIE, token))
+        } else {
+            req.insert_header(("Authorization", format!("Bearer {}", token)))
+        };
+        let resp = test::call_service(&app, req.to_request()).await;
+        let status = resp.status();
+        let sets_cookies = resp.headers().contains_key(header::SET_COOKIE);
+        let body = test::read_body(resp).await;
+        (
+            status,
+            sets_cookies,
+            serde_json::from_slice(&body).unwrap_or_default(),
+            state,
+        )
+    }
+
+    fn failures(failures: i32, locked_until: Option<chrono::DateTime<Utc>>) -> LoginThrottleModel {
+        LoginThrottleModel {
+            key: "user:alice".into(),
+            failures,
+            last_failure_at: Utc::now(),
+            locked_until,
+        }
+    }
+
+    #[actix_web::test]
+    async fn change_password_counts_wrong_current_passwords() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<RevokedTokenModel>::new()])
+            .append_query_results([vec![alice("secret")]])
+            .append_query_results([Vec::<LoginThrottleModel>::new()])
+            .append_query_results([vec![failures(1, None)]]);
+
+        let (status, _, _, state) = change(
+            db,
+            Some("current"),
+            false,
+            json!({"current_password": "guess", "new_password": "new password"}),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let log = state.db.clone().into_transaction_log();
+        assert!(
+            log.iter()
+                .flat_map(|transaction| transaction.statements())
+                .any(|statement| statement.sql.starts_with("INSERT INTO login_throttles"))
+        );
+    }
+
+    #[actix_web::test]
+    async fn change_password_refuses_locked_usernames() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<RevokedTokenModel>::new()])
+            .append_query_results([vec![alice("secret")]])
+            .append_query_results([vec![failures(
+                5,
+                Some(Utc::now() + chrono::Duration::minutes(10)),
+            )]]);
+
+        let (status, _, _, _) = change(
+            db,
+            Some("current"),
+            false,
+            json!({"current_password": "secret", "new_password": "new password"}),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[actix_web::test]
+    async fn change_password_reports_policy_violations() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<RevokedTokenModel>::new()])
+            .append_query_results([vec![alice("secret")]])
+            .append_query_results([Vec::<LoginThrottleModel>::new()]);
+
+        let (status, _, body, _) = change(
+            db,
+            Some("current"),
+            false,
+            json!({"current_password": "secret", "new_password": "alice"}),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let rules: Vec<_> = body["violations"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|violation| violation["rule"].as_str().unwrap())
+            .collect();
+        assert_eq!(rules, ["min_length", "contains_username"]);
+    }
+
+    #[actix_web::test]
+    async fn change_password_keeps_the_current_session() {
+        // The password is stored, then the laptop session is ended: the session, its refresh
+        // tokens and its access tokens.
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<RevokedTokenModel>::new()])
+            .append_query_results([vec![alice("secret")]])
+            .append_query_results([Vec::<LoginThrottleModel>::new()])
+            .append_query_results([vec![session("current"), session("laptop")]])
+            .append_exec_results([exec(1), exec(1), exec(1), exec(1)]);
+
+        let (status, sets_cookies, body, state) = change(
+            db,
+            Some("current"),
+            false,
+            json!({"current_password": "secret", "new_password": "new password"}),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({ "revoked": 1 }));
+        assert!(!sets_cookies);
+        let log = state.db.clone().into_transaction_log();
+        let revoked: Vec<_> = log
+            .iter()
+            .flat_map(|transaction| transaction.statements())
+            .filter(|statement| statement.sql.starts_with(r#"INSERT INTO "revoked_tokens""#))
+            .map(|statement| format!("{:?}", statement.values))
+            .collect();
+        assert_eq!(revoked.len(), 1);
+        assert!(revoked[0].contains("session:laptop"));
+    }
+
+    #[actix_web::test]
+    async fn sessionless_cookie_callers_get_new_cookies() {
+        // Everything is revoked, then a new session starts: refresh token and session row.
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<RevokedTokenModel>::new()])
+            .append_query_results([vec![alice("secret")]])
+            .append_query_results([Vec::<LoginThrottleModel>::new()])
+            .append_query_results([Vec::<UserModel>::new()]) // roles
+            .append_exec_results([exec(1), exec(1), exec(1), exec(1), exec(1), exec(1)]);
+
+        let (status, sets_cookies, body, _) = change(
+            db,
+            None,
+            true,
+            json!({"current_password": "secret", "new_password": "new password"}),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(sets_cookies);
+        assert!(body.get("token").is_none());
+    }
+
+    #[actix_web::test]
+    async fn passkey_only_accounts_set_their_first_password() {
+        let passkey_only = UserModel {
+            password: String::new(),
+            ..alice("unused")
+        };
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<RevokedTokenModel>::new()])
+            .append_query_results([vec![passkey_only]])
+            .append_query_results([vec![session("current")]])
+            .append_exec_results([exec(1)]);
+
+        let (status, _, body, _) = change(
+            db,
+            Some("current"),
+            false,
+            json!({"new_password": "new password"}),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({ "revoked": 0 }));
+    }
+
+    #[actix_web::test]
+    async fn reset_password_rejects_unknown_tokens() {
+        let state = web::Data::new(test_state(
+            MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<PasswordResetModel>::new()])
+                .into_connection(),
+        ));
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/auth/password/reset")
+            .set_json(json!({"token": "guess", "password": "new password"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,196 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::middleware::AuthenticatedUser;
+use crate::policy::PolicyContext;
+use crate::state::AppState;
+
+#[derive(Deserialize)]
+pub struct ExplainRequest {
+    pub action: String,
+    #[serde(default)]
+    pub resource: Value,
+    /// Subject attributes to test with; defaults to the caller's own claims.
+    pub subject: Option<Value>,
+    /// Request attributes to test with; defaults to those of the explain call itself.
+    pub request: Option<Value>,
+    /// Evaluates as of this instant instead of now, e.g. to check time-based rules.
+    pub at: Option<DateTime<Utc>>,
+}
+
+/// Evaluates a hypothetical policy check and returns the full decision trace.
+/// Requires `policies:read`; `routes::configure` mounts it behind `require_permission`.
+pub async fn explain(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    body: web::Json<ExplainRequest>,
+) -> HttpResponse {
+    let body = body.into_inner();
+    let mut context = PolicyContext::new(&user.claims)
+        .with_request(&req)
+        .with_resource(body.resource);
+    if let Some(subject) = body.subject {
+        context.subject = subject;
+    }
+    if let Some(request) = body.request {
+        context.request = request;
+    }
+
+    let decision =
+        state
+            .policy
+            .current()
+            .evaluate(&body.action, &context, body.at.unwrap_or_else(Utc::now));
+
+    HttpResponse::Ok().json(decision)
+}
+
+/// Re-reads the policy file now instead of waiting for the reloader. Requires `policies:write`
+/// and a policy that allows `policies:reload`, both checked by guards in `routes::configure`.
+pub async fn reload_policy(state: web::Data<AppState>) -> HttpResponse {
+    match state.policy.reload() {
+        Ok(reloaded) => HttpResponse::Ok().json(json!({
+            "reloaded": reloaded,
+            "rules": state.policy.current().rule_count(),
+        })),
+        Err(e) => HttpResponse::UnprocessableEntity().body(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{App, http::StatusCode, test};
+    use sea_orm::{DatabaseBackend, MockDatabase};
+
+    use crate::models::revoked_token::Model as RevokedTokenModel;
+    use crate::policy::Policy;
+    use crate::routes;
+    use crate::state::tests::test_state;
+    use crate::utils::UserGrants;
+
+    use super::*;
+
+    fn state() -> web::Data<AppState> {
+        let mut state = test_state(
+            MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<RevokedTokenModel>::new()])
+                .into_connection(),
+        );
+        let policy = Policy::parse(
+            r#"
+            [[rules]]
+            name = "business-hours"
+            effect = "allow"
+            actions = ["documents:edit"]
+            when = ["'editor' in subject.roles", "env.hour >= 9", "env.hour < 17"]
+
+            [[rules]]
+            name = "admins-reload"
+            effect = "allow"
+            actions = ["policies:reload"]
+            when = ["'admin' in subject.roles"]
+            "#,
+        )
+        .unwrap();
+        state.policy = Arc::new(policy.into());
+
+        web::Data::new(state)
+    }
+
+    #[actix_web::test]
+    async fn explain_traces_the_decision() {
+        let state = state();
+        let token = state
+            .issue_access_token(
+                1,
+                &UserGrants::new(["admin".to_string()], ["policies:read".to_string()]),
+            )
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/admin/policy/explain")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(json!({
+                "action": "documents:edit",
+                "subject": { "roles": ["editor"] },
+                "at": "2025-12-10T20:00:00Z",
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let decision: Value = test::read_body_json(resp).await;
+        assert_eq!(decision["allowed"], false);
+        assert_eq!(decision["rule"], Value::Null);
+        assert_eq!(decision["attributes"]["request"]["method"], "POST");
+        let conditions = &decision["rules"][0]["conditions"];
+        assert_eq!(conditions[0]["passed"], true);
+        assert_eq!(conditions[2]["condition"], "env.hour < 17");
+        assert_eq!(conditions[2]["left"], 20);
+        assert_eq!(conditions[2]["passed"], false);
+    }
+
+    #[actix_web::test]
+    async fn reload_requires_policies_write() {
+        let state = state();
+        let token = state
+            .issue_access_token(
+                1,
+                &UserGrants::new(["admin".to_string()], ["policies:read".to_string()]),
+            )
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/admin/policy/reload")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    async fn reload_status(role: &str) -> StatusCode {
+        let state = state();
+        let token = state
+            .issue_access_token(
+                1,
+                &UserGrants::new([role.to_string()], ["policies:write".to_string()]),
+            )
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/admin/policy/reload")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        test::call_service(&app, req).await.status()
+    }
+
+    #[actix_web::test]
+    async fn reload_requires_the_policy_to_allow_it() {
+        assert_eq!(reload_status("admin").await, StatusCode::OK);
+        assert_eq!(reload_status("operator").await, StatusCode::FORBIDDEN);
+    }
+}
@@ -0,0 +1,263 @@
+use actix_web::{HttpResponse, delete, get, web};
+use serde_json::json;
+
+use crate::middleware::AuthenticatedUser;
+use crate::models::session::Model as SessionModel;
+use crate::services::session_service::{end_session, list_sessions as load_sessions};
+use crate::state::AppState;
+
+/// Path segment of `DELETE /me/sessions/{id}` that signs out every session but the caller's.
+const OTHER_SESSIONS: &str = "others";
+
+/// Signs one of the caller's sessions out.
+async fn sign_out(state: &AppState, session: &SessionModel) -> Result<(), HttpResponse> {
+    end_session(
+        &state.db,
+        session.user_id,
+        &session.family_id,
+        state.config.jwt.access_token_ttl_secs,
+    )
+    .await
+    .map_err(|e| {
+        HttpResponse::InternalServerError().body(format!("DB error on ending session: {}", e))
+    })
+}
+
+/// Loads the user's live sessions.
+async fn sessions_of(state: &AppState, user_id: i32) -> Result<Vec<SessionModel>, HttpResponse> {
+    load_sessions(&state.db, user_id, state.config.jwt.refresh_token_ttl_secs)
+        .await
+        .map_err(|e| {
+            HttpResponse::InternalServerError().body(format!("DB error on loading sessions: {}", e))
+        })
+}
+
+/// Signs out every session of the user except `current`, returning how many there were.
+pub(crate) async fn sign_out_other_sessions(
+    state: &AppState,
+    user_id: i32,
+    current: &str,
+) -> Result<usize, HttpResponse> {
+    let sessions = sessions_of(state, user_id).await?;
+    let others: Vec<_> = sessions
+        .iter()
+        .filter(|session| session.family_id != current)
+        .collect();
+    for session in &others {
+        sign_out(state, session).await?;
+    }
+    Ok(others.len())
+}
+
+/// Lists where the caller is signed in. Mounted inside the `/me` scope.
+#[get("/sessions")]
+pub async fn list_sessions(state: web::Data<AppState>, user: AuthenticatedUser) -> HttpResponse {
+    let sessions = match sessions_of(&state, user.user_id).await {
+        Ok(sessions) => sessions,
+        Err(response) => return response,
+    };
+
+    HttpResponse::Ok().json(
+        sessions
+            .iter()
+            .map(|session| {
+                json!({
+                    "id": session.id,
+                    "user_agent": session.user_agent,
+                    "ip_address": session.ip_address,
+                    "created_at": session.created_at,
+                    "last_seen_at": session.last_seen_at,
+                    "current": user.claims.sid.as_deref() == Some(session.family_id.as_str()),
+                })
+            })
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// Signs out one of the caller's sessions, or with `others` every session but the current.
+#[delete("/sessions/{id}")]
+pub async fn revoke_session(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let target = path.into_inner();
+    if target == OTHER_SESSIONS {
+        let Some(current) = user.claims.sid.as_deref() else {
+            return HttpResponse::BadRequest().body("This token doesn't belong to a session.");
+        };
+        return match sign_out_other_sessions(&state, user.user_id, current).await {
+            Ok(revoked) => HttpResponse::Ok().json(json!({ "revoked": revoked })),
+            Err(response) => response,
+        };
+    }
+
+    let sessions = match sessions_of(&state, user.user_id).await {
+        Ok(sessions) => sessions,
+        Err(response) => return response,
+    };
+
+    let Some(session) = target
+        .parse::<i32>()
+        .ok()
+        .and_then(|id| sessions.iter().find(|session| session.id == id))
+    else {
+        return HttpResponse::NotFound().body("Session not found.");
+    };
+    match sign_out(&state, session).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(response) => response,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, http::StatusCode, test};
+    use chrono::{Duration, Utc};
+    use sea_orm::{DatabaseBackend, MockDatabase};
+    use serde_json::Value;
+
+    use crate::models::revoked_token::Model as RevokedTokenModel;
+    use crate::routes;
+    use crate::state::tests::{exec, test_state};
+    use crate::utils::UserGrants;
+
+    use super::*;
+
+    fn session(id: i32, family_id: &str) -> SessionModel {
+        let now = Utc::now();
+        SessionModel {
+            id,
+            user_id: 1,
+            family_id: family_id.into(),
+            user_agent: Some("curl/8.5.0".into()),
+            ip_address: Some("203.0.113.7".into()),
+            created_at: now - Duration::days(1),
+            last_seen_at: now - Duration::minutes(id.into()),
+            revoked_at: None,
+        }
+    }
+
+    /// Calls the endpoint as user 1 signed in through the session with family `current`.
+    async fn call(
+        db: MockDatabase,
+        req: test::TestRequest,
+    ) -> (StatusCode, Vec<u8>, web::Data<AppState>) {
+        let state = web::Data::new(test_state(db.into_connection()));
+        let token = state
+            .issue_access_token(1, &UserGrants::default().in_session(Some("current")))
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let req = req
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let status = resp.status();
+        let body = test::read_body(resp).await.to_vec();
+        (status, body, state)
+    }
+
+    /// The SQL run while handling the request, each with its bound values.
+    fn statements(state: web::Data<AppState>) -> Vec<String> {
+        state
+            .db
+            .clone()
+            .into_transaction_log()
+            .iter()
+            .flat_map(|transaction| transaction.statements())
+            .map(|statement| format!("{} {:?}", statement.sql, statement.values))
+            .collect()
+    }
+
+    /// The sessions whose access tokens the request revoked.
+    fn revoked_sessions(statements: &[String]) -> Vec<&str> {
+        ["current", "laptop", "phone"]
+            .into_iter()
+            .filter(|sid| {
+                statements.iter().any(|sql| {
+                    sql.starts_with(r#"INSERT INTO "revoked_tokens""#)
+                        && sql.contains(&format!("session:{}", sid))
+                })
+            })
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn list_marks_the_current_session() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<RevokedTokenModel>::new()])
+            .append_query_results([vec![session(1, "current"), session(2, "laptop")]]);
+
+        let (status, body, _) = call(db, test::TestRequest::get().uri("/me/sessions")).await;
+
+        assert_eq!(status, StatusCode::OK);
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        let sessions = body.as_array().unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0]["current"], true);
+        assert_eq!(sessions[1]["current"], false);
+        assert_eq!(sessions[1]["user_agent"], "curl/8.5.0");
+        assert!(sessions[1].get("family_id").is_none());
+    }
+
+    #[actix_web::test]
+    async fn revoking_a_session_ends_its_tokens() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<RevokedTokenModel>::new()])
+            .append_query_results([vec![session(1, "current"), session(2, "laptop")]])
+            .append_exec_results([exec(1), exec(1), exec(1)]);
+
+        let (status, _, state) = call(db, test::TestRequest::delete().uri("/me/sessions/2")).await;
+
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let statements = statements(state);
+        assert!(
+            statements
+                .iter()
+                .any(|sql| sql.starts_with(r#"UPDATE "sessions" SET "revoked_at""#))
+        );
+        assert!(
+            statements
+                .iter()
+                .any(|sql| sql.starts_with(r#"UPDATE "refresh_tokens" SET "revoked_at""#))
+        );
+        assert_eq!(revoked_sessions(&statements), ["laptop"]);
+    }
+
+    #[actix_web::test]
+    async fn revoking_others_keeps_the_current_session() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<RevokedTokenModel>::new()])
+            .append_query_results([vec![
+                session(1, "current"),
+                session(2, "laptop"),
+                session(3, "phone"),
+            ]])
+            .append_exec_results([exec(1), exec(1), exec(1), exec(1), exec(1), exec(1)]);
+
+        let (status, body, state) =
+            call(db, test::TestRequest::delete().uri("/me/sessions/others")).await;
+
+        assert_eq!(status, StatusCode::OK);
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["revoked"], 2);
+        assert_eq!(revoked_sessions(&statements(state)), ["laptop", "phone"]);
+    }
+
+    #[actix_web::test]
+    async fn revoking_an_unknown_session_is_not_found() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<RevokedTokenModel>::new()])
+            .append_query_results([vec![session(1, "current")]]);
+
+        let (status, _, _) = call(db, test::TestRequest::delete().uri("/me/sessions/9")).await;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
@@ -1,29 +1,25 @@
-use actix_web::{HttpRequest, HttpResponse, Responder, ResponseError, get, web};
+use actix_web::{HttpResponse, Responder, get, web};
 use serde_json::json;
 
-use crate::state::{self, AppState};
+use crate::middleware::AuthenticatedUser;
+use crate::services::user_service::{Tenant, find_user_by_id};
+use crate::state::AppState;
 
 #[get("/")]
 pub async fn index() -> impl Responder {
     HttpResponse::Ok().body("Welcome to home page.")
 }
 
-#[get("/me")]
-pub async fn profile(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
-    let token = match state::bearer_token(&req) {
-        Ok(token) => token,
-        Err(err) => return err.error_response(),
-    };
-
-    let claims = match state.validate_token(&token) {
-        Ok(claims) => claims,
-        Err(err) => return err.error_response(),
-    };
-
-    match crate::services::user_service::find_user_by_id(&state.db, claims.sub).await {
+/// Mounted inside the `/me` scope, which is wrapped in [`crate::middleware::JwtAuth`].
+#[get("")]
+pub async fn profile(state: web::Data<AppState>, user: AuthenticatedUser) -> HttpResponse {
+    match find_user_by_id(&state.db, Tenant::Global, user.user_id).await {
         Ok(Some(user)) => HttpResponse::Ok().json(json!({
             "id": user.id,
             "username": user.username,
+            "active_organization_id": user.active_organization_id,
+            "email": user.email,
+            "email_verified": user.email_verified_at.is_some(),
         })),
         Ok(None) => HttpResponse::NotFound().body("User not found"),
         Err(e) => {
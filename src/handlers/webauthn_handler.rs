@@ -0,0 +1,775 @@
+use actix_web::{HttpResponse, delete, get, post, web};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::config::WebauthnConfig;
+use crate::handlers::auth_handler::{create_account, issue_token_pair, token_pair_response};
+use crate::handlers::email_handler::parse_email;
+use crate::middleware::{AuthenticatedUser, ClientInfo};
+use crate::models::webauthn_challenge::Model as WebauthnChallengeModel;
+use crate::models::webauthn_credential::Model as WebauthnCredentialModel;
+use crate::services::user_service::{Tenant, find_user_by_id, find_user_by_username};
+use crate::services::webauthn_service::{
+    Ceremony, ChallengeSubject, PasskeyRemoval, add_webauthn_credential,
+    delete_webauthn_credential, find_webauthn_challenge, find_webauthn_credential,
+    issue_webauthn_challenge, list_webauthn_credentials, record_webauthn_use,
+    spend_webauthn_challenge,
+};
+use crate::state::AppState;
+use crate::utils::auth_cookie::TransportQuery;
+use crate::utils::webauthn::{
+    RegisteredCredential, SUPPORTED_ALGORITHMS, WebauthnError, decode_base64url, encode_base64url,
+    parse_client_data, verify_assertion, verify_registration,
+};
+use crate::utils::{generate_opaque_token, validate_username};
+
+/// Label for passkeys registered without one.
+const DEFAULT_PASSKEY_NAME: &str = "Passkey";
+
+#[derive(Deserialize)]
+pub struct PasskeySignupRequest {
+    username: String,
+    /// Optional unless `EMAIL_VERIFICATION_REQUIRED` is set.
+    email: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct PasskeyLoginRequest {
+    /// Leave out to let the authenticator offer its discoverable passkeys.
+    username: Option<String>,
+}
+
+/// The `PublicKeyCredential` from `navigator.credentials.create()`, in its JSON form.
+#[derive(Deserialize)]
+pub struct RegistrationResponse {
+    response: AttestationResponse,
+    /// Label shown in the passkey list.
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    client_data_json: String,
+    #[serde(rename = "attestationObject")]
+    attestation_object: String,
+}
+
+/// The `PublicKeyCredential` from `navigator.credentials.get()`, in its JSON form.
+#[derive(Deserialize)]
+pub struct AuthenticationResponse {
+    /// Base64url credential ID.
+    id: String,
+    response: AssertionResponse,
+}
+
+#[derive(Deserialize)]
+struct AssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    client_data_json: String,
+    #[serde(rename = "authenticatorData")]
+    authenticator_data: String,
+    signature: String,
+    #[serde(rename = "userHandle")]
+    user_handle: Option<String>,
+}
+
+fn credential_descriptors(credentials: &[WebauthnCredentialModel]) -> Vec<Value> {
+    credentials
+        .iter()
+        .map(|credential| json!({"type": "public-key", "id": credential.credential_id}))
+        .collect()
+}
+
+fn user_verification(config: &WebauthnConfig) -> &'static str {
+    if config.require_user_verification {
+        "required"
+    } else {
+        "preferred"
+    }
+}
+
+/// `PublicKeyCredentialCreationOptions` for `navigator.credentials.create()`.
+fn creation_options(
+    config: &WebauthnConfig,
+    challenge: &str,
+    user_handle: &str,
+    username: &str,
+    existing: &[WebauthnCredentialModel],
+) -> Value {
+    json!({
+        "publicKey": {
+            "challenge": challenge,
+            "rp": {"id": config.rp_id, "name": config.rp_name},
+            "user": {"id": user_handle, "name": username, "displayName": username},
+            "pubKeyCredParams": SUPPORTED_ALGORITHMS
+                .iter()
+                .map(|alg| json!({"type": "public-key", "alg": alg}))
+                .collect::<Vec<_>>(),
+            "timeout": config.challenge_ttl_secs * 1000,
+            "attestation": "none",
+            "authenticatorSelection": {
+                "residentKey": "required",
+                "requireResidentKey": true,
+                "userVerification": user_verification(config),
+            },
+            "excludeCredentials": credential_descriptors(existing),
+        }
+    })
+}
+
+/// `PublicKeyCredentialRequestOptions` for `navigator.credentials.get()`.
+fn request_options(
+    config: &WebauthnConfig,
+    challenge: &str,
+    allowed: &[WebauthnCredentialModel],
+) -> Value {
+    json!({
+        "publicKey": {
+            "challenge": challenge,
+            "rpId": config.rp_id,
+            "timeout": config.challenge_ttl_secs * 1000,
+            "userVerification": user_verification(config),
+            "allowCredentials": credential_descriptors(allowed),
+        }
+    })
+}
+
+fn credential_json(credential: &WebauthnCredentialModel) -> Value {
+    json!({
+        "id": credential.id,
+        "name": credential.name,
+        "created_at": credential.created_at,
+        "last_used_at": credential.last_used_at,
+    })
+}
+
+/// Verifies a registration response and spends its challenge, which must have been issued
+/// for `owner` (`None` for a passkey sign-up).
+async fn finish_registration(
+    state: &AppState,
+    payload: &RegistrationResponse,
+    owner: Option<i32>,
+) -> Result<(WebauthnChallengeModel, RegisteredCredential), HttpResponse> {
+    let bad_request = |e: WebauthnError| HttpResponse::BadRequest().body(e.to_string());
+    let client_data_json =
+        decode_base64url(&payload.response.client_data_json).map_err(bad_request)?;
+    let client_data = parse_client_data(&client_data_json).map_err(bad_request)?;
+
+    let pending =
+        match find_webauthn_challenge(&state.db, &client_data.challenge, Ceremony::Registration)
+            .await
+        {
+            Ok(Some(pending)) if pending.user_id == owner => pending,
+            Ok(_) => return Err(HttpResponse::BadRequest().body("Unknown or expired challenge.")),
+            Err(e) => {
+                return Err(HttpResponse::InternalServerError()
+                    .body(format!("DB error on loading WebAuthn challenge: {}", e)));
+            }
+        };
+
+    let attestation_object =
+        decode_base64url(&payload.response.attestation_object).map_err(bad_request)?;
+    let credential = verify_registration(&state.config.webauthn, &client_data, &attestation_object)
+        .map_err(bad_request)?;
+
+    let credential_id = encode_base64url(&credential.credential_id);
+    match find_webauthn_credential(&state.db, &credential_id).await {
+        Ok(Some(_)) => {
+            return Err(HttpResponse::Conflict().body("Passkey is already registered."));
+        }
+        Ok(None) => {}
+        Err(e) => {
+            return Err(HttpResponse::InternalServerError()
+                .body(format!("DB error on checking passkey: {}", e)));
+        }
+    }
+    match spend_webauthn_challenge(&state.db, &pending).await {
+        Ok(true) => Ok((pending, credential)),
+        Ok(false) => Err(HttpResponse::BadRequest().body("Unknown or expired challenge.")),
+        Err(e) => Err(HttpResponse::InternalServerError()
+            .body(format!("DB error on spending WebAuthn challenge: {}", e))),
+    }
+}
+
+/// Starts signing up with a passkey instead of a password.
+#[post("/auth/webauthn/register/start")]
+pub async fn start_passkey_signup(
+    state: web::Data<AppState>,
+    payload: web::Json<PasskeySignupRequest>,
+) -> HttpResponse {
+    let email = match parse_email(&state, payload.email.as_deref()) {
+        Ok(email) => email,
+        Err(reason) => return HttpResponse::BadRequest().body(reason),
+    };
+    let username = match validate_username(&payload.username) {
+        Ok(username) => username,
+        Err(reason) => return HttpResponse::BadRequest().body(reason.to_string()),
+    };
+    // Checked again when the account is created; this just fails before the ceremony.
+    match find_user_by_username(&state.db, Tenant::Global, &username).await {
+        Ok(Some(_)) => return HttpResponse::BadRequest().body("Username already exists."),
+        Ok(None) => {}
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on checking username: {}", e));
+        }
+    }
+
+    let user_handle = generate_opaque_token();
+    let config = &state.config.webauthn;
+    let subject = ChallengeSubject {
+        username: Some(username.clone()),
+        email,
+        user_handle: Some(user_handle.clone()),
+        ..Default::default()
+    };
+    match issue_webauthn_challenge(
+        &state.db,
+        Ceremony::Registration,
+        subject,
+        config.challenge_ttl_secs,
+    )
+    .await
+    {
+        Ok(challenge) => HttpResponse::Ok().json(creation_options(
+            config,
+            &challenge,
+            &user_handle,
+            &username,
+            &[],
+        )),
+        Err(e) => HttpResponse::InternalServerError()
+            .body(format!("DB error on issuing WebAuthn challenge: {}", e)),
+    }
+}
+
+/// Creates a passwordless account from a verified passkey; answers like `POST /auth/register`.
+#[post("/auth/webauthn/register/finish")]
+pub async fn finish_passkey_signup(
+    client: ClientInfo,
+    state: web::Data<AppState>,
+    query: web::Query<TransportQuery>,
+    payload: web::Json<RegistrationResponse>,
+) -> HttpResponse {
+    let (pending, credential) = match finish_registration(&state, &payload, None).await {
+        Ok(verified) => verified,
+        Err(response) => return response,
+    };
+    let (Some(username), Some(user_handle)) = (pending.username, pending.user_handle) else {
+        return HttpResponse::BadRequest().body("Unknown or expired challenge.");
+    };
+
+    let created_user = match create_account(&state, &username, None, pending.email).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let name = payload
+        .name
+        .clone()
+        .unwrap_or_else(|| DEFAULT_PASSKEY_NAME.to_string());
+    if let Err(e) =
+        add_webauthn_credential(&state.db, created_user.id, user_handle, name, &credential).await
+    {
+        return HttpResponse::InternalServerError()
+            .body(format!("DB error on storing passkey: {}", e));
+    }
+    let user = json!({
+        "id": created_user.id,
+        "username": created_user.username,
+        "email": created_user.email,
+    });
+
+    if state.config.email_verification.required {
+        return HttpResponse::Accepted().json(json!({
+            "user": user,
+            "email_verification": "pending",
+        }));
+    }
+
+    match issue_token_pair(&state, &client, created_user.id, None).await {
+        Ok(tokens) => token_pair_response(&state, tokens, query.transport, json!({ "user": user })),
+        Err(response) => response,
+    }
+}
+
+/// Starts adding a passkey to the caller's account. Mounted inside the `/me` scope.
+#[post("/webauthn/register/start")]
+pub async fn start_passkey_registration(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+) -> HttpResponse {
+    let account = match find_user_by_id(&state.db, Tenant::Global, user.user_id).await {
+        Ok(Some(account)) => account,
+        Ok(None) => return HttpResponse::NotFound().body("User not found"),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on fetching user: {}", e));
+        }
+    };
+    let existing = match list_webauthn_credentials(&state.db, account.id).await {
+        Ok(existing) => existing,
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on loading passkeys: {}", e));
+        }
+    };
+
+    // Authenticators key discoverable passkeys by user handle, so keep the first one's.
+    let user_handle = existing
+        .first()
+        .map(|credential| credential.user_handle.clone())
+        .unwrap_or_else(generate_opaque_token);
+    let config = &state.config.webauthn;
+    let subject = ChallengeSubject {
+        user_id: Some(account.id),
+        user_handle: Some(user_handle.clone()),
+        ..Default::default()
+    };
+    match issue_webauthn_challenge(
+        &state.db,
+        Ceremony::Registration,
+        subject,
+        config.challenge_ttl_secs,
+    )
+    .await
+    {
+        Ok(challenge) => HttpResponse::Ok().json(creation_options(
+            config,
+            &challenge,
+            &user_handle,
+            &account.username,
+            &existing,
+        )),
+        Err(e) => HttpResponse::InternalServerError()
+            .body(format!("DB error on issuing WebAuthn challenge: {}", e)),
+    }
+}
+
+/// Stores a passkey for the caller once the authenticator's response verifies.
+#[post("/webauthn/register/finish")]
+pub async fn finish_passkey_registration(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    payload: web::Json<RegistrationResponse>,
+) -> HttpResponse {
+    let (pending, credential) =
+        match finish_registration(&state, &payload, Some(user.user_id)).await {
+            Ok(verified) => verified,
+            Err(response) => return response,
+        };
+    let Some(user_handle) = pending.user_handle else {
+        return HttpResponse::BadRequest().body("Unknown or expired challenge.");
+    };
+
+    let name = payload
+        .name
+        .clone()
+        .unwrap_or_else(|| DEFAULT_PASSKEY_NAME.to_string());
+    match add_webauthn_credential(&state.db, user.user_id, user_handle, name, &credential).await {
+        Ok(stored) => HttpResponse::Created().json(credential_json(&stored)),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("DB error on storing passkey: {}", e))
+        }
+    }
+}
+
+#[get("/webauthn/credentials")]
+pub async fn list_passkeys(state: web::Data<AppState>, user: AuthenticatedUser) -> HttpResponse {
+    match list_webauthn_credentials(&state.db, user.user_id).await {
+        Ok(credentials) => {
+            HttpResponse::Ok().json(credentials.iter().map(credential_json).collect::<Vec<_>>())
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("DB error on loading passkeys: {}", e))
+        }
+    }
+}
+
+/// Removes one of the caller's passkeys, unless it is the only way into a passwordless
+/// account.
+#[delete("/webauthn/credentials/{id}")]
+pub async fn delete_passkey(
+    state: web::Data<AppState>,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> HttpResponse {
+    let credential_id = path.into_inner();
+    let account = match find_user_by_id(&state.db, Tenant::Global, user.user_id).await {
+        Ok(Some(account)) => account,
+        Ok(None) => return HttpResponse::NotFound().body("User not found"),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on fetching user: {}", e));
+        }
+    };
+
+    match delete_webauthn_credential(
+        &state.db,
+        account.id,
+        credential_id,
+        account.password.is_empty(),
+    )
+    .await
+    {
+        Ok(PasskeyRemoval::Removed) => HttpResponse::NoContent().finish(),
+        Ok(PasskeyRemoval::NotFound) => HttpResponse::NotFound().body("Passkey not found."),
+        Ok(PasskeyRemoval::LastPasskey) => HttpResponse::Conflict()
+            .body("Cannot remove the only passkey of an account without a password."),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("DB error on deleting passkey: {}", e))
+        }
+    }
+}
+
+/// Starts a passkey login, for one user's passkeys or, without a username, any
+/// discoverable passkey for this site.
+#[post("/auth/webauthn/login/start")]
+pub async fn start_passkey_login(
+    state: web::Data<AppState>,
+    payload: web::Json<PasskeyLoginRequest>,
+) -> HttpResponse {
+    let mut subject = ChallengeSubject::default();
+    let mut allowed = Vec::new();
+    if let Some(username) = &payload.username {
+        match find_user_by_username(&state.db, Tenant::Global, username).await {
+            Ok(Some(user)) => {
+                allowed = match list_webauthn_credentials(&state.db, user.id).await {
+                    Ok(credentials) => credentials,
+                    Err(e) => {
+                        return HttpResponse::InternalServerError()
+                            .body(format!("DB error on loading passkeys: {}", e));
+                    }
+                };
+                subject.user_id = Some(user.id);
+            }
+            // Unknown usernames get the same answer as users without passkeys.
+            Ok(None) => {}
+            Err(e) => {
+                return HttpResponse::InternalServerError()
+                    .body(format!("DB error on fetching user: {}", e));
+            }
+        }
+    }
+
+    let config = &state.config.webauthn;
+    match issue_webauthn_challenge(
+        &state.db,
+        Ceremony::Authentication,
+        subject,
+        config.challenge_ttl_secs,
+    )
+    .await
+    {
+        Ok(challenge) => HttpResponse::Ok().json(request_options(config, &challenge, &allowed)),
+        Err(e) => HttpResponse::InternalServerError()
+            .body(format!("DB error on issuing WebAuthn challenge: {}", e)),
+    }
+}
+
+/// Exchanges a signed login challenge for a token pair. Passkeys stand in for both the
+/// password and the second factor.
+#[post("/auth/webauthn/login/finish")]
+pub async fn finish_passkey_login(
+    client: ClientInfo,
+    state: web::Data<AppState>,
+    query: web::Query<TransportQuery>,
+    payload: web::Json<AuthenticationResponse>,
+) -> HttpResponse {
+    let response = &payload.response;
+    let decoded = (|| -> Result<_, WebauthnError> {
+        let client_data_json = decode_base64url(&response.client_data_json)?;
+        let client_data = parse_client_data(&client_data_json)?;
+        let authenticator_data = decode_base64url(&response.authenticator_data)?;
+        let signature = decode_base64url(&response.signature)?;
+        Ok((client_data_json, client_data, authenticator_data, signature))
+    })();
+    let (client_data_json, client_data, authenticator_data, signature) = match decoded {
+        Ok(decoded) => decoded,
+        Err(e) => return HttpResponse::BadRequest().body(e.to_string()),
+    };
+
+    let pending =
+        match find_webauthn_challenge(&state.db, &client_data.challenge, Ceremony::Authentication)
+            .await
+        {
+            Ok(Some(pending)) => pending,
+            Ok(None) => return HttpResponse::Unauthorized().body("Unknown or expired challenge."),
+            Err(e) => {
+                return HttpResponse::InternalServerError()
+                    .body(format!("DB error on loading WebAuthn challenge: {}", e));
+            }
+        };
+    let credential = match find_webauthn_credential(&state.db, &payload.id).await {
+        Ok(Some(credential))
+            if pending
+                .user_id
+                .is_none_or(|user_id| user_id == credential.user_id)
+                && response
+                    .user_handle
+                    .as_ref()
+                    .is_none_or(|handle| *handle == credential.user_handle) =>
+        {
+            credential
+        }
+        Ok(_) => return HttpResponse::Unauthorized().body("Unknown passkey."),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on loading passkey: {}", e));
+        }
+    };
+
+    let sign_count = match verify_assertion(
+        &state.config.webauthn,
+        &client_data,
+        &client_data_json,
+        &authenticator_data,
+        &signature,
+        &credential.public_key,
+        u32::try_from(credential.sign_count).unwrap_or(u32::MAX),
+    ) {
+        Ok(sign_count) => sign_count,
+        Err(e) => return HttpResponse::Unauthorized().body(e.to_string()),
+    };
+
+    match spend_webauthn_challenge(&state.db, &pending).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Unauthorized().body("Unknown or expired challenge."),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on spending WebAuthn challenge: {}", e));
+        }
+    }
+    match record_webauthn_use(&state.db, &credential, sign_count).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return HttpResponse::Unauthorized().body("Passkey was used concurrently; try again.");
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on recording passkey use: {}", e));
+        }
+    }
+
+    let user = match find_user_by_id(&state.db, Tenant::Global, credential.user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::Unauthorized().body("Unknown passkey."),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("DB error on fetching user: {}", e));
+        }
+    };
+    if state.config.email_verification.required && user.email_verified_at.is_none() {
+        return HttpResponse::Forbidden().body("Email address not verified.");
+    }
+
+    match issue_token_pair(&state, &client, user.id, user.active_organization_id).await {
+        Ok(tokens) => token_pair_response(&state, tokens, query.transport, json!({})),
+        Err(response) => response,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, http::StatusCode, test};
+    use chrono::{Duration, Utc};
+    use sea_orm::{DatabaseBackend, MockDatabase};
+
+    use crate::models::revoked_token::Model as RevokedTokenModel;
+    use crate::models::role::Model as RoleModel;
+    use crate::models::role_permission::Model as RolePermissionModel;
+    use crate::models::user::Model as UserModel;
+    use crate::models::user_role::Model as UserRoleModel;
+    use crate::routes;
+    use crate::state::tests::{exec, test_config, test_state};
+    use crate::utils::UserGrants;
+    use crate::utils::hash_opaque_token;
+    use crate::utils::webauthn::tests::SoftAuthenticator;
+
+    use super::*;
+
+    fn bob() -> UserModel {
+        UserModel {
+            id: 9,
+            username: "bob".into(),
+            username_canonical: "bob".into(),
+            password: String::new(),
+            active_organization_id: None,
+            email: None,
+            email_verified_at: None,
+        }
+    }
+
+    fn pending(ceremony: &str, user_id: Option<i32>) -> WebauthnChallengeModel {
+        WebauthnChallengeModel {
+            id: 2,
+            challenge_hash: hash_opaque_token("challenge"),
+            ceremony: ceremony.into(),
+            user_id,
+            username: user_id.is_none().then(|| "bob".into()),
+            email: None,
+            user_handle: Some("handle".into()),
+            expires_at: Utc::now() + Duration::minutes(5),
+            created_at: Utc::now(),
+            used_at: None,
+        }
+    }
+
+    fn stored(authenticator: &SoftAuthenticator) -> WebauthnCredentialModel {
+        WebauthnCredentialModel {
+            id: 5,
+            user_id: 9,
+            credential_id: encode_base64url(&authenticator.credential_id),
+            public_key: authenticator.cose_key(),
+            sign_count: i64::from(authenticator.sign_count),
+            user_handle: "handle".into(),
+            name: DEFAULT_PASSKEY_NAME.into(),
+            created_at: Utc::now(),
+            last_used_at: None,
+        }
+    }
+
+    async fn send(db: MockDatabase, req: test::TestRequest) -> (StatusCode, Value) {
+        let state = web::Data::new(test_state(db.into_connection()));
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(routes::configure),
+        )
+        .await;
+
+        let resp = test::call_service(&app, req.to_request()).await;
+        let status = resp.status();
+        let body = test::read_body(resp).await;
+
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    #[actix_web::test]
+    async fn signup_creates_a_passwordless_account() {
+        let mut authenticator = SoftAuthenticator::new();
+        let mut created = stored(&authenticator);
+        created.sign_count = 1;
+        let user_role = RoleModel {
+            id: 2,
+            name: "user".into(),
+            description: None,
+        };
+        // Challenge, duplicate check, username check, user insert, default role, passkey
+        // insert, then the token's grants; the challenge, role and refresh token are written.
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![pending("registration", None)]])
+            .append_query_results([Vec::<WebauthnCredentialModel>::new()])
+            .append_query_results([vec![], vec![bob()]])
+            .append_query_results([vec![user_role.clone()]])
+            .append_query_results([vec![created]])
+            .append_query_results([vec![UserRoleModel {
+                user_id: 9,
+                role_id: 2,
+            }]])
+            .append_query_results([vec![user_role]])
+            .append_query_results([Vec::<RolePermissionModel>::new()])
+            .append_exec_results([exec(1), exec(1), exec(1), exec(1)]);
+
+        let response = authenticator.register(&test_config().webauthn, "challenge");
+        let (status, body) = send(
+            db,
+            test::TestRequest::post()
+                .uri("/auth/webauthn/register/finish")
+                .set_json(response),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["user"]["username"], "bob");
+        assert!(body["token"].is_string());
+    }
+
+    #[actix_web::test]
+    async fn login_exchanges_a_signed_challenge_for_tokens() {
+        let mut authenticator = SoftAuthenticator::new();
+        let credential = stored(&authenticator);
+        // The challenge is spent, the counter recorded and a refresh token stored.
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![pending("authentication", None)]])
+            .append_query_results([vec![credential]])
+            .append_query_results([vec![bob()]])
+            .append_query_results([Vec::<UserRoleModel>::new()])
+            .append_exec_results([exec(1), exec(1), exec(1), exec(1)]);
+
+        let response = authenticator.authenticate(&test_config().webauthn, "challenge");
+        let (status, body) = send(
+            db,
+            test::TestRequest::post()
+                .uri("/auth/webauthn/login/finish")
+                .set_json(response),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(body["token"].is_string());
+        assert!(body["refresh_token"].is_string());
+    }
+
+    #[actix_web::test]
+    async fn login_rejects_signatures_from_another_key() {
+        let registered = SoftAuthenticator::new();
+        let mut impostor = SoftAuthenticator::new();
+        impostor.credential_id = registered.credential_id.clone();
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![pending("authentication", None)]])
+            .append_query_results([vec![stored(&registered)]]);
+
+        let response = impostor.authenticate(&test_config().webauthn, "challenge");
+        let (status, _) = send(
+            db,
+            test::TestRequest::post()
+                .uri("/auth/webauthn/login/finish")
+                .set_json(response),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn login_rejects_a_challenge_issued_for_another_user() {
+        let mut authenticator = SoftAuthenticator::new();
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![pending("authentication", Some(4))]])
+            .append_query_results([vec![stored(&authenticator)]]);
+
+        let response = authenticator.authenticate(&test_config().webauthn, "challenge");
+        let (status, _) = send(
+            db,
+            test::TestRequest::post()
+                .uri("/auth/webauthn/login/finish")
+                .set_json(response),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn passwordless_accounts_keep_their_last_passkey() {
+        let authenticator = SoftAuthenticator::new();
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<RevokedTokenModel>::new()])
+            .append_query_results([vec![bob()]])
+            .append_query_results([vec![stored(&authenticator)]]);
+        let token = test_state(MockDatabase::new(DatabaseBackend::Postgres).into_connection())
+            .issue_access_token(9, &UserGrants::default())
+            .unwrap();
+
+        let (status, _) = send(
+            db,
+            test::TestRequest::delete()
+                .uri("/me/webauthn/credentials/5")
+                .insert_header(("Authorization", format!("Bearer {}", token))),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::CONFLICT);
+    }
+}
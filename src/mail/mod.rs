@@ -0,0 +1,229 @@
+//! Outgoing mail behind the [`Mailer`] trait.
+//!
+//! `MAIL_TRANSPORT` picks the implementation: [`SmtpMailer`] delivers to a real server,
+//! while [`FileMailer`] writes the rendered messages to stdout or a file so local
+//! development and tests run without one.
+
+pub mod smtp;
+
+use std::fmt::{self, Display};
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use actix_web::web;
+use chrono::Utc;
+
+use crate::config::{MailConfig, MailTransport};
+
+pub use smtp::SmtpMailer;
+
+/// A plain-text message to a single recipient.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+impl Email {
+    /// Renders the message as RFC 5322 text with CRLF line endings.
+    pub fn render(&self, from: &str) -> Result<String, MailError> {
+        for value in [from, &self.to, &self.subject] {
+            if value.contains(['\r', '\n']) {
+                return Err(MailError::InvalidHeader(value.to_string()));
+            }
+        }
+
+        let domain = from
+            .rsplit_once('@')
+            .map_or("localhost", |(_, domain)| domain);
+        let mut message = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMessage-ID: <{}@{}>\r\n\
+             MIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\n\
+             Content-Transfer-Encoding: 8bit\r\n\r\n",
+            from,
+            self.to,
+            self.subject,
+            Utc::now().to_rfc2822(),
+            uuid::Uuid::new_v4(),
+            domain,
+        );
+        for line in self.body.lines() {
+            message.push_str(line);
+            message.push_str("\r\n");
+        }
+
+        Ok(message)
+    }
+}
+
+/// Why a message could not be sent.
+#[derive(Debug)]
+pub enum MailError {
+    Io(io::Error),
+    Tls(String),
+    /// The server answered a command with an unexpected reply.
+    Rejected {
+        command: String,
+        reply: String,
+    },
+    /// A header value contained a line break.
+    InvalidHeader(String),
+}
+
+impl From<io::Error> for MailError {
+    fn from(err: io::Error) -> Self {
+        MailError::Io(err)
+    }
+}
+
+impl Display for MailError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MailError::Io(e) => write!(f, "mail transport failed: {}", e),
+            MailError::Tls(reason) => write!(f, "TLS negotiation failed: {}", reason),
+            MailError::Rejected { command, reply } => {
+                write!(f, "server rejected {}: {}", command, reply)
+            }
+            MailError::InvalidHeader(value) => {
+                write!(f, "header value {:?} contains a line break", value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MailError {}
+
+/// Delivers [`Email`]s. Implementations block, so call them through [`deliver`].
+pub trait Mailer: Send + Sync {
+    fn send(&self, email: &Email) -> Result<(), MailError>;
+}
+
+/// Writes rendered messages to stdout or appends them to a file.
+pub struct FileMailer {
+    from: String,
+    path: Option<PathBuf>,
+    lock: Mutex<()>,
+}
+
+impl FileMailer {
+    pub fn stdout(from: String) -> Self {
+        Self {
+            from,
+            path: None,
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn file(from: String, path: impl Into<PathBuf>) -> Self {
+        Self {
+            from,
+            path: Some(path.into()),
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+impl Mailer for FileMailer {
+    fn send(&self, email: &Email) -> Result<(), MailError> {
+        let message = email.render(&self.from)?;
+        // Keep concurrent messages from interleaving.
+        let _guard = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+
+        match &self.path {
+            Some(path) => {
+                let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+                writeln!(file, "{}", message)?;
+            }
+            None => {
+                let mut stdout = io::stdout().lock();
+                writeln!(stdout, "{}", message)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Builds the mailer selected by `MAIL_TRANSPORT`.
+pub fn mailer_from_config(config: &MailConfig) -> Arc<dyn Mailer> {
+    match config.transport {
+        MailTransport::Stdout => Arc::new(FileMailer::stdout(config.from.clone())),
+        MailTransport::File => Arc::new(FileMailer::file(config.from.clone(), &config.file_path)),
+        MailTransport::Smtp => Arc::new(SmtpMailer::new(config.from.clone(), config.smtp.clone())),
+    }
+}
+
+/// Sends `email` on the blocking thread pool.
+pub async fn deliver(mailer: &Arc<dyn Mailer>, email: Email) -> Result<(), MailError> {
+    let mailer = Arc::clone(mailer);
+
+    web::block(move || mailer.send(&email))
+        .await
+        .map_err(|e| MailError::Io(io::Error::other(e.to_string())))?
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Keeps sent messages in memory for assertions.
+    #[derive(Default)]
+    pub(crate) struct Outbox(Mutex<Vec<Email>>);
+
+    impl Outbox {
+        pub(crate) fn sent(&self) -> Vec<Email> {
+            self.0.lock().unwrap().clone()
+        }
+    }
+
+    impl Mailer for Outbox {
+        fn send(&self, email: &Email) -> Result<(), MailError> {
+            self.0.lock().unwrap().push(email.clone());
+            Ok(())
+        }
+    }
+
+    fn email() -> Email {
+        Email {
+            to: "bob@example.com".into(),
+            subject: "Hello".into(),
+            body: "first line\nsecond line".into(),
+        }
+    }
+
+    #[test]
+    fn render_uses_crlf_and_headers() {
+        let message = email().render("noreply@example.org").unwrap();
+
+        assert!(message.starts_with("From: noreply@example.org\r\nTo: bob@example.com\r\n"));
+        assert!(message.contains("Message-ID: <"));
+        assert!(message.contains("@example.org>\r\n"));
+        assert!(message.ends_with("\r\n\r\nfirst line\r\nsecond line\r\n"));
+    }
+
+    #[test]
+    fn render_rejects_header_injection() {
+        let mut email = email();
+        email.subject = "Hi\r\nBcc: everyone@example.com".into();
+
+        assert!(matches!(
+            email.render("noreply@example.org"),
+            Err(MailError::InvalidHeader(_))
+        ));
+    }
+
+    #[test]
+    fn file_mailer_appends_messages() {
+        let path = std::env::temp_dir().join(format!("mail-{}.log", uuid::Uuid::new_v4()));
+        let mailer = FileMailer::file("noreply@example.org".into(), &path);
+
+        mailer.send(&email()).unwrap();
+        mailer.send(&email()).unwrap();
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written.matches("To: bob@example.com").count(), 2);
+    }
+}
@@ -0,0 +1,315 @@
+//! A minimal blocking SMTP client: EHLO, optional STARTTLS and `AUTH PLAIN`, one message
+//! per connection.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+
+use crate::config::SmtpConfig;
+use crate::mail::{Email, MailError, Mailer};
+
+/// Delivers mail through an SMTP submission server.
+pub struct SmtpMailer {
+    from: String,
+    config: SmtpConfig,
+    tls: Arc<ClientConfig>,
+}
+
+impl SmtpMailer {
+    pub fn new(from: String, config: SmtpConfig) -> Self {
+        let roots = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        let tls =
+            ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .expect("ring supports the default TLS versions")
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+
+        Self {
+            from,
+            config,
+            tls: Arc::new(tls),
+        }
+    }
+
+    fn connect(&self) -> Result<TcpStream, MailError> {
+        let timeout = Duration::from_secs(self.config.timeout_secs);
+        let mut last_error = None;
+
+        for addr in (self.config.host.as_str(), self.config.port).to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, timeout) {
+                Ok(stream) => {
+                    stream.set_read_timeout(Some(timeout))?;
+                    stream.set_write_timeout(Some(timeout))?;
+                    return Ok(stream);
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        Err(last_error
+            .unwrap_or_else(|| std::io::Error::other("host did not resolve"))
+            .into())
+    }
+
+    /// Everything after the (possibly encrypted) EHLO.
+    fn transact<S: Read + Write>(
+        &self,
+        session: &mut Session<S>,
+        email: &Email,
+        message: &str,
+    ) -> Result<(), MailError> {
+        if let (Some(username), Some(password)) = (&self.config.username, &self.config.password) {
+            let credentials = STANDARD.encode(format!("\0{}\0{}", username, password));
+            session.command_as(&format!("AUTH PLAIN {}", credentials), "AUTH PLAIN", 2)?;
+        }
+
+        session.command(&format!("MAIL FROM:<{}>", self.from), 2)?;
+        session.command(&format!("RCPT TO:<{}>", email.to), 2)?;
+        session.command("DATA", 3)?;
+        session.send_data(message)?;
+        // The message is accepted at this point; a failed QUIT doesn't matter.
+        let _ = session.command("QUIT", 2);
+        Ok(())
+    }
+}
+
+impl Mailer for SmtpMailer {
+    fn send(&self, email: &Email) -> Result<(), MailError> {
+        let message = email.render(&self.from)?;
+        let ehlo = format!("EHLO {}", self.config.helo_name);
+
+        let mut session = Session::new(self.connect()?);
+        session.reply(2, "greeting")?;
+        let extensions = session.command(&ehlo, 2)?;
+
+        if !self.config.starttls {
+            return self.transact(&mut session, email, &message);
+        }
+
+        if !extensions
+            .iter()
+            .any(|line| line.eq_ignore_ascii_case("STARTTLS"))
+        {
+            return Err(MailError::Tls("server does not offer STARTTLS".to_string()));
+        }
+        session.command("STARTTLS", 2)?;
+
+        let server_name = ServerName::try_from(self.config.host.clone())
+            .map_err(|e| MailError::Tls(e.to_string()))?;
+        let connection = ClientConnection::new(Arc::clone(&self.tls), server_name)
+            .map_err(|e| MailError::Tls(e.to_string()))?;
+        let mut session = Session::new(StreamOwned::new(connection, session.into_inner()));
+        session.command(&ehlo, 2)?;
+
+        self.transact(&mut session, email, &message)
+    }
+}
+
+/// One side of the SMTP conversation over a plain or TLS stream.
+struct Session<S: Read + Write> {
+    stream: BufReader<S>,
+}
+
+impl<S: Read + Write> Session<S> {
+    fn new(stream: S) -> Self {
+        Self {
+            stream: BufReader::new(stream),
+        }
+    }
+
+    fn into_inner(self) -> S {
+        self.stream.into_inner()
+    }
+
+    /// Reads a possibly multi-line reply and checks its class (`2` = done, `3` = go on).
+    ///
+    /// Returns the text of every line, without the status code.
+    fn reply(&mut self, class: u16, command: &str) -> Result<Vec<String>, MailError> {
+        let mut lines = Vec::new();
+
+        loop {
+            let mut line = String::new();
+            if self.stream.read_line(&mut line)? == 0 {
+                return Err(MailError::Rejected {
+                    command: command.to_string(),
+                    reply: "connection closed".to_string(),
+                });
+            }
+            let line = line.trim_end();
+            let code = line.get(..3).and_then(|code| code.parse::<u16>().ok());
+            let Some(code) = code else {
+                return Err(MailError::Rejected {
+                    command: command.to_string(),
+                    reply: line.to_string(),
+                });
+            };
+
+            lines.push(line.get(4..).unwrap_or_default().to_string());
+            if line.as_bytes().get(3) == Some(&b'-') {
+                continue;
+            }
+            if code / 100 != class {
+                return Err(MailError::Rejected {
+                    command: command.to_string(),
+                    reply: line.to_string(),
+                });
+            }
+            return Ok(lines);
+        }
+    }
+
+    fn command(&mut self, line: &str, class: u16) -> Result<Vec<String>, MailError> {
+        self.command_as(line, line, class)
+    }
+
+    /// Like [`Self::command`], but reports failures as `label` to keep secrets out of errors.
+    fn command_as(
+        &mut self,
+        line: &str,
+        label: &str,
+        class: u16,
+    ) -> Result<Vec<String>, MailError> {
+        let stream = self.stream.get_mut();
+        stream.write_all(line.as_bytes())?;
+        stream.write_all(b"\r\n")?;
+        stream.flush()?;
+
+        self.reply(class, label)
+    }
+
+    /// Sends the message body, dot-stuffed and terminated by `.`.
+    fn send_data(&mut self, message: &str) -> Result<(), MailError> {
+        let stream = self.stream.get_mut();
+        for line in message.split_terminator("\r\n") {
+            if line.starts_with('.') {
+                stream.write_all(b".")?;
+            }
+            stream.write_all(line.as_bytes())?;
+            stream.write_all(b"\r\n")?;
+        }
+        stream.write_all(b".\r\n")?;
+        stream.flush()?;
+
+        self.reply(2, "message")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::thread;
+
+    use super::*;
+
+    /// Plays the server side of one SMTP conversation and returns what the client sent.
+    fn fake_server(replies: &'static [&'static str]) -> (u16, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            let mut received = Vec::new();
+
+            writer.write_all(b"220 test ESMTP\r\n").unwrap();
+            for reply in replies {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                received.push(line.trim_end().to_string());
+                if line.trim_end() == "DATA" {
+                    writer.write_all(b"354 go ahead\r\n").unwrap();
+                    loop {
+                        let mut data = String::new();
+                        reader.read_line(&mut data).unwrap();
+                        received.push(data.trim_end().to_string());
+                        if data == ".\r\n" {
+                            break;
+                        }
+                    }
+                }
+                writer.write_all(reply.as_bytes()).unwrap();
+            }
+            received
+        });
+
+        (port, handle)
+    }
+
+    fn config(port: u16) -> SmtpConfig {
+        SmtpConfig {
+            host: "127.0.0.1".into(),
+            port,
+            starttls: false,
+            ..Default::default()
+        }
+    }
+
+    fn email(body: &str) -> Email {
+        Email {
+            to: "bob@example.com".into(),
+            subject: "Hello".into(),
+            body: body.into(),
+        }
+    }
+
+    #[test]
+    fn sends_a_dot_stuffed_message() {
+        let (port, server) = fake_server(&[
+            "250-test\r\n250 8BITMIME\r\n",
+            "250 ok\r\n",
+            "250 ok\r\n",
+            "250 queued\r\n",
+            "221 bye\r\n",
+        ]);
+        let mailer = SmtpMailer::new("noreply@example.org".into(), config(port));
+
+        mailer.send(&email("hi\n.hidden")).unwrap();
+
+        let received = server.join().unwrap();
+        assert_eq!(received[0], "EHLO localhost");
+        assert_eq!(received[1], "MAIL FROM:<noreply@example.org>");
+        assert_eq!(received[2], "RCPT TO:<bob@example.com>");
+        assert_eq!(received[3], "DATA");
+        assert!(received.contains(&"..hidden".to_string()));
+        assert_eq!(received.last().unwrap(), "QUIT");
+    }
+
+    #[test]
+    fn reports_rejected_recipients() {
+        let (port, server) = fake_server(&["250 test\r\n", "250 ok\r\n", "550 no such user\r\n"]);
+        let mailer = SmtpMailer::new("noreply@example.org".into(), config(port));
+
+        let err = mailer.send(&email("hi")).unwrap_err();
+
+        assert!(matches!(err, MailError::Rejected { ref command, ref reply }
+                if command == "RCPT TO:<bob@example.com>" && reply == "550 no such user"));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn requires_starttls_when_enabled() {
+        let (port, server) = fake_server(&["250 test\r\n"]);
+        let mailer = SmtpMailer::new(
+            "noreply@example.org".into(),
+            SmtpConfig {
+                starttls: true,
+                ..config(port)
+            },
+        );
+
+        assert!(matches!(mailer.send(&email("hi")), Err(MailError::Tls(_))));
+        server.join().unwrap();
+    }
+}
@@ -1,34 +1,108 @@
 mod config;
 mod db;
 mod handlers;
+mod mail;
 mod middleware;
 mod models;
+mod policy;
 mod routes;
 mod services;
 mod state;
 mod utils;
 
-use actix_web::{App, HttpServer, web};
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::{
+    App, HttpServer,
+    middleware::{Condition, Logger},
+    web,
+};
 use config::AppConfig;
 use db::establish_connection;
+use mail::mailer_from_config;
+use middleware::CsrfProtection;
+use policy::{PolicyEngine, spawn_policy_reloader};
 use routes::configure as configure_routes;
+use services::key_service::{load_key_states, spawn_key_state_reloader};
+use services::login_throttle_service::spawn_login_throttle_sweeper;
+use services::token_service::spawn_revoked_token_sweeper;
 use state::AppState;
+use utils::KeyRing;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let app_config = AppConfig::from_env();
-    let db_connection = establish_connection(&app_config.database_url)
+    let app_config = AppConfig::load().unwrap_or_else(|errors| {
+        eprintln!("{}", errors);
+        std::process::exit(1);
+    });
+    env_logger::Builder::new()
+        .parse_filters(&app_config.logging.level)
+        .init();
+
+    let key_ring =
+        Arc::new(KeyRing::from_config(&app_config.jwt).expect("Failed to load JWT key ring"));
+    let policy = Arc::new(
+        PolicyEngine::from_config(&app_config.policy).expect("Failed to load access policy"),
+    );
+    let db_connection = establish_connection(&app_config.database)
         .await
         .expect("Failed to connect to Postgres");
+    let saved_key_states = load_key_states(&db_connection)
+        .await
+        .expect("Failed to load saved JWT key states");
+    key_ring
+        .restore_states(&saved_key_states)
+        .expect("Failed to restore saved JWT key states");
 
-    let shared_state = web::Data::new(AppState::new(db_connection, app_config.clone()));
+    spawn_revoked_token_sweeper(
+        db_connection.clone(),
+        Duration::from_secs(app_config.revoked_token_sweep_interval_secs),
+    );
+    spawn_login_throttle_sweeper(
+        db_connection.clone(),
+        app_config.login_throttle.clone(),
+        Duration::from_secs(app_config.login_throttle.sweep_interval_secs),
+    );
 
-    HttpServer::new(move || {
+    if app_config.jwt.key_state_reload_interval_secs > 0 {
+        spawn_key_state_reloader(
+            db_connection.clone(),
+            Arc::clone(&key_ring),
+            Duration::from_secs(app_config.jwt.key_state_reload_interval_secs),
+        );
+    }
+    if app_config.policy.reload_interval_secs > 0 {
+        spawn_policy_reloader(
+            Arc::clone(&policy),
+            Duration::from_secs(app_config.policy.reload_interval_secs),
+        );
+    }
+
+    let shared_state = web::Data::new(AppState::new(
+        db_connection,
+        app_config.clone(),
+        key_ring,
+        policy,
+        mailer_from_config(&app_config.mail),
+    ));
+
+    let access_log = app_config.logging.access_log;
+    let mut server = HttpServer::new(move || {
         App::new()
+            .wrap(CsrfProtection)
+            .wrap(Condition::new(access_log, Logger::default()))
             .app_data(shared_state.clone())
             .configure(configure_routes)
-    })
-    .bind(&app_config.bind_address)?
-    .run()
-    .await
+    });
+    if let Some(workers) = app_config.server.workers {
+        server = server.workers(workers);
+    }
+
+    log::info!(
+        "starting {:?} server on {}",
+        app_config.environment,
+        app_config.server.bind_address
+    );
+    server.bind(&app_config.server.bind_address)?.run().await
 }
@@ -1,21 +1,230 @@
-use actix_web::{Error, HttpMessage, dev::ServiceRequest, error::ErrorUnauthorized, web};
+use std::rc::Rc;
 
-use crate::state::{self, AppState};
+use actix_web::{
+    Error, FromRequest, HttpMessage, HttpRequest,
+    body::{BoxBody, MessageBody},
+    dev::{Payload, Service, ServiceRequest, ServiceResponse, Transform, forward_ready},
+    web,
+};
+use futures_util::future::{LocalBoxFuture, Ready, ready};
+
+use crate::state::{self, AppState, AuthError};
 use crate::utils::TokenClaims;
 
-/// Simple middleware helper that can be used once JWT support is added.
-#[allow(dead_code)]
-pub fn ensure_auth_header(req: &ServiceRequest) -> Result<TokenClaims, Error> {
-    let token =
-        state::bearer_token(req.request()).map_err(|err| ErrorUnauthorized(err.to_string()))?;
+/// The caller of a protected endpoint, resolved from a valid, unrevoked bearer token.
+#[derive(Clone, Debug)]
+pub struct AuthenticatedUser {
+    pub user_id: i32,
+    pub claims: TokenClaims,
+}
 
+impl From<TokenClaims> for AuthenticatedUser {
+    fn from(claims: TokenClaims) -> Self {
+        Self {
+            user_id: claims.sub,
+            claims,
+        }
+    }
+}
+
+/// Validates the request's bearer token against the shared [`AppState`].
+pub(crate) async fn authenticate(req: &HttpRequest) -> Result<AuthenticatedUser, AuthError> {
+    let token = state::bearer_token(req)?;
     let state = req
         .app_data::<web::Data<AppState>>()
-        .ok_or_else(|| ErrorUnauthorized("Missing application state"))?;
+        .ok_or(AuthError::InvalidToken)?;
+
+    state
+        .validate_token(&token)
+        .await
+        .map(AuthenticatedUser::from)
+}
+
+/// The user a [`JwtAuth`] in front of a guard already resolved, or else the result of
+/// [`authenticate`], stored for the guards and handlers behind it.
+pub(crate) async fn authenticate_once(
+    req: &ServiceRequest,
+) -> Result<AuthenticatedUser, AuthError> {
+    let stored = req.extensions().get::<AuthenticatedUser>().cloned();
+    if let Some(user) = stored {
+        return Ok(user);
+    }
+
+    let user = authenticate(req.request()).await?;
+    req.extensions_mut().insert(user.clone());
+    Ok(user)
+}
+
+/// Reuses the user stored by [`JwtAuth`] when present, otherwise validates the token itself,
+/// so handlers work the same whether or not their scope is wrapped.
+impl FromRequest for AuthenticatedUser {
+    type Error = AuthError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+
+        Box::pin(async move {
+            if let Some(user) = req.extensions().get::<AuthenticatedUser>() {
+                return Ok(user.clone());
+            }
+
+            authenticate(&req).await
+        })
+    }
+}
+
+/// Middleware that rejects requests without a valid bearer token with `401`.
+///
+/// On success the [`AuthenticatedUser`] is stored in the request extensions for the extractor.
+pub struct JwtAuth;
+
+impl<S, B> Transform<S, ServiceRequest> for JwtAuth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = JwtAuthMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(JwtAuthMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct JwtAuthMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for JwtAuthMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+
+        Box::pin(async move {
+            match authenticate(req.request()).await {
+                Ok(user) => {
+                    req.extensions_mut().insert(user);
+                    service
+                        .call(req)
+                        .await
+                        .map(ServiceResponse::map_into_boxed_body)
+                }
+                Err(err) => Ok(req.error_response(err)),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, HttpResponse, http::StatusCode, test};
+    use sea_orm::{DatabaseBackend, MockDatabase};
+
+    use crate::models::revoked_token::Model as RevokedTokenModel;
+    use crate::state::tests::test_state;
+    use crate::utils::UserGrants;
+
+    use super::*;
+
+    async fn whoami(user: AuthenticatedUser) -> HttpResponse {
+        HttpResponse::Ok().body(user.user_id.to_string())
+    }
+
+    /// State whose mock answers exactly one revocation lookup.
+    fn one_lookup_state() -> web::Data<AppState> {
+        web::Data::new(test_state(
+            MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<RevokedTokenModel>::new()])
+                .into_connection(),
+        ))
+    }
+
+    #[actix_web::test]
+    async fn middleware_passes_authenticated_user_to_handler() {
+        let state = one_lookup_state();
+        let token = state
+            .issue_access_token(42, &UserGrants::default())
+            .unwrap();
+        let app = test::init_service(
+            App::new().app_data(state.clone()).service(
+                web::scope("/me")
+                    .wrap(JwtAuth)
+                    .route("", web::get().to(whoami)),
+            ),
+        )
+        .await;
+
+        // The extractor must reuse the middleware's result rather than query again.
+        let req = test::TestRequest::get()
+            .uri("/me")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(test::read_body(resp).await, "42");
+    }
+
+    #[actix_web::test]
+    async fn middleware_rejects_missing_and_invalid_tokens() {
+        let state = one_lookup_state();
+        let app = test::init_service(
+            App::new().app_data(state.clone()).service(
+                web::scope("/me")
+                    .wrap(JwtAuth)
+                    .route("", web::get().to(whoami)),
+            ),
+        )
+        .await;
+
+        let missing = test::TestRequest::get().uri("/me").to_request();
+        let resp = test::call_service(&app, missing).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(resp.headers().get("WWW-Authenticate").unwrap(), "Bearer");
+
+        let garbage = test::TestRequest::get()
+            .uri("/me")
+            .insert_header(("Authorization", "Bearer not-a-jwt"))
+            .to_request();
+        let resp = test::call_service(&app, garbage).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
 
-    let claims = state.validate_token(&token).map_err(Error::from)?;
+    #[actix_web::test]
+    async fn extractor_validates_without_middleware() {
+        let state = one_lookup_state();
+        let token = state.issue_access_token(7, &UserGrants::default()).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .route("/whoami", web::get().to(whoami)),
+        )
+        .await;
 
-    req.extensions_mut().insert(claims.clone());
+        let req = test::TestRequest::get()
+            .uri("/whoami")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(test::read_body(resp).await, "7");
 
-    Ok(claims)
+        let req = test::TestRequest::get().uri("/whoami").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
 }
@@ -0,0 +1,192 @@
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+
+use actix_web::{
+    FromRequest, HttpRequest,
+    dev::Payload,
+    http::header::{self, HeaderMap},
+    web,
+};
+use futures_util::future::{Ready, ready};
+
+use crate::state::AppState;
+
+/// Longest user agent stored with a session; anything after it is cut off.
+const MAX_USER_AGENT_CHARS: usize = 512;
+
+/// Where a request came from, as recorded for the session it starts.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClientInfo {
+    pub user_agent: Option<String>,
+    /// The [`client_ip`], the same one login throttling keys on.
+    pub ip_address: Option<String>,
+}
+
+impl FromRequest for ClientInfo {
+    type Error = Infallible;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let user_agent = req
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.chars().take(MAX_USER_AGENT_CHARS).collect());
+
+        ready(Ok(ClientInfo {
+            user_agent,
+            ip_address: client_ip(req).map(|ip| ip.to_string()),
+        }))
+    }
+}
+
+/// The address of the client behind the request.
+///
+/// That is the connection's peer unless the peer is one of the configured trusted proxies, in
+/// which case the forwarding headers are followed back to the first hop that isn't.
+pub fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    let trusted_proxies = req
+        .app_data::<web::Data<AppState>>()
+        .map(|state| state.config.server.trusted_proxies.as_slice())
+        .unwrap_or_default();
+
+    Some(forwarded_client_ip(peer, req.headers(), trusted_proxies))
+}
+
+fn forwarded_client_ip(peer: IpAddr, headers: &HeaderMap, trusted_proxies: &[IpAddr]) -> IpAddr {
+    if !trusted_proxies.contains(&peer) {
+        return peer;
+    }
+
+    // Proxies append, so the hops nearest to us come last. Anything left of the first
+    // untrusted hop could have been written by the client.
+    let mut client = peer;
+    for hop in forwarded_chain(headers).iter().rev() {
+        if !trusted_proxies.contains(&client) {
+            break;
+        }
+        match parse_hop(hop) {
+            Some(ip) => client = ip,
+            None => break,
+        }
+    }
+    client
+}
+
+/// The `for=` values of `Forwarded`, or else the entries of `X-Forwarded-For`, oldest first.
+fn forwarded_chain(headers: &HeaderMap) -> Vec<String> {
+    let values = |name| {
+        headers
+            .get_all(name)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect::<Vec<_>>()
+    };
+
+    let forwarded = values(header::FORWARDED);
+    if !forwarded.is_empty() {
+        return forwarded
+            .iter()
+            .map(|element| {
+                element
+                    .split(';')
+                    .filter_map(|pair| pair.trim().split_once('='))
+                    .find(|(name, _)| name.eq_ignore_ascii_case("for"))
+                    .map(|(_, value)| value.trim_matches('"').to_string())
+                    .unwrap_or_default()
+            })
+            .collect();
+    }
+    values(header::X_FORWARDED_FOR)
+        .into_iter()
+        .map(str::to_string)
+        .collect()
+}
+
+/// Parses a hop such as `192.0.2.1`, `192.0.2.1:4711`, or `[2001:db8::1]:4711`. Obfuscated
+/// identifiers and `unknown` yield `None`.
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    hop.parse::<IpAddr>()
+        .or_else(|_| hop.parse::<SocketAddr>().map(|addr| addr.ip()))
+        .or_else(|_| hop.trim_start_matches('[').trim_end_matches(']').parse())
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::header::HeaderValue, test::TestRequest};
+
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(
+                header::HeaderName::from_static(name),
+                HeaderValue::from_static(value),
+            );
+        }
+        headers
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn untrusted_peers_cannot_claim_another_address() {
+        let headers = headers(&[("x-forwarded-for", "203.0.113.9")]);
+
+        assert_eq!(
+            forwarded_client_ip(ip("198.51.100.7"), &headers, &[ip("10.0.0.1")]),
+            ip("198.51.100.7")
+        );
+    }
+
+    #[test]
+    fn trusted_proxies_are_followed_back_to_the_first_untrusted_hop() {
+        let trusted = [ip("10.0.0.1"), ip("10.0.0.2")];
+        // The client prepended a spoofed address; 10.0.0.2 saw 203.0.113.9 connect.
+        let headers = headers(&[("x-forwarded-for", "1.2.3.4, 203.0.113.9, 10.0.0.2")]);
+
+        assert_eq!(
+            forwarded_client_ip(ip("10.0.0.1"), &headers, &trusted),
+            ip("203.0.113.9")
+        );
+    }
+
+    #[test]
+    fn forwarded_header_takes_precedence() {
+        let headers = headers(&[
+            ("x-forwarded-for", "1.2.3.4"),
+            ("forwarded", r#"for="[2001:db8::17]:4711";proto=https"#),
+        ]);
+
+        assert_eq!(
+            forwarded_client_ip(ip("10.0.0.1"), &headers, &[ip("10.0.0.1")]),
+            ip("2001:db8::17")
+        );
+    }
+
+    #[test]
+    fn unparsable_hops_stop_at_the_last_known_address() {
+        let headers = headers(&[("forwarded", "for=_hidden")]);
+
+        assert_eq!(
+            forwarded_client_ip(ip("10.0.0.1"), &headers, &[ip("10.0.0.1")]),
+            ip("10.0.0.1")
+        );
+    }
+
+    #[test]
+    fn requests_without_app_state_use_the_peer() {
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.1:4711".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "203.0.113.9"))
+            .to_http_request();
+
+        assert_eq!(client_ip(&req), Some(ip("10.0.0.1")));
+    }
+}
@@ -0,0 +1,167 @@
+use std::rc::Rc;
+
+use actix_web::{
+    Error, HttpRequest, HttpResponse,
+    body::{BoxBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform, forward_ready},
+};
+use futures_util::future::{LocalBoxFuture, Ready, ready};
+use subtle::ConstantTimeEq;
+
+use crate::state::authorization_bearer;
+use crate::utils::auth_cookie::{
+    ACCESS_TOKEN_COOKIE, CSRF_COOKIE, CSRF_HEADER, REFRESH_TOKEN_COOKIE,
+};
+
+/// Whether a request passes the double-submit check.
+///
+/// Only state-changing requests that authenticate by cookie are checked: bearer tokens and
+/// safe methods can't be forged by another site. Those must repeat the CSRF cookie in the
+/// [`CSRF_HEADER`], which only scripts on an origin that can read the cookie manage.
+pub(crate) fn csrf_check_passes(req: &HttpRequest) -> bool {
+    if req.method().is_safe() || authorization_bearer(req).is_some() {
+        return true;
+    }
+    if req.cookie(ACCESS_TOKEN_COOKIE).is_none() && req.cookie(REFRESH_TOKEN_COOKIE).is_none() {
+        return true;
+    }
+
+    let Some(expected) = req.cookie(CSRF_COOKIE) else {
+        return false;
+    };
+    req.headers().get(CSRF_HEADER).is_some_and(|provided| {
+        !expected.value().is_empty()
+            && bool::from(provided.as_bytes().ct_eq(expected.value().as_bytes()))
+    })
+}
+
+/// Middleware that rejects cookie-authenticated, state-changing requests whose
+/// `X-CSRF-Token` header doesn't match the CSRF cookie with `403`.
+pub struct CsrfProtection;
+
+impl<S, B> Transform<S, ServiceRequest> for CsrfProtection
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = CsrfProtectionMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(CsrfProtectionMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct CsrfProtectionMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for CsrfProtectionMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+
+        Box::pin(async move {
+            if !csrf_check_passes(req.request()) {
+                return Ok(req.into_response(
+                    HttpResponse::Forbidden().body("Missing or invalid CSRF token."),
+                ));
+            }
+
+            service
+                .call(req)
+                .await
+                .map(ServiceResponse::map_into_boxed_body)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, cookie::Cookie, http::StatusCode, test, web};
+
+    use super::*;
+
+    async fn ok() -> HttpResponse {
+        HttpResponse::Ok().finish()
+    }
+
+    async fn status(req: test::TestRequest) -> StatusCode {
+        let app = test::init_service(
+            App::new()
+                .wrap(CsrfProtection)
+                .route("/", web::get().to(ok))
+                .route("/", web::post().to(ok)),
+        )
+        .await;
+
+        test::call_service(&app, req.uri("/").to_request())
+            .await
+            .status()
+    }
+
+    #[actix_web::test]
+    async fn cookie_requests_must_repeat_the_csrf_token() {
+        let session = || {
+            test::TestRequest::post()
+                .cookie(Cookie::new(ACCESS_TOKEN_COOKIE, "jwt"))
+                .cookie(Cookie::new(CSRF_COOKIE, "csrf"))
+        };
+
+        assert_eq!(status(session()).await, StatusCode::FORBIDDEN);
+        assert_eq!(
+            status(session().insert_header((CSRF_HEADER, "other"))).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(session().insert_header((CSRF_HEADER, "csrf"))).await,
+            StatusCode::OK
+        );
+        // Without the CSRF cookie there is nothing to match.
+        assert_eq!(
+            status(
+                test::TestRequest::post()
+                    .cookie(Cookie::new(REFRESH_TOKEN_COOKIE, "rt"))
+                    .insert_header((CSRF_HEADER, ""))
+            )
+            .await,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[actix_web::test]
+    async fn bearer_and_safe_requests_skip_the_check() {
+        assert_eq!(
+            status(test::TestRequest::post()).await,
+            StatusCode::OK,
+            "no cookies"
+        );
+        assert_eq!(
+            status(
+                test::TestRequest::post()
+                    .cookie(Cookie::new(ACCESS_TOKEN_COOKIE, "jwt"))
+                    .insert_header(("Authorization", "Bearer jwt"))
+            )
+            .await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(test::TestRequest::get().cookie(Cookie::new(ACCESS_TOKEN_COOKIE, "jwt"))).await,
+            StatusCode::OK
+        );
+    }
+}
@@ -1 +1,11 @@
 pub mod auth_middleware;
+pub mod client_info;
+pub mod csrf_middleware;
+pub mod permission_middleware;
+pub mod policy_middleware;
+
+pub use auth_middleware::{AuthenticatedUser, JwtAuth};
+pub use client_info::{ClientInfo, client_ip};
+pub use csrf_middleware::CsrfProtection;
+pub use permission_middleware::require_permission;
+pub use policy_middleware::require_policy;
@@ -0,0 +1,161 @@
+use std::rc::Rc;
+
+use actix_web::{
+    Error,
+    body::{BoxBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform, forward_ready},
+};
+use futures_util::future::{LocalBoxFuture, Ready, ready};
+
+use crate::middleware::auth_middleware::authenticate_once;
+use crate::state::AuthError;
+
+/// Guards a scope or resource so only callers whose token grants `permission` get through.
+///
+/// Callers without a valid token get `401`, callers lacking the permission get `403`.
+/// Wrapping inside [`crate::middleware::JwtAuth`] reuses its result; on its own the guard
+/// authenticates the request itself.
+pub fn require_permission(permission: &'static str) -> RequirePermission {
+    RequirePermission { permission }
+}
+
+pub struct RequirePermission {
+    permission: &'static str,
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequirePermission
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = RequirePermissionMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequirePermissionMiddleware {
+            service: Rc::new(service),
+            permission: self.permission,
+        }))
+    }
+}
+
+pub struct RequirePermissionMiddleware<S> {
+    service: Rc<S>,
+    permission: &'static str,
+}
+
+impl<S, B> Service<ServiceRequest> for RequirePermissionMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let permission = self.permission;
+
+        Box::pin(async move {
+            let user = match authenticate_once(&req).await {
+                Ok(user) => user,
+                Err(err) => return Ok(req.error_response(err)),
+            };
+
+            if !user.claims.has_permission(permission) {
+                return Ok(req.error_response(AuthError::Forbidden));
+            }
+
+            service
+                .call(req)
+                .await
+                .map(ServiceResponse::map_into_boxed_body)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, HttpResponse, http::StatusCode, test, web};
+    use sea_orm::{DatabaseBackend, MockDatabase};
+
+    use crate::middleware::JwtAuth;
+    use crate::models::revoked_token::Model as RevokedTokenModel;
+    use crate::state::{AppState, tests::test_state};
+    use crate::utils::UserGrants;
+
+    use super::*;
+
+    fn state(lookups: usize) -> web::Data<AppState> {
+        web::Data::new(test_state(
+            MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results(vec![Vec::<RevokedTokenModel>::new(); lookups])
+                .into_connection(),
+        ))
+    }
+
+    fn grants(permissions: &[&str]) -> UserGrants {
+        UserGrants::new(
+            ["admin".to_string()],
+            permissions.iter().map(|p| p.to_string()),
+        )
+    }
+
+    #[actix_web::test]
+    async fn allows_callers_with_the_permission() {
+        let state = state(1);
+        let token = state
+            .issue_access_token(1, &grants(&["users:read", "users:write"]))
+            .unwrap();
+        let app = test::init_service(
+            App::new().app_data(state.clone()).service(
+                web::scope("/admin")
+                    .wrap(require_permission("users:write"))
+                    .wrap(JwtAuth)
+                    .route("", web::post().to(HttpResponse::NoContent)),
+            ),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/admin")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[actix_web::test]
+    async fn forbids_callers_without_the_permission() {
+        let state = state(1);
+        let token = state
+            .issue_access_token(1, &grants(&["users:read"]))
+            .unwrap();
+        let app = test::init_service(
+            App::new().app_data(state.clone()).service(
+                web::resource("/admin")
+                    .wrap(require_permission("users:write"))
+                    .route(web::post().to(HttpResponse::NoContent)),
+            ),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/admin")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::post().uri("/admin").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,159 @@
+use std::rc::Rc;
+
+use actix_web::{
+    Error,
+    body::{BoxBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform, forward_ready},
+    web,
+};
+use chrono::Utc;
+use futures_util::future::{LocalBoxFuture, Ready, ready};
+
+use crate::middleware::auth_middleware::authenticate_once;
+use crate::policy::PolicyContext;
+use crate::state::{AppState, AuthError};
+
+/// Guards a scope or resource so only callers the access policy allows `action` get through.
+///
+/// The check sees the caller's claims and the request; rules about a particular resource
+/// belong in the handler, which knows its attributes. Callers without a valid token get
+/// `401`, denied callers `403`. Like [`crate::middleware::require_permission`] it reuses a
+/// [`crate::middleware::JwtAuth`] in front of it.
+pub fn require_policy(action: &'static str) -> RequirePolicy {
+    RequirePolicy { action }
+}
+
+pub struct RequirePolicy {
+    action: &'static str,
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequirePolicy
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = RequirePolicyMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequirePolicyMiddleware {
+            service: Rc::new(service),
+            action: self.action,
+        }))
+    }
+}
+
+pub struct RequirePolicyMiddleware<S> {
+    service: Rc<S>,
+    action: &'static str,
+}
+
+impl<S, B> Service<ServiceRequest> for RequirePolicyMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let action = self.action;
+
+        Box::pin(async move {
+            let user = match authenticate_once(&req).await {
+                Ok(user) => user,
+                Err(err) => return Ok(req.error_response(err)),
+            };
+
+            let allowed = req.app_data::<web::Data<AppState>>().is_some_and(|state| {
+                let context = PolicyContext::new(&user.claims).with_request(req.request());
+                state
+                    .policy
+                    .current()
+                    .evaluate(action, &context, Utc::now())
+                    .allowed
+            });
+            if !allowed {
+                return Ok(req.error_response(AuthError::Forbidden));
+            }
+
+            service
+                .call(req)
+                .await
+                .map(ServiceResponse::map_into_boxed_body)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{App, HttpResponse, http::StatusCode, test};
+    use sea_orm::{DatabaseBackend, MockDatabase};
+
+    use crate::models::revoked_token::Model as RevokedTokenModel;
+    use crate::policy::Policy;
+    use crate::state::tests::test_state;
+    use crate::utils::UserGrants;
+
+    use super::*;
+
+    async fn status(roles: &[&str]) -> StatusCode {
+        let mut state = test_state(
+            MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<RevokedTokenModel>::new()])
+                .into_connection(),
+        );
+        let policy = Policy::parse(
+            r#"
+            [[rules]]
+            name = "editors-publish"
+            effect = "allow"
+            actions = ["documents:publish"]
+            when = ["'editor' in subject.roles"]
+            "#,
+        )
+        .unwrap();
+        state.policy = Arc::new(policy.into());
+        let state = web::Data::new(state);
+
+        let token = state
+            .issue_access_token(
+                1,
+                &UserGrants::new(roles.iter().map(|role| role.to_string()), []),
+            )
+            .unwrap();
+        let app = test::init_service(
+            App::new().app_data(state.clone()).service(
+                web::resource("/publish")
+                    .wrap(require_policy("documents:publish"))
+                    .route(web::post().to(HttpResponse::NoContent)),
+            ),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/publish")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        test::call_service(&app, req).await.status()
+    }
+
+    #[actix_web::test]
+    async fn allows_callers_the_policy_allows() {
+        assert_eq!(status(&["editor"]).await, StatusCode::NO_CONTENT);
+    }
+
+    #[actix_web::test]
+    async fn forbids_callers_the_policy_denies() {
+        assert_eq!(status(&["user"]).await, StatusCode::FORBIDDEN);
+    }
+}
@@ -0,0 +1,26 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "login_throttles")]
+pub struct Model {
+    /// `user:<canonical username>` or `ip:<address>`.
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub key: String,
+    /// Failed logins within the current failure window.
+    pub failures: i32,
+    pub last_failure_at: DateTimeUtc,
+    /// Logins are refused until then, whatever the password.
+    pub locked_until: Option<DateTimeUtc>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        panic!("No relations defined")
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -1 +1,22 @@
+pub mod email_verification;
+pub mod invitation;
+pub mod invitation_event;
+pub mod login_throttle;
+pub mod magic_link;
+pub mod membership;
+pub mod mfa_challenge;
+pub mod organization;
+pub mod password_reset;
+pub mod permission;
+pub mod recovery_code;
+pub mod refresh_token;
+pub mod revoked_token;
+pub mod role;
+pub mod role_permission;
+pub mod session;
+pub mod signing_key_state;
+pub mod totp_credential;
 pub mod user;
+pub mod user_role;
+pub mod webauthn_challenge;
+pub mod webauthn_credential;
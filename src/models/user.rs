@@ -7,7 +7,15 @@ pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
     pub username: String,
+    /// [`crate::utils::canonical_username`] of `username`; unique, and what lookups compare.
+    pub username_canonical: String,
     pub password: String,
+    /// Organization put into the access token at login.
+    pub active_organization_id: Option<i32>,
+    /// Lowercased contact address; unique when set.
+    pub email: Option<String>,
+    /// Set once the current `email` was confirmed through a verification link.
+    pub email_verified_at: Option<DateTimeUtc>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter)]
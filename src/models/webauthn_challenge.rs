@@ -0,0 +1,35 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "webauthn_challenges")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    /// SHA-256 of the base64url challenge.
+    #[sea_orm(unique)]
+    pub challenge_hash: String,
+    /// `registration` or `authentication`.
+    pub ceremony: String,
+    /// The account a passkey is added to or, for a login, the account named up front.
+    pub user_id: Option<i32>,
+    /// Account to create when a passkey-only sign-up finishes.
+    pub username: Option<String>,
+    pub email: Option<String>,
+    /// Base64url user handle the registration options carried.
+    pub user_handle: Option<String>,
+    pub expires_at: DateTimeUtc,
+    pub created_at: DateTimeUtc,
+    pub used_at: Option<DateTimeUtc>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        panic!("No relations defined")
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,34 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "webauthn_credentials")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub user_id: i32,
+    /// Base64url credential ID chosen by the authenticator.
+    #[sea_orm(unique)]
+    pub credential_id: String,
+    /// COSE-encoded public key.
+    pub public_key: Vec<u8>,
+    /// Last signature counter seen; `0` for authenticators that don't count.
+    pub sign_count: i64,
+    /// Base64url WebAuthn user handle, shared by all of a user's passkeys.
+    pub user_handle: String,
+    /// Label chosen by the user, e.g. "Work laptop".
+    pub name: String,
+    pub created_at: DateTimeUtc,
+    pub last_used_at: Option<DateTimeUtc>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        panic!("No relations defined")
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,360 @@
+//! The condition language used in policy rules.
+//!
+//! A condition compares two operands, e.g. `subject.org_id == resource.org_id`,
+//! `env.hour >= 9` or `'editor' in subject.roles`. Operands are attribute paths rooted at
+//! `subject`, `resource`, `request` or `env`, or literals: quoted strings, numbers,
+//! `true`/`false`/`null`, and `[...]` lists of literals. Missing attributes resolve to `null`.
+
+use std::cmp::Ordering;
+use std::fmt::{self, Display};
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// Attribute roots a path may start with.
+const ROOTS: [&str; 4] = ["subject", "resource", "request", "env"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Operator {
+    #[serde(rename = "==")]
+    Eq,
+    #[serde(rename = "!=")]
+    Ne,
+    #[serde(rename = "<")]
+    Lt,
+    #[serde(rename = "<=")]
+    Le,
+    #[serde(rename = ">")]
+    Gt,
+    #[serde(rename = ">=")]
+    Ge,
+    /// Left is an element of the right list, or a substring of the right string.
+    #[serde(rename = "in")]
+    In,
+    /// Right is an element of the left list, or a substring of the left string.
+    #[serde(rename = "contains")]
+    Contains,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Operand {
+    Path(Vec<String>),
+    Literal(Value),
+}
+
+/// A parsed `left <op> right` comparison.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Condition {
+    source: String,
+    left: Operand,
+    operator: Operator,
+    right: Operand,
+}
+
+/// Why a condition failed to parse.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub condition: String,
+    pub reason: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid condition `{}`: {}", self.condition, self.reason)
+    }
+}
+
+/// The outcome of one condition, with the resolved operands for explanations.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ConditionTrace {
+    pub condition: String,
+    pub left: Value,
+    pub operator: Operator,
+    pub right: Value,
+    pub passed: bool,
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Self, ParseError> {
+        let error = |reason: String| ParseError {
+            condition: source.to_string(),
+            reason,
+        };
+
+        let tokens = tokenize(source).map_err(error)?;
+        let [left, operator, right] = <[Token; 3]>::try_from(tokens)
+            .map_err(|_| error("expected `<operand> <operator> <operand>`".to_string()))?;
+
+        let operator = match operator {
+            Token::Operator(operator) => operator,
+            other => return Err(error(format!("expected an operator, found {:?}", other))),
+        };
+
+        Ok(Self {
+            source: source.trim().to_string(),
+            left: operand(left).map_err(error)?,
+            operator,
+            right: operand(right).map_err(error)?,
+        })
+    }
+
+    /// Evaluates the condition against an attribute document with the four roots as keys.
+    pub fn evaluate(&self, attributes: &Value) -> ConditionTrace {
+        let left = resolve(&self.left, attributes);
+        let right = resolve(&self.right, attributes);
+
+        ConditionTrace {
+            condition: self.source.clone(),
+            passed: compare(&left, self.operator, &right),
+            left,
+            operator: self.operator,
+            right,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Operator(Operator),
+    Word(String),
+    Literal(Value),
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.trim().chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '\'' | '"' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some(end) if end == c => break,
+                        Some(ch) => text.push(ch),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+                tokens.push(Token::Literal(Value::String(text)));
+            }
+            '[' => {
+                let mut raw = String::new();
+                let mut depth = 0;
+                for ch in chars.by_ref() {
+                    raw.push(ch);
+                    match ch {
+                        '[' => depth += 1,
+                        ']' => depth -= 1,
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                // Lists accept single-quoted strings too; JSON only knows double quotes.
+                let list = serde_json::from_str(&raw.replace('\'', "\""))
+                    .map_err(|e| format!("invalid list {}: {}", raw, e))?;
+                tokens.push(Token::Literal(list));
+            }
+            '=' | '!' | '<' | '>' => {
+                let mut op = String::new();
+                while let Some(&ch) = chars.peek() {
+                    if matches!(ch, '=' | '!' | '<' | '>') {
+                        op.push(ch);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let operator = match op.as_str() {
+                    "==" => Operator::Eq,
+                    "!=" => Operator::Ne,
+                    "<" => Operator::Lt,
+                    "<=" => Operator::Le,
+                    ">" => Operator::Gt,
+                    ">=" => Operator::Ge,
+                    _ => return Err(format!("unknown operator `{}`", op)),
+                };
+                tokens.push(Token::Operator(operator));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&ch) = chars.peek() {
+                    if ch.is_whitespace() || matches!(ch, '=' | '!' | '<' | '>' | '[' | '\'' | '"')
+                    {
+                        break;
+                    }
+                    word.push(ch);
+                    chars.next();
+                }
+                tokens.push(match word.as_str() {
+                    "in" => Token::Operator(Operator::In),
+                    "contains" => Token::Operator(Operator::Contains),
+                    _ => Token::Word(word),
+                });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn operand(token: Token) -> Result<Operand, String> {
+    match token {
+        Token::Literal(value) => Ok(Operand::Literal(value)),
+        Token::Operator(operator) => Err(format!("expected an operand, found {:?}", operator)),
+        Token::Word(word) => match word.as_str() {
+            "true" => Ok(Operand::Literal(Value::Bool(true))),
+            "false" => Ok(Operand::Literal(Value::Bool(false))),
+            "null" => Ok(Operand::Literal(Value::Null)),
+            _ => {
+                if let Ok(number) = serde_json::from_str::<serde_json::Number>(&word) {
+                    return Ok(Operand::Literal(Value::Number(number)));
+                }
+
+                let path: Vec<String> = word.split('.').map(str::to_string).collect();
+                if !ROOTS.contains(&path[0].as_str()) || path.iter().any(String::is_empty) {
+                    return Err(format!(
+                        "`{}` is not a literal or a path under {}",
+                        word,
+                        ROOTS.join("/")
+                    ));
+                }
+                Ok(Operand::Path(path))
+            }
+        },
+    }
+}
+
+fn resolve(operand: &Operand, attributes: &Value) -> Value {
+    match operand {
+        Operand::Literal(value) => value.clone(),
+        Operand::Path(path) => path
+            .iter()
+            .try_fold(attributes, |value, key| value.get(key))
+            .cloned()
+            .unwrap_or(Value::Null),
+    }
+}
+
+fn compare(left: &Value, operator: Operator, right: &Value) -> bool {
+    match operator {
+        Operator::Eq => equal(left, right),
+        Operator::Ne => !equal(left, right),
+        Operator::Lt => order(left, right) == Some(Ordering::Less),
+        Operator::Le => matches!(order(left, right), Some(Ordering::Less | Ordering::Equal)),
+        Operator::Gt => order(left, right) == Some(Ordering::Greater),
+        Operator::Ge => matches!(
+            order(left, right),
+            Some(Ordering::Greater | Ordering::Equal)
+        ),
+        Operator::In => contains(right, left),
+        Operator::Contains => contains(left, right),
+    }
+}
+
+/// Equality that treats `1` and `1.0` as the same number.
+fn equal(left: &Value, right: &Value) -> bool {
+    match (left.as_f64(), right.as_f64()) {
+        (Some(l), Some(r)) => l == r,
+        _ => left == right,
+    }
+}
+
+fn order(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (Value::Number(l), Value::Number(r)) => l.as_f64()?.partial_cmp(&r.as_f64()?),
+        (Value::String(l), Value::String(r)) => Some(l.cmp(r)),
+        _ => None,
+    }
+}
+
+fn contains(haystack: &Value, needle: &Value) -> bool {
+    match (haystack, needle) {
+        (Value::Array(items), _) => items.iter().any(|item| equal(item, needle)),
+        (Value::String(text), Value::String(part)) => text.contains(part.as_str()),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn attributes() -> Value {
+        json!({
+            "subject": { "sub": 7, "org_id": 3, "roles": ["editor", "user"] },
+            "resource": { "org_id": 3, "owner": 7, "title": "Quarterly report" },
+            "request": { "method": "PUT" },
+            "env": { "hour": 10 },
+        })
+    }
+
+    fn passes(source: &str) -> bool {
+        Condition::parse(source)
+            .unwrap_or_else(|e| panic!("{}", e))
+            .evaluate(&attributes())
+            .passed
+    }
+
+    #[test]
+    fn compares_paths_and_literals() {
+        assert!(passes("subject.org_id == resource.org_id"));
+        assert!(passes("resource.owner == subject.sub"));
+        assert!(passes("env.hour >= 9"));
+        assert!(!passes("env.hour>=17"));
+        assert!(passes("request.method == 'PUT'"));
+        assert!(passes("request.method != \"GET\""));
+        assert!(passes("subject.org_id == 3.0"));
+    }
+
+    #[test]
+    fn membership_operators() {
+        assert!(passes("'editor' in subject.roles"));
+        assert!(!passes("'admin' in subject.roles"));
+        assert!(passes("subject.roles contains 'user'"));
+        assert!(passes("request.method in ['PUT', 'PATCH']"));
+        assert!(passes("resource.title contains 'report'"));
+    }
+
+    #[test]
+    fn missing_attributes_are_null() {
+        assert!(passes("resource.deleted_at == null"));
+        assert!(!passes("resource.deleted_at > 3"));
+    }
+
+    #[test]
+    fn trace_carries_resolved_operands() {
+        let trace = Condition::parse("env.hour < 9")
+            .unwrap()
+            .evaluate(&attributes());
+
+        assert_eq!(trace.left, json!(10));
+        assert_eq!(trace.right, json!(9));
+        assert!(!trace.passed);
+    }
+
+    #[test]
+    fn rejects_malformed_conditions() {
+        for source in [
+            "subject.org_id",
+            "subject.org_id === 3",
+            "user.id == 3",
+            "'unterminated == subject.sub",
+            "subject.sub == == 3",
+            "subject. == 3",
+        ] {
+            assert!(
+                Condition::parse(source).is_err(),
+                "{} should not parse",
+                source
+            );
+        }
+    }
+}
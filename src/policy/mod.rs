@@ -0,0 +1,605 @@
+//! Attribute-based access control on top of roles and permissions.
+//!
+//! A policy is a TOML file of named rules. Each rule applies to a set of actions and holds
+//! when all of its `when` conditions (see [`condition`]) pass against four attribute roots:
+//!
+//! - `subject`: the caller's token claims (`sub`, `roles`, `permissions`, ...)
+//! - `resource`: attributes of the object being acted on, supplied by the handler
+//! - `request`: `method`, `path` and `ip` of the HTTP request
+//! - `env`: `hour`, `minute`, `weekday` (1 = Monday), `date` and `timestamp` at evaluation
+//!
+//! A matching `deny` rule beats any `allow`; with no matching rule the policy `default`
+//! (deny unless stated otherwise) applies.
+//!
+//! ```toml
+//! default = "deny"
+//! utc_offset_minutes = 60
+//!
+//! [[rules]]
+//! name = "edit-own-org-in-business-hours"
+//! effect = "allow"
+//! actions = ["documents:edit"]
+//! when = [
+//!     "subject.org_id == resource.org_id",
+//!     "env.weekday <= 5",
+//!     "env.hour >= 9",
+//!     "env.hour < 17",
+//! ]
+//! ```
+
+pub mod condition;
+
+use std::collections::HashSet;
+use std::fmt::{self, Display};
+use std::path::PathBuf;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::{Duration, SystemTime};
+
+use actix_web::HttpRequest;
+use chrono::{DateTime, Datelike, FixedOffset, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::config::PolicyConfig;
+use crate::middleware::client_ip;
+use crate::utils::TokenClaims;
+
+pub use condition::{Condition, ConditionTrace};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Effect {
+    Allow,
+    #[default]
+    Deny,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    #[serde(default)]
+    default: Effect,
+    /// Offset applied to the clock before computing the `env` attributes.
+    #[serde(default)]
+    utc_offset_minutes: i32,
+    #[serde(default)]
+    rules: Vec<RuleFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleFile {
+    name: String,
+    effect: Effect,
+    actions: Vec<String>,
+    #[serde(default)]
+    when: Vec<String>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    name: String,
+    effect: Effect,
+    actions: Vec<String>,
+    conditions: Vec<Condition>,
+}
+
+impl Rule {
+    /// Actions match exactly, `*` matches everything and `documents:*` a whole prefix.
+    fn applies_to(&self, action: &str) -> bool {
+        self.actions
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => action.starts_with(prefix),
+                None => pattern == action,
+            })
+    }
+}
+
+/// A parsed, validated rule set.
+#[derive(Debug)]
+pub struct Policy {
+    default: Effect,
+    utc_offset: FixedOffset,
+    rules: Vec<Rule>,
+}
+
+impl Default for Policy {
+    /// The policy used when no file is configured: no rules, everything denied.
+    fn default() -> Self {
+        Self {
+            default: Effect::Deny,
+            utc_offset: FixedOffset::east_opt(0).expect("zero offset is valid"),
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl Policy {
+    pub fn parse(raw: &str) -> Result<Self, PolicyError> {
+        let file: PolicyFile =
+            toml::from_str(raw).map_err(|e| PolicyError::Syntax(e.to_string()))?;
+
+        let utc_offset = FixedOffset::east_opt(file.utc_offset_minutes * 60).ok_or_else(|| {
+            PolicyError::Syntax(format!(
+                "utc_offset_minutes {} is out of range",
+                file.utc_offset_minutes
+            ))
+        })?;
+
+        let mut names = HashSet::new();
+        let mut rules = Vec::with_capacity(file.rules.len());
+        for rule in file.rules {
+            let invalid = |reason: String| PolicyError::Rule {
+                rule: rule.name.clone(),
+                reason,
+            };
+
+            if rule.name.trim().is_empty() {
+                return Err(invalid("name must not be empty".to_string()));
+            }
+            if !names.insert(rule.name.clone()) {
+                return Err(invalid("name is used by another rule".to_string()));
+            }
+            if rule.actions.is_empty() {
+                return Err(invalid("must list at least one action".to_string()));
+            }
+
+            let conditions = rule
+                .when
+                .iter()
+                .map(|source| Condition::parse(source))
+                .collect::<Result<_, _>>()
+                .map_err(|e| invalid(e.to_string()))?;
+
+            rules.push(Rule {
+                name: rule.name,
+                effect: rule.effect,
+                actions: rule.actions,
+                conditions,
+            });
+        }
+
+        Ok(Self {
+            default: file.default,
+            utc_offset,
+            rules,
+        })
+    }
+
+    pub fn rule_count(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Decides whether `action` is permitted, tracing every rule that applies to it.
+    ///
+    /// All conditions are evaluated even after one fails so the trace explains each of them.
+    pub fn evaluate(&self, action: &str, context: &PolicyContext, now: DateTime<Utc>) -> Decision {
+        let attributes = json!({
+            "subject": context.subject,
+            "resource": context.resource,
+            "request": context.request,
+            "env": self.environment(now),
+        });
+
+        let rules: Vec<RuleTrace> = self
+            .rules
+            .iter()
+            .filter(|rule| rule.applies_to(action))
+            .map(|rule| {
+                let conditions: Vec<ConditionTrace> = rule
+                    .conditions
+                    .iter()
+                    .map(|condition| condition.evaluate(&attributes))
+                    .collect();
+
+                RuleTrace {
+                    name: rule.name.clone(),
+                    effect: rule.effect,
+                    matched: conditions.iter().all(|condition| condition.passed),
+                    conditions,
+                }
+            })
+            .collect();
+
+        let decisive = [Effect::Deny, Effect::Allow]
+            .into_iter()
+            .find_map(|effect| {
+                rules
+                    .iter()
+                    .find(|rule| rule.matched && rule.effect == effect)
+            });
+        let (effect, rule) = match decisive {
+            Some(rule) => (rule.effect, Some(rule.name.clone())),
+            None => (self.default, None),
+        };
+
+        Decision {
+            action: action.to_string(),
+            allowed: effect == Effect::Allow,
+            effect,
+            rule,
+            attributes,
+            rules,
+        }
+    }
+
+    fn environment(&self, now: DateTime<Utc>) -> Value {
+        let local = now.with_timezone(&self.utc_offset);
+
+        json!({
+            "timestamp": now.timestamp(),
+            "date": local.format("%Y-%m-%d").to_string(),
+            "hour": local.hour(),
+            "minute": local.minute(),
+            "weekday": local.weekday().number_from_monday(),
+        })
+    }
+}
+
+/// The `subject`, `resource` and `request` attributes of a policy check.
+#[derive(Clone, Debug, Default)]
+pub struct PolicyContext {
+    pub subject: Value,
+    pub resource: Value,
+    pub request: Value,
+}
+
+impl PolicyContext {
+    /// Context whose subject is the caller's token claims.
+    pub fn new(claims: &TokenClaims) -> Self {
+        Self {
+            subject: serde_json::to_value(claims).unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_resource(mut self, resource: Value) -> Self {
+        self.resource = resource;
+        self
+    }
+
+    pub fn with_request(mut self, req: &HttpRequest) -> Self {
+        self.request = json!({
+            "method": req.method().as_str(),
+            "path": req.path(),
+            "ip": client_ip(req).map(|ip| ip.to_string()),
+        });
+        self
+    }
+}
+
+/// The outcome of a policy check and how it was reached.
+#[derive(Clone, Debug, Serialize)]
+pub struct Decision {
+    pub action: String,
+    pub allowed: bool,
+    pub effect: Effect,
+    /// Rule that decided the outcome, `None` when the policy default applied.
+    pub rule: Option<String>,
+    /// The attribute document the conditions were evaluated against.
+    pub attributes: Value,
+    /// Every rule that applies to the action, in file order.
+    pub rules: Vec<RuleTrace>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RuleTrace {
+    pub name: String,
+    pub effect: Effect,
+    pub matched: bool,
+    pub conditions: Vec<ConditionTrace>,
+}
+
+#[derive(Debug)]
+pub enum PolicyError {
+    Read { path: String, reason: String },
+    Syntax(String),
+    Rule { rule: String, reason: String },
+}
+
+impl Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyError::Read { path, reason } => {
+                write!(f, "cannot read policy file {}: {}", path, reason)
+            }
+            PolicyError::Syntax(reason) => write!(f, "invalid policy file: {}", reason),
+            PolicyError::Rule { rule, reason } => write!(f, "invalid rule `{}`: {}", rule, reason),
+        }
+    }
+}
+
+impl std::error::Error for PolicyError {}
+
+struct Loaded {
+    policy: Arc<Policy>,
+    modified: Option<SystemTime>,
+}
+
+/// The active [`Policy`], swappable at runtime when its file changes.
+pub struct PolicyEngine {
+    path: Option<PathBuf>,
+    loaded: RwLock<Loaded>,
+}
+
+impl Default for PolicyEngine {
+    fn default() -> Self {
+        Policy::default().into()
+    }
+}
+
+impl From<Policy> for PolicyEngine {
+    fn from(policy: Policy) -> Self {
+        Self {
+            path: None,
+            loaded: RwLock::new(Loaded {
+                policy: Arc::new(policy),
+                modified: None,
+            }),
+        }
+    }
+}
+
+impl PolicyEngine {
+    /// Loads the configured policy file; a broken file fails startup rather than deny everything.
+    pub fn from_config(config: &PolicyConfig) -> Result<Self, PolicyError> {
+        let engine = Self {
+            path: config.path.as_ref().map(PathBuf::from),
+            ..Default::default()
+        };
+        engine.reload()?;
+
+        Ok(engine)
+    }
+
+    pub fn current(&self) -> Arc<Policy> {
+        let loaded = self.loaded.read().unwrap_or_else(PoisonError::into_inner);
+        Arc::clone(&loaded.policy)
+    }
+
+    /// Re-reads the policy file. Returns `false` when no file is configured.
+    ///
+    /// On error the previous policy stays in effect.
+    pub fn reload(&self) -> Result<bool, PolicyError> {
+        let Some(path) = &self.path else {
+            return Ok(false);
+        };
+        let read_error = |e: std::io::Error| PolicyError::Read {
+            path: path.display().to_string(),
+            reason: e.to_string(),
+        };
+
+        let modified = std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        let raw = std::fs::read_to_string(path).map_err(read_error)?;
+        let policy = Policy::parse(&raw)?;
+
+        *self.loaded.write().unwrap_or_else(PoisonError::into_inner) = Loaded {
+            policy: Arc::new(policy),
+            modified,
+        };
+        Ok(true)
+    }
+
+    /// Reloads the file only if its modification time changed since the last load.
+    pub fn reload_if_changed(&self) -> Result<bool, PolicyError> {
+        let Some(path) = &self.path else {
+            return Ok(false);
+        };
+
+        let modified = std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        let loaded = self
+            .loaded
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .modified;
+
+        if modified.is_some() && modified == loaded {
+            Ok(false)
+        } else {
+            self.reload()
+        }
+    }
+}
+
+/// Polls the policy file and swaps in new rules when it changes.
+pub fn spawn_policy_reloader(engine: Arc<PolicyEngine>, period: Duration) {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(period);
+
+        loop {
+            interval.tick().await;
+            match engine.reload_if_changed() {
+                Ok(true) => log::info!(
+                    "reloaded access policy ({} rules)",
+                    engine.current().rule_count()
+                ),
+                Ok(false) => {}
+                // Keep serving the last good policy until the file is fixed.
+                Err(e) => log::warn!("access policy reload failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    const POLICY: &str = r#"
+        utc_offset_minutes = 60
+
+        [[rules]]
+        name = "edit-own-org-in-business-hours"
+        effect = "allow"
+        actions = ["documents:edit"]
+        when = [
+            "subject.org_id == resource.org_id",
+            "env.weekday <= 5",
+            "env.hour >= 9",
+            "env.hour < 17",
+        ]
+
+        [[rules]]
+        name = "read-documents"
+        effect = "allow"
+        actions = ["documents:*"]
+        when = ["request.method == 'GET'"]
+
+        [[rules]]
+        name = "locked-documents"
+        effect = "deny"
+        actions = ["*"]
+        when = ["resource.locked == true"]
+    "#;
+
+    fn context(org_id: i32, resource: Value) -> PolicyContext {
+        PolicyContext {
+            subject: json!({ "sub": 1, "org_id": org_id }),
+            resource,
+            request: json!({ "method": "PUT" }),
+        }
+    }
+
+    /// Wednesday 2025-12-10 at the given UTC hour.
+    fn at(hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 12, 10, hour, 30, 0).unwrap()
+    }
+
+    #[test]
+    fn allows_edits_in_own_org_during_business_hours() {
+        let policy = Policy::parse(POLICY).unwrap();
+
+        let decision =
+            policy.evaluate("documents:edit", &context(3, json!({ "org_id": 3 })), at(9));
+
+        assert!(decision.allowed);
+        assert_eq!(
+            decision.rule.as_deref(),
+            Some("edit-own-org-in-business-hours")
+        );
+        assert_eq!(decision.attributes["env"]["hour"], 10);
+    }
+
+    #[test]
+    fn denies_other_orgs_and_after_hours_by_default() {
+        let policy = Policy::parse(POLICY).unwrap();
+
+        let other_org =
+            policy.evaluate("documents:edit", &context(4, json!({ "org_id": 3 })), at(9));
+        // 16:30 UTC is 17:30 at the policy's offset.
+        let after_hours = policy.evaluate(
+            "documents:edit",
+            &context(3, json!({ "org_id": 3 })),
+            at(16),
+        );
+
+        for decision in [other_org, after_hours] {
+            assert!(!decision.allowed);
+            assert_eq!(decision.rule, None);
+            assert_eq!(decision.rules.len(), 3);
+        }
+    }
+
+    #[test]
+    fn deny_rules_override_allows() {
+        let policy = Policy::parse(POLICY).unwrap();
+
+        let decision = policy.evaluate(
+            "documents:edit",
+            &context(3, json!({ "org_id": 3, "locked": true })),
+            at(9),
+        );
+
+        assert!(!decision.allowed);
+        assert_eq!(decision.rule.as_deref(), Some("locked-documents"));
+    }
+
+    #[test]
+    fn trace_explains_failed_conditions() {
+        let policy = Policy::parse(POLICY).unwrap();
+
+        let decision =
+            policy.evaluate("documents:edit", &context(4, json!({ "org_id": 3 })), at(9));
+
+        let rule = &decision.rules[0];
+        assert!(!rule.matched);
+        let failed: Vec<_> = rule
+            .conditions
+            .iter()
+            .filter(|condition| !condition.passed)
+            .map(|condition| condition.condition.as_str())
+            .collect();
+        assert_eq!(failed, ["subject.org_id == resource.org_id"]);
+    }
+
+    #[test]
+    fn unrelated_actions_fall_back_to_default() {
+        let policy = Policy::parse("default = \"allow\"").unwrap();
+
+        let decision = policy.evaluate("anything", &PolicyContext::default(), at(9));
+
+        assert!(decision.allowed);
+        assert!(decision.rules.is_empty());
+        assert!(
+            !PolicyEngine::default()
+                .current()
+                .evaluate("anything", &PolicyContext::default(), at(9))
+                .allowed
+        );
+    }
+
+    #[test]
+    fn example_policy_parses() {
+        let policy = Policy::parse(include_str!("../../policy.example.toml")).unwrap();
+
+        assert_eq!(policy.rule_count(), 4);
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        for raw in [
+            "[[rules]]\nname = \"a\"\neffect = \"allow\"\nactions = []",
+            "[[rules]]\nname = \"a\"\neffect = \"permit\"\nactions = [\"x\"]",
+            "[[rules]]\nname = \"a\"\neffect = \"allow\"\nactions = [\"x\"]\nwhen = [\"subject.sub\"]",
+            "[[rules]]\nname = \"a\"\neffect = \"allow\"\nactions = [\"x\"]\n[[rules]]\nname = \"a\"\neffect = \"deny\"\nactions = [\"x\"]",
+            "utc_offset_minutes = 100000",
+        ] {
+            assert!(Policy::parse(raw).is_err(), "{} should not parse", raw);
+        }
+    }
+
+    #[test]
+    fn reloads_when_the_file_changes() {
+        let path = std::env::temp_dir().join(format!("policy-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "").unwrap();
+        let engine = PolicyEngine::from_config(&PolicyConfig {
+            path: Some(path.display().to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(engine.current().rule_count(), 0);
+        assert!(!engine.reload_if_changed().unwrap());
+
+        std::fs::write(
+            &path,
+            "[[rules]]\nname = \"all\"\neffect = \"allow\"\nactions = [\"*\"]",
+        )
+        .unwrap();
+        assert!(engine.reload().unwrap());
+        assert_eq!(engine.current().rule_count(), 1);
+
+        // A broken edit keeps the last good policy.
+        std::fs::write(&path, "rules = 3").unwrap();
+        assert!(engine.reload().is_err());
+        assert_eq!(engine.current().rule_count(), 1);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,14 +1,127 @@
 use actix_web::web;
 
 use crate::handlers::{
-    auth_handler::{login, logout, register},
+    admin_handler::{grant_role, list_users, lock_status, revoke_role, unlock},
+    auth_handler::{login, logout, refresh, register},
+    email_handler::{update_email, verify_email},
+    invitation_handler::{accept_invitation, create_invitation, revoke_invitation},
+    key_handler::{jwks, list_keys, promote_key, retire_key},
+    magic_link_handler::{magic_link_callback, magic_link_page, request_magic_link},
+    mfa_handler::{confirm_totp, disable_totp, enroll_totp, regenerate_recovery_codes, verify_mfa},
+    organization_handler::{create_organization, list_members, switch_organization},
+    password_handler::{change_password, forgot_password, reset_password},
+    policy_handler::{explain, reload_policy},
+    session_handler::{list_sessions, revoke_session},
     user_handler::{index, profile},
+    webauthn_handler::{
+        delete_passkey, finish_passkey_login, finish_passkey_registration, finish_passkey_signup,
+        list_passkeys, start_passkey_login, start_passkey_registration, start_passkey_signup,
+    },
 };
+use crate::middleware::{JwtAuth, require_permission, require_policy};
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(index);
-    cfg.service(profile);
+    cfg.service(
+        web::scope("/me")
+            .wrap(JwtAuth)
+            .service(profile)
+            .service(update_email)
+            .service(change_password)
+            .service(list_sessions)
+            .service(revoke_session)
+            .service(enroll_totp)
+            .service(confirm_totp)
+            .service(disable_totp)
+            .service(regenerate_recovery_codes)
+            .service(start_passkey_registration)
+            .service(finish_passkey_registration)
+            .service(list_passkeys)
+            .service(delete_passkey),
+    );
+    cfg.service(
+        web::scope("/orgs")
+            .wrap(JwtAuth)
+            .service(create_organization)
+            .service(list_members)
+            .service(switch_organization)
+            .service(create_invitation)
+            .service(revoke_invitation),
+    );
+    cfg.service(accept_invitation);
     cfg.service(logout);
     cfg.service(login);
+    cfg.service(verify_mfa);
+    cfg.service(request_magic_link);
+    cfg.service(magic_link_page);
+    cfg.service(magic_link_callback);
     cfg.service(register);
+    cfg.service(start_passkey_signup);
+    cfg.service(finish_passkey_signup);
+    cfg.service(start_passkey_login);
+    cfg.service(finish_passkey_login);
+    cfg.service(refresh);
+    cfg.service(verify_email);
+    cfg.service(forgot_password);
+    cfg.service(reset_password);
+    cfg.service(jwks);
+    cfg.service(
+        web::scope("/admin/users")
+            .wrap(JwtAuth)
+            .service(
+                web::resource("")
+                    .wrap(require_permission("users:read"))
+                    .route(web::get().to(list_users)),
+            )
+            .service(
+                web::resource("/{id}/roles/{role}")
+                    .wrap(require_permission("users:write"))
+                    .route(web::put().to(grant_role))
+                    .route(web::delete().to(revoke_role)),
+            )
+            .service(
+                web::resource("/{id}/lock")
+                    .wrap(require_permission("users:read"))
+                    .route(web::get().to(lock_status)),
+            )
+            .service(
+                web::resource("/{id}/unlock")
+                    .wrap(require_permission("users:write"))
+                    .route(web::post().to(unlock)),
+            ),
+    );
+    cfg.service(
+        web::scope("/admin/keys")
+            .wrap(JwtAuth)
+            .service(
+                web::resource("")
+                    .wrap(require_permission("keys:read"))
+                    .route(web::get().to(list_keys)),
+            )
+            .service(
+                web::resource("/{kid}/promote")
+                    .wrap(require_permission("keys:write"))
+                    .route(web::post().to(promote_key)),
+            )
+            .service(
+                web::resource("/{kid}/retire")
+                    .wrap(require_permission("keys:write"))
+                    .route(web::post().to(retire_key)),
+            ),
+    );
+    cfg.service(
+        web::scope("/admin/policy")
+            .wrap(JwtAuth)
+            .service(
+                web::resource("/explain")
+                    .wrap(require_permission("policies:read"))
+                    .route(web::post().to(explain)),
+            )
+            .service(
+                web::resource("/reload")
+                    .wrap(require_policy("policies:reload"))
+                    .wrap(require_permission("policies:write"))
+                    .route(web::post().to(reload_policy)),
+            ),
+    );
 }
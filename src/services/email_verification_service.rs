@@ -0,0 +1,159 @@
+use chrono::{Duration, Utc};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set, sea_query::Expr};
+
+use crate::models::email_verification::{
+    ActiveModel as EmailVerificationActiveModel, Column as EmailVerificationColumn,
+    Entity as EmailVerificationEntity,
+};
+use crate::services::user_service::mark_email_verified;
+use crate::utils::{generate_opaque_token, hash_opaque_token};
+
+/// Result of presenting an email verification token.
+#[derive(Debug, PartialEq, Eq)]
+pub enum VerificationOutcome {
+    /// The user's address is now verified.
+    Verified { user_id: i32 },
+    /// No such token.
+    Invalid,
+    /// The token is past its expiry.
+    Expired,
+    /// The token was already used.
+    Used,
+    /// The user changed their address after the link was sent.
+    Superseded,
+}
+
+/// Issues a verification token for `email`. Only its hash is stored.
+pub async fn issue_email_verification(
+    db: &DatabaseConnection,
+    user_id: i32,
+    email: String,
+    ttl_secs: i64,
+) -> Result<String, sea_orm::DbErr> {
+    let token = generate_opaque_token();
+    let now = Utc::now();
+
+    let verification = EmailVerificationActiveModel {
+        user_id: Set(user_id),
+        email: Set(email),
+        token_hash: Set(hash_opaque_token(&token)),
+        expires_at: Set(now + Duration::seconds(ttl_secs)),
+        created_at: Set(now),
+        used_at: Set(None),
+        ..Default::default()
+    };
+
+    EmailVerificationEntity::insert(verification)
+        .exec_without_returning(db)
+        .await?;
+
+    Ok(token)
+}
+
+/// Spends a verification token and marks the address it was issued for as verified.
+pub async fn verify_email(
+    db: &DatabaseConnection,
+    presented: &str,
+) -> Result<VerificationOutcome, sea_orm::DbErr> {
+    let Some(verification) = EmailVerificationEntity::find()
+        .filter(EmailVerificationColumn::TokenHash.eq(hash_opaque_token(presented)))
+        .one(db)
+        .await?
+    else {
+        return Ok(VerificationOutcome::Invalid);
+    };
+
+    if verification.used_at.is_some() {
+        return Ok(VerificationOutcome::Used);
+    }
+    if verification.expires_at <= Utc::now() {
+        return Ok(VerificationOutcome::Expired);
+    }
+
+    // Conditional update so a token can only be spent once.
+    let claimed = EmailVerificationEntity::update_many()
+        .col_expr(EmailVerificationColumn::UsedAt, Expr::value(Utc::now()))
+        .filter(EmailVerificationColumn::Id.eq(verification.id))
+        .filter(EmailVerificationColumn::UsedAt.is_null())
+        .exec(db)
+        .await?;
+    if claimed.rows_affected == 0 {
+        return Ok(VerificationOutcome::Used);
+    }
+
+    if mark_email_verified(db, verification.user_id, &verification.email).await? {
+        Ok(VerificationOutcome::Verified {
+            user_id: verification.user_id,
+        })
+    } else {
+        Ok(VerificationOutcome::Superseded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{DatabaseBackend, MockDatabase};
+
+    use crate::models::email_verification::Model as EmailVerificationModel;
+    use crate::state::tests::exec;
+
+    use super::*;
+
+    fn stored(token: &str) -> EmailVerificationModel {
+        EmailVerificationModel {
+            id: 1,
+            user_id: 3,
+            email: "bob@example.com".into(),
+            token_hash: hash_opaque_token(token),
+            expires_at: Utc::now() + Duration::hours(1),
+            created_at: Utc::now(),
+            used_at: None,
+        }
+    }
+
+    #[actix_web::test]
+    async fn verifies_the_address_once() {
+        let mut used = stored("token");
+        used.used_at = Some(Utc::now());
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![stored("token")], vec![used]])
+            .append_exec_results([exec(1), exec(1)])
+            .into_connection();
+
+        assert_eq!(
+            verify_email(&db, "token").await.unwrap(),
+            VerificationOutcome::Verified { user_id: 3 }
+        );
+        assert_eq!(
+            verify_email(&db, "token").await.unwrap(),
+            VerificationOutcome::Used
+        );
+    }
+
+    #[actix_web::test]
+    async fn changed_addresses_are_not_verified() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![stored("token")]])
+            .append_exec_results([exec(1), exec(0)])
+            .into_connection();
+
+        assert_eq!(
+            verify_email(&db, "token").await.unwrap(),
+            VerificationOutcome::Superseded
+        );
+    }
+
+    #[actix_web::test]
+    async fn expired_tokens_are_rejected() {
+        let mut expired = stored("token");
+        expired.expires_at = Utc::now() - Duration::minutes(1);
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![expired]])
+            .into_connection();
+
+        assert_eq!(
+            verify_email(&db, "token").await.unwrap(),
+            VerificationOutcome::Expired
+        );
+    }
+}
@@ -0,0 +1,407 @@
+use chrono::{DateTime, Duration, Utc};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait,
+    QueryFilter, Set, TransactionTrait, sea_query::Expr,
+};
+
+use crate::models::invitation::{
+    ActiveModel as InvitationActiveModel, Column as InvitationColumn, Entity as InvitationEntity,
+    Model as InvitationModel,
+};
+use crate::models::invitation_event::{
+    ActiveModel as InvitationEventActiveModel, Entity as InvitationEventEntity,
+};
+use crate::models::membership::{ActiveModel as MembershipActiveModel, Entity as MembershipEntity};
+use crate::utils::{generate_opaque_token, hash_opaque_token};
+
+/// Lifecycle transitions recorded in `invitation_events`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InvitationEvent {
+    Created,
+    Accepted,
+    Revoked,
+}
+
+impl InvitationEvent {
+    fn as_str(self) -> &'static str {
+        match self {
+            InvitationEvent::Created => "created",
+            InvitationEvent::Accepted => "accepted",
+            InvitationEvent::Revoked => "revoked",
+        }
+    }
+}
+
+/// What an invite token refers to.
+#[derive(Debug, PartialEq, Eq)]
+pub enum InvitationLookup {
+    /// Can still be accepted.
+    Pending(InvitationModel),
+    /// No invitation has this token.
+    Invalid,
+    Expired,
+    /// Already accepted; tokens are single-use.
+    Used,
+    Revoked,
+}
+
+/// Result of revoking an invitation.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RevokeOutcome {
+    Revoked,
+    /// No such invitation in the organization.
+    NotFound,
+    /// Accepted invitations stay on record; remove the member instead.
+    AlreadyAccepted,
+}
+
+async fn record_event<C: ConnectionTrait>(
+    db: &C,
+    invitation_id: i32,
+    event: InvitationEvent,
+    actor_id: Option<i32>,
+) -> Result<(), DbErr> {
+    InvitationEventEntity::insert(InvitationEventActiveModel {
+        invitation_id: Set(invitation_id),
+        event: Set(event.as_str().to_string()),
+        actor_id: Set(actor_id),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    })
+    .exec_without_returning(db)
+    .await
+    .map(|_| ())
+}
+
+/// Invites `invitee` (a username or email address) into an organization with `role_id`.
+///
+/// Returns the invitation and the plaintext token for the invite link; only its hash is kept.
+pub async fn create_invitation(
+    db: &DatabaseConnection,
+    organization_id: i32,
+    invited_by: i32,
+    invitee: String,
+    role_id: i32,
+    ttl_secs: i64,
+) -> Result<(InvitationModel, String), DbErr> {
+    let token = generate_opaque_token();
+    let now = Utc::now();
+
+    let txn = db.begin().await?;
+    let invitation = InvitationActiveModel {
+        organization_id: Set(organization_id),
+        invitee: Set(invitee),
+        role_id: Set(role_id),
+        invited_by: Set(Some(invited_by)),
+        token_hash: Set(hash_opaque_token(&token)),
+        expires_at: Set(now + Duration::seconds(ttl_secs)),
+        created_at: Set(now),
+        accepted_at: Set(None),
+        accepted_by: Set(None),
+        revoked_at: Set(None),
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await?;
+    record_event(
+        &txn,
+        invitation.id,
+        InvitationEvent::Created,
+        Some(invited_by),
+    )
+    .await?;
+    txn.commit().await?;
+
+    Ok((invitation, token))
+}
+
+/// Resolves an invite token and reports whether it can still be accepted.
+pub async fn find_invitation(
+    db: &DatabaseConnection,
+    token: &str,
+) -> Result<InvitationLookup, DbErr> {
+    let Some(invitation) = InvitationEntity::find()
+        .filter(InvitationColumn::TokenHash.eq(hash_opaque_token(token)))
+        .one(db)
+        .await?
+    else {
+        return Ok(InvitationLookup::Invalid);
+    };
+
+    Ok(if invitation.accepted_at.is_some() {
+        InvitationLookup::Used
+    } else if invitation.revoked_at.is_some() {
+        InvitationLookup::Revoked
+    } else if invitation.expires_at <= Utc::now() {
+        InvitationLookup::Expired
+    } else {
+        InvitationLookup::Pending(invitation)
+    })
+}
+
+/// Spends the invitation, on behalf of `accepted_by` when already known.
+///
+/// Returns `false` if it was accepted, revoked or expired in the meantime. The update is
+/// conditional so two concurrent acceptances can't both succeed.
+pub async fn claim_invitation<C: ConnectionTrait>(
+    db: &C,
+    invitation: &InvitationModel,
+    accepted_by: Option<i32>,
+) -> Result<bool, DbErr> {
+    let now = Utc::now();
+    let claimed = InvitationEntity::update_many()
+        .col_expr(InvitationColumn::AcceptedAt, Expr::value(now))
+        .col_expr(InvitationColumn::AcceptedBy, Expr::value(accepted_by))
+        .filter(InvitationColumn::Id.eq(invitation.id))
+        .filter(InvitationColumn::AcceptedAt.is_null())
+        .filter(InvitationColumn::RevokedAt.is_null())
+        .filter(InvitationColumn::ExpiresAt.gt(now))
+        .exec(db)
+        .await?;
+
+    Ok(claimed.rows_affected > 0)
+}
+
+/// Hands back an invitation claimed for an account that then couldn't be created, so the
+/// invite link keeps working.
+pub async fn release_invitation(
+    db: &DatabaseConnection,
+    invitation: &InvitationModel,
+) -> Result<(), DbErr> {
+    InvitationEntity::update_many()
+        .col_expr(
+            InvitationColumn::AcceptedAt,
+            Expr::value(None::<DateTime<Utc>>),
+        )
+        .filter(InvitationColumn::Id.eq(invitation.id))
+        .filter(InvitationColumn::AcceptedBy.is_null())
+        .exec(db)
+        .await
+        .map(|_| ())
+}
+
+async fn join_organization<C: ConnectionTrait>(
+    db: &C,
+    invitation: &InvitationModel,
+    user_id: i32,
+) -> Result<(), DbErr> {
+    MembershipEntity::insert(MembershipActiveModel {
+        organization_id: Set(invitation.organization_id),
+        user_id: Set(user_id),
+        role_id: Set(invitation.role_id),
+        created_at: Set(Utc::now()),
+    })
+    .on_conflict_do_nothing()
+    .exec_without_returning(db)
+    .await?;
+    record_event(db, invitation.id, InvitationEvent::Accepted, Some(user_id)).await
+}
+
+/// Spends the invitation and makes `user_id` a member with the invited role.
+///
+/// Returns `false` if it was accepted, revoked or expired in the meantime. Users who already
+/// belong to the organization keep their current role.
+pub async fn accept_invitation(
+    db: &DatabaseConnection,
+    invitation: &InvitationModel,
+    user_id: i32,
+) -> Result<bool, DbErr> {
+    let txn = db.begin().await?;
+    if !claim_invitation(&txn, invitation, Some(user_id)).await? {
+        return Ok(false);
+    }
+    join_organization(&txn, invitation, user_id).await?;
+    txn.commit().await?;
+
+    Ok(true)
+}
+
+/// Finishes an invitation claimed with [`claim_invitation`] before its account existed:
+/// records the account and makes it a member with the invited role.
+pub async fn complete_invitation(
+    db: &DatabaseConnection,
+    invitation: &InvitationModel,
+    user_id: i32,
+) -> Result<(), DbErr> {
+    let txn = db.begin().await?;
+    InvitationEntity::update_many()
+        .col_expr(InvitationColumn::AcceptedBy, Expr::value(user_id))
+        .filter(InvitationColumn::Id.eq(invitation.id))
+        .exec(&txn)
+        .await?;
+    join_organization(&txn, invitation, user_id).await?;
+    txn.commit().await
+}
+
+/// Revokes a pending invitation of the organization. Revoking twice is not an error.
+pub async fn revoke_invitation(
+    db: &DatabaseConnection,
+    organization_id: i32,
+    invitation_id: i32,
+    actor_id: i32,
+) -> Result<RevokeOutcome, DbErr> {
+    let Some(invitation) = InvitationEntity::find_by_id(invitation_id)
+        .filter(InvitationColumn::OrganizationId.eq(organization_id))
+        .one(db)
+        .await?
+    else {
+        return Ok(RevokeOutcome::NotFound);
+    };
+
+    if invitation.accepted_at.is_some() {
+        return Ok(RevokeOutcome::AlreadyAccepted);
+    }
+    if invitation.revoked_at.is_some() {
+        return Ok(RevokeOutcome::Revoked);
+    }
+
+    let txn = db.begin().await?;
+    let revoked = InvitationEntity::update_many()
+        .col_expr(InvitationColumn::RevokedAt, Expr::value(Utc::now()))
+        .filter(InvitationColumn::Id.eq(invitation.id))
+        .filter(InvitationColumn::AcceptedAt.is_null())
+        .filter(InvitationColumn::RevokedAt.is_null())
+        .exec(&txn)
+        .await?;
+    if revoked.rows_affected == 0 {
+        // Accepted between the lookup and the update.
+        return Ok(RevokeOutcome::AlreadyAccepted);
+    }
+    record_event(
+        &txn,
+        invitation.id,
+        InvitationEvent::Revoked,
+        Some(actor_id),
+    )
+    .await?;
+    txn.commit().await?;
+
+    Ok(RevokeOutcome::Revoked)
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{DatabaseBackend, MockDatabase};
+
+    use crate::state::tests::exec;
+
+    use super::*;
+
+    fn invitation(token: &str) -> InvitationModel {
+        InvitationModel {
+            id: 7,
+            organization_id: 4,
+            invitee: "bob".into(),
+            role_id: 5,
+            invited_by: Some(1),
+            token_hash: hash_opaque_token(token),
+            expires_at: Utc::now() + Duration::days(1),
+            created_at: Utc::now(),
+            accepted_at: None,
+            accepted_by: None,
+            revoked_at: None,
+        }
+    }
+
+    fn event_names(db: DatabaseConnection) -> Vec<String> {
+        db.into_transaction_log()
+            .iter()
+            .flat_map(|transaction| transaction.statements())
+            .filter(|statement| {
+                statement
+                    .sql
+                    .starts_with(r#"INSERT INTO "invitation_events""#)
+            })
+            .map(|statement| statement.values.as_ref().unwrap().0[1].to_string())
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn lookup_reports_each_state() {
+        let mut expired = invitation("expired");
+        expired.expires_at = Utc::now() - Duration::minutes(1);
+        let mut used = invitation("used");
+        used.accepted_at = Some(Utc::now());
+        let mut revoked = invitation("revoked");
+        revoked.revoked_at = Some(Utc::now());
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([
+                vec![invitation("pending")],
+                vec![],
+                vec![expired],
+                vec![used],
+                vec![revoked],
+            ])
+            .into_connection();
+
+        assert!(matches!(
+            find_invitation(&db, "pending").await.unwrap(),
+            InvitationLookup::Pending(_)
+        ));
+        assert_eq!(
+            find_invitation(&db, "nope").await.unwrap(),
+            InvitationLookup::Invalid
+        );
+        assert_eq!(
+            find_invitation(&db, "expired").await.unwrap(),
+            InvitationLookup::Expired
+        );
+        assert_eq!(
+            find_invitation(&db, "used").await.unwrap(),
+            InvitationLookup::Used
+        );
+        assert_eq!(
+            find_invitation(&db, "revoked").await.unwrap(),
+            InvitationLookup::Revoked
+        );
+    }
+
+    #[actix_web::test]
+    async fn accepting_is_single_use_and_audited() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_exec_results([exec(1), exec(1), exec(1), exec(0)])
+            .into_connection();
+        let pending = invitation("token");
+
+        assert!(accept_invitation(&db, &pending, 2).await.unwrap());
+        assert!(!accept_invitation(&db, &pending, 3).await.unwrap());
+
+        assert_eq!(event_names(db), ["'accepted'"]);
+    }
+
+    #[actix_web::test]
+    async fn revoking_records_an_event_once() {
+        let mut accepted = invitation("accepted");
+        accepted.accepted_at = Some(Utc::now());
+        let mut revoked = invitation("revoked");
+        revoked.revoked_at = Some(Utc::now());
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([
+                vec![invitation("token")],
+                vec![revoked],
+                vec![accepted],
+                vec![],
+            ])
+            .append_exec_results([exec(1), exec(1)])
+            .into_connection();
+
+        assert_eq!(
+            revoke_invitation(&db, 4, 7, 1).await.unwrap(),
+            RevokeOutcome::Revoked
+        );
+        assert_eq!(
+            revoke_invitation(&db, 4, 7, 1).await.unwrap(),
+            RevokeOutcome::Revoked
+        );
+        assert_eq!(
+            revoke_invitation(&db, 4, 7, 1).await.unwrap(),
+            RevokeOutcome::AlreadyAccepted
+        );
+        assert_eq!(
+            revoke_invitation(&db, 4, 8, 1).await.unwrap(),
+            RevokeOutcome::NotFound
+        );
+
+        assert_eq!(event_names(db), ["'revoked'"]);
+    }
+}
@@ -0,0 +1,127 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use sea_orm::{DatabaseConnection, DbErr, EntityTrait, Set, sea_query::OnConflict};
+
+use crate::models::signing_key_state::{
+    ActiveModel as SigningKeyStateActiveModel, Column as SigningKeyStateColumn,
+    Entity as SigningKeyStateEntity,
+};
+use crate::utils::KeyRing;
+use crate::utils::jwt_keys::{KeyState, KeyStatus};
+
+/// Loads the key states saved by [`save_key_states`], for [`crate::utils::KeyRing::restore_states`].
+pub async fn load_key_states(db: &DatabaseConnection) -> Result<Vec<(String, KeyState)>, DbErr> {
+    SigningKeyStateEntity::find()
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|saved| {
+            let state = saved
+                .state
+                .parse()
+                .map_err(|e| DbErr::Custom(format!("key {}: {}", saved.kid, e)))?;
+            Ok((saved.kid, state))
+        })
+        .collect()
+}
+
+/// Saves the state of every key in the ring so a restart keeps the rotation.
+pub async fn save_key_states(db: &DatabaseConnection, keys: &[KeyStatus]) -> Result<(), DbErr> {
+    let now = Utc::now();
+    let rows = keys.iter().map(|key| SigningKeyStateActiveModel {
+        kid: Set(key.kid.clone()),
+        state: Set(key.state.as_str().to_string()),
+        updated_at: Set(now),
+    });
+
+    SigningKeyStateEntity::insert_many(rows)
+        .on_conflict(
+            OnConflict::column(SigningKeyStateColumn::Kid)
+                .update_columns([
+                    SigningKeyStateColumn::State,
+                    SigningKeyStateColumn::UpdatedAt,
+                ])
+                .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await
+        .map(|_| ())
+}
+
+/// Puts the ring into the states last saved by any replica.
+pub async fn reload_key_states(db: &DatabaseConnection, ring: &KeyRing) -> Result<(), String> {
+    let _rotation = ring.lock_rotation().await;
+    let saved = load_key_states(db).await.map_err(|e| e.to_string())?;
+    ring.restore_states(&saved).map_err(|e| e.to_string())
+}
+
+/// Reloads the saved key states every `period`, so a rotation made through one replica
+/// reaches the others.
+pub fn spawn_key_state_reloader(db: DatabaseConnection, ring: Arc<KeyRing>, period: Duration) {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(period);
+
+        loop {
+            interval.tick().await;
+            // Keep the current states until the saved ones can be read and applied.
+            if let Err(e) = reload_key_states(&db, &ring).await {
+                log::warn!("JWT key state reload failed: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonwebtoken::Algorithm;
+    use sea_orm::{DatabaseBackend, MockDatabase};
+
+    use crate::models::signing_key_state::Model as SigningKeyStateModel;
+    use crate::utils::jwt_keys::tests::fixture_key;
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn load_rejects_unknown_states() {
+        let saved = |state: &str| SigningKeyStateModel {
+            kid: "key-1".into(),
+            state: state.into(),
+            updated_at: Utc::now(),
+        };
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![saved("verify_only")], vec![saved("revoked")]])
+            .into_connection();
+
+        assert_eq!(
+            load_key_states(&db).await.unwrap(),
+            [("key-1".to_string(), KeyState::VerifyOnly)]
+        );
+        assert!(load_key_states(&db).await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn reload_applies_states_saved_elsewhere() {
+        let ring = KeyRing::new(vec![
+            (fixture_key(Algorithm::EdDSA), KeyState::Active),
+            (fixture_key(Algorithm::ES256), KeyState::VerifyOnly),
+        ])
+        .unwrap();
+        let saved = |kid: &str, state: &str| SigningKeyStateModel {
+            kid: kid.into(),
+            state: state.into(),
+            updated_at: Utc::now(),
+        };
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![
+                saved("EdDSA-key", "verify_only"),
+                saved("ES256-key", "active"),
+            ]])
+            .into_connection();
+
+        reload_key_states(&db, &ring).await.unwrap();
+
+        assert_eq!(ring.active().unwrap().kid, "ES256-key");
+    }
+}
@@ -0,0 +1,294 @@
+use std::net::IpAddr;
+use std::time::Duration as StdDuration;
+
+use chrono::{DateTime, Duration, Utc};
+use sea_orm::{
+    ColumnTrait, Condition, DatabaseBackend, DatabaseConnection, DbErr, EntityTrait, QueryFilter,
+    Statement,
+};
+
+use crate::config::LoginThrottleConfig;
+use crate::models::login_throttle::{
+    Column as LoginThrottleColumn, Entity as LoginThrottleEntity, Model as LoginThrottleModel,
+};
+use crate::services::spawn_sweeper;
+use crate::utils::canonical_username;
+
+/// Counts a failure, restarting the count once the previous one is outside the window, and
+/// locks the key when the count reaches the limit. One statement, so concurrent failures
+/// are all counted.
+const RECORD_FAILURE_SQL: &str = "\
+INSERT INTO login_throttles (key, failures, last_failure_at, locked_until) \
+VALUES ($1, 1, $2, CASE WHEN 1 >= $4 THEN $5 END) \
+ON CONFLICT (key) DO UPDATE SET \
+    failures = CASE WHEN login_throttles.last_failure_at > $3 \
+        THEN login_throttles.failures + 1 ELSE 1 END, \
+    last_failure_at = EXCLUDED.last_failure_at, \
+    locked_until = CASE WHEN (CASE WHEN login_throttles.last_failure_at > $3 \
+        THEN login_throttles.failures + 1 ELSE 1 END) >= $4 \
+        THEN $5 ELSE login_throttles.locked_until END \
+RETURNING key, failures, last_failure_at, locked_until";
+
+/// Whether a key may attempt a login now.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Throttle {
+    Open,
+    /// Too soon after the last failure.
+    Delayed {
+        until: DateTime<Utc>,
+    },
+    /// Locked out after too many failures.
+    Locked {
+        until: DateTime<Utc>,
+    },
+}
+
+impl Throttle {
+    /// Whole seconds until the next attempt is allowed, for `Retry-After`.
+    pub fn retry_after_secs(&self, now: DateTime<Utc>) -> i64 {
+        match self {
+            Throttle::Open => 0,
+            Throttle::Delayed { until } | Throttle::Locked { until } => {
+                let millis = (*until - now).num_milliseconds();
+                ((millis + 999) / 1000).max(1)
+            }
+        }
+    }
+
+    /// The stricter of two throttles: locks beat delays, later beats sooner.
+    fn max(self, other: Throttle) -> Throttle {
+        match (self, other) {
+            (Throttle::Locked { until: a }, Throttle::Locked { until: b }) => {
+                Throttle::Locked { until: a.max(b) }
+            }
+            (locked @ Throttle::Locked { .. }, _) | (_, locked @ Throttle::Locked { .. }) => locked,
+            (Throttle::Delayed { until: a }, Throttle::Delayed { until: b }) => {
+                Throttle::Delayed { until: a.max(b) }
+            }
+            (delayed @ Throttle::Delayed { .. }, _) | (_, delayed @ Throttle::Delayed { .. }) => {
+                delayed
+            }
+            (Throttle::Open, Throttle::Open) => Throttle::Open,
+        }
+    }
+}
+
+/// Throttle key of a username, in canonical form so variants share one count.
+pub fn user_key(username: &str) -> String {
+    format!("user:{}", canonical_username(username))
+}
+
+pub fn ip_key(ip: IpAddr) -> String {
+    format!("ip:{}", ip)
+}
+
+/// Wait after `failures` failures: the base delay, doubled per further failure, capped.
+fn delay(config: &LoginThrottleConfig, failures: i32) -> Duration {
+    let doublings = (failures - 1).clamp(0, 30) as u32;
+    let secs = config
+        .base_delay_secs
+        .saturating_mul(1 << doublings)
+        .min(config.max_delay_secs);
+
+    Duration::seconds(secs)
+}
+
+/// What a stored entry means at `now`.
+pub fn throttle_of(
+    config: &LoginThrottleConfig,
+    entry: &LoginThrottleModel,
+    now: DateTime<Utc>,
+) -> Throttle {
+    if let Some(until) = entry.locked_until
+        && until > now
+    {
+        return Throttle::Locked { until };
+    }
+    if entry.last_failure_at <= now - Duration::seconds(config.failure_window_secs) {
+        return Throttle::Open;
+    }
+
+    let until = entry.last_failure_at + delay(config, entry.failures);
+    if until > now {
+        Throttle::Delayed { until }
+    } else {
+        Throttle::Open
+    }
+}
+
+/// The strictest throttle across `keys`, e.g. the username and the client IP of a login.
+pub async fn check_login_throttle(
+    db: &DatabaseConnection,
+    config: &LoginThrottleConfig,
+    keys: &[String],
+) -> Result<Throttle, DbErr> {
+    let now = Utc::now();
+    let entries = LoginThrottleEntity::find()
+        .filter(LoginThrottleColumn::Key.is_in(keys.iter().cloned()))
+        .all(db)
+        .await?;
+
+    Ok(entries
+        .iter()
+        .map(|entry| throttle_of(config, entry, now))
+        .fold(Throttle::Open, Throttle::max))
+}
+
+/// Records a failed login against `key`, locking it once `max_failures` is reached.
+pub async fn record_login_failure(
+    db: &DatabaseConnection,
+    config: &LoginThrottleConfig,
+    key: &str,
+    max_failures: i32,
+) -> Result<LoginThrottleModel, DbErr> {
+    let now = Utc::now();
+    let statement = Statement::from_sql_and_values(
+        DatabaseBackend::Postgres,
+        RECORD_FAILURE_SQL,
+        [
+            key.into(),
+            now.into(),
+            (now - Duration::seconds(config.failure_window_secs)).into(),
+            max_failures.into(),
+            (now + Duration::seconds(config.lockout_secs)).into(),
+        ],
+    );
+
+    LoginThrottleEntity::find()
+        .from_raw_sql(statement)
+        .one(db)
+        .await?
+        .ok_or_else(|| DbErr::RecordNotFound("login throttle upsert returned nothing".into()))
+}
+
+/// Fetches the entry of a key, if it has recent failures.
+pub async fn find_login_throttle(
+    db: &DatabaseConnection,
+    key: &str,
+) -> Result<Option<LoginThrottleModel>, DbErr> {
+    LoginThrottleEntity::find_by_id(key.to_string())
+        .one(db)
+        .await
+}
+
+/// Forgets the failures of a key and lifts its lock. Returns whether there were any.
+pub async fn clear_login_throttle(db: &DatabaseConnection, key: &str) -> Result<bool, DbErr> {
+    LoginThrottleEntity::delete_by_id(key.to_string())
+        .exec(db)
+        .await
+        .map(|result| result.rows_affected > 0)
+}
+
+/// Deletes entries whose failures have aged out and that are no longer locked.
+pub async fn delete_stale_login_throttles(
+    db: &DatabaseConnection,
+    config: &LoginThrottleConfig,
+    now: DateTime<Utc>,
+) -> Result<u64, DbErr> {
+    LoginThrottleEntity::delete_many()
+        .filter(
+            LoginThrottleColumn::LastFailureAt
+                .lte(now - Duration::seconds(config.failure_window_secs)),
+        )
+        .filter(
+            Condition::any()
+                .add(LoginThrottleColumn::LockedUntil.is_null())
+                .add(LoginThrottleColumn::LockedUntil.lte(now)),
+        )
+        .exec(db)
+        .await
+        .map(|result| result.rows_affected)
+}
+
+/// Spawns a background task that periodically purges stale throttle entries.
+pub fn spawn_login_throttle_sweeper(
+    db: DatabaseConnection,
+    config: LoginThrottleConfig,
+    period: StdDuration,
+) {
+    spawn_sweeper("login throttle", period, move || {
+        let db = db.clone();
+        let config = config.clone();
+        async move { delete_stale_login_throttles(&db, &config, Utc::now()).await }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::MockDatabase;
+
+    use super::*;
+
+    fn entry(failures: i32, secs_ago: i64) -> LoginThrottleModel {
+        LoginThrottleModel {
+            key: "user:alice".into(),
+            failures,
+            last_failure_at: Utc::now() - Duration::seconds(secs_ago),
+            locked_until: None,
+        }
+    }
+
+    #[test]
+    fn delay_doubles_up_to_the_cap() {
+        let config = LoginThrottleConfig {
+            base_delay_secs: 2,
+            max_delay_secs: 10,
+            ..Default::default()
+        };
+
+        let delays: Vec<_> = (1..=5)
+            .map(|failures| delay(&config, failures).num_seconds())
+            .collect();
+        assert_eq!(delays, [2, 4, 8, 10, 10]);
+        assert_eq!(delay(&config, i32::MAX).num_seconds(), 10);
+    }
+
+    #[test]
+    fn entries_delay_lock_and_age_out() {
+        let config = LoginThrottleConfig::default();
+        let now = Utc::now();
+
+        assert!(matches!(
+            throttle_of(&config, &entry(3, 1), now),
+            Throttle::Delayed { .. }
+        ));
+        assert_eq!(throttle_of(&config, &entry(1, 5), now), Throttle::Open);
+
+        let mut locked = entry(5, 1);
+        locked.locked_until = Some(now + Duration::minutes(10));
+        assert!(matches!(
+            throttle_of(&config, &locked, now),
+            Throttle::Locked { .. }
+        ));
+        assert_eq!(
+            throttle_of(&config, &locked, now).retry_after_secs(now),
+            600
+        );
+
+        let expired = LoginThrottleModel {
+            locked_until: Some(now - Duration::seconds(1)),
+            ..entry(5, config.failure_window_secs + 1)
+        };
+        assert_eq!(throttle_of(&config, &expired, now), Throttle::Open);
+    }
+
+    #[actix_web::test]
+    async fn the_strictest_key_wins() {
+        let mut locked = entry(50, 1);
+        locked.key = "ip:10.0.0.1".into();
+        locked.locked_until = Some(Utc::now() + Duration::minutes(1));
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![entry(2, 1), locked]])
+            .into_connection();
+
+        let throttle = check_login_throttle(
+            &db,
+            &LoginThrottleConfig::default(),
+            &[user_key("Alice"), "ip:10.0.0.1".into()],
+        )
+        .await
+        .unwrap();
+
+        assert!(matches!(throttle, Throttle::Locked { .. }));
+    }
+}
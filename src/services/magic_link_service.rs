@@ -0,0 +1,189 @@
+use chrono::{DateTime, Duration, Utc};
+use sea_orm::{
+    ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter,
+    QueryOrder, Set, Statement, TransactionTrait, sea_query::Expr,
+};
+
+use crate::models::magic_link::{
+    ActiveModel as MagicLinkActiveModel, Column as MagicLinkColumn, Entity as MagicLinkEntity,
+    Model as MagicLinkModel,
+};
+use crate::utils::{generate_opaque_token, hash_opaque_token};
+
+/// Outcome of [`issue_magic_link`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum MagicLinkIssue {
+    /// The new sign-in token, to be mailed out.
+    Issued(String),
+    /// The address used up its links for the window; another one is allowed at this time.
+    Limited(DateTime<Utc>),
+}
+
+/// Issues a sign-in token for `email` unless the address already requested `max_per_address`
+/// links within the last `window_secs`. Only its hash is stored.
+///
+/// Counting and inserting happen under a per-address lock, so concurrent requests can't slip
+/// past the limit together. Links that neither work nor count towards a limit any more are
+/// pruned along the way.
+pub async fn issue_magic_link(
+    db: &DatabaseConnection,
+    email: &str,
+    ttl_secs: i64,
+    window_secs: i64,
+    max_per_address: usize,
+) -> Result<MagicLinkIssue, DbErr> {
+    let token = generate_opaque_token();
+    let now = Utc::now();
+    let window = Duration::seconds(window_secs);
+
+    let txn = db.begin().await?;
+    txn.execute_raw(Statement::from_sql_and_values(
+        txn.get_database_backend(),
+        "SELECT pg_advisory_xact_lock(hashtext($1))",
+        [format!("magic_link:{}", email).into()],
+    ))
+    .await?;
+
+    let recent = MagicLinkEntity::find()
+        .filter(MagicLinkColumn::Email.eq(email))
+        .filter(MagicLinkColumn::CreatedAt.gt(now - window))
+        .order_by_asc(MagicLinkColumn::CreatedAt)
+        .all(&txn)
+        .await?;
+    if recent.len() >= max_per_address {
+        // The limit frees up once enough of the oldest requests leave the window.
+        let freed_by = &recent[recent.len() - max_per_address];
+        return Ok(MagicLinkIssue::Limited(freed_by.created_at + window));
+    }
+
+    MagicLinkEntity::delete_many()
+        .filter(
+            Condition::all()
+                .add(MagicLinkColumn::ExpiresAt.lte(now))
+                .add(MagicLinkColumn::CreatedAt.lte(now - window)),
+        )
+        .exec(&txn)
+        .await?;
+
+    let link = MagicLinkActiveModel {
+        email: Set(email.to_string()),
+        token_hash: Set(hash_opaque_token(&token)),
+        expires_at: Set(now + Duration::seconds(ttl_secs)),
+        created_at: Set(now),
+        used_at: Set(None),
+        ..Default::default()
+    };
+    MagicLinkEntity::insert(link)
+        .exec_without_returning(&txn)
+        .await?;
+    txn.commit().await?;
+
+    Ok(MagicLinkIssue::Issued(token))
+}
+
+/// Resolves a sign-in token that can still be redeemed.
+///
+/// Unknown, used and expired tokens all yield `None` so callers can't tell them apart.
+pub async fn find_magic_link(
+    db: &DatabaseConnection,
+    presented: &str,
+) -> Result<Option<MagicLinkModel>, DbErr> {
+    let link = MagicLinkEntity::find()
+        .filter(MagicLinkColumn::TokenHash.eq(hash_opaque_token(presented)))
+        .one(db)
+        .await?;
+
+    Ok(link.filter(|link| link.used_at.is_none() && link.expires_at > Utc::now()))
+}
+
+/// Spends a link found by [`find_magic_link`].
+///
+/// Returns `false` if it was spent or expired in the meantime.
+pub async fn redeem_magic_link(
+    db: &DatabaseConnection,
+    link: &MagicLinkModel,
+) -> Result<bool, DbErr> {
+    let now = Utc::now();
+
+    // Conditional update so a link can only be used once.
+    let claimed = MagicLinkEntity::update_many()
+        .col_expr(MagicLinkColumn::UsedAt, Expr::value(now))
+        .filter(MagicLinkColumn::Id.eq(link.id))
+        .filter(MagicLinkColumn::UsedAt.is_null())
+        .filter(MagicLinkColumn::ExpiresAt.gt(now))
+        .exec(db)
+        .await?;
+
+    Ok(claimed.rows_affected > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{DatabaseBackend, MockDatabase};
+
+    use crate::state::tests::exec;
+
+    use super::*;
+
+    fn stored(token: &str) -> MagicLinkModel {
+        MagicLinkModel {
+            id: 1,
+            email: "bob@example.com".into(),
+            token_hash: hash_opaque_token(token),
+            expires_at: Utc::now() + Duration::minutes(15),
+            created_at: Utc::now(),
+            used_at: None,
+        }
+    }
+
+    #[actix_web::test]
+    async fn links_work_once_and_only_until_they_expire() {
+        let mut used = stored("used");
+        used.used_at = Some(Utc::now());
+        let mut expired = stored("expired");
+        expired.expires_at = Utc::now() - Duration::seconds(1);
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![stored("token")], vec![used], vec![expired]])
+            .append_exec_results([exec(1), exec(0)])
+            .into_connection();
+
+        let link = find_magic_link(&db, "token").await.unwrap().unwrap();
+        assert_eq!(find_magic_link(&db, "used").await.unwrap(), None);
+        assert_eq!(find_magic_link(&db, "expired").await.unwrap(), None);
+
+        assert!(redeem_magic_link(&db, &link).await.unwrap());
+        // A concurrent redemption loses the conditional update.
+        assert!(!redeem_magic_link(&db, &link).await.unwrap());
+    }
+
+    #[actix_web::test]
+    async fn addresses_are_locked_while_their_links_are_counted() {
+        let recent: Vec<_> = (0..2).map(|_| stored("old")).collect();
+        let freed_at = recent[1].created_at + Duration::hours(1);
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_exec_results([exec(1)])
+            .append_query_results([recent])
+            .into_connection();
+
+        assert_eq!(
+            issue_magic_link(&db, "bob@example.com", 900, 3600, 1)
+                .await
+                .unwrap(),
+            MagicLinkIssue::Limited(freed_at)
+        );
+
+        let log = db.into_transaction_log();
+        let statements = log[0].statements();
+        assert_eq!(
+            statements[1].sql,
+            "SELECT pg_advisory_xact_lock(hashtext($1))"
+        );
+        assert!(statements[2].sql.starts_with(r#"SELECT "magic_links"."#));
+        // Nothing is stored once the limit is hit.
+        assert!(
+            !statements
+                .iter()
+                .any(|statement| statement.sql.starts_with("INSERT"))
+        );
+    }
+}
@@ -0,0 +1,366 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::{Duration, Utc};
+use sea_orm::{
+    ColumnTrait, Condition, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set,
+    TransactionTrait,
+    sea_query::{Expr, ExprTrait, OnConflict},
+};
+
+use crate::models::mfa_challenge::{
+    ActiveModel as MfaChallengeActiveModel, Column as MfaChallengeColumn,
+    Entity as MfaChallengeEntity, Model as MfaChallengeModel,
+};
+use crate::models::recovery_code::{
+    ActiveModel as RecoveryCodeActiveModel, Column as RecoveryCodeColumn,
+    Entity as RecoveryCodeEntity,
+};
+use crate::models::totp_credential::{
+    ActiveModel as TotpCredentialActiveModel, Column as TotpCredentialColumn,
+    Entity as TotpCredentialEntity, Model as TotpCredentialModel,
+};
+use crate::utils::{
+    base32_decode, base32_encode, generate_opaque_token, hash_opaque_token, verify_totp,
+};
+
+/// Characters of a recovery code, skipping look-alikes such as `0`/`o` and `1`/`l`.
+const RECOVERY_CODE_ALPHABET: &[u8; 32] = b"abcdefghijkmnpqrstuvwxyz23456789";
+
+/// Generates a recovery code like `7kq2m-xw9dp`: ten characters, 50 bits of entropy.
+fn generate_recovery_code() -> String {
+    let mut bytes = [0u8; 10];
+    OsRng.fill_bytes(&mut bytes);
+
+    let code: String = bytes
+        .iter()
+        .map(|byte| RECOVERY_CODE_ALPHABET[(byte & 31) as usize] as char)
+        .collect();
+    format!("{}-{}", &code[..5], &code[5..])
+}
+
+/// Hash of a recovery code as typed, ignoring case, spaces and dashes.
+fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+
+    hash_opaque_token(&normalized)
+}
+
+/// The user's TOTP credential, confirmed or still being enrolled.
+pub async fn find_totp_credential(
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> Result<Option<TotpCredentialModel>, DbErr> {
+    TotpCredentialEntity::find_by_id(user_id).one(db).await
+}
+
+/// Stores a fresh, unconfirmed TOTP key for the user, replacing an earlier unfinished
+/// enrollment.
+pub async fn start_totp_enrollment(
+    db: &DatabaseConnection,
+    user_id: i32,
+    secret: &[u8],
+) -> Result<(), DbErr> {
+    let credential = TotpCredentialActiveModel {
+        user_id: Set(user_id),
+        secret: Set(base32_encode(secret)),
+        confirmed_at: Set(None),
+        last_used_step: Set(None),
+        created_at: Set(Utc::now()),
+    };
+
+    TotpCredentialEntity::insert(credential)
+        .on_conflict(
+            OnConflict::column(TotpCredentialColumn::UserId)
+                .update_columns([
+                    TotpCredentialColumn::Secret,
+                    TotpCredentialColumn::ConfirmedAt,
+                    TotpCredentialColumn::LastUsedStep,
+                    TotpCredentialColumn::CreatedAt,
+                ])
+                .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await
+        .map(|_| ())
+}
+
+/// Checks a code from the authenticator app and marks its time step as used.
+///
+/// Returns `false` for wrong codes and for a code whose step was already accepted, so each
+/// code works once even when two requests race.
+pub async fn redeem_totp_code(
+    db: &DatabaseConnection,
+    credential: &TotpCredentialModel,
+    code: &str,
+) -> Result<bool, DbErr> {
+    let Some(secret) = base32_decode(&credential.secret) else {
+        return Ok(false);
+    };
+    let Some(step) = verify_totp(&secret, code, Utc::now()) else {
+        return Ok(false);
+    };
+
+    let claimed = TotpCredentialEntity::update_many()
+        .col_expr(TotpCredentialColumn::LastUsedStep, Expr::value(step))
+        .filter(TotpCredentialColumn::UserId.eq(credential.user_id))
+        .filter(TotpCredentialColumn::Secret.eq(credential.secret.clone()))
+        .filter(
+            Condition::any()
+                .add(TotpCredentialColumn::LastUsedStep.is_null())
+                .add(TotpCredentialColumn::LastUsedStep.lt(step)),
+        )
+        .exec(db)
+        .await?;
+
+    Ok(claimed.rows_affected > 0)
+}
+
+/// Turns on the second factor for the user and hands out their first recovery codes.
+pub async fn confirm_totp_enrollment(
+    db: &DatabaseConnection,
+    user_id: i32,
+    recovery_code_count: usize,
+) -> Result<Vec<String>, DbErr> {
+    let txn = db.begin().await?;
+    TotpCredentialEntity::update_many()
+        .col_expr(TotpCredentialColumn::ConfirmedAt, Expr::value(Utc::now()))
+        .filter(TotpCredentialColumn::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+    let codes = store_recovery_codes(&txn, user_id, recovery_code_count).await?;
+    txn.commit().await?;
+
+    Ok(codes)
+}
+
+/// Replaces the user's recovery codes with a new batch, returned in plain text. Only their
+/// hashes are stored.
+pub async fn replace_recovery_codes(
+    db: &DatabaseConnection,
+    user_id: i32,
+    count: usize,
+) -> Result<Vec<String>, DbErr> {
+    let txn = db.begin().await?;
+    let codes = store_recovery_codes(&txn, user_id, count).await?;
+    txn.commit().await?;
+
+    Ok(codes)
+}
+
+async fn store_recovery_codes<C: sea_orm::ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+    count: usize,
+) -> Result<Vec<String>, DbErr> {
+    RecoveryCodeEntity::delete_many()
+        .filter(RecoveryCodeColumn::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+
+    let now = Utc::now();
+    let codes: Vec<String> = (0..count).map(|_| generate_recovery_code()).collect();
+    RecoveryCodeEntity::insert_many(codes.iter().map(|code| RecoveryCodeActiveModel {
+        user_id: Set(user_id),
+        code_hash: Set(hash_recovery_code(code)),
+        created_at: Set(now),
+        used_at: Set(None),
+        ..Default::default()
+    }))
+    .exec_without_returning(db)
+    .await?;
+
+    Ok(codes)
+}
+
+/// Spends one of the user's recovery codes. Returns `false` for unknown or used codes.
+pub async fn redeem_recovery_code(
+    db: &DatabaseConnection,
+    user_id: i32,
+    code: &str,
+) -> Result<bool, DbErr> {
+    // Conditional update so a code can only be spent once.
+    let claimed = RecoveryCodeEntity::update_many()
+        .col_expr(RecoveryCodeColumn::UsedAt, Expr::value(Utc::now()))
+        .filter(RecoveryCodeColumn::UserId.eq(user_id))
+        .filter(RecoveryCodeColumn::CodeHash.eq(hash_recovery_code(code)))
+        .filter(RecoveryCodeColumn::UsedAt.is_null())
+        .exec(db)
+        .await?;
+
+    Ok(claimed.rows_affected > 0)
+}
+
+/// Turns the second factor off, dropping the TOTP key and every recovery code.
+pub async fn disable_totp(db: &DatabaseConnection, user_id: i32) -> Result<(), DbErr> {
+    let txn = db.begin().await?;
+    RecoveryCodeEntity::delete_many()
+        .filter(RecoveryCodeColumn::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+    TotpCredentialEntity::delete_by_id(user_id)
+        .exec(&txn)
+        .await?;
+    txn.commit().await
+}
+
+/// Issues the challenge token a password login hands out to users with a second factor,
+/// pruning the user's spent and expired ones. Only its hash is stored.
+pub async fn issue_mfa_challenge(
+    db: &DatabaseConnection,
+    user_id: i32,
+    ttl_secs: i64,
+) -> Result<String, DbErr> {
+    let token = generate_opaque_token();
+    let now = Utc::now();
+
+    MfaChallengeEntity::delete_many()
+        .filter(MfaChallengeColumn::UserId.eq(user_id))
+        .filter(
+            Condition::any()
+                .add(MfaChallengeColumn::UsedAt.is_not_null())
+                .add(MfaChallengeColumn::ExpiresAt.lte(now)),
+        )
+        .exec(db)
+        .await?;
+
+    let challenge = MfaChallengeActiveModel {
+        user_id: Set(user_id),
+        token_hash: Set(hash_opaque_token(&token)),
+        failed_attempts: Set(0),
+        expires_at: Set(now + Duration::seconds(ttl_secs)),
+        created_at: Set(now),
+        used_at: Set(None),
+        ..Default::default()
+    };
+    MfaChallengeEntity::insert(challenge)
+        .exec_without_returning(db)
+        .await?;
+
+    Ok(token)
+}
+
+/// Resolves a challenge token that can still be exchanged.
+///
+/// Unknown, used, expired and exhausted tokens all yield `None`.
+pub async fn find_mfa_challenge(
+    db: &DatabaseConnection,
+    presented: &str,
+    max_attempts: i32,
+) -> Result<Option<MfaChallengeModel>, DbErr> {
+    let challenge = MfaChallengeEntity::find()
+        .filter(MfaChallengeColumn::TokenHash.eq(hash_opaque_token(presented)))
+        .one(db)
+        .await?;
+
+    Ok(challenge.filter(|challenge| {
+        challenge.used_at.is_none()
+            && challenge.expires_at > Utc::now()
+            && challenge.failed_attempts < max_attempts
+    }))
+}
+
+/// Counts a wrong code against the challenge.
+pub async fn record_mfa_challenge_failure(
+    db: &DatabaseConnection,
+    challenge_id: i32,
+) -> Result<(), DbErr> {
+    MfaChallengeEntity::update_many()
+        .col_expr(
+            MfaChallengeColumn::FailedAttempts,
+            Expr::col(MfaChallengeColumn::FailedAttempts).add(1),
+        )
+        .filter(MfaChallengeColumn::Id.eq(challenge_id))
+        .exec(db)
+        .await
+        .map(|_| ())
+}
+
+/// Spends a challenge found by [`find_mfa_challenge`].
+///
+/// Returns `false` if it was spent or expired in the meantime.
+pub async fn complete_mfa_challenge(
+    db: &DatabaseConnection,
+    challenge: &MfaChallengeModel,
+) -> Result<bool, DbErr> {
+    let now = Utc::now();
+
+    // Conditional update so a challenge can only be exchanged once.
+    let claimed = MfaChallengeEntity::update_many()
+        .col_expr(MfaChallengeColumn::UsedAt, Expr::value(now))
+        .filter(MfaChallengeColumn::Id.eq(challenge.id))
+        .filter(MfaChallengeColumn::UsedAt.is_null())
+        .filter(MfaChallengeColumn::ExpiresAt.gt(now))
+        .exec(db)
+        .await?;
+
+    Ok(claimed.rows_affected > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{DatabaseBackend, MockDatabase};
+
+    use crate::state::tests::exec;
+    use crate::utils::totp::hotp;
+
+    use super::*;
+
+    #[test]
+    fn recovery_codes_are_matched_loosely() {
+        let code = generate_recovery_code();
+
+        assert_eq!(code.len(), 11);
+        assert_eq!(
+            hash_recovery_code(&code),
+            hash_recovery_code(&format!(" {} ", code.to_uppercase().replace('-', "")))
+        );
+        assert_ne!(code, generate_recovery_code());
+    }
+
+    #[actix_web::test]
+    async fn totp_codes_are_accepted_once() {
+        let secret = b"12345678901234567890";
+        let credential = TotpCredentialModel {
+            user_id: 4,
+            secret: base32_encode(secret),
+            confirmed_at: Some(Utc::now()),
+            last_used_step: None,
+            created_at: Utc::now(),
+        };
+        let code = format!(
+            "{:06}",
+            hotp(secret, crate::utils::totp::totp_step(Utc::now()) as u64)
+        );
+        // The second redemption loses the conditional update on `last_used_step`.
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_exec_results([exec(1), exec(0)])
+            .into_connection();
+
+        assert!(redeem_totp_code(&db, &credential, &code).await.unwrap());
+        assert!(!redeem_totp_code(&db, &credential, &code).await.unwrap());
+        // Wrong codes never reach the database.
+        assert!(!redeem_totp_code(&db, &credential, "000000x").await.unwrap());
+    }
+
+    #[actix_web::test]
+    async fn exhausted_challenges_are_not_found() {
+        let challenge = MfaChallengeModel {
+            id: 1,
+            user_id: 4,
+            token_hash: hash_opaque_token("token"),
+            failed_attempts: 5,
+            expires_at: Utc::now() + Duration::minutes(5),
+            created_at: Utc::now(),
+            used_at: None,
+        };
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![challenge.clone()], vec![challenge]])
+            .into_connection();
+
+        assert!(find_mfa_challenge(&db, "token", 6).await.unwrap().is_some());
+        assert_eq!(find_mfa_challenge(&db, "token", 5).await.unwrap(), None);
+    }
+}
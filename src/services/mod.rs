@@ -1 +1,39 @@
+pub mod email_verification_service;
+pub mod invitation_service;
+pub mod key_service;
+pub mod login_throttle_service;
+pub mod magic_link_service;
+pub mod mfa_service;
+pub mod organization_service;
+pub mod password_reset_service;
+pub mod refresh_token_service;
+pub mod role_service;
+pub mod session_service;
+pub mod token_service;
 pub mod user_service;
+pub mod webauthn_service;
+
+use std::future::Future;
+use std::time::Duration;
+
+use sea_orm::DbErr;
+
+/// Spawns a background task that runs `sweep` every `period`, logging failures as `name`.
+///
+/// A failed sweep only delays cleanup; the next tick retries.
+pub fn spawn_sweeper<F, Fut>(name: &'static str, period: Duration, mut sweep: F)
+where
+    F: FnMut() -> Fut + 'static,
+    Fut: Future<Output = Result<u64, DbErr>>,
+{
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(period);
+
+        loop {
+            interval.tick().await;
+            if let Err(e) = sweep().await {
+                log::warn!("{} sweep failed: {}", name, e);
+            }
+        }
+    });
+}
@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set,
+    TransactionTrait,
+};
+
+use crate::models::membership::{
+    ActiveModel as MembershipActiveModel, Column as MembershipColumn, Entity as MembershipEntity,
+    Model as MembershipModel,
+};
+use crate::models::organization::{
+    ActiveModel as OrganizationActiveModel, Model as OrganizationModel,
+};
+use crate::models::role::{Column as RoleColumn, Entity as RoleEntity};
+use crate::models::user::Model as UserModel;
+use crate::services::role_service::find_role_by_name;
+use crate::services::user_service::{Tenant, list_users};
+
+/// Organization role given to whoever creates the organization.
+pub const OWNER_ROLE: &str = "owner";
+
+/// Organization role given to invited users unless the invitation says otherwise.
+pub const MEMBER_ROLE: &str = "member";
+
+/// Roles that can be held within an organization, as opposed to global roles.
+pub const ORG_ROLES: [&str; 2] = [OWNER_ROLE, MEMBER_ROLE];
+
+/// A user together with their role in one organization.
+pub struct Member {
+    pub user: UserModel,
+    pub role: String,
+}
+
+/// Creates an organization with `owner_id` as its first member, holding [`OWNER_ROLE`].
+pub async fn create_organization(
+    db: &DatabaseConnection,
+    owner_id: i32,
+    name: String,
+) -> Result<OrganizationModel, DbErr> {
+    let owner_role = find_role_by_name(db, OWNER_ROLE)
+        .await?
+        .ok_or_else(|| DbErr::RecordNotFound(format!("role {}", OWNER_ROLE)))?;
+    let now = Utc::now();
+
+    // An organization without its owner could never be managed, so both rows go in together.
+    let txn = db.begin().await?;
+    let organization = OrganizationActiveModel {
+        name: Set(name),
+        created_at: Set(now),
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await?;
+
+    MembershipEntity::insert(MembershipActiveModel {
+        organization_id: Set(organization.id),
+        user_id: Set(owner_id),
+        role_id: Set(owner_role.id),
+        created_at: Set(now),
+    })
+    .exec_without_returning(&txn)
+    .await?;
+    txn.commit().await?;
+
+    Ok(organization)
+}
+
+pub async fn find_membership(
+    db: &DatabaseConnection,
+    organization_id: i32,
+    user_id: i32,
+) -> Result<Option<MembershipModel>, DbErr> {
+    MembershipEntity::find_by_id((organization_id, user_id))
+        .one(db)
+        .await
+}
+
+/// Lists the organization's members with their roles, ordered by user ID.
+pub async fn list_members(
+    db: &DatabaseConnection,
+    organization_id: i32,
+) -> Result<Vec<Member>, DbErr> {
+    let role_by_user: HashMap<i32, i32> = MembershipEntity::find()
+        .filter(MembershipColumn::OrganizationId.eq(organization_id))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|membership| (membership.user_id, membership.role_id))
+        .collect();
+
+    if role_by_user.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let users = list_users(db, Tenant::Organization(organization_id)).await?;
+    let role_names: HashMap<i32, String> = RoleEntity::find()
+        .filter(RoleColumn::Id.is_in(role_by_user.values().copied()))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|role| (role.id, role.name))
+        .collect();
+
+    Ok(users
+        .into_iter()
+        .filter_map(|user| {
+            let role = role_names.get(role_by_user.get(&user.id)?)?.clone();
+            Some(Member { user, role })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{DatabaseBackend, MockDatabase};
+
+    use crate::models::role::Model as RoleModel;
+    use crate::state::tests::exec;
+
+    use super::*;
+
+    fn role(id: i32, name: &str) -> RoleModel {
+        RoleModel {
+            id,
+            name: name.into(),
+            description: None,
+        }
+    }
+
+    fn user(id: i32, username: &str) -> UserModel {
+        UserModel {
+            id,
+            username: username.into(),
+            username_canonical: username.into(),
+            password: String::new(),
+            active_organization_id: None,
+            email: None,
+            email_verified_at: None,
+        }
+    }
+
+    #[actix_web::test]
+    async fn creator_becomes_owner() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![role(3, OWNER_ROLE)]])
+            .append_query_results([vec![OrganizationModel {
+                id: 9,
+                name: "Acme".into(),
+                created_at: Utc::now(),
+            }]])
+            .append_exec_results([exec(1)])
+            .into_connection();
+
+        let organization = create_organization(&db, 1, "Acme".into()).await.unwrap();
+
+        assert_eq!(organization.id, 9);
+        let log = db.into_transaction_log();
+        let membership = log
+            .iter()
+            .flat_map(|transaction| transaction.statements())
+            .find(|statement| statement.sql.starts_with(r#"INSERT INTO "memberships""#))
+            .expect("membership should be inserted");
+        let values = membership.values.as_ref().unwrap().0.clone();
+        assert_eq!(values[..3], [9.into(), 1.into(), 3.into()]);
+    }
+
+    #[actix_web::test]
+    async fn members_carry_their_role() {
+        let now = Utc::now();
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![
+                MembershipModel {
+                    organization_id: 9,
+                    user_id: 1,
+                    role_id: 3,
+                    created_at: now,
+                },
+                MembershipModel {
+                    organization_id: 9,
+                    user_id: 2,
+                    role_id: 4,
+                    created_at: now,
+                },
+            ]])
+            .append_query_results([vec![user(1, "alice"), user(2, "bob")]])
+            .append_query_results([vec![role(3, OWNER_ROLE), role(4, "member")]])
+            .into_connection();
+
+        let members = list_members(&db, 9).await.unwrap();
+
+        let summary: Vec<_> = members
+            .iter()
+            .map(|member| (member.user.username.as_str(), member.role.as_str()))
+            .collect();
+        assert_eq!(summary, [("alice", "owner"), ("bob", "member")]);
+    }
+}
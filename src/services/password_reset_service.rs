@@ -0,0 +1,128 @@
+use chrono::{Duration, Utc};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set, sea_query::Expr};
+
+use crate::models::password_reset::{
+    ActiveModel as PasswordResetActiveModel, Column as PasswordResetColumn,
+    Entity as PasswordResetEntity, Model as PasswordResetModel,
+};
+use crate::utils::{generate_opaque_token, hash_opaque_token};
+
+/// Issues a password reset token for the user. Only its hash is stored.
+pub async fn issue_password_reset(
+    db: &DatabaseConnection,
+    user_id: i32,
+    ttl_secs: i64,
+) -> Result<String, sea_orm::DbErr> {
+    let token = generate_opaque_token();
+    let now = Utc::now();
+
+    let reset = PasswordResetActiveModel {
+        user_id: Set(user_id),
+        token_hash: Set(hash_opaque_token(&token)),
+        expires_at: Set(now + Duration::seconds(ttl_secs)),
+        created_at: Set(now),
+        used_at: Set(None),
+        ..Default::default()
+    };
+
+    PasswordResetEntity::insert(reset)
+        .exec_without_returning(db)
+        .await?;
+
+    Ok(token)
+}
+
+/// Resolves a reset token that can still be redeemed.
+///
+/// Unknown, used and expired tokens all yield `None` so callers can't tell them apart.
+pub async fn find_password_reset(
+    db: &DatabaseConnection,
+    presented: &str,
+) -> Result<Option<PasswordResetModel>, sea_orm::DbErr> {
+    let reset = PasswordResetEntity::find()
+        .filter(PasswordResetColumn::TokenHash.eq(hash_opaque_token(presented)))
+        .one(db)
+        .await?;
+
+    Ok(reset.filter(|reset| reset.used_at.is_none() && reset.expires_at > Utc::now()))
+}
+
+/// Spends a reset token found by [`find_password_reset`], along with any other pending
+/// tokens of the user.
+///
+/// Returns `false` if it was spent or expired in the meantime.
+pub async fn redeem_password_reset(
+    db: &DatabaseConnection,
+    reset: &PasswordResetModel,
+) -> Result<bool, sea_orm::DbErr> {
+    let now = Utc::now();
+
+    // Conditional update so a token can only be spent once.
+    let claimed = PasswordResetEntity::update_many()
+        .col_expr(PasswordResetColumn::UsedAt, Expr::value(now))
+        .filter(PasswordResetColumn::Id.eq(reset.id))
+        .filter(PasswordResetColumn::UsedAt.is_null())
+        .filter(PasswordResetColumn::ExpiresAt.gt(now))
+        .exec(db)
+        .await?;
+    if claimed.rows_affected == 0 {
+        return Ok(false);
+    }
+
+    PasswordResetEntity::update_many()
+        .col_expr(PasswordResetColumn::UsedAt, Expr::value(now))
+        .filter(PasswordResetColumn::UserId.eq(reset.user_id))
+        .filter(PasswordResetColumn::UsedAt.is_null())
+        .exec(db)
+        .await?;
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{DatabaseBackend, MockDatabase};
+
+    use crate::state::tests::exec;
+
+    use super::*;
+
+    fn stored(token: &str) -> PasswordResetModel {
+        PasswordResetModel {
+            id: 1,
+            user_id: 4,
+            token_hash: hash_opaque_token(token),
+            expires_at: Utc::now() + Duration::minutes(30),
+            created_at: Utc::now(),
+            used_at: None,
+        }
+    }
+
+    #[actix_web::test]
+    async fn redeems_a_token_once() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_exec_results([exec(1), exec(2), exec(0)])
+            .into_connection();
+        let reset = stored("token");
+
+        assert!(redeem_password_reset(&db, &reset).await.unwrap());
+        // A concurrent redemption loses the conditional update.
+        assert!(!redeem_password_reset(&db, &reset).await.unwrap());
+    }
+
+    #[actix_web::test]
+    async fn finds_only_pending_tokens() {
+        let mut used = stored("used");
+        used.used_at = Some(Utc::now());
+        let mut expired = stored("expired");
+        expired.expires_at = Utc::now() - Duration::seconds(1);
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![stored("token")], vec![used], vec![expired], vec![]])
+            .into_connection();
+
+        assert!(find_password_reset(&db, "token").await.unwrap().is_some());
+        assert_eq!(find_password_reset(&db, "used").await.unwrap(), None);
+        assert_eq!(find_password_reset(&db, "expired").await.unwrap(), None);
+        assert_eq!(find_password_reset(&db, "unknown").await.unwrap(), None);
+    }
+}
@@ -0,0 +1,278 @@
+use chrono::{Duration, Utc};
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, QueryFilter, Set,
+    TransactionTrait, sea_query::Expr,
+};
+use uuid::Uuid;
+
+use crate::models::refresh_token::{
+    ActiveModel as RefreshTokenActiveModel, Column as RefreshTokenColumn,
+    Entity as RefreshTokenEntity,
+};
+use crate::utils::{generate_opaque_token, hash_opaque_token};
+
+/// Result of presenting a refresh token for rotation.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RefreshOutcome {
+    /// The token was valid; it is now spent and `refresh_token` replaces it.
+    Rotated {
+        user_id: i32,
+        family_id: String,
+        refresh_token: String,
+    },
+    /// No such token.
+    Invalid,
+    /// The token is past its expiry.
+    Expired,
+    /// The token was already used or revoked; its whole family has been revoked.
+    Reused { user_id: i32, family_id: String },
+}
+
+/// Issues a refresh token, starting a new family unless one is given.
+///
+/// Only the hash is stored; the returned plaintext must be handed to the client.
+pub async fn issue_refresh_token<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+    family_id: Option<String>,
+    ttl_secs: i64,
+) -> Result<String, sea_orm::DbErr> {
+    let token = generate_opaque_token();
+    let now = Utc::now();
+
+    let refresh_token = RefreshTokenActiveModel {
+        user_id: Set(user_id),
+        family_id: Set(family_id.unwrap_or_else(|| Uuid::new_v4().to_string())),
+        token_hash: Set(hash_opaque_token(&token)),
+        expires_at: Set(now + Duration::seconds(ttl_secs)),
+        created_at: Set(now),
+        used_at: Set(None),
+        revoked_at: Set(None),
+        ..Default::default()
+    };
+
+    RefreshTokenEntity::insert(refresh_token)
+        .exec_without_returning(db)
+        .await?;
+
+    Ok(token)
+}
+
+/// Exchanges a refresh token for a new one in the same family.
+///
+/// Presenting a token that was already exchanged (or revoked) is treated as theft:
+/// every token in the family is revoked so neither party can keep refreshing.
+pub async fn rotate_refresh_token(
+    db: &DatabaseConnection,
+    presented: &str,
+    ttl_secs: i64,
+) -> Result<RefreshOutcome, sea_orm::DbErr> {
+    let Some(stored) = RefreshTokenEntity::find()
+        .filter(RefreshTokenColumn::TokenHash.eq(hash_opaque_token(presented)))
+        .one(db)
+        .await?
+    else {
+        return Ok(RefreshOutcome::Invalid);
+    };
+
+    if stored.used_at.is_some() || stored.revoked_at.is_some() {
+        revoke_refresh_family(db, &stored.family_id).await?;
+        return Ok(RefreshOutcome::Reused {
+            user_id: stored.user_id,
+            family_id: stored.family_id,
+        });
+    }
+
+    if stored.expires_at <= Utc::now() {
+        return Ok(RefreshOutcome::Expired);
+    }
+
+    // Conditional update so two concurrent exchanges can't both succeed, nor one
+    // race a revocation. The successor is written in the same transaction so a
+    // failed insert doesn't leave the token spent with nothing to replace it.
+    let txn = db.begin().await?;
+    let claimed = RefreshTokenEntity::update_many()
+        .col_expr(RefreshTokenColumn::UsedAt, Expr::value(Utc::now()))
+        .filter(RefreshTokenColumn::Id.eq(stored.id))
+        .filter(RefreshTokenColumn::UsedAt.is_null())
+        .filter(RefreshTokenColumn::RevokedAt.is_null())
+        .exec(&txn)
+        .await?;
+
+    if claimed.rows_affected == 0 {
+        revoke_refresh_family(&txn, &stored.family_id).await?;
+        txn.commit().await?;
+        return Ok(RefreshOutcome::Reused {
+            user_id: stored.user_id,
+            family_id: stored.family_id,
+        });
+    }
+
+    let refresh_token = issue_refresh_token(
+        &txn,
+        stored.user_id,
+        Some(stored.family_id.clone()),
+        ttl_secs,
+    )
+    .await?;
+    txn.commit().await?;
+
+    Ok(RefreshOutcome::Rotated {
+        user_id: stored.user_id,
+        family_id: stored.family_id,
+        refresh_token,
+    })
+}
+
+/// Revokes every live token in a refresh token family.
+pub async fn revoke_refresh_family<C: ConnectionTrait>(
+    db: &C,
+    family_id: &str,
+) -> Result<u64, sea_orm::DbErr> {
+    RefreshTokenEntity::update_many()
+        .col_expr(RefreshTokenColumn::RevokedAt, Expr::value(Utc::now()))
+        .filter(RefreshTokenColumn::FamilyId.eq(family_id))
+        .filter(RefreshTokenColumn::RevokedAt.is_null())
+        .exec(db)
+        .await
+        .map(|result| result.rows_affected)
+}
+
+/// Revokes every live refresh token of a user, ending all of their sessions.
+pub async fn revoke_user_refresh_tokens(
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> Result<u64, sea_orm::DbErr> {
+    RefreshTokenEntity::update_many()
+        .col_expr(RefreshTokenColumn::RevokedAt, Expr::value(Utc::now()))
+        .filter(RefreshTokenColumn::UserId.eq(user_id))
+        .filter(RefreshTokenColumn::RevokedAt.is_null())
+        .exec(db)
+        .await
+        .map(|result| result.rows_affected)
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{DatabaseBackend, MockDatabase};
+
+    use crate::models::refresh_token::Model as RefreshTokenModel;
+    use crate::state::tests::exec;
+
+    use super::*;
+
+    fn stored(token: &str) -> RefreshTokenModel {
+        RefreshTokenModel {
+            id: 1,
+            user_id: 9,
+            family_id: "family".into(),
+            token_hash: hash_opaque_token(token),
+            expires_at: Utc::now() + Duration::days(1),
+            created_at: Utc::now(),
+            used_at: None,
+            revoked_at: None,
+        }
+    }
+
+    #[actix_web::test]
+    async fn rotate_issues_replacement_in_same_family() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![stored("presented")]])
+            .append_exec_results([exec(1), exec(1)])
+            .into_connection();
+
+        let outcome = rotate_refresh_token(&db, "presented", 60).await.unwrap();
+
+        match outcome {
+            RefreshOutcome::Rotated {
+                user_id,
+                family_id,
+                refresh_token,
+            } => {
+                assert_eq!(user_id, 9);
+                assert_eq!(family_id, "family");
+                assert_ne!(refresh_token, "presented");
+            }
+            other => panic!("expected rotation, got {:?}", other),
+        }
+
+        // The claim skips revoked tokens and commits together with its successor.
+        let log = db.into_transaction_log();
+        assert_eq!(log.len(), 2);
+        let statements = log[1].statements();
+        assert_eq!(statements[0].sql, "BEGIN");
+        assert!(statements[1].sql.starts_with("UPDATE"));
+        assert!(statements[1].sql.contains("\"revoked_at\" IS NULL"));
+        assert!(statements[2].sql.starts_with("INSERT"));
+        assert_eq!(statements[3].sql, "COMMIT");
+    }
+
+    #[actix_web::test]
+    async fn rotate_rejects_unknown_token() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<RefreshTokenModel>::new()])
+            .into_connection();
+
+        let outcome = rotate_refresh_token(&db, "nope", 60).await.unwrap();
+
+        assert_eq!(outcome, RefreshOutcome::Invalid);
+    }
+
+    #[actix_web::test]
+    async fn rotate_rejects_expired_token() {
+        let expired = RefreshTokenModel {
+            expires_at: Utc::now() - Duration::seconds(1),
+            ..stored("old")
+        };
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![expired]])
+            .into_connection();
+
+        let outcome = rotate_refresh_token(&db, "old", 60).await.unwrap();
+
+        assert_eq!(outcome, RefreshOutcome::Expired);
+    }
+
+    #[actix_web::test]
+    async fn replayed_token_revokes_family() {
+        let used = RefreshTokenModel {
+            used_at: Some(Utc::now()),
+            ..stored("replayed")
+        };
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![used]])
+            .append_exec_results([exec(2)])
+            .into_connection();
+
+        let outcome = rotate_refresh_token(&db, "replayed", 60).await.unwrap();
+        assert_eq!(
+            outcome,
+            RefreshOutcome::Reused {
+                user_id: 9,
+                family_id: "family".into(),
+            }
+        );
+
+        let log = db.into_transaction_log();
+        assert_eq!(log.len(), 2);
+        assert!(log[1].statements()[0].sql.contains("\"revoked_at\""));
+    }
+
+    #[actix_web::test]
+    async fn concurrent_exchange_loses_race_and_revokes_family() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![stored("raced")]])
+            .append_exec_results([exec(0), exec(1)])
+            .into_connection();
+
+        let outcome = rotate_refresh_token(&db, "raced", 60).await.unwrap();
+
+        assert_eq!(
+            outcome,
+            RefreshOutcome::Reused {
+                user_id: 9,
+                family_id: "family".into(),
+            }
+        );
+    }
+}
@@ -0,0 +1,306 @@
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set, TryInsertResult};
+
+use crate::models::membership::Entity as MembershipEntity;
+use crate::models::permission::{Column as PermissionColumn, Entity as PermissionEntity};
+use crate::models::role::{Column as RoleColumn, Entity as RoleEntity, Model as RoleModel};
+use crate::models::role_permission::{
+    Column as RolePermissionColumn, Entity as RolePermissionEntity,
+};
+use crate::models::user_role::{
+    ActiveModel as UserRoleActiveModel, Column as UserRoleColumn, Entity as UserRoleEntity,
+};
+use crate::services::organization_service::ORG_ROLES;
+use crate::utils::UserGrants;
+
+/// Role given to every newly registered account.
+pub const DEFAULT_ROLE: &str = "user";
+
+/// Loads the names of the user's roles and of every permission those roles carry.
+///
+/// With an `organization_id` the user is a member of, their role there and its permissions are
+/// added as the organization's grants, kept apart from the global ones; otherwise the
+/// organization is ignored.
+pub async fn load_user_grants(
+    db: &DatabaseConnection,
+    user_id: i32,
+    organization_id: Option<i32>,
+) -> Result<UserGrants, sea_orm::DbErr> {
+    let global_role_ids: Vec<i32> = UserRoleEntity::find()
+        .filter(UserRoleColumn::UserId.eq(user_id))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|user_role| user_role.role_id)
+        .collect();
+
+    let membership = match organization_id {
+        Some(organization_id) => {
+            MembershipEntity::find_by_id((organization_id, user_id))
+                .one(db)
+                .await?
+        }
+        None => None,
+    };
+
+    let mut role_ids = global_role_ids.clone();
+    role_ids.extend(membership.as_ref().map(|membership| membership.role_id));
+    if role_ids.is_empty() {
+        return Ok(UserGrants::default());
+    }
+
+    let roles = RoleEntity::find()
+        .filter(RoleColumn::Id.is_in(role_ids.clone()))
+        .all(db)
+        .await?;
+
+    let role_permissions = RolePermissionEntity::find()
+        .filter(RolePermissionColumn::RoleId.is_in(role_ids))
+        .all(db)
+        .await?;
+
+    let permissions = if role_permissions.is_empty() {
+        Vec::new()
+    } else {
+        PermissionEntity::find()
+            .filter(
+                PermissionColumn::Id.is_in(
+                    role_permissions
+                        .iter()
+                        .map(|role_permission| role_permission.permission_id),
+                ),
+            )
+            .all(db)
+            .await?
+    };
+    let permissions_of = |role_id: i32| {
+        role_permissions
+            .iter()
+            .filter(move |role_permission| role_permission.role_id == role_id)
+            .filter_map(|role_permission| {
+                permissions
+                    .iter()
+                    .find(|permission| permission.id == role_permission.permission_id)
+                    .map(|permission| permission.name.clone())
+            })
+    };
+
+    let grants = UserGrants::new(
+        roles
+            .iter()
+            .filter(|role| global_role_ids.contains(&role.id))
+            .map(|role| role.name.clone()),
+        global_role_ids
+            .iter()
+            .flat_map(|&role_id| permissions_of(role_id)),
+    );
+
+    let organization_role = membership.and_then(|membership| {
+        roles
+            .iter()
+            .find(|role| role.id == membership.role_id)
+            .map(|role| (membership.organization_id, role))
+    });
+    Ok(match organization_role {
+        Some((organization_id, role)) => grants.in_organization(
+            organization_id,
+            role.name.clone(),
+            permissions_of(role.id).collect::<Vec<_>>(),
+        ),
+        None => grants,
+    })
+}
+
+/// Looks a role up by its unique name.
+pub async fn find_role_by_name(
+    db: &DatabaseConnection,
+    name: &str,
+) -> Result<Option<RoleModel>, sea_orm::DbErr> {
+    RoleEntity::find()
+        .filter(RoleColumn::Name.eq(name))
+        .one(db)
+        .await
+}
+
+/// Gives the user a global role by name.
+///
+/// Returns `None` when no such role exists and `Some(false)` if the user already had it.
+/// Organization roles can't be granted globally; they only come with a membership.
+pub async fn assign_role(
+    db: &DatabaseConnection,
+    user_id: i32,
+    role_name: &str,
+) -> Result<Option<bool>, sea_orm::DbErr> {
+    if ORG_ROLES.contains(&role_name) {
+        return Ok(None);
+    }
+    let Some(role) = find_role_by_name(db, role_name).await? else {
+        return Ok(None);
+    };
+
+    let user_role = UserRoleActiveModel {
+        user_id: Set(user_id),
+        role_id: Set(role.id),
+    };
+
+    let result = UserRoleEntity::insert(user_role)
+        .on_conflict_do_nothing()
+        .exec_without_returning(db)
+        .await?;
+
+    Ok(Some(
+        matches!(result, TryInsertResult::Inserted(rows) if rows > 0),
+    ))
+}
+
+/// Takes a role away from the user. Returns `false` if they did not have it.
+pub async fn remove_role(
+    db: &DatabaseConnection,
+    user_id: i32,
+    role_name: &str,
+) -> Result<bool, sea_orm::DbErr> {
+    let Some(role) = find_role_by_name(db, role_name).await? else {
+        return Ok(false);
+    };
+
+    UserRoleEntity::delete_many()
+        .filter(UserRoleColumn::UserId.eq(user_id))
+        .filter(UserRoleColumn::RoleId.eq(role.id))
+        .exec(db)
+        .await
+        .map(|result| result.rows_affected > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use sea_orm::{DatabaseBackend, MockDatabase};
+
+    use crate::models::membership::Model as MembershipModel;
+    use crate::models::permission::Model as PermissionModel;
+    use crate::models::role_permission::Model as RolePermissionModel;
+    use crate::models::user_role::Model as UserRoleModel;
+    use crate::state::tests::exec;
+
+    use super::*;
+
+    fn role(id: i32, name: &str) -> RoleModel {
+        RoleModel {
+            id,
+            name: name.into(),
+            description: None,
+        }
+    }
+
+    #[actix_web::test]
+    async fn loads_roles_and_their_permissions() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![
+                UserRoleModel {
+                    user_id: 1,
+                    role_id: 1,
+                },
+                UserRoleModel {
+                    user_id: 1,
+                    role_id: 2,
+                },
+            ]])
+            .append_query_results([vec![role(1, "admin"), role(2, "user")]])
+            .append_query_results([vec![
+                RolePermissionModel {
+                    role_id: 1,
+                    permission_id: 10,
+                },
+                RolePermissionModel {
+                    role_id: 1,
+                    permission_id: 11,
+                },
+            ]])
+            .append_query_results([vec![
+                PermissionModel {
+                    id: 11,
+                    name: "users:write".into(),
+                    description: None,
+                },
+                PermissionModel {
+                    id: 10,
+                    name: "users:read".into(),
+                    description: None,
+                },
+            ]])
+            .into_connection();
+
+        let grants = load_user_grants(&db, 1, None).await.unwrap();
+
+        assert_eq!(grants.roles, ["admin", "user"]);
+        assert_eq!(grants.permissions, ["users:read", "users:write"]);
+    }
+
+    #[actix_web::test]
+    async fn user_without_roles_has_no_grants() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<UserRoleModel>::new()])
+            .into_connection();
+
+        let grants = load_user_grants(&db, 1, None).await.unwrap();
+
+        assert_eq!(grants, UserGrants::default());
+    }
+
+    #[actix_web::test]
+    async fn organization_role_is_scoped_to_the_active_organization() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![UserRoleModel {
+                user_id: 1,
+                role_id: 2,
+            }]])
+            .append_query_results([vec![MembershipModel {
+                organization_id: 4,
+                user_id: 1,
+                role_id: 5,
+                created_at: Utc::now(),
+            }]])
+            .append_query_results([vec![role(2, "user"), role(5, "member")]])
+            .append_query_results([vec![RolePermissionModel {
+                role_id: 5,
+                permission_id: 20,
+            }]])
+            .append_query_results([vec![PermissionModel {
+                id: 20,
+                name: "members:read".into(),
+                description: None,
+            }]])
+            .into_connection();
+
+        let grants = load_user_grants(&db, 1, Some(4)).await.unwrap();
+
+        assert_eq!(grants.roles, ["user"]);
+        assert!(grants.permissions.is_empty());
+        assert_eq!(grants.org_id, Some(4));
+        assert_eq!(grants.org_role.as_deref(), Some("member"));
+        assert_eq!(grants.org_permissions, ["members:read"]);
+    }
+
+    #[actix_web::test]
+    async fn organization_is_dropped_for_non_members() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<UserRoleModel>::new()])
+            .append_query_results([Vec::<MembershipModel>::new()])
+            .into_connection();
+
+        let grants = load_user_grants(&db, 1, Some(4)).await.unwrap();
+
+        assert_eq!(grants, UserGrants::default());
+    }
+
+    #[actix_web::test]
+    async fn assign_role_reports_unknown_and_duplicate_roles() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![], vec![role(1, "admin")], vec![role(1, "admin")]])
+            .append_exec_results([exec(1), exec(0)])
+            .into_connection();
+
+        assert_eq!(assign_role(&db, 1, "nope").await.unwrap(), None);
+        assert_eq!(assign_role(&db, 1, "admin").await.unwrap(), Some(true));
+        assert_eq!(assign_role(&db, 1, "admin").await.unwrap(), Some(false));
+    }
+}
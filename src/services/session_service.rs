@@ -0,0 +1,106 @@
+use chrono::{Duration, Utc};
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder, Set,
+    sea_query::Expr,
+};
+
+use crate::middleware::ClientInfo;
+use crate::models::session::{
+    ActiveModel as SessionActiveModel, Column as SessionColumn, Entity as SessionEntity,
+    Model as SessionModel,
+};
+use crate::services::refresh_token_service::revoke_refresh_family;
+use crate::services::token_service::revoke_session_tokens;
+
+/// Records a new login under its refresh token family.
+pub async fn start_session(
+    db: &DatabaseConnection,
+    user_id: i32,
+    family_id: &str,
+    client: &ClientInfo,
+) -> Result<(), DbErr> {
+    let now = Utc::now();
+    let session = SessionActiveModel {
+        user_id: Set(user_id),
+        family_id: Set(family_id.to_string()),
+        user_agent: Set(client.user_agent.clone()),
+        ip_address: Set(client.ip_address.clone()),
+        created_at: Set(now),
+        last_seen_at: Set(now),
+        revoked_at: Set(None),
+        ..Default::default()
+    };
+
+    SessionEntity::insert(session)
+        .exec_without_returning(db)
+        .await
+        .map(|_| ())
+}
+
+/// Marks the session of a refresh token family as just used.
+pub async fn touch_session(db: &DatabaseConnection, family_id: &str) -> Result<(), DbErr> {
+    SessionEntity::update_many()
+        .col_expr(SessionColumn::LastSeenAt, Expr::value(Utc::now()))
+        .filter(SessionColumn::FamilyId.eq(family_id))
+        .exec(db)
+        .await
+        .map(|_| ())
+}
+
+/// The user's live sessions, most recently seen first.
+///
+/// Sessions idle for longer than a refresh token lives can't be resumed and are left out.
+pub async fn list_sessions(
+    db: &DatabaseConnection,
+    user_id: i32,
+    refresh_token_ttl_secs: i64,
+) -> Result<Vec<SessionModel>, DbErr> {
+    SessionEntity::find()
+        .filter(SessionColumn::UserId.eq(user_id))
+        .filter(SessionColumn::RevokedAt.is_null())
+        .filter(
+            SessionColumn::LastSeenAt.gt(Utc::now() - Duration::seconds(refresh_token_ttl_secs)),
+        )
+        .order_by_desc(SessionColumn::LastSeenAt)
+        .all(db)
+        .await
+}
+
+/// Marks the session of a refresh token family revoked. Returns `false` if it already was.
+///
+/// [`end_session`] also revokes its refresh tokens and access tokens.
+pub async fn mark_session_revoked(db: &DatabaseConnection, family_id: &str) -> Result<bool, DbErr> {
+    SessionEntity::update_many()
+        .col_expr(SessionColumn::RevokedAt, Expr::value(Utc::now()))
+        .filter(SessionColumn::FamilyId.eq(family_id))
+        .filter(SessionColumn::RevokedAt.is_null())
+        .exec(db)
+        .await
+        .map(|result| result.rows_affected > 0)
+}
+
+/// Signs a session out: its refresh tokens stop working and so do its access tokens.
+pub async fn end_session(
+    db: &DatabaseConnection,
+    user_id: i32,
+    family_id: &str,
+    access_token_ttl_secs: i64,
+) -> Result<(), DbErr> {
+    mark_session_revoked(db, family_id).await?;
+    revoke_refresh_family(db, family_id).await?;
+    revoke_session_tokens(db, user_id, family_id, access_token_ttl_secs).await
+}
+
+/// Marks every session of the user revoked, e.g. after a password reset.
+pub async fn mark_user_sessions_revoked(
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> Result<u64, DbErr> {
+    SessionEntity::update_many()
+        .col_expr(SessionColumn::RevokedAt, Expr::value(Utc::now()))
+        .filter(SessionColumn::UserId.eq(user_id))
+        .filter(SessionColumn::RevokedAt.is_null())
+        .exec(db)
+        .await
+        .map(|result| result.rows_affected)
+}
@@ -0,0 +1,262 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    ColumnTrait, Condition, DatabaseConnection, EntityTrait, QueryFilter, Set, TryInsertResult,
+    sea_query::OnConflict,
+};
+
+use crate::models::revoked_token::{
+    ActiveModel as RevokedTokenActiveModel, Column as RevokedTokenColumn,
+    Entity as RevokedTokenEntity,
+};
+use crate::services::spawn_sweeper;
+use crate::utils::TokenClaims;
+
+/// Records a token ID as revoked until the token's own expiry.
+///
+/// Returns `false` when the token was already revoked.
+pub async fn revoke_token(
+    db: &DatabaseConnection,
+    jti: String,
+    user_id: i32,
+    expires_at: DateTime<Utc>,
+) -> Result<bool, sea_orm::DbErr> {
+    let revoked = RevokedTokenActiveModel {
+        jti: Set(jti),
+        user_id: Set(user_id),
+        expires_at: Set(expires_at),
+        revoked_at: Set(Utc::now()),
+    };
+
+    let result = RevokedTokenEntity::insert(revoked)
+        .on_conflict_do_nothing()
+        .exec_without_returning(db)
+        .await?;
+
+    Ok(matches!(result, TryInsertResult::Inserted(rows) if rows > 0))
+}
+
+/// Key of the row that revokes every token a user was issued before its `revoked_at`.
+fn user_cutoff_key(user_id: i32) -> String {
+    format!("user:{}", user_id)
+}
+
+/// Revokes every access token issued to the user before now, e.g. after a password reset.
+///
+/// Stored as one `revoked_tokens` row that lives until the newest of those tokens expires.
+/// Token `iat`s have second precision, so tokens minted within the current second survive.
+pub async fn revoke_user_tokens(
+    db: &DatabaseConnection,
+    user_id: i32,
+    access_token_ttl_secs: i64,
+) -> Result<(), sea_orm::DbErr> {
+    let now = Utc::now();
+    let cutoff = DateTime::from_timestamp(now.timestamp(), 0).unwrap_or(now);
+    let revoked = RevokedTokenActiveModel {
+        jti: Set(user_cutoff_key(user_id)),
+        user_id: Set(user_id),
+        expires_at: Set(now + chrono::Duration::seconds(access_token_ttl_secs)),
+        revoked_at: Set(cutoff),
+    };
+
+    RevokedTokenEntity::insert(revoked)
+        .on_conflict(
+            OnConflict::column(RevokedTokenColumn::Jti)
+                .update_columns([RevokedTokenColumn::ExpiresAt, RevokedTokenColumn::RevokedAt])
+                .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await
+        .map(|_| ())
+}
+
+/// Key of the row that revokes every token minted for a session.
+fn session_key(session_id: &str) -> String {
+    format!("session:{}", session_id)
+}
+
+/// Revokes every access token minted for the session, e.g. when it is signed out remotely.
+pub async fn revoke_session_tokens(
+    db: &DatabaseConnection,
+    user_id: i32,
+    session_id: &str,
+    access_token_ttl_secs: i64,
+) -> Result<(), sea_orm::DbErr> {
+    let now = Utc::now();
+    let revoked = RevokedTokenActiveModel {
+        jti: Set(session_key(session_id)),
+        user_id: Set(user_id),
+        expires_at: Set(now + chrono::Duration::seconds(access_token_ttl_secs)),
+        revoked_at: Set(now),
+    };
+
+    RevokedTokenEntity::insert(revoked)
+        .on_conflict_do_nothing()
+        .exec_without_returning(db)
+        .await
+        .map(|_| ())
+}
+
+/// Checks whether a token was revoked on its own, by a [`revoke_user_tokens`] cutoff, or
+/// along with its session.
+pub async fn is_token_revoked(
+    db: &DatabaseConnection,
+    claims: &TokenClaims,
+) -> Result<bool, sea_orm::DbErr> {
+    let issued_at = DateTime::from_timestamp(claims.iat as i64, 0).unwrap_or_default();
+
+    let mut revoked_by = Condition::any()
+        .add(RevokedTokenColumn::Jti.eq(claims.jti.clone()))
+        .add(
+            Condition::all()
+                .add(RevokedTokenColumn::Jti.eq(user_cutoff_key(claims.sub)))
+                .add(RevokedTokenColumn::RevokedAt.gt(issued_at)),
+        );
+    if let Some(session_id) = &claims.sid {
+        revoked_by = revoked_by.add(RevokedTokenColumn::Jti.eq(session_key(session_id)));
+    }
+
+    RevokedTokenEntity::find()
+        .filter(revoked_by)
+        .one(db)
+        .await
+        .map(|revoked| revoked.is_some())
+}
+
+/// Deletes revocation entries whose tokens have expired on their own.
+pub async fn delete_expired_revoked_tokens(
+    db: &DatabaseConnection,
+    now: DateTime<Utc>,
+) -> Result<u64, sea_orm::DbErr> {
+    RevokedTokenEntity::delete_many()
+        .filter(RevokedTokenColumn::ExpiresAt.lte(now))
+        .exec(db)
+        .await
+        .map(|result| result.rows_affected)
+}
+
+/// Spawns a background task that periodically purges expired revocation entries.
+pub fn spawn_revoked_token_sweeper(db: DatabaseConnection, period: Duration) {
+    spawn_sweeper("revoked token", period, move || {
+        let db = db.clone();
+        async move { delete_expired_revoked_tokens(&db, Utc::now()).await }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{DatabaseBackend, MockDatabase};
+
+    use crate::models::revoked_token::Model as RevokedTokenModel;
+    use crate::state::tests::exec;
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn revoke_token_reports_conflicts() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_exec_results([exec(1), exec(0)])
+            .into_connection();
+        let expires_at = Utc::now();
+
+        assert!(
+            revoke_token(&db, "jti-1".into(), 1, expires_at)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !revoke_token(&db, "jti-1".into(), 1, expires_at)
+                .await
+                .unwrap()
+        );
+    }
+
+    fn claims(jti: &str) -> TokenClaims {
+        TokenClaims {
+            sub: 1,
+            exp: 0,
+            iat: 1_700_000_000,
+            nbf: 0,
+            iss: String::new(),
+            aud: String::new(),
+            jti: jti.into(),
+            roles: Vec::new(),
+            permissions: Vec::new(),
+            org_id: None,
+            org_role: None,
+            org_permissions: Vec::new(),
+            sid: None,
+        }
+    }
+
+    #[actix_web::test]
+    async fn is_token_revoked_checks_for_row() {
+        let revoked = RevokedTokenModel {
+            jti: "jti-1".into(),
+            user_id: 1,
+            expires_at: Utc::now(),
+            revoked_at: Utc::now(),
+        };
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![revoked], vec![]])
+            .into_connection();
+
+        assert!(is_token_revoked(&db, &claims("jti-1")).await.unwrap());
+        assert!(!is_token_revoked(&db, &claims("jti-2")).await.unwrap());
+    }
+
+    #[actix_web::test]
+    async fn revocation_lookup_includes_the_user_cutoff() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<RevokedTokenModel>::new()])
+            .into_connection();
+
+        is_token_revoked(&db, &claims("jti-1")).await.unwrap();
+
+        let log = db.into_transaction_log();
+        let sql = log[0].statements()[0].sql.clone();
+        assert!(
+            sql.contains(
+                r#""revoked_tokens"."jti" = $1 OR ("revoked_tokens"."jti" = $2 AND "revoked_tokens"."revoked_at" > $3)"#
+            ),
+            "{}",
+            sql
+        );
+        assert_eq!(
+            log[0].statements()[0].values.as_ref().unwrap().0[1],
+            "user:1".into()
+        );
+    }
+
+    #[actix_web::test]
+    async fn revocation_lookup_includes_the_session() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<RevokedTokenModel>::new()])
+            .into_connection();
+
+        let claims = TokenClaims {
+            sid: Some("family-1".into()),
+            ..claims("jti-1")
+        };
+        is_token_revoked(&db, &claims).await.unwrap();
+
+        let log = db.into_transaction_log();
+        let values = &log[0].statements()[0].values.as_ref().unwrap().0;
+        assert!(values.contains(&"session:family-1".into()), "{:?}", values);
+    }
+
+    #[actix_web::test]
+    async fn delete_expired_returns_rows_affected() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_exec_results([exec(3)])
+            .into_connection();
+
+        assert_eq!(
+            delete_expired_revoked_tokens(&db, Utc::now())
+                .await
+                .unwrap(),
+            3
+        );
+    }
+}
@@ -1,15 +1,57 @@
+use crate::models::membership::{Column as MembershipColumn, Entity as MembershipEntity};
 use crate::models::user::{
     ActiveModel as UserActiveModel, Entity as UserEntity, Model as UserModel,
 };
-use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
+use crate::utils::{TokenClaims, canonical_username};
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder,
+    Select, Set,
+    sea_query::{Expr, Query},
+};
+
+/// Which accounts a user query can see.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tenant {
+    /// Every account; for authentication and platform-wide administration.
+    Global,
+    /// Only members of the organization.
+    Organization(i32),
+}
+
+impl Tenant {
+    /// The caller's active organization, or every account when they have none.
+    pub fn of(claims: &TokenClaims) -> Self {
+        claims.org_id.map_or(Tenant::Global, Tenant::Organization)
+    }
 
-/// Fetches a user by username from Postgres.
+    fn scope(self, query: Select<UserEntity>) -> Select<UserEntity> {
+        match self {
+            Tenant::Global => query,
+            Tenant::Organization(org_id) => query.filter(
+                <UserEntity as EntityTrait>::Column::Id.in_subquery(
+                    Query::select()
+                        .column(MembershipColumn::UserId)
+                        .from(MembershipEntity)
+                        .and_where(MembershipColumn::OrganizationId.eq(org_id))
+                        .to_owned(),
+                ),
+            ),
+        }
+    }
+}
+
+/// Fetches a user by username, compared in canonical form.
 pub async fn find_user_by_username(
     db: &DatabaseConnection,
+    tenant: Tenant,
     username: &str,
 ) -> Result<Option<UserModel>, sea_orm::DbErr> {
-    UserEntity::find()
-        .filter(<UserEntity as EntityTrait>::Column::Username.eq(username.to_owned()))
+    tenant
+        .scope(UserEntity::find())
+        .filter(
+            <UserEntity as EntityTrait>::Column::UsernameCanonical.eq(canonical_username(username)),
+        )
         .one(db)
         .await
 }
@@ -17,22 +59,182 @@ pub async fn find_user_by_username(
 /// Fetches a user by primary key.
 pub async fn find_user_by_id(
     db: &DatabaseConnection,
+    tenant: Tenant,
     user_id: i32,
 ) -> Result<Option<UserModel>, sea_orm::DbErr> {
-    UserEntity::find_by_id(user_id).one(db).await
+    tenant.scope(UserEntity::find_by_id(user_id)).one(db).await
 }
 
-/// Inserts a new user record.
+/// Fetches every user the tenant can see, ordered by ID.
+pub async fn list_users(
+    db: &DatabaseConnection,
+    tenant: Tenant,
+) -> Result<Vec<UserModel>, sea_orm::DbErr> {
+    tenant
+        .scope(UserEntity::find())
+        .order_by_asc(<UserEntity as EntityTrait>::Column::Id)
+        .all(db)
+        .await
+}
+
+/// Fetches a user by (lowercased) email address.
+pub async fn find_user_by_email(
+    db: &DatabaseConnection,
+    tenant: Tenant,
+    email: &str,
+) -> Result<Option<UserModel>, sea_orm::DbErr> {
+    tenant
+        .scope(UserEntity::find())
+        .filter(<UserEntity as EntityTrait>::Column::Email.eq(email.to_owned()))
+        .one(db)
+        .await
+}
+
+/// Inserts a new user record with an unverified `email`.
 pub async fn create_user(
     db: &DatabaseConnection,
     username: String,
     password: String,
+    email: Option<String>,
 ) -> Result<UserModel, sea_orm::DbErr> {
     let new_user = UserActiveModel {
+        username_canonical: Set(canonical_username(&username)),
         username: Set(username),
         password: Set(password),
+        email: Set(email),
+        email_verified_at: Set(None),
         ..Default::default()
     };
 
     new_user.insert(db).await
 }
+
+/// Replaces the stored password hash for a user.
+pub async fn update_user_password(
+    db: &DatabaseConnection,
+    user_id: i32,
+    password: String,
+) -> Result<(), sea_orm::DbErr> {
+    UserEntity::update_many()
+        .col_expr(
+            <UserEntity as EntityTrait>::Column::Password,
+            Expr::value(password),
+        )
+        .filter(<UserEntity as EntityTrait>::Column::Id.eq(user_id))
+        .exec(db)
+        .await
+        .map(|_| ())
+}
+
+/// Replaces the user's email address; the new one starts out unverified.
+pub async fn set_email(
+    db: &DatabaseConnection,
+    user_id: i32,
+    email: String,
+) -> Result<(), sea_orm::DbErr> {
+    UserEntity::update_many()
+        .col_expr(
+            <UserEntity as EntityTrait>::Column::Email,
+            Expr::value(email),
+        )
+        .col_expr(
+            <UserEntity as EntityTrait>::Column::EmailVerifiedAt,
+            Expr::value(Option::<DateTime<Utc>>::None),
+        )
+        .filter(<UserEntity as EntityTrait>::Column::Id.eq(user_id))
+        .exec(db)
+        .await
+        .map(|_| ())
+}
+
+/// Takes `email` away from the account holding it, unless that account has verified it.
+///
+/// An unverified address proves nothing, so it must not lock the real owner out.
+pub async fn release_unverified_email(
+    db: &DatabaseConnection,
+    email: &str,
+) -> Result<u64, sea_orm::DbErr> {
+    UserEntity::update_many()
+        .col_expr(
+            <UserEntity as EntityTrait>::Column::Email,
+            Expr::value(Option::<String>::None),
+        )
+        .filter(<UserEntity as EntityTrait>::Column::Email.eq(email.to_owned()))
+        .filter(<UserEntity as EntityTrait>::Column::EmailVerifiedAt.is_null())
+        .exec(db)
+        .await
+        .map(|result| result.rows_affected)
+}
+
+/// Marks `email` as verified, unless the user has switched to another address since.
+pub async fn mark_email_verified(
+    db: &DatabaseConnection,
+    user_id: i32,
+    email: &str,
+) -> Result<bool, sea_orm::DbErr> {
+    UserEntity::update_many()
+        .col_expr(
+            <UserEntity as EntityTrait>::Column::EmailVerifiedAt,
+            Expr::value(Utc::now()),
+        )
+        .filter(<UserEntity as EntityTrait>::Column::Id.eq(user_id))
+        .filter(<UserEntity as EntityTrait>::Column::Email.eq(email.to_owned()))
+        .exec(db)
+        .await
+        .map(|result| result.rows_affected > 0)
+}
+
+/// Sets the organization put into the user's tokens from the next login or refresh on.
+pub async fn set_active_organization(
+    db: &DatabaseConnection,
+    user_id: i32,
+    organization_id: Option<i32>,
+) -> Result<(), sea_orm::DbErr> {
+    UserEntity::update_many()
+        .col_expr(
+            <UserEntity as EntityTrait>::Column::ActiveOrganizationId,
+            Expr::value(organization_id),
+        )
+        .filter(<UserEntity as EntityTrait>::Column::Id.eq(user_id))
+        .exec(db)
+        .await
+        .map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{DatabaseBackend, MockDatabase};
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn organization_tenant_only_sees_members() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<UserModel>::new(), Vec::<UserModel>::new()])
+            .into_connection();
+
+        list_users(&db, Tenant::Global).await.unwrap();
+        list_users(&db, Tenant::Organization(3)).await.unwrap();
+
+        let log = db.into_transaction_log();
+        assert!(!log[0].statements()[0].sql.contains("memberships"));
+        let scoped = &log[1].statements()[0];
+        assert!(scoped.sql.contains(
+            r#""users"."id" IN (SELECT "user_id" FROM "memberships" WHERE "memberships"."organization_id" = $1)"#
+        ), "{}", scoped.sql);
+    }
+
+    #[actix_web::test]
+    async fn email_lookups_are_scoped_to_the_tenant() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([Vec::<UserModel>::new()])
+            .into_connection();
+
+        find_user_by_email(&db, Tenant::Organization(3), "ada@example.com")
+            .await
+            .unwrap();
+
+        let log = db.into_transaction_log();
+        assert!(log[0].statements()[0].sql.contains("memberships"));
+    }
+}
@@ -0,0 +1,316 @@
+use chrono::{Duration, Utc};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect, Set, TransactionTrait, sea_query::Expr,
+};
+
+use crate::models::webauthn_challenge::{
+    ActiveModel as WebauthnChallengeActiveModel, Column as WebauthnChallengeColumn,
+    Entity as WebauthnChallengeEntity, Model as WebauthnChallengeModel,
+};
+use crate::models::webauthn_credential::{
+    ActiveModel as WebauthnCredentialActiveModel, Column as WebauthnCredentialColumn,
+    Entity as WebauthnCredentialEntity, Model as WebauthnCredentialModel,
+};
+use crate::utils::webauthn::{RegisteredCredential, encode_base64url};
+use crate::utils::{generate_opaque_token, hash_opaque_token};
+
+/// The two WebAuthn ceremonies a challenge can belong to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ceremony {
+    Registration,
+    Authentication,
+}
+
+impl Ceremony {
+    fn as_str(self) -> &'static str {
+        match self {
+            Ceremony::Registration => "registration",
+            Ceremony::Authentication => "authentication",
+        }
+    }
+}
+
+/// Who a ceremony is for; see the matching `webauthn_challenges` columns.
+#[derive(Default)]
+pub struct ChallengeSubject {
+    pub user_id: Option<i32>,
+    pub username: Option<String>,
+    pub email: Option<String>,
+    pub user_handle: Option<String>,
+}
+
+/// Issues a random base64url challenge for a ceremony, pruning expired ones. Only its hash
+/// is stored.
+pub async fn issue_webauthn_challenge(
+    db: &DatabaseConnection,
+    ceremony: Ceremony,
+    subject: ChallengeSubject,
+    ttl_secs: i64,
+) -> Result<String, DbErr> {
+    let challenge = generate_opaque_token();
+    let now = Utc::now();
+
+    // Anyone can start a ceremony, so stale rows are cleared as new ones arrive.
+    WebauthnChallengeEntity::delete_many()
+        .filter(WebauthnChallengeColumn::ExpiresAt.lte(now))
+        .exec(db)
+        .await?;
+
+    let pending = WebauthnChallengeActiveModel {
+        challenge_hash: Set(hash_opaque_token(&challenge)),
+        ceremony: Set(ceremony.as_str().to_string()),
+        user_id: Set(subject.user_id),
+        username: Set(subject.username),
+        email: Set(subject.email),
+        user_handle: Set(subject.user_handle),
+        expires_at: Set(now + Duration::seconds(ttl_secs)),
+        created_at: Set(now),
+        used_at: Set(None),
+        ..Default::default()
+    };
+    WebauthnChallengeEntity::insert(pending)
+        .exec_without_returning(db)
+        .await?;
+
+    Ok(challenge)
+}
+
+/// Resolves the challenge a client signed over, if it is still pending for `ceremony`.
+pub async fn find_webauthn_challenge(
+    db: &DatabaseConnection,
+    challenge: &str,
+    ceremony: Ceremony,
+) -> Result<Option<WebauthnChallengeModel>, DbErr> {
+    let pending = WebauthnChallengeEntity::find()
+        .filter(WebauthnChallengeColumn::ChallengeHash.eq(hash_opaque_token(challenge)))
+        .one(db)
+        .await?;
+
+    Ok(pending.filter(|pending| {
+        pending.ceremony == ceremony.as_str()
+            && pending.used_at.is_none()
+            && pending.expires_at > Utc::now()
+    }))
+}
+
+/// Spends a challenge found by [`find_webauthn_challenge`].
+///
+/// Returns `false` if it was spent or expired in the meantime.
+pub async fn spend_webauthn_challenge(
+    db: &DatabaseConnection,
+    pending: &WebauthnChallengeModel,
+) -> Result<bool, DbErr> {
+    let now = Utc::now();
+
+    // Conditional update so a challenge can only be answered once.
+    let claimed = WebauthnChallengeEntity::update_many()
+        .col_expr(WebauthnChallengeColumn::UsedAt, Expr::value(now))
+        .filter(WebauthnChallengeColumn::Id.eq(pending.id))
+        .filter(WebauthnChallengeColumn::UsedAt.is_null())
+        .filter(WebauthnChallengeColumn::ExpiresAt.gt(now))
+        .exec(db)
+        .await?;
+
+    Ok(claimed.rows_affected > 0)
+}
+
+/// The user's passkeys, oldest first.
+pub async fn list_webauthn_credentials(
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> Result<Vec<WebauthnCredentialModel>, DbErr> {
+    WebauthnCredentialEntity::find()
+        .filter(WebauthnCredentialColumn::UserId.eq(user_id))
+        .order_by_asc(WebauthnCredentialColumn::Id)
+        .all(db)
+        .await
+}
+
+/// Looks a passkey up by the base64url credential ID an authenticator presented.
+pub async fn find_webauthn_credential(
+    db: &DatabaseConnection,
+    credential_id: &str,
+) -> Result<Option<WebauthnCredentialModel>, DbErr> {
+    WebauthnCredentialEntity::find()
+        .filter(WebauthnCredentialColumn::CredentialId.eq(credential_id))
+        .one(db)
+        .await
+}
+
+/// Stores a passkey that passed registration.
+pub async fn add_webauthn_credential(
+    db: &DatabaseConnection,
+    user_id: i32,
+    user_handle: String,
+    name: String,
+    credential: &RegisteredCredential,
+) -> Result<WebauthnCredentialModel, DbErr> {
+    WebauthnCredentialActiveModel {
+        user_id: Set(user_id),
+        credential_id: Set(encode_base64url(&credential.credential_id)),
+        public_key: Set(credential.public_key.clone()),
+        sign_count: Set(i64::from(credential.sign_count)),
+        user_handle: Set(user_handle),
+        name: Set(name),
+        created_at: Set(Utc::now()),
+        last_used_at: Set(None),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+}
+
+/// Records a successful login with the passkey and its new signature counter.
+///
+/// Returns `false` when another login with the same counter got there first.
+pub async fn record_webauthn_use(
+    db: &DatabaseConnection,
+    credential: &WebauthnCredentialModel,
+    sign_count: u32,
+) -> Result<bool, DbErr> {
+    let updated = WebauthnCredentialEntity::update_many()
+        .col_expr(
+            WebauthnCredentialColumn::SignCount,
+            Expr::value(i64::from(sign_count)),
+        )
+        .col_expr(
+            WebauthnCredentialColumn::LastUsedAt,
+            Expr::value(Utc::now()),
+        )
+        .filter(WebauthnCredentialColumn::Id.eq(credential.id))
+        .filter(WebauthnCredentialColumn::SignCount.eq(credential.sign_count))
+        .exec(db)
+        .await?;
+
+    Ok(updated.rows_affected > 0)
+}
+
+/// Outcome of [`delete_webauthn_credential`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum PasskeyRemoval {
+    Removed,
+    /// The user has no such passkey.
+    NotFound,
+    /// It is the user's last passkey and `keep_last` was set.
+    LastPasskey,
+}
+
+/// Removes one of the user's passkeys, refusing to remove the last one when `keep_last` is set.
+///
+/// The user's passkeys stay locked from the check to the delete, so concurrent removals can't
+/// each leave the other's passkey as the supposed spare and together remove both.
+pub async fn delete_webauthn_credential(
+    db: &DatabaseConnection,
+    user_id: i32,
+    credential_id: i32,
+    keep_last: bool,
+) -> Result<PasskeyRemoval, DbErr> {
+    let txn = db.begin().await?;
+    let credentials = WebauthnCredentialEntity::find()
+        .filter(WebauthnCredentialColumn::UserId.eq(user_id))
+        .lock_exclusive()
+        .all(&txn)
+        .await?;
+    if !credentials
+        .iter()
+        .any(|credential| credential.id == credential_id)
+    {
+        return Ok(PasskeyRemoval::NotFound);
+    }
+    if keep_last && credentials.len() == 1 {
+        return Ok(PasskeyRemoval::LastPasskey);
+    }
+
+    WebauthnCredentialEntity::delete_by_id(credential_id)
+        .exec(&txn)
+        .await?;
+    txn.commit().await?;
+
+    Ok(PasskeyRemoval::Removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{DatabaseBackend, MockDatabase};
+
+    use crate::state::tests::exec;
+
+    use super::*;
+
+    fn pending(ceremony: &str) -> WebauthnChallengeModel {
+        WebauthnChallengeModel {
+            id: 1,
+            challenge_hash: hash_opaque_token("challenge"),
+            ceremony: ceremony.into(),
+            user_id: Some(4),
+            username: None,
+            email: None,
+            user_handle: None,
+            expires_at: Utc::now() + Duration::minutes(5),
+            created_at: Utc::now(),
+            used_at: None,
+        }
+    }
+
+    #[actix_web::test]
+    async fn challenges_only_answer_their_own_ceremony() {
+        let mut expired = pending("authentication");
+        expired.expires_at = Utc::now() - Duration::seconds(1);
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([
+                vec![pending("authentication")],
+                vec![pending("registration")],
+                vec![expired],
+            ])
+            .into_connection();
+
+        for expected in [true, false, false] {
+            let found = find_webauthn_challenge(&db, "challenge", Ceremony::Authentication)
+                .await
+                .unwrap();
+            assert_eq!(found.is_some(), expected);
+        }
+    }
+
+    fn passkey(id: i32) -> WebauthnCredentialModel {
+        WebauthnCredentialModel {
+            id,
+            user_id: 4,
+            credential_id: format!("credential-{}", id),
+            public_key: Vec::new(),
+            sign_count: 0,
+            user_handle: "handle".into(),
+            name: "Laptop".into(),
+            created_at: Utc::now(),
+            last_used_at: None,
+        }
+    }
+
+    #[actix_web::test]
+    async fn the_last_passkey_is_kept_under_lock() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([vec![passkey(1), passkey(2)], vec![passkey(2)]])
+            .append_exec_results([exec(1)])
+            .into_connection();
+
+        assert_eq!(
+            delete_webauthn_credential(&db, 4, 1, true).await.unwrap(),
+            PasskeyRemoval::Removed
+        );
+        // A concurrent removal of the other passkey waits for the lock, then finds it alone.
+        assert_eq!(
+            delete_webauthn_credential(&db, 4, 2, true).await.unwrap(),
+            PasskeyRemoval::LastPasskey
+        );
+
+        let log = db.into_transaction_log();
+        assert!(log[0].statements()[1].sql.ends_with("FOR UPDATE"));
+        assert!(
+            !log[1]
+                .statements()
+                .iter()
+                .any(|statement| statement.sql.starts_with("DELETE"))
+        );
+    }
+}
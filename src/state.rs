@@ -1,52 +1,93 @@
-use std::collections::HashSet;
 use std::fmt::{self, Display};
-use std::sync::Mutex;
+use std::sync::Arc;
 
-use actix_web::{HttpRequest, HttpResponse, ResponseError, http::StatusCode};
-use sea_orm::DatabaseConnection;
+use actix_web::{
+    HttpRequest, HttpResponse, ResponseError,
+    http::{StatusCode, header::WWW_AUTHENTICATE},
+};
+use chrono::DateTime;
+use sea_orm::{DatabaseConnection, DbErr};
 
 use crate::config::AppConfig;
-use crate::utils::{TokenClaims, decode_token};
+use crate::mail::Mailer;
+use crate::policy::PolicyEngine;
+use crate::services::token_service;
+use jsonwebtoken::{decode_header, errors::ErrorKind};
+
+use crate::utils::auth_cookie::ACCESS_TOKEN_COOKIE;
+use crate::utils::{KeyRing, TokenClaims, UserGrants, decode_token, encode_token};
 
 /// Shared state required by the handlers and middleware.
 pub struct AppState {
     pub db: DatabaseConnection,
     pub config: AppConfig,
-    pub revoked_tokens: Mutex<HashSet<String>>,
+    /// Shared with the reloader task that picks up rotations saved by other replicas.
+    pub key_ring: Arc<KeyRing>,
+    /// Shared with the reloader task that swaps in edited policy files.
+    pub policy: Arc<PolicyEngine>,
+    pub mailer: Arc<dyn Mailer>,
 }
 
 impl AppState {
-    pub fn new(db: DatabaseConnection, config: AppConfig) -> Self {
+    pub fn new(
+        db: DatabaseConnection,
+        config: AppConfig,
+        key_ring: Arc<KeyRing>,
+        policy: Arc<PolicyEngine>,
+        mailer: Arc<dyn Mailer>,
+    ) -> Self {
         Self {
             db,
             config,
-            revoked_tokens: Mutex::new(HashSet::new()),
+            key_ring,
+            policy,
+            mailer,
         }
     }
 
-    pub fn validate_token(&self, token: &str) -> Result<TokenClaims, AuthError> {
-        let claims =
-            decode_token(&self.config.jwt_secret, token).map_err(|_| AuthError::InvalidToken)?;
+    /// Mints an access token carrying the user's grants with the active signing key.
+    pub fn issue_access_token(
+        &self,
+        user_id: i32,
+        grants: &UserGrants,
+    ) -> jsonwebtoken::errors::Result<String> {
+        let key = self
+            .key_ring
+            .active()
+            .map_err(|_| jsonwebtoken::errors::Error::from(ErrorKind::InvalidKeyFormat))?;
+
+        encode_token(&self.config.jwt, &key, user_id, grants)
+    }
 
-        let revoked = self
-            .revoked_tokens
-            .lock()
-            .map_err(|_| AuthError::LockError)?;
+    /// Verifies a token with the ring key named by its `kid` header.
+    fn decode(&self, token: &str) -> Result<TokenClaims, AuthError> {
+        let header = decode_header(token).map_err(|_| AuthError::InvalidToken)?;
+        let key = self
+            .key_ring
+            .verification_key(header.kid.as_deref())
+            .map_err(|_| AuthError::InvalidToken)?;
 
-        if revoked.contains(token) {
+        decode_token(&self.config.jwt, &key, token).map_err(|_| AuthError::InvalidToken)
+    }
+
+    pub async fn validate_token(&self, token: &str) -> Result<TokenClaims, AuthError> {
+        let claims = self.decode(token)?;
+
+        if token_service::is_token_revoked(&self.db, &claims).await? {
             Err(AuthError::RevokedToken)
         } else {
             Ok(claims)
         }
     }
 
-    pub fn revoke_token(&self, token: &str) -> Result<bool, AuthError> {
-        let mut revoked = self
-            .revoked_tokens
-            .lock()
-            .map_err(|_| AuthError::LockError)?;
+    /// Revokes a token until it expires. Returns `false` if it was already revoked.
+    pub async fn revoke_token(&self, claims: &TokenClaims) -> Result<bool, AuthError> {
+        let expires_at =
+            DateTime::from_timestamp(claims.exp as i64, 0).ok_or(AuthError::InvalidToken)?;
 
-        Ok(revoked.insert(token.to_owned()))
+        token_service::revoke_token(&self.db, claims.jti.clone(), claims.sub, expires_at)
+            .await
+            .map_err(AuthError::from)
     }
 }
 
@@ -55,7 +96,14 @@ pub enum AuthError {
     InvalidToken,
     RevokedToken,
     MissingHeader,
-    LockError,
+    Forbidden,
+    Database(DbErr),
+}
+
+impl From<DbErr> for AuthError {
+    fn from(err: DbErr) -> Self {
+        AuthError::Database(err)
+    }
 }
 
 impl Display for AuthError {
@@ -64,7 +112,8 @@ impl Display for AuthError {
             AuthError::InvalidToken => write!(f, "Invalid or expired token"),
             AuthError::RevokedToken => write!(f, "Token has been revoked"),
             AuthError::MissingHeader => write!(f, "Missing Authorization header"),
-            AuthError::LockError => write!(f, "Internal lock error"),
+            AuthError::Forbidden => write!(f, "Forbidden"),
+            AuthError::Database(e) => write!(f, "DB error on token lookup: {}", e),
         }
     }
 }
@@ -72,79 +121,166 @@ impl Display for AuthError {
 impl ResponseError for AuthError {
     fn status_code(&self) -> StatusCode {
         match self {
-            AuthError::InvalidToken | AuthError::RevokedToken => StatusCode::UNAUTHORIZED,
-            AuthError::MissingHeader => StatusCode::BAD_REQUEST,
-            AuthError::LockError => StatusCode::INTERNAL_SERVER_ERROR,
+            AuthError::InvalidToken | AuthError::RevokedToken | AuthError::MissingHeader => {
+                StatusCode::UNAUTHORIZED
+            }
+            AuthError::Forbidden => StatusCode::FORBIDDEN,
+            AuthError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 
     fn error_response(&self) -> HttpResponse {
-        HttpResponse::build(self.status_code()).body(self.to_string())
+        let mut response = HttpResponse::build(self.status_code());
+        if self.status_code() == StatusCode::UNAUTHORIZED {
+            response.insert_header((WWW_AUTHENTICATE, "Bearer"));
+        }
+
+        response.body(self.to_string())
     }
 }
 
-/// Extracts the bearer token from the Authorization header.
-pub fn bearer_token(req: &HttpRequest) -> Result<String, AuthError> {
+/// The token of an `Authorization: Bearer` header, if the request has one.
+pub(crate) fn authorization_bearer(req: &HttpRequest) -> Option<&str> {
     req.headers()
         .get("Authorization")
         .and_then(|value| value.to_str().ok())
         .and_then(|value| value.strip_prefix("Bearer ").map(str::trim))
+}
+
+/// Whether the request authenticates with the access token cookie rather than a header.
+pub fn authenticated_by_cookie(req: &HttpRequest) -> bool {
+    authorization_bearer(req).is_none() && req.cookie(ACCESS_TOKEN_COOKIE).is_some()
+}
+
+/// Extracts the bearer token from the Authorization header, falling back to the access token
+/// cookie of browser sessions.
+pub fn bearer_token(req: &HttpRequest) -> Result<String, AuthError> {
+    authorization_bearer(req)
         .map(str::to_string)
+        .or_else(|| {
+            req.cookie(ACCESS_TOKEN_COOKIE)
+                .map(|cookie| cookie.value().to_string())
+        })
         .ok_or(AuthError::MissingHeader)
 }
 
 #[cfg(test)]
-mod tests {
-    use actix_web::test::TestRequest;
-    use sea_orm::{DatabaseBackend, MockDatabase};
+pub(crate) mod tests {
+    use actix_web::{cookie::Cookie, test::TestRequest};
+    use chrono::Utc;
+    use sea_orm::{DatabaseBackend, MockDatabase, MockExecResult};
 
-    use crate::utils::encode_token;
+    use crate::config::{DatabaseConfig, Environment, PasswordHashingConfig};
+    use crate::mail::tests::Outbox;
+    use crate::models::revoked_token::Model as RevokedTokenModel;
+    use crate::utils::SigningKey;
 
     use super::*;
 
-    fn mock_state() -> AppState {
-        let db = MockDatabase::new(DatabaseBackend::Postgres).into_connection();
-        let config = AppConfig {
-            database_url: "postgres://localhost:5432/postgres".to_string(),
-            bind_address: "127.0.0.1:8080".to_string(),
-            jwt_secret: "test-secret".to_string(),
-        };
+    pub(crate) fn test_config() -> AppConfig {
+        AppConfig {
+            environment: Environment::Development,
+            database: DatabaseConfig {
+                url: "postgres://localhost:5432/postgres".to_string(),
+                ..Default::default()
+            },
+            jwt: crate::utils::jwt::tests::test_config(),
+            password_hashing: PasswordHashingConfig {
+                memory_kib: 1024,
+                iterations: 1,
+                parallelism: 1,
+            },
+            ..Default::default()
+        }
+    }
 
-        AppState::new(db, config)
+    /// A mock write that touched `rows_affected` rows.
+    pub(crate) fn exec(rows_affected: u64) -> MockExecResult {
+        MockExecResult {
+            last_insert_id: 0,
+            rows_affected,
+        }
     }
 
-    #[test]
-    fn validate_token_accepts_valid_token() {
-        let state = mock_state();
-        let token =
-            encode_token(&state.config.jwt_secret, 7).expect("token should encode successfully");
+    /// App state over the given connection, signing with the HMAC test key.
+    ///
+    /// Mail goes to an [`Outbox`]; swap in a shared one to inspect what was sent.
+    pub(crate) fn test_state(db: DatabaseConnection) -> AppState {
+        let config = test_config();
+        let signing_key = SigningKey::hmac(&config.jwt.key_id, config.jwt.secret.as_bytes());
+
+        AppState::new(
+            db,
+            config,
+            Arc::new(signing_key.into()),
+            Default::default(),
+            Arc::new(Outbox::default()),
+        )
+    }
+
+    fn mock_state(db: MockDatabase) -> AppState {
+        test_state(db.into_connection())
+    }
+
+    #[actix_web::test]
+    async fn validate_token_accepts_valid_token() {
+        let state = mock_state(
+            MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<RevokedTokenModel>::new()]),
+        );
+        let token = state
+            .issue_access_token(7, &UserGrants::default())
+            .expect("token should encode successfully");
 
         let claims = state
             .validate_token(&token)
+            .await
             .expect("token should validate successfully");
 
         assert_eq!(claims.sub, 7);
     }
 
-    #[test]
-    fn validate_token_rejects_revoked_token() {
-        let state = mock_state();
-        let token =
-            encode_token(&state.config.jwt_secret, 5).expect("token should encode successfully");
+    #[actix_web::test]
+    async fn validate_token_rejects_revoked_token() {
+        let config = test_config();
+        let signing_key = SigningKey::hmac(&config.jwt.key_id, config.jwt.secret.as_bytes());
+        let token = encode_token(&config.jwt, &signing_key, 5, &UserGrants::default())
+            .expect("token should encode");
+        let claims = decode_token(&config.jwt, &signing_key, &token).unwrap();
+        let revoked = RevokedTokenModel {
+            jti: claims.jti,
+            user_id: 5,
+            expires_at: Utc::now(),
+            revoked_at: Utc::now(),
+        };
+        let state = mock_state(
+            MockDatabase::new(DatabaseBackend::Postgres).append_query_results([vec![revoked]]),
+        );
 
-        assert!(state.revoke_token(&token).expect("lock should not fail"));
-        let result = state.validate_token(&token);
+        let result = state.validate_token(&token).await;
 
         assert!(matches!(result, Err(AuthError::RevokedToken)));
     }
 
-    #[test]
-    fn revoke_token_is_idempotent() {
-        let state = mock_state();
-        let token = "dummy-token";
+    #[actix_web::test]
+    async fn validate_token_rejects_garbage() {
+        let state = mock_state(MockDatabase::new(DatabaseBackend::Postgres));
+
+        let result = state.validate_token("not-a-jwt").await;
 
-        assert!(state.revoke_token(token).unwrap());
-        assert!(!state.revoke_token(token).unwrap());
+        assert!(matches!(result, Err(AuthError::InvalidToken)));
+    }
+
+    #[actix_web::test]
+    async fn revoke_token_is_idempotent() {
+        let state = mock_state(
+            MockDatabase::new(DatabaseBackend::Postgres).append_exec_results([exec(1), exec(0)]),
+        );
+        let token = state.issue_access_token(2, &UserGrants::default()).unwrap();
+        let claims = state.decode(&token).unwrap();
+
+        assert!(state.revoke_token(&claims).await.unwrap());
+        assert!(!state.revoke_token(&claims).await.unwrap());
     }
 
     #[test]
@@ -157,6 +293,62 @@ mod tests {
         assert_eq!(token, "some-token");
     }
 
+    #[actix_web::test]
+    async fn validate_token_uses_key_named_by_kid() {
+        use jsonwebtoken::Algorithm;
+
+        use crate::utils::jwt_keys::{KeyState, tests::fixture_key};
+
+        let old = fixture_key(Algorithm::RS256);
+        let new = fixture_key(Algorithm::ES256);
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([
+                Vec::<RevokedTokenModel>::new(),
+                Vec::<RevokedTokenModel>::new(),
+            ])
+            .into_connection();
+        let ring =
+            KeyRing::new(vec![(old, KeyState::Active), (new, KeyState::VerifyOnly)]).unwrap();
+        let state = AppState::new(
+            db,
+            test_config(),
+            Arc::new(ring),
+            Default::default(),
+            Arc::new(Outbox::default()),
+        );
+
+        let before = state.issue_access_token(1, &UserGrants::default()).unwrap();
+        state.key_ring.promote("ES256-key").unwrap();
+        let after = state.issue_access_token(2, &UserGrants::default()).unwrap();
+
+        assert_eq!(
+            decode_header(&after).unwrap().kid.as_deref(),
+            Some("ES256-key")
+        );
+        assert_eq!(state.validate_token(&before).await.unwrap().sub, 1);
+        assert_eq!(state.validate_token(&after).await.unwrap().sub, 2);
+
+        state.key_ring.retire("RS256-key").unwrap();
+        assert!(matches!(
+            state.validate_token(&before).await,
+            Err(AuthError::InvalidToken)
+        ));
+    }
+
+    #[test]
+    fn bearer_token_falls_back_to_the_cookie() {
+        let req = TestRequest::default()
+            .cookie(Cookie::new(ACCESS_TOKEN_COOKIE, "cookie-token"))
+            .to_http_request();
+        assert_eq!(bearer_token(&req).unwrap(), "cookie-token");
+
+        let req = TestRequest::default()
+            .insert_header(("Authorization", "Bearer header-token"))
+            .cookie(Cookie::new(ACCESS_TOKEN_COOKIE, "cookie-token"))
+            .to_http_request();
+        assert_eq!(bearer_token(&req).unwrap(), "header-token");
+    }
+
     #[test]
     fn bearer_token_errors_without_header() {
         let req = TestRequest::default().to_http_request();
@@ -0,0 +1,170 @@
+use actix_web::cookie::{Cookie, SameSite, time::Duration};
+use serde::Deserialize;
+
+use crate::config::{AuthCookieConfig, JwtConfig, SameSitePolicy};
+
+/// HttpOnly cookie carrying the access token.
+pub const ACCESS_TOKEN_COOKIE: &str = "access_token";
+/// HttpOnly cookie carrying the refresh token, only sent to `POST /auth/refresh`.
+pub const REFRESH_TOKEN_COOKIE: &str = "refresh_token";
+/// Script-readable cookie holding the CSRF token the client echoes in [`CSRF_HEADER`].
+pub const CSRF_COOKIE: &str = "csrf_token";
+/// Header state-changing requests authenticated by cookie repeat the CSRF token in.
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+/// HttpOnly cookie tying a sign-in link's form to the browser that opened the link.
+pub const MAGIC_LINK_NONCE_COOKIE: &str = "magic_link_nonce";
+
+const REFRESH_TOKEN_PATH: &str = "/auth/refresh";
+
+/// How a login hands out its tokens.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenTransport {
+    /// In the JSON body, for clients that send `Authorization: Bearer` themselves.
+    #[default]
+    Body,
+    /// In HttpOnly cookies, for browsers; scripts only get to read the CSRF token.
+    Cookie,
+}
+
+/// `?transport=` for endpoints whose body is not ours to extend, such as WebAuthn responses.
+#[derive(Debug, Default, Deserialize)]
+pub struct TransportQuery {
+    #[serde(default)]
+    pub transport: TokenTransport,
+}
+
+fn cookie(
+    config: &AuthCookieConfig,
+    name: &'static str,
+    value: String,
+    path: &'static str,
+    http_only: bool,
+    max_age: Duration,
+) -> Cookie<'static> {
+    let mut cookie = Cookie::build(name, value)
+        .path(path)
+        .secure(config.secure)
+        .http_only(http_only)
+        .same_site(match config.same_site {
+            SameSitePolicy::Strict => SameSite::Strict,
+            SameSitePolicy::Lax => SameSite::Lax,
+            SameSitePolicy::None => SameSite::None,
+        })
+        .max_age(max_age)
+        .finish();
+    if let Some(domain) = &config.domain {
+        cookie.set_domain(domain.clone());
+    }
+    cookie
+}
+
+/// The access token cookie on its own, for replacing it mid-session.
+pub fn access_token_cookie(
+    config: &AuthCookieConfig,
+    jwt: &JwtConfig,
+    access_token: String,
+) -> Cookie<'static> {
+    cookie(
+        config,
+        ACCESS_TOKEN_COOKIE,
+        access_token,
+        "/",
+        true,
+        Duration::seconds(jwt.access_token_ttl_secs),
+    )
+}
+
+/// The cookies of a cookie-mode session: both tokens, HttpOnly, and the CSRF token.
+///
+/// The CSRF cookie lives as long as the refresh token so a refreshed session keeps it.
+pub fn session_cookies(
+    config: &AuthCookieConfig,
+    jwt: &JwtConfig,
+    access_token: String,
+    refresh_token: String,
+    csrf_token: String,
+) -> [Cookie<'static>; 3] {
+    let refresh_ttl = Duration::seconds(jwt.refresh_token_ttl_secs);
+
+    [
+        access_token_cookie(config, jwt, access_token),
+        cookie(
+            config,
+            REFRESH_TOKEN_COOKIE,
+            refresh_token,
+            REFRESH_TOKEN_PATH,
+            true,
+            refresh_ttl,
+        ),
+        cookie(config, CSRF_COOKIE, csrf_token, "/", false, refresh_ttl),
+    ]
+}
+
+/// The nonce cookie set by the sign-in link page; the form it serves repeats the nonce.
+///
+/// Always `SameSite=Strict`: only a form served by this site may post it back.
+pub fn magic_link_nonce_cookie(
+    config: &AuthCookieConfig,
+    nonce: String,
+    max_age: Duration,
+) -> Cookie<'static> {
+    let mut cookie = cookie(config, MAGIC_LINK_NONCE_COOKIE, nonce, "/", true, max_age);
+    cookie.set_same_site(SameSite::Strict);
+    cookie
+}
+
+/// Cookies that make the browser drop a cookie-mode session.
+pub fn cleared_session_cookies(config: &AuthCookieConfig) -> [Cookie<'static>; 3] {
+    [
+        (ACCESS_TOKEN_COOKIE, "/", true),
+        (REFRESH_TOKEN_COOKIE, REFRESH_TOKEN_PATH, true),
+        (CSRF_COOKIE, "/", false),
+    ]
+    .map(|(name, path, http_only)| {
+        cookie(config, name, String::new(), path, http_only, Duration::ZERO)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_cookies_are_http_only_and_csrf_cookie_is_readable() {
+        let config = AuthCookieConfig {
+            domain: Some("example.com".into()),
+            ..AuthCookieConfig::default()
+        };
+        let jwt = JwtConfig::default();
+
+        let [access, refresh, csrf] = session_cookies(
+            &config,
+            &jwt,
+            "access".into(),
+            "refresh".into(),
+            "csrf".into(),
+        );
+
+        assert_eq!(access.http_only(), Some(true));
+        assert_eq!(access.secure(), Some(true));
+        assert_eq!(access.same_site(), Some(SameSite::Strict));
+        assert_eq!(access.domain(), Some("example.com"));
+        assert_eq!(
+            access.max_age(),
+            Some(Duration::seconds(jwt.access_token_ttl_secs))
+        );
+        assert_eq!(refresh.path(), Some(REFRESH_TOKEN_PATH));
+        assert_eq!(refresh.http_only(), Some(true));
+        assert_eq!(csrf.http_only(), Some(false));
+        assert_eq!(csrf.value(), "csrf");
+    }
+
+    #[test]
+    fn cleared_cookies_expire_immediately() {
+        for cookie in cleared_session_cookies(&AuthCookieConfig::default()) {
+            assert_eq!(cookie.value(), "");
+            assert_eq!(cookie.max_age(), Some(Duration::ZERO));
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use argon2::{
+    Algorithm, Argon2, Params, Version,
+    password_hash::{
+        self, PasswordHash, PasswordHasher, PasswordVerifier, SaltString, rand_core::OsRng,
+    },
+};
+use scrypt::Scrypt;
+use std::sync::OnceLock;
+use subtle::ConstantTimeEq;
+
+use crate::config::PasswordHashingConfig;
+
+/// Outcome of checking a password against the value stored in `users.password`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordVerification {
+    /// The password does not match (or the stored value is unusable).
+    Mismatch,
+    /// The password matches a hash produced with the current parameters.
+    Valid,
+    /// The password matches, but the stored value is plaintext, a legacy algorithm,
+    /// or Argon2 with parameters that differ from the configured ones.
+    NeedsRehash,
+}
+
+impl PasswordVerification {
+    pub fn is_match(self) -> bool {
+        !matches!(self, PasswordVerification::Mismatch)
+    }
+}
+
+/// Builds an Argon2id hasher from the configured cost parameters.
+fn argon2(config: &PasswordHashingConfig) -> Result<Argon2<'static>, password_hash::Error> {
+    let params = Params::new(
+        config.memory_kib,
+        config.iterations,
+        config.parallelism,
+        None,
+    )?;
+
+    Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+}
+
+/// Hash a password with Argon2id, returning a PHC-formatted string.
+pub fn hash_password(
+    config: &PasswordHashingConfig,
+    password: &str,
+) -> Result<String, password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    argon2(config)?
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+}
+
+/// Verify a password against a stored credential in constant time.
+///
+/// Understands Argon2 and scrypt PHC strings, bcrypt (`$2a$`/`$2b$`/`$2x$`/`$2y$`)
+/// and legacy plaintext rows. Any other `$`-prefixed value is treated as an unknown
+/// hash format and never matches, so a leaked hash can't be replayed as a password.
+pub fn verify_password(
+    config: &PasswordHashingConfig,
+    password: &str,
+    stored: &str,
+) -> PasswordVerification {
+    if stored.starts_with("$argon2") {
+        verify_argon2(config, password, stored)
+    } else if stored.starts_with("$scrypt$") {
+        verify_phc(&Scrypt, password, stored)
+    } else if ["$2a$", "$2b$", "$2x$", "$2y$"]
+        .iter()
+        .any(|prefix| stored.starts_with(prefix))
+    {
+        match bcrypt::verify(password, stored) {
+            Ok(true) => PasswordVerification::NeedsRehash,
+            _ => PasswordVerification::Mismatch,
+        }
+    } else if stored.starts_with('$') || stored.is_empty() {
+        PasswordVerification::Mismatch
+    } else if bool::from(password.as_bytes().ct_eq(stored.as_bytes())) {
+        PasswordVerification::NeedsRehash
+    } else {
+        PasswordVerification::Mismatch
+    }
+}
+
+/// Spends as long as [`verify_password`] does on an Argon2 hash, without a stored credential.
+///
+/// Logins for unknown accounts call this so they take as long as a wrong password; the hash is
+/// made once, with the configured parameters, and never matches anything worth knowing.
+pub fn verify_dummy_password(config: &PasswordHashingConfig, password: &str) {
+    static DUMMY_HASH: OnceLock<Option<String>> = OnceLock::new();
+
+    if let Some(hash) = DUMMY_HASH.get_or_init(|| hash_password(config, "dummy password").ok()) {
+        verify_argon2(config, password, hash);
+    }
+}
+
+fn verify_argon2(
+    config: &PasswordHashingConfig,
+    password: &str,
+    stored: &str,
+) -> PasswordVerification {
+    let Ok(parsed) = PasswordHash::new(stored) else {
+        return PasswordVerification::Mismatch;
+    };
+
+    if Argon2::default()
+        .verify_password(password.as_bytes(), &parsed)
+        .is_err()
+    {
+        return PasswordVerification::Mismatch;
+    }
+
+    let current = parsed.algorithm == Algorithm::Argon2id.ident()
+        && parsed.version == Some(Version::V0x13.into())
+        && Params::try_from(&parsed).is_ok_and(|params| {
+            params.m_cost() == config.memory_kib
+                && params.t_cost() == config.iterations
+                && params.p_cost() == config.parallelism
+        });
+
+    if current {
+        PasswordVerification::Valid
+    } else {
+        PasswordVerification::NeedsRehash
+    }
+}
+
+fn verify_phc(
+    verifier: &impl PasswordVerifier,
+    password: &str,
+    stored: &str,
+) -> PasswordVerification {
+    let matches = PasswordHash::new(stored).is_ok_and(|parsed| {
+        verifier
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok()
+    });
+
+    if matches {
+        PasswordVerification::NeedsRehash
+    } else {
+        PasswordVerification::Mismatch
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fast_config() -> PasswordHashingConfig {
+        PasswordHashingConfig {
+            memory_kib: 1024,
+            iterations: 1,
+            parallelism: 1,
+        }
+    }
+
+    #[test]
+    fn hash_and_verify_roundtrip() {
+        let config = fast_config();
+        let hash = hash_password(&config, "hunter2").expect("password should hash");
+
+        assert!(hash.starts_with("$argon2id$v=19$m=1024,t=1,p=1$"));
+        assert_eq!(
+            verify_password(&config, "hunter2", &hash),
+            PasswordVerification::Valid
+        );
+        assert_eq!(
+            verify_password(&config, "hunter3", &hash),
+            PasswordVerification::Mismatch
+        );
+    }
+
+    #[test]
+    fn hashes_are_salted() {
+        let first = hash_password(&fast_config(), "same").unwrap();
+        let second = hash_password(&fast_config(), "same").unwrap();
+
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn hash_rejects_invalid_params() {
+        let config = PasswordHashingConfig {
+            memory_kib: 1,
+            ..fast_config()
+        };
+
+        assert!(hash_password(&config, "pw").is_err());
+    }
+
+    #[test]
+    fn outdated_argon2_params_need_rehash() {
+        let old = hash_password(&fast_config(), "pw").unwrap();
+        let bumped = PasswordHashingConfig {
+            iterations: 2,
+            ..fast_config()
+        };
+
+        assert_eq!(
+            verify_password(&bumped, "pw", &old),
+            PasswordVerification::NeedsRehash
+        );
+        assert_eq!(
+            verify_password(&bumped, "nope", &old),
+            PasswordVerification::Mismatch
+        );
+    }
+
+    #[test]
+    fn argon2i_hash_needs_rehash() {
+        let config = fast_config();
+        let params = Params::new(config.memory_kib, config.iterations, 1, None).unwrap();
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = Argon2::new(Algorithm::Argon2i, Version::V0x13, params)
+            .hash_password(b"pw", &salt)
+            .unwrap()
+            .to_string();
+
+        assert_eq!(
+            verify_password(&config, "pw", &hash),
+            PasswordVerification::NeedsRehash
+        );
+    }
+
+    #[test]
+    fn plaintext_matches_and_needs_rehash() {
+        let config = fast_config();
+
+        assert_eq!(
+            verify_password(&config, "secret", "secret"),
+            PasswordVerification::NeedsRehash
+        );
+        assert_eq!(
+            verify_password(&config, "Secret", "secret"),
+            PasswordVerification::Mismatch
+        );
+        assert_eq!(
+            verify_password(&config, "", ""),
+            PasswordVerification::Mismatch
+        );
+    }
+
+    #[test]
+    fn bcrypt_hash_matches_and_needs_rehash() {
+        let config = fast_config();
+        let hash = bcrypt::hash("pw", 4).unwrap();
+
+        assert_eq!(
+            verify_password(&config, "pw", &hash),
+            PasswordVerification::NeedsRehash
+        );
+        assert_eq!(
+            verify_password(&config, "other", &hash),
+            PasswordVerification::Mismatch
+        );
+    }
+
+    #[test]
+    fn scrypt_hash_matches_and_needs_rehash() {
+        let config = fast_config();
+        let salt = SaltString::generate(&mut OsRng);
+        let params = scrypt::Params::new(4, 8, 1, 32).unwrap();
+        let hash = Scrypt
+            .hash_password_customized(b"pw", None, None, params, &salt)
+            .unwrap()
+            .to_string();
+
+        assert_eq!(
+            verify_password(&config, "pw", &hash),
+            PasswordVerification::NeedsRehash
+        );
+        assert_eq!(
+            verify_password(&config, "other", &hash),
+            PasswordVerification::Mismatch
+        );
+    }
+
+    #[test]
+    fn unknown_hash_formats_never_match() {
+        let config = fast_config();
+        let stored = "$5$rounds=5000$salt$hash";
+
+        assert_eq!(
+            verify_password(&config, stored, stored),
+            PasswordVerification::Mismatch
+        );
+    }
+}
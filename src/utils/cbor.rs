@@ -0,0 +1,286 @@
+use std::fmt::{self, Display};
+
+/// Nesting deeper than this is rejected; WebAuthn structures are a few levels deep.
+const MAX_DEPTH: usize = 16;
+
+/// A decoded CBOR data item (RFC 8949), limited to the subset WebAuthn uses: integers, byte
+/// and text strings, arrays, maps, booleans and null, all with definite lengths.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Cbor {
+    Integer(i64),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Cbor>),
+    /// Entries in encoded order.
+    Map(Vec<(Cbor, Cbor)>),
+    Bool(bool),
+    Null,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct CborError(&'static str);
+
+impl Display for CborError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid CBOR: {}", self.0)
+    }
+}
+
+impl std::error::Error for CborError {}
+
+impl Cbor {
+    /// The value stored under `key` when this is a map.
+    pub fn get(&self, key: &Cbor) -> Option<&Cbor> {
+        match self {
+            Cbor::Map(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// The value stored under a text key, as in attestation objects.
+    pub fn get_text(&self, key: &str) -> Option<&Cbor> {
+        self.get(&Cbor::Text(key.to_string()))
+    }
+
+    /// The value stored under an integer key, as in COSE keys.
+    pub fn get_int(&self, key: i64) -> Option<&Cbor> {
+        self.get(&Cbor::Integer(key))
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Cbor::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Cbor::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Cbor::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+/// Decodes one data item that must span all of `input`.
+pub fn decode(input: &[u8]) -> Result<Cbor, CborError> {
+    match decode_prefix(input)? {
+        (value, []) => Ok(value),
+        _ => Err(CborError("trailing bytes")),
+    }
+}
+
+/// Decodes the data item at the start of `input`, returning it with the bytes after it.
+pub fn decode_prefix(input: &[u8]) -> Result<(Cbor, &[u8]), CborError> {
+    let mut reader = Reader { input, position: 0 };
+    let value = reader.item(0)?;
+
+    Ok((value, &input[reader.position..]))
+}
+
+struct Reader<'a> {
+    input: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], CborError> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|&end| end <= self.input.len())
+            .ok_or(CborError("unexpected end of input"))?;
+        let bytes = &self.input[self.position..end];
+        self.position = end;
+
+        Ok(bytes)
+    }
+
+    /// Reads an initial byte, returning the major type and its argument.
+    fn head(&mut self) -> Result<(u8, u64), CborError> {
+        let initial = self.take(1)?[0];
+        let info = initial & 0x1f;
+        let argument = match info {
+            0..=23 => u64::from(info),
+            24 => u64::from(self.take(1)?[0]),
+            25 => u64::from(u16::from_be_bytes(self.take(2)?.try_into().unwrap())),
+            26 => u64::from(u32::from_be_bytes(self.take(4)?.try_into().unwrap())),
+            27 => u64::from_be_bytes(self.take(8)?.try_into().unwrap()),
+            31 => return Err(CborError("indefinite lengths are not supported")),
+            _ => return Err(CborError("reserved additional information")),
+        };
+
+        Ok((initial >> 5, argument))
+    }
+
+    fn length(&mut self, argument: u64) -> Result<usize, CborError> {
+        // Every element takes at least a byte, so longer claims are necessarily truncated.
+        usize::try_from(argument)
+            .ok()
+            .filter(|&len| len <= self.input.len() - self.position)
+            .ok_or(CborError("length exceeds input"))
+    }
+
+    fn item(&mut self, depth: usize) -> Result<Cbor, CborError> {
+        if depth > MAX_DEPTH {
+            return Err(CborError("nested too deeply"));
+        }
+
+        let (major, argument) = self.head()?;
+        match major {
+            0 => i64::try_from(argument)
+                .map(Cbor::Integer)
+                .map_err(|_| CborError("integer out of range")),
+            1 => i64::try_from(argument)
+                .map(|n| Cbor::Integer(-1 - n))
+                .map_err(|_| CborError("integer out of range")),
+            2 => {
+                let len = self.length(argument)?;
+                Ok(Cbor::Bytes(self.take(len)?.to_vec()))
+            }
+            3 => {
+                let len = self.length(argument)?;
+                String::from_utf8(self.take(len)?.to_vec())
+                    .map(Cbor::Text)
+                    .map_err(|_| CborError("text is not UTF-8"))
+            }
+            4 => {
+                let len = self.length(argument)?;
+                (0..len)
+                    .map(|_| self.item(depth + 1))
+                    .collect::<Result<_, _>>()
+                    .map(Cbor::Array)
+            }
+            5 => {
+                let len = self.length(argument)?;
+                (0..len)
+                    .map(|_| Ok((self.item(depth + 1)?, self.item(depth + 1)?)))
+                    .collect::<Result<_, _>>()
+                    .map(Cbor::Map)
+            }
+            6 => Err(CborError("tags are not supported")),
+            _ => match argument {
+                20 => Ok(Cbor::Bool(false)),
+                21 => Ok(Cbor::Bool(true)),
+                22 => Ok(Cbor::Null),
+                _ => Err(CborError("unsupported simple value or float")),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Minimal encoder for building test fixtures.
+    pub(crate) fn encode(value: &Cbor) -> Vec<u8> {
+        fn head(out: &mut Vec<u8>, major: u8, argument: u64) {
+            let major = major << 5;
+            match argument {
+                0..=23 => out.push(major | argument as u8),
+                24..=0xff => out.extend([major | 24, argument as u8]),
+                0x100..=0xffff => {
+                    out.push(major | 25);
+                    out.extend((argument as u16).to_be_bytes());
+                }
+                _ => {
+                    out.push(major | 26);
+                    out.extend((argument as u32).to_be_bytes());
+                }
+            }
+        }
+
+        let mut out = Vec::new();
+        match value {
+            Cbor::Integer(n) if *n >= 0 => head(&mut out, 0, *n as u64),
+            Cbor::Integer(n) => head(&mut out, 1, (-1 - *n) as u64),
+            Cbor::Bytes(bytes) => {
+                head(&mut out, 2, bytes.len() as u64);
+                out.extend(bytes);
+            }
+            Cbor::Text(text) => {
+                head(&mut out, 3, text.len() as u64);
+                out.extend(text.as_bytes());
+            }
+            Cbor::Array(items) => {
+                head(&mut out, 4, items.len() as u64);
+                items.iter().for_each(|item| out.extend(encode(item)));
+            }
+            Cbor::Map(entries) => {
+                head(&mut out, 5, entries.len() as u64);
+                for (key, value) in entries {
+                    out.extend(encode(key));
+                    out.extend(encode(value));
+                }
+            }
+            Cbor::Bool(value) => out.push(0xf4 | u8::from(*value)),
+            Cbor::Null => out.push(0xf6),
+        }
+        out
+    }
+
+    #[test]
+    fn decodes_rfc_8949_examples() {
+        assert_eq!(decode(&[0x17]).unwrap(), Cbor::Integer(23));
+        assert_eq!(decode(&[0x19, 0x03, 0xe8]).unwrap(), Cbor::Integer(1000));
+        assert_eq!(decode(&[0x38, 0x63]).unwrap(), Cbor::Integer(-100));
+        assert_eq!(
+            decode(&[0x44, 1, 2, 3, 4]).unwrap(),
+            Cbor::Bytes(vec![1, 2, 3, 4])
+        );
+        assert_eq!(
+            decode(&[0x62, 0x22, 0x5c]).unwrap(),
+            Cbor::Text("\"\\".into())
+        );
+        assert_eq!(
+            decode(&[0xa2, 0x01, 0x02, 0x03, 0x04]).unwrap(),
+            Cbor::Map(vec![
+                (Cbor::Integer(1), Cbor::Integer(2)),
+                (Cbor::Integer(3), Cbor::Integer(4)),
+            ])
+        );
+        assert_eq!(decode(&[0xf5]).unwrap(), Cbor::Bool(true));
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        // Truncated byte string, oversized array, trailing bytes, indefinite length, float.
+        for input in [
+            &[0x45, 1, 2][..],
+            &[0x9b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+            &[0x01, 0x02],
+            &[0x5f, 0x41, 0x00, 0xff],
+            &[0xf9, 0x3c, 0x00],
+        ] {
+            assert!(decode(input).is_err(), "{:x?}", input);
+        }
+        assert!(decode(&[0x81; 40]).is_err());
+    }
+
+    #[test]
+    fn prefix_decoding_returns_the_rest() {
+        let (value, rest) = decode_prefix(&[0x63, b'a', b'b', b'c', 0xff]).unwrap();
+
+        assert_eq!(value.as_text(), Some("abc"));
+        assert_eq!(rest, &[0xff]);
+    }
+
+    #[test]
+    fn encoder_round_trips() {
+        let value = Cbor::Map(vec![
+            (Cbor::Text("fmt".into()), Cbor::Text("none".into())),
+            (Cbor::Integer(-257), Cbor::Bytes(vec![0; 300])),
+            (Cbor::Integer(3), Cbor::Array(vec![Cbor::Null])),
+        ]);
+
+        assert_eq!(decode(&encode(&value)).unwrap(), value);
+    }
+}
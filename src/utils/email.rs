@@ -0,0 +1,52 @@
+/// Longest address SMTP can deliver to (RFC 5321 path limit minus the angle brackets).
+const MAX_EMAIL_LEN: usize = 254;
+
+/// Trims and lowercases an email address, or returns `None` if it isn't plausibly one.
+///
+/// Only the shape is checked (`local@domain.tld`, no whitespace); the verification link
+/// proves the address actually works.
+pub fn normalize_email(raw: &str) -> Option<String> {
+    let email = raw.trim().to_lowercase();
+    if email.len() > MAX_EMAIL_LEN || email.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return None;
+    }
+
+    let (local, domain) = email.split_once('@')?;
+    let plausible = !local.is_empty()
+        && !domain.contains('@')
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && !email.contains(['<', '>', ',', ';']);
+
+    plausible.then_some(email)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_valid_addresses() {
+        assert_eq!(
+            normalize_email("  Bob@Example.COM ").as_deref(),
+            Some("bob@example.com")
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_addresses() {
+        for raw in [
+            "",
+            "bob",
+            "@example.com",
+            "bob@localhost",
+            "bob@example.com.",
+            "bob@@example.com",
+            "bob smith@example.com",
+            "bob@example.com>, eve@example.com",
+        ] {
+            assert_eq!(normalize_email(raw), None, "{}", raw);
+        }
+    }
+}
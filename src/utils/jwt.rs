@@ -1,61 +1,427 @@
 use chrono::{Duration, Utc};
-use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, encode};
+use jsonwebtoken::{Header, Validation, decode, decode_header, encode, errors::ErrorKind};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::config::JwtConfig;
+use crate::utils::SigningKey;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TokenClaims {
     pub sub: i32,
     pub exp: usize,
+    /// Issued-at time.
+    pub iat: usize,
+    /// Not-before time; equal to `iat` for tokens minted here.
+    pub nbf: usize,
+    pub iss: String,
+    pub aud: String,
+    /// Unique token ID, used as the key for revocation.
+    pub jti: String,
+    /// Role names held when the token was minted.
+    #[serde(default)]
+    pub roles: Vec<String>,
+    /// Permissions granted by those roles, e.g. `users:write`.
+    #[serde(default)]
+    pub permissions: Vec<String>,
+    /// Active organization; tenant-scoped permissions only apply within it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub org_id: Option<i32>,
+    /// The user's role in the active organization.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub org_role: Option<String>,
+    /// Permissions of `org_role`; they only hold within `org_id`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub org_permissions: Vec<String>,
+    /// Session (refresh token family) the token was minted for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+}
+
+impl TokenClaims {
+    /// Whether the global roles grant `permission`.
+    pub fn has_permission(&self, permission: &str) -> bool {
+        self.permissions.iter().any(|granted| granted == permission)
+    }
+
+    /// Whether the role in the active organization grants `permission` there.
+    pub fn has_org_permission(&self, permission: &str) -> bool {
+        self.org_permissions
+            .iter()
+            .any(|granted| granted == permission)
+    }
 }
 
-/// Encode a JWT for the provided subject (typically a user ID).
-pub fn encode_token(secret: &str, subject: i32) -> jsonwebtoken::errors::Result<String> {
-    let expiration = Utc::now()
-        .checked_add_signed(Duration::minutes(30))
+/// Roles and permissions embedded into an access token.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UserGrants {
+    pub roles: Vec<String>,
+    /// Permissions of the global roles.
+    pub permissions: Vec<String>,
+    pub org_id: Option<i32>,
+    pub org_role: Option<String>,
+    /// Permissions of the organization role, kept apart so they never hold outside it.
+    pub org_permissions: Vec<String>,
+    /// Session the token belongs to, so revoking the session revokes the token.
+    pub session_id: Option<String>,
+}
+
+impl UserGrants {
+    /// Builds grants with sorted, de-duplicated names.
+    pub fn new(
+        roles: impl IntoIterator<Item = String>,
+        permissions: impl IntoIterator<Item = String>,
+    ) -> Self {
+        Self {
+            roles: normalize(roles),
+            permissions: normalize(permissions),
+            ..Default::default()
+        }
+    }
+
+    /// Marks the grants as acting in `org_id`, where the user holds `role` with `permissions`.
+    pub fn in_organization(
+        self,
+        org_id: i32,
+        role: String,
+        permissions: impl IntoIterator<Item = String>,
+    ) -> Self {
+        Self {
+            org_id: Some(org_id),
+            org_role: Some(role),
+            org_permissions: normalize(permissions),
+            ..self
+        }
+    }
+
+    /// Ties the token to a session.
+    pub fn in_session(self, session_id: Option<&str>) -> Self {
+        Self {
+            session_id: session_id.map(str::to_string),
+            ..self
+        }
+    }
+}
+
+fn normalize(names: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut names: Vec<String> = names.into_iter().collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Encode a JWT for the provided subject (typically a user ID) and its grants.
+pub fn encode_token(
+    config: &JwtConfig,
+    key: &SigningKey,
+    subject: i32,
+    grants: &UserGrants,
+) -> jsonwebtoken::errors::Result<String> {
+    let now = Utc::now();
+    let expiration = now
+        .checked_add_signed(Duration::seconds(config.access_token_ttl_secs))
         .expect("failed to create expiration")
         .timestamp() as usize;
+    let issued_at = now.timestamp() as usize;
 
     let claims = TokenClaims {
         sub: subject,
         exp: expiration,
+        iat: issued_at,
+        nbf: issued_at,
+        iss: config.issuer.clone(),
+        aud: config.audience.clone(),
+        jti: Uuid::new_v4().to_string(),
+        roles: grants.roles.clone(),
+        permissions: grants.permissions.clone(),
+        org_id: grants.org_id,
+        org_role: grants.org_role.clone(),
+        org_permissions: grants.org_permissions.clone(),
+        sid: grants.session_id.clone(),
+    };
+
+    let header = Header {
+        kid: Some(key.kid.clone()),
+        ..Header::new(key.algorithm)
     };
 
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_ref()),
-    )
+    let encoding_key = key.encoding_key().ok_or(ErrorKind::InvalidKeyFormat)?;
+
+    encode(&header, &claims, encoding_key)
 }
 
 /// Decode and validate a JWT returning its claims.
-pub fn decode_token(secret: &str, token: &str) -> jsonwebtoken::errors::Result<TokenClaims> {
-    decode::<TokenClaims>(
-        token,
-        &DecodingKey::from_secret(secret.as_ref()),
-        &Validation::default(),
-    )
-    .map(|data| data.claims)
+///
+/// Enforces the key's algorithm and `kid`, plus `exp`, `nbf`, `iss` and `aud`,
+/// allowing the configured leeway.
+pub fn decode_token(
+    config: &JwtConfig,
+    key: &SigningKey,
+    token: &str,
+) -> jsonwebtoken::errors::Result<TokenClaims> {
+    let header = decode_header(token)?;
+    if header.kid.as_deref().is_some_and(|kid| kid != key.kid) {
+        return Err(ErrorKind::InvalidSignature.into());
+    }
+
+    decode::<TokenClaims>(token, key.decoding_key(), &validation(config, key))
+        .map(|data| data.claims)
+}
+
+fn validation(config: &JwtConfig, key: &SigningKey) -> Validation {
+    let mut validation = Validation::new(key.algorithm);
+    validation.leeway = config.leeway_secs;
+    validation.validate_nbf = true;
+    validation.set_issuer(&[&config.issuer]);
+    validation.set_audience(&[&config.audience]);
+    validation.set_required_spec_claims(&["exp", "nbf", "iss", "aud"]);
+    validation
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
+    use jsonwebtoken::{Algorithm, EncodingKey};
+
+    use crate::utils::jwt_keys::tests::fixture_key;
+
     use super::*;
 
+    pub(crate) fn test_config() -> JwtConfig {
+        JwtConfig {
+            algorithm: Algorithm::HS256,
+            secret: "test-secret".to_string(),
+            key_id: "test-key".to_string(),
+            private_key_path: None,
+            public_key_path: None,
+            keys: Vec::new(),
+            issuer: "test-issuer".to_string(),
+            audience: "test-audience".to_string(),
+            leeway_secs: 30,
+            access_token_ttl_secs: 60,
+            refresh_token_ttl_secs: 120,
+            key_state_reload_interval_secs: 0,
+        }
+    }
+
+    fn claims_at(config: &JwtConfig, offset_secs: i64) -> TokenClaims {
+        let now = Utc::now().timestamp() + offset_secs;
+
+        TokenClaims {
+            sub: 1,
+            exp: (now + 60) as usize,
+            iat: now as usize,
+            nbf: now as usize,
+            iss: config.issuer.clone(),
+            aud: config.audience.clone(),
+            jti: Uuid::new_v4().to_string(),
+            roles: Vec::new(),
+            permissions: Vec::new(),
+            org_id: None,
+            org_role: None,
+            org_permissions: Vec::new(),
+            sid: None,
+        }
+    }
+
+    fn hmac_key(config: &JwtConfig) -> SigningKey {
+        SigningKey::hmac(&config.key_id, config.secret.as_bytes())
+    }
+
+    fn sign(config: &JwtConfig, claims: &TokenClaims) -> String {
+        encode(
+            &Header::new(Algorithm::HS256),
+            claims,
+            &EncodingKey::from_secret(config.secret.as_ref()),
+        )
+        .unwrap()
+    }
+
     #[test]
     fn encode_and_decode_roundtrip() {
-        let secret = "test-secret";
+        let config = test_config();
+        let key = hmac_key(&config);
         let subject = 42;
 
-        let token = encode_token(secret, subject).expect("token should encode");
-        let claims = decode_token(secret, &token).expect("token should decode");
+        let token = encode_token(&config, &key, subject, &UserGrants::default())
+            .expect("token should encode");
+        let claims = decode_token(&config, &key, &token).expect("token should decode");
 
         assert_eq!(claims.sub, subject);
         assert!(claims.exp > Utc::now().timestamp() as usize);
+        assert_eq!(claims.iss, "test-issuer");
+        assert_eq!(claims.aud, "test-audience");
+        assert!(claims.iat <= claims.nbf);
+    }
+
+    #[test]
+    fn asymmetric_roundtrip_sets_kid_header() {
+        let config = test_config();
+
+        for algorithm in [Algorithm::RS256, Algorithm::ES256, Algorithm::EdDSA] {
+            let key = fixture_key(algorithm);
+            let token = encode_token(&config, &key, 7, &UserGrants::default())
+                .expect("token should encode");
+
+            let header = decode_header(&token).unwrap();
+            assert_eq!(header.alg, algorithm);
+            assert_eq!(header.kid.as_deref(), Some(key.kid.as_str()));
+            assert_eq!(decode_token(&config, &key, &token).unwrap().sub, 7);
+        }
+    }
+
+    #[test]
+    fn tokens_get_unique_ids() {
+        let config = test_config();
+        let key = hmac_key(&config);
+        let first = decode_token(
+            &config,
+            &key,
+            &encode_token(&config, &key, 1, &UserGrants::default()).unwrap(),
+        )
+        .unwrap();
+        let second = decode_token(
+            &config,
+            &key,
+            &encode_token(&config, &key, 1, &UserGrants::default()).unwrap(),
+        )
+        .unwrap();
+
+        assert_ne!(first.jti, second.jti);
     }
 
     #[test]
     fn decode_fails_with_wrong_secret() {
-        let token = encode_token("one-secret", 1).expect("token should encode");
-        assert!(decode_token("different-secret", &token).is_err());
+        let config = test_config();
+        let token = encode_token(&config, &hmac_key(&config), 1, &UserGrants::default())
+            .expect("token should encode");
+        let other = SigningKey::hmac(&config.key_id, b"different-secret");
+
+        assert!(decode_token(&config, &other, &token).is_err());
+    }
+
+    #[test]
+    fn decode_rejects_other_algorithm_or_kid() {
+        let config = test_config();
+        let rsa = fixture_key(Algorithm::RS256);
+        let token = encode_token(&config, &rsa, 1, &UserGrants::default()).unwrap();
+
+        assert!(decode_token(&config, &fixture_key(Algorithm::ES256), &token).is_err());
+        assert!(decode_token(&config, &hmac_key(&config), &token).is_err());
+
+        let mut renamed = rsa.clone();
+        renamed.kid = "other".to_string();
+        assert!(decode_token(&config, &renamed, &token).is_err());
+    }
+
+    #[test]
+    fn decode_rejects_foreign_issuer_or_audience() {
+        let config = test_config();
+        let key = hmac_key(&config);
+        let token = encode_token(&config, &key, 1, &UserGrants::default()).unwrap();
+        let staging_issuer = JwtConfig {
+            issuer: "staging".to_string(),
+            ..test_config()
+        };
+        let other_audience = JwtConfig {
+            audience: "billing".to_string(),
+            ..test_config()
+        };
+
+        assert!(decode_token(&staging_issuer, &key, &token).is_err());
+        assert!(decode_token(&other_audience, &key, &token).is_err());
+    }
+
+    #[test]
+    fn decode_rejects_tokens_not_yet_valid() {
+        let config = test_config();
+        let token = sign(&config, &claims_at(&config, 600));
+
+        assert!(decode_token(&config, &hmac_key(&config), &token).is_err());
+    }
+
+    #[test]
+    fn decode_allows_clock_skew_within_leeway() {
+        let config = test_config();
+        let token = sign(&config, &claims_at(&config, 10));
+
+        assert!(decode_token(&config, &hmac_key(&config), &token).is_ok());
+    }
+
+    #[test]
+    fn decode_rejects_tokens_without_registered_claims() {
+        #[derive(Serialize)]
+        struct Bare {
+            sub: i32,
+            exp: usize,
+        }
+
+        let config = test_config();
+        let token = encode(
+            &Header::new(Algorithm::HS256),
+            &Bare {
+                sub: 1,
+                exp: (Utc::now().timestamp() + 60) as usize,
+            },
+            &EncodingKey::from_secret(config.secret.as_ref()),
+        )
+        .unwrap();
+
+        assert!(decode_token(&config, &hmac_key(&config), &token).is_err());
+    }
+
+    #[test]
+    fn grants_round_trip_through_the_token() {
+        let config = test_config();
+        let key = hmac_key(&config);
+        let grants = UserGrants::new(
+            ["user".to_string(), "admin".to_string(), "admin".to_string()],
+            ["users:write".to_string()],
+        );
+
+        let token = encode_token(&config, &key, 1, &grants).unwrap();
+        let claims = decode_token(&config, &key, &token).unwrap();
+
+        assert_eq!(claims.roles, ["admin", "user"]);
+        assert!(claims.has_permission("users:write"));
+        assert!(!claims.has_permission("users:read"));
+        assert_eq!(claims.org_id, None);
+    }
+
+    #[test]
+    fn organization_round_trips_through_the_token() {
+        let config = test_config();
+        let key = hmac_key(&config);
+        let grants =
+            UserGrants::default().in_organization(4, "member".into(), ["members:read".to_string()]);
+
+        let token = encode_token(&config, &key, 1, &grants).unwrap();
+        let claims = decode_token(&config, &key, &token).unwrap();
+
+        assert_eq!(claims.org_id, Some(4));
+        assert_eq!(claims.org_role.as_deref(), Some("member"));
+        assert!(claims.has_org_permission("members:read"));
+        assert!(!claims.has_permission("members:read"));
+    }
+
+    #[test]
+    fn tokens_without_grants_decode_with_none() {
+        let config = test_config();
+        let mut claims = serde_json::to_value(claims_at(&config, 0)).unwrap();
+        claims.as_object_mut().unwrap().remove("roles");
+        claims.as_object_mut().unwrap().remove("permissions");
+        let token = encode(
+            &Header {
+                kid: Some(config.key_id.clone()),
+                ..Header::new(Algorithm::HS256)
+            },
+            &claims,
+            &EncodingKey::from_secret(config.secret.as_ref()),
+        )
+        .unwrap();
+
+        let decoded = decode_token(&config, &hmac_key(&config), &token).unwrap();
+
+        assert!(decoded.roles.is_empty());
+        assert!(decoded.permissions.is_empty());
     }
 }
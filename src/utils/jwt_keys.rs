@@ -0,0 +1,689 @@
+use std::fmt::{self, Display};
+use std::str::FromStr;
+use std::sync::RwLock;
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use ed25519_dalek::pkcs8::DecodePublicKey as _;
+use futures_util::lock::{Mutex, MutexGuard};
+use jsonwebtoken::{
+    Algorithm, DecodingKey, EncodingKey,
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, EllipticCurveKeyParameters,
+        EllipticCurveKeyType, Jwk, JwkSet, KeyAlgorithm, OctetKeyPairParameters, OctetKeyPairType,
+        PublicKeyUse, RSAKeyParameters, RSAKeyType,
+    },
+};
+use rsa::traits::PublicKeyParts;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{JwtConfig, JwtKeyConfig};
+
+/// A JWT signing key together with the `kid` it is published under.
+#[derive(Clone)]
+pub struct SigningKey {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    /// `None` for keys loaded from a public key only, which can verify but never sign.
+    encoding: Option<EncodingKey>,
+    decoding: DecodingKey,
+    /// Public half as a JWK; `None` for shared secrets, which must never be published.
+    jwk: Option<Jwk>,
+}
+
+#[derive(Debug)]
+pub enum KeyError {
+    Io(String, std::io::Error),
+    MissingKeyPath(&'static str),
+    InvalidKey(String),
+    UnsupportedAlgorithm(Algorithm),
+}
+
+impl Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyError::Io(path, e) => write!(f, "failed to read key file {}: {}", path, e),
+            KeyError::MissingKeyPath(var) => write!(f, "{} must be set for asymmetric JWTs", var),
+            KeyError::InvalidKey(e) => write!(f, "invalid key: {}", e),
+            KeyError::UnsupportedAlgorithm(alg) => write!(f, "unsupported JWT algorithm {:?}", alg),
+        }
+    }
+}
+
+impl SigningKey {
+    /// An HS256 key backed by a shared secret.
+    pub fn hmac(kid: impl Into<String>, secret: &[u8]) -> Self {
+        Self {
+            kid: kid.into(),
+            algorithm: Algorithm::HS256,
+            encoding: Some(EncodingKey::from_secret(secret)),
+            decoding: DecodingKey::from_secret(secret),
+            jwk: None,
+        }
+    }
+
+    /// An RS256, ES256 or EdDSA keypair from PEM-encoded PKCS#8 private and SPKI public keys.
+    pub fn from_pem(
+        kid: impl Into<String>,
+        algorithm: Algorithm,
+        private_pem: &[u8],
+        public_pem: &[u8],
+    ) -> Result<Self, KeyError> {
+        let invalid = |e: jsonwebtoken::errors::Error| KeyError::InvalidKey(e.to_string());
+        let encoding = match algorithm {
+            Algorithm::RS256 => EncodingKey::from_rsa_pem(private_pem).map_err(invalid)?,
+            Algorithm::ES256 => EncodingKey::from_ec_pem(private_pem).map_err(invalid)?,
+            Algorithm::EdDSA => EncodingKey::from_ed_pem(private_pem).map_err(invalid)?,
+            other => return Err(KeyError::UnsupportedAlgorithm(other)),
+        };
+        let mut key = Self::verifying_from_pem(kid, algorithm, public_pem)?;
+        key.encoding = Some(encoding);
+
+        Ok(key)
+    }
+
+    /// A verify-only RS256, ES256 or EdDSA key from an SPKI public key PEM.
+    pub fn verifying_from_pem(
+        kid: impl Into<String>,
+        algorithm: Algorithm,
+        public_pem: &[u8],
+    ) -> Result<Self, KeyError> {
+        let invalid = |e: jsonwebtoken::errors::Error| KeyError::InvalidKey(e.to_string());
+        let decoding = match algorithm {
+            Algorithm::RS256 => DecodingKey::from_rsa_pem(public_pem).map_err(invalid)?,
+            Algorithm::ES256 => DecodingKey::from_ec_pem(public_pem).map_err(invalid)?,
+            Algorithm::EdDSA => DecodingKey::from_ed_pem(public_pem).map_err(invalid)?,
+            other => return Err(KeyError::UnsupportedAlgorithm(other)),
+        };
+        let kid = kid.into();
+        let jwk = public_jwk(&kid, algorithm, public_pem)?;
+
+        Ok(Self {
+            kid,
+            algorithm,
+            encoding: None,
+            decoding,
+            jwk: Some(jwk),
+        })
+    }
+
+    /// Loads the key described by the JWT settings: the shared secret for HS256,
+    /// otherwise the PEM files at the configured paths.
+    pub fn from_config(config: &JwtConfig) -> Result<Self, KeyError> {
+        if config.algorithm == Algorithm::HS256 {
+            return Ok(Self::hmac(&config.key_id, config.secret.as_bytes()));
+        }
+
+        let private_path = config
+            .private_key_path
+            .as_deref()
+            .ok_or(KeyError::MissingKeyPath("JWT_PRIVATE_KEY_PATH"))?;
+        let public_path = config
+            .public_key_path
+            .as_deref()
+            .ok_or(KeyError::MissingKeyPath("JWT_PUBLIC_KEY_PATH"))?;
+
+        Self::from_pem(
+            &config.key_id,
+            config.algorithm,
+            &read_key_file(private_path)?,
+            &read_key_file(public_path)?,
+        )
+    }
+
+    /// Loads an additional key-ring entry. The private key is optional for
+    /// asymmetric keys that only need to verify.
+    pub fn from_key_config(config: &JwtKeyConfig) -> Result<Self, KeyError> {
+        if config.algorithm == Algorithm::HS256 {
+            let secret = config
+                .secret
+                .as_deref()
+                .ok_or(KeyError::MissingKeyPath("secret"))?;
+            return Ok(Self::hmac(&config.kid, secret.as_bytes()));
+        }
+
+        let public_pem = read_key_file(
+            config
+                .public_key_path
+                .as_deref()
+                .ok_or(KeyError::MissingKeyPath("public_key_path"))?,
+        )?;
+
+        match config.private_key_path.as_deref() {
+            Some(private_path) => Self::from_pem(
+                &config.kid,
+                config.algorithm,
+                &read_key_file(private_path)?,
+                &public_pem,
+            ),
+            None => Self::verifying_from_pem(&config.kid, config.algorithm, &public_pem),
+        }
+    }
+
+    /// The private signing key, if this key is able to sign.
+    pub fn encoding_key(&self) -> Option<&EncodingKey> {
+        self.encoding.as_ref()
+    }
+
+    pub fn decoding_key(&self) -> &DecodingKey {
+        &self.decoding
+    }
+
+    /// The public JWK, or `None` for shared secrets.
+    pub fn jwk(&self) -> Option<&Jwk> {
+        self.jwk.as_ref()
+    }
+}
+
+/// Lifecycle of a key in the ring.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyState {
+    /// Signs new tokens; exactly one key is active.
+    Active,
+    /// Still verifies tokens it signed earlier and stays in the JWKS.
+    VerifyOnly,
+    /// No longer trusted for anything.
+    Retired,
+}
+
+impl KeyState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            KeyState::Active => "active",
+            KeyState::VerifyOnly => "verify_only",
+            KeyState::Retired => "retired",
+        }
+    }
+}
+
+impl FromStr for KeyState {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "active" => Ok(KeyState::Active),
+            "verify_only" => Ok(KeyState::VerifyOnly),
+            "retired" => Ok(KeyState::Retired),
+            other => Err(format!("unknown key state `{}`", other)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum KeyRingError {
+    UnknownKey(String),
+    DuplicateKey(String),
+    /// The ring must contain exactly one active key.
+    ActiveKeyCount(usize),
+    /// The key has no private half, or is retired, so it can't be promoted.
+    CannotSign(String),
+    /// The active key has to be replaced before it can be retired.
+    RetiringActiveKey(String),
+    Load(String, KeyError),
+    LockError,
+}
+
+impl Display for KeyRingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyRingError::UnknownKey(kid) => write!(f, "unknown key {}", kid),
+            KeyRingError::DuplicateKey(kid) => write!(f, "duplicate key {}", kid),
+            KeyRingError::ActiveKeyCount(count) => {
+                write!(f, "expected exactly one active key, found {}", count)
+            }
+            KeyRingError::CannotSign(kid) => write!(f, "key {} cannot be used for signing", kid),
+            KeyRingError::RetiringActiveKey(kid) => {
+                write!(f, "key {} is active; promote another key first", kid)
+            }
+            KeyRingError::Load(kid, e) => write!(f, "failed to load key {}: {}", kid, e),
+            KeyRingError::LockError => write!(f, "Internal lock error"),
+        }
+    }
+}
+
+/// Summary of a key-ring entry, safe to show to operators.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct KeyStatus {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    pub state: KeyState,
+    pub can_sign: bool,
+}
+
+/// The set of keys used to sign and verify tokens.
+///
+/// Rotation is a two-step affair: add the new key as verify-only so its JWK is
+/// published ahead of time, then promote it. The previous active key drops to
+/// verify-only and keeps validating live tokens until they expire, after which it
+/// can be retired.
+pub struct KeyRing {
+    keys: RwLock<Vec<(SigningKey, KeyState)>>,
+    /// Held across a state change and its save, see [`KeyRing::lock_rotation`].
+    rotation: Mutex<()>,
+}
+
+impl From<SigningKey> for KeyRing {
+    fn from(key: SigningKey) -> Self {
+        Self {
+            keys: RwLock::new(vec![(key, KeyState::Active)]),
+            rotation: Mutex::new(()),
+        }
+    }
+}
+
+impl KeyRing {
+    pub fn new(keys: Vec<(SigningKey, KeyState)>) -> Result<Self, KeyRingError> {
+        Self::check(&keys)?;
+
+        Ok(Self {
+            keys: RwLock::new(keys),
+            rotation: Mutex::new(()),
+        })
+    }
+
+    /// Waits for exclusive use of the ring's states while they are changed and saved.
+    ///
+    /// Promotions, retirements and reloads take it so none of them lands between another's
+    /// change and its save (or undo). Signing and verification don't, so they never wait on
+    /// the database.
+    pub async fn lock_rotation(&self) -> MutexGuard<'_, ()> {
+        self.rotation.lock().await
+    }
+
+    fn check(keys: &[(SigningKey, KeyState)]) -> Result<(), KeyRingError> {
+        for (index, (key, _)) in keys.iter().enumerate() {
+            if keys[..index].iter().any(|(other, _)| other.kid == key.kid) {
+                return Err(KeyRingError::DuplicateKey(key.kid.clone()));
+            }
+        }
+
+        let active = keys
+            .iter()
+            .filter(|(_, state)| *state == KeyState::Active)
+            .collect::<Vec<_>>();
+        match active.as_slice() {
+            [(key, _)] if key.encoding.is_none() => {
+                return Err(KeyRingError::CannotSign(key.kid.clone()));
+            }
+            [_] => {}
+            other => return Err(KeyRingError::ActiveKeyCount(other.len())),
+        }
+
+        Ok(())
+    }
+
+    /// Builds the ring from the primary key settings (active) plus any extra `keys`.
+    pub fn from_config(config: &JwtConfig) -> Result<Self, KeyRingError> {
+        let primary = SigningKey::from_config(config)
+            .map_err(|e| KeyRingError::Load(config.key_id.clone(), e))?;
+        let mut keys = vec![(primary, KeyState::Active)];
+
+        for key_config in &config.keys {
+            let key = SigningKey::from_key_config(key_config)
+                .map_err(|e| KeyRingError::Load(key_config.kid.clone(), e))?;
+            keys.push((key, key_config.state));
+        }
+
+        Self::new(keys)
+    }
+
+    /// Puts keys back into the states saved by earlier promotions and retirements.
+    ///
+    /// Saved states of keys that are no longer configured are ignored. Nothing changes when the
+    /// result would not be a valid ring, e.g. because the saved active key lost its private half.
+    pub fn restore_states(&self, saved: &[(String, KeyState)]) -> Result<(), KeyRingError> {
+        let mut keys = self.keys.write().map_err(|_| KeyRingError::LockError)?;
+
+        let mut restored = keys.clone();
+        for (key, state) in restored.iter_mut() {
+            if let Some((_, saved)) = saved.iter().find(|(kid, _)| *kid == key.kid) {
+                *state = *saved;
+            }
+        }
+        Self::check(&restored)?;
+
+        *keys = restored;
+        Ok(())
+    }
+
+    /// The key new tokens are signed with.
+    pub fn active(&self) -> Result<SigningKey, KeyRingError> {
+        let keys = self.keys.read().map_err(|_| KeyRingError::LockError)?;
+
+        keys.iter()
+            .find(|(_, state)| *state == KeyState::Active)
+            .map(|(key, _)| key.clone())
+            .ok_or(KeyRingError::ActiveKeyCount(0))
+    }
+
+    /// The key that should verify a token with the given `kid` header.
+    ///
+    /// Tokens without a `kid` are checked against the active key. Retired keys
+    /// never verify.
+    pub fn verification_key(&self, kid: Option<&str>) -> Result<SigningKey, KeyRingError> {
+        let keys = self.keys.read().map_err(|_| KeyRingError::LockError)?;
+
+        keys.iter()
+            .find(|(key, state)| match kid {
+                Some(kid) => key.kid == kid && *state != KeyState::Retired,
+                None => *state == KeyState::Active,
+            })
+            .map(|(key, _)| key.clone())
+            .ok_or_else(|| KeyRingError::UnknownKey(kid.unwrap_or_default().to_string()))
+    }
+
+    /// Makes `kid` the active key, demoting the current one to verify-only.
+    pub fn promote(&self, kid: &str) -> Result<(), KeyRingError> {
+        let mut keys = self.keys.write().map_err(|_| KeyRingError::LockError)?;
+
+        let (key, state) = keys
+            .iter()
+            .find(|(key, _)| key.kid == kid)
+            .ok_or_else(|| KeyRingError::UnknownKey(kid.to_string()))?;
+        if key.encoding.is_none() || *state == KeyState::Retired {
+            return Err(KeyRingError::CannotSign(kid.to_string()));
+        }
+
+        for (key, state) in keys.iter_mut() {
+            if key.kid == kid {
+                *state = KeyState::Active;
+            } else if *state == KeyState::Active {
+                *state = KeyState::VerifyOnly;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Stops trusting `kid` entirely; tokens it signed fail validation from now on.
+    pub fn retire(&self, kid: &str) -> Result<(), KeyRingError> {
+        let mut keys = self.keys.write().map_err(|_| KeyRingError::LockError)?;
+
+        let (_, state) = keys
+            .iter_mut()
+            .find(|(key, _)| key.kid == kid)
+            .ok_or_else(|| KeyRingError::UnknownKey(kid.to_string()))?;
+        if *state == KeyState::Active {
+            return Err(KeyRingError::RetiringActiveKey(kid.to_string()));
+        }
+        *state = KeyState::Retired;
+
+        Ok(())
+    }
+
+    pub fn statuses(&self) -> Result<Vec<KeyStatus>, KeyRingError> {
+        let keys = self.keys.read().map_err(|_| KeyRingError::LockError)?;
+
+        Ok(keys
+            .iter()
+            .map(|(key, state)| KeyStatus {
+                kid: key.kid.clone(),
+                algorithm: key.algorithm,
+                state: *state,
+                can_sign: key.encoding.is_some(),
+            })
+            .collect())
+    }
+
+    /// Public JWKs of every key that can still verify tokens.
+    pub fn jwk_set(&self) -> Result<JwkSet, KeyRingError> {
+        let keys = self.keys.read().map_err(|_| KeyRingError::LockError)?;
+
+        Ok(JwkSet {
+            keys: keys
+                .iter()
+                .filter(|(_, state)| *state != KeyState::Retired)
+                .flat_map(|(key, _)| key.jwk().cloned())
+                .collect(),
+        })
+    }
+}
+
+fn read_key_file(path: &str) -> Result<Vec<u8>, KeyError> {
+    std::fs::read(path).map_err(|e| KeyError::Io(path.to_string(), e))
+}
+
+/// Builds the public JWK for a verification key from its SPKI PEM.
+fn public_jwk(kid: &str, algorithm: Algorithm, public_pem: &[u8]) -> Result<Jwk, KeyError> {
+    let pem = std::str::from_utf8(public_pem).map_err(|e| KeyError::InvalidKey(e.to_string()))?;
+    let invalid = |e: rsa::pkcs8::spki::Error| KeyError::InvalidKey(e.to_string());
+
+    let (key_algorithm, parameters) = match algorithm {
+        Algorithm::RS256 => {
+            let key = rsa::RsaPublicKey::from_public_key_pem(pem).map_err(invalid)?;
+            (
+                KeyAlgorithm::RS256,
+                AlgorithmParameters::RSA(RSAKeyParameters {
+                    key_type: RSAKeyType::RSA,
+                    n: URL_SAFE_NO_PAD.encode(key.n().to_bytes_be()),
+                    e: URL_SAFE_NO_PAD.encode(key.e().to_bytes_be()),
+                }),
+            )
+        }
+        Algorithm::ES256 => {
+            let key = p256::PublicKey::from_public_key_pem(pem).map_err(invalid)?;
+            let point = p256::EncodedPoint::from(key);
+            let (Some(x), Some(y)) = (point.x(), point.y()) else {
+                return Err(KeyError::InvalidKey("compressed EC point".to_string()));
+            };
+            (
+                KeyAlgorithm::ES256,
+                AlgorithmParameters::EllipticCurve(EllipticCurveKeyParameters {
+                    key_type: EllipticCurveKeyType::EC,
+                    curve: EllipticCurve::P256,
+                    x: URL_SAFE_NO_PAD.encode(x),
+                    y: URL_SAFE_NO_PAD.encode(y),
+                }),
+            )
+        }
+        Algorithm::EdDSA => {
+            let key = ed25519_dalek::VerifyingKey::from_public_key_pem(pem).map_err(invalid)?;
+            (
+                KeyAlgorithm::EdDSA,
+                AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                    key_type: OctetKeyPairType::OctetKeyPair,
+                    curve: EllipticCurve::Ed25519,
+                    x: URL_SAFE_NO_PAD.encode(key.as_bytes()),
+                }),
+            )
+        }
+        other => return Err(KeyError::UnsupportedAlgorithm(other)),
+    };
+
+    Ok(Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            key_algorithm: Some(key_algorithm),
+            key_id: Some(kid.to_string()),
+            ..Default::default()
+        },
+        algorithm: parameters,
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn fixture_key(algorithm: Algorithm) -> SigningKey {
+        let (private_pem, public_pem): (&[u8], &[u8]) = match algorithm {
+            Algorithm::RS256 => (
+                include_bytes!("../../fixtures/jwt/rsa_private.pem"),
+                include_bytes!("../../fixtures/jwt/rsa_public.pem"),
+            ),
+            Algorithm::ES256 => (
+                include_bytes!("../../fixtures/jwt/ec_private.pem"),
+                include_bytes!("../../fixtures/jwt/ec_public.pem"),
+            ),
+            Algorithm::EdDSA => (
+                include_bytes!("../../fixtures/jwt/ed_private.pem"),
+                include_bytes!("../../fixtures/jwt/ed_public.pem"),
+            ),
+            other => panic!("no fixture for {:?}", other),
+        };
+
+        SigningKey::from_pem(
+            format!("{:?}-key", algorithm),
+            algorithm,
+            private_pem,
+            public_pem,
+        )
+        .expect("fixture key should load")
+    }
+
+    #[test]
+    fn hmac_keys_are_never_published() {
+        let key = SigningKey::hmac("hs", b"secret");
+
+        assert!(key.jwk().is_none());
+    }
+
+    #[test]
+    fn asymmetric_keys_publish_jwk_with_kid() {
+        for algorithm in [Algorithm::RS256, Algorithm::ES256, Algorithm::EdDSA] {
+            let key = fixture_key(algorithm);
+            let jwk = key.jwk().expect("asymmetric keys have a JWK");
+
+            assert_eq!(jwk.common.key_id.as_deref(), Some(key.kid.as_str()));
+            assert!(DecodingKey::from_jwk(jwk).is_ok());
+        }
+    }
+
+    #[test]
+    fn from_pem_rejects_mismatched_key_type() {
+        let result = SigningKey::from_pem(
+            "bad",
+            Algorithm::RS256,
+            include_bytes!("../../fixtures/jwt/ec_private.pem"),
+            include_bytes!("../../fixtures/jwt/ec_public.pem"),
+        );
+
+        assert!(result.is_err());
+    }
+
+    fn ring() -> KeyRing {
+        let verify_only = SigningKey::verifying_from_pem(
+            "ed-public",
+            Algorithm::EdDSA,
+            include_bytes!("../../fixtures/jwt/ed_public.pem"),
+        )
+        .unwrap();
+
+        KeyRing::new(vec![
+            (fixture_key(Algorithm::RS256), KeyState::Active),
+            (fixture_key(Algorithm::ES256), KeyState::VerifyOnly),
+            (verify_only, KeyState::VerifyOnly),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn ring_requires_exactly_one_signing_active_key() {
+        assert!(matches!(
+            KeyRing::new(vec![(fixture_key(Algorithm::RS256), KeyState::VerifyOnly)]),
+            Err(KeyRingError::ActiveKeyCount(0))
+        ));
+        assert!(matches!(
+            KeyRing::new(vec![
+                (fixture_key(Algorithm::RS256), KeyState::Active),
+                (fixture_key(Algorithm::ES256), KeyState::Active),
+            ]),
+            Err(KeyRingError::ActiveKeyCount(2))
+        ));
+        assert!(matches!(
+            KeyRing::new(vec![
+                (fixture_key(Algorithm::RS256), KeyState::Active),
+                (fixture_key(Algorithm::RS256), KeyState::VerifyOnly),
+            ]),
+            Err(KeyRingError::DuplicateKey(_))
+        ));
+    }
+
+    #[test]
+    fn promote_demotes_previous_active_key() {
+        let ring = ring();
+
+        ring.promote("ES256-key").unwrap();
+
+        assert_eq!(ring.active().unwrap().kid, "ES256-key");
+        assert!(ring.verification_key(Some("RS256-key")).is_ok());
+        let states = ring.statuses().unwrap();
+        assert_eq!(states[0].state, KeyState::VerifyOnly);
+        assert_eq!(states[1].state, KeyState::Active);
+    }
+
+    #[test]
+    fn restore_states_applies_saved_rotations() {
+        let ring = ring();
+
+        ring.restore_states(&[
+            ("RS256-key".to_string(), KeyState::Retired),
+            ("ES256-key".to_string(), KeyState::Active),
+            ("removed-key".to_string(), KeyState::Active),
+        ])
+        .unwrap();
+        assert_eq!(ring.active().unwrap().kid, "ES256-key");
+        assert!(ring.verification_key(Some("RS256-key")).is_err());
+
+        assert!(matches!(
+            ring.restore_states(&[("ed-public".to_string(), KeyState::Active)]),
+            Err(KeyRingError::ActiveKeyCount(2))
+        ));
+        assert_eq!(ring.active().unwrap().kid, "ES256-key");
+    }
+
+    #[test]
+    fn promote_rejects_keys_without_private_half() {
+        let ring = ring();
+
+        assert!(matches!(
+            ring.promote("ed-public"),
+            Err(KeyRingError::CannotSign(_))
+        ));
+        assert!(matches!(
+            ring.promote("missing"),
+            Err(KeyRingError::UnknownKey(_))
+        ));
+    }
+
+    #[test]
+    fn retired_keys_stop_verifying_and_leave_the_jwks() {
+        let ring = ring();
+        assert_eq!(ring.jwk_set().unwrap().keys.len(), 3);
+
+        ring.retire("ES256-key").unwrap();
+
+        assert!(ring.verification_key(Some("ES256-key")).is_err());
+        assert_eq!(ring.jwk_set().unwrap().keys.len(), 2);
+        assert!(matches!(
+            ring.retire("RS256-key"),
+            Err(KeyRingError::RetiringActiveKey(_))
+        ));
+        assert!(matches!(
+            ring.promote("ES256-key"),
+            Err(KeyRingError::CannotSign(_))
+        ));
+    }
+
+    #[test]
+    fn tokens_without_kid_verify_against_active_key() {
+        let ring = ring();
+
+        assert_eq!(ring.verification_key(None).unwrap().kid, "RS256-key");
+    }
+
+    #[test]
+    fn from_config_requires_key_paths_for_asymmetric_algorithms() {
+        let config = JwtConfig {
+            algorithm: Algorithm::ES256,
+            private_key_path: None,
+            public_key_path: None,
+            ..crate::utils::jwt::tests::test_config()
+        };
+
+        assert!(matches!(
+            SigningKey::from_config(&config),
+            Err(KeyError::MissingKeyPath("JWT_PRIVATE_KEY_PATH"))
+        ));
+    }
+}
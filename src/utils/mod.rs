@@ -1,3 +1,21 @@
+pub mod auth_cookie;
+pub mod auth_utils;
+pub mod cbor;
+pub mod email;
 pub mod jwt;
+pub mod jwt_keys;
+pub mod opaque_token;
+pub mod password_policy;
+pub mod totp;
+pub mod username;
+pub mod webauthn;
 
-pub use jwt::{TokenClaims, decode_token, encode_token};
+pub use auth_cookie::TokenTransport;
+pub use auth_utils::{PasswordVerification, hash_password, verify_dummy_password, verify_password};
+pub use email::normalize_email;
+pub use jwt::{TokenClaims, UserGrants, decode_token, encode_token};
+pub use jwt_keys::{KeyRing, SigningKey};
+pub use opaque_token::{generate_opaque_token, hash_opaque_token};
+pub use password_policy::{PasswordViolation, check_password, is_breached};
+pub use totp::{base32_decode, base32_encode, generate_totp_secret, otpauth_uri, verify_totp};
+pub use username::{canonical_username, validate_username};
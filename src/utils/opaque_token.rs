@@ -0,0 +1,50 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use sha2::{Digest, Sha256};
+
+/// Generates a random, URL-safe token carrying 256 bits of entropy.
+pub fn generate_opaque_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Hashes an opaque token for storage so a database leak doesn't expose usable tokens.
+///
+/// The tokens are high-entropy, so a fast unsalted digest is sufficient.
+pub fn hash_opaque_token(token: &str) -> String {
+    let digest = Sha256::digest(token.as_bytes());
+
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_tokens_are_unique_and_url_safe() {
+        let first = generate_opaque_token();
+        let second = generate_opaque_token();
+
+        assert_ne!(first, second);
+        assert_eq!(first.len(), 43);
+        assert!(
+            first
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        );
+    }
+
+    #[test]
+    fn hash_is_stable_hex_digest() {
+        let hash = hash_opaque_token("abc");
+
+        assert_eq!(
+            hash,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(hash, hash_opaque_token("abc"));
+    }
+}
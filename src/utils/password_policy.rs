@@ -0,0 +1,208 @@
+//! Password policy: configurable composition rules plus an optional offline breach check.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, ErrorKind};
+use std::path::Path;
+
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+
+use crate::config::PasswordPolicyConfig;
+
+/// Hex digits of the SHA-1 hash that name a breach range file.
+const RANGE_PREFIX_LEN: usize = 5;
+
+/// One policy rule, reported by its snake_case name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PasswordRule {
+    MinLength,
+    MaxLength,
+    Lowercase,
+    Uppercase,
+    Digit,
+    Symbol,
+    ContainsUsername,
+    Breached,
+}
+
+/// A rule the password broke, with a message fit for the user.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PasswordViolation {
+    pub rule: PasswordRule,
+    pub message: String,
+}
+
+impl PasswordViolation {
+    fn new(rule: PasswordRule, message: impl Into<String>) -> Self {
+        Self {
+            rule,
+            message: message.into(),
+        }
+    }
+}
+
+/// Checks the composition rules; see [`is_breached`] for the breach list.
+///
+/// Every broken rule is reported, so users can fix them all at once.
+pub fn check_password(
+    config: &PasswordPolicyConfig,
+    password: &str,
+    username: Option<&str>,
+) -> Vec<PasswordViolation> {
+    let mut violations = Vec::new();
+    let length = password.chars().count();
+
+    if length < config.min_length {
+        violations.push(PasswordViolation::new(
+            PasswordRule::MinLength,
+            format!("Must be at least {} characters long.", config.min_length),
+        ));
+    }
+    if length > config.max_length {
+        violations.push(PasswordViolation::new(
+            PasswordRule::MaxLength,
+            format!("Must be at most {} characters long.", config.max_length),
+        ));
+    }
+
+    let classes = [
+        (
+            config.require_lowercase,
+            PasswordRule::Lowercase,
+            "Must contain a lowercase letter.",
+            char::is_lowercase as fn(char) -> bool,
+        ),
+        (
+            config.require_uppercase,
+            PasswordRule::Uppercase,
+            "Must contain an uppercase letter.",
+            char::is_uppercase,
+        ),
+        (
+            config.require_digit,
+            PasswordRule::Digit,
+            "Must contain a digit.",
+            char::is_numeric,
+        ),
+        (
+            config.require_symbol,
+            PasswordRule::Symbol,
+            "Must contain a symbol.",
+            |c: char| !c.is_alphanumeric(),
+        ),
+    ];
+    for (required, rule, message, matches) in classes {
+        if required && !password.chars().any(matches) {
+            violations.push(PasswordViolation::new(rule, message));
+        }
+    }
+
+    if let Some(username) = username.map(str::trim).filter(|name| !name.is_empty())
+        && config.disallow_username
+        && password.to_lowercase().contains(&username.to_lowercase())
+    {
+        violations.push(PasswordViolation::new(
+            PasswordRule::ContainsUsername,
+            "Must not contain the username.",
+        ));
+    }
+
+    violations
+}
+
+/// Looks the password up in a directory of breached-password range files.
+///
+/// Like the k-anonymity range API of Have I Been Pwned, the first five hex digits of the
+/// uppercase SHA-1 hash name the file (`21BD1.txt`) and each line holds the remaining 35
+/// digits and a count, `SUFFIX:COUNT`. A missing file means no breached password has that
+/// prefix. Reads from disk, so call it off the async executor.
+pub fn is_breached(dir: &Path, password: &str) -> io::Result<bool> {
+    let digest = Sha1::digest(password.as_bytes());
+    let hash: String = digest.iter().map(|byte| format!("{:02X}", byte)).collect();
+    let (prefix, suffix) = hash.split_at(RANGE_PREFIX_LEN);
+
+    let file = match File::open(dir.join(format!("{}.txt", prefix))) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let listed = line.split(':').next().unwrap_or_default().trim();
+        if listed.eq_ignore_ascii_case(suffix) {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(violations: &[PasswordViolation]) -> Vec<PasswordRule> {
+        violations.iter().map(|violation| violation.rule).collect()
+    }
+
+    #[test]
+    fn reports_every_broken_rule() {
+        let config = PasswordPolicyConfig {
+            require_lowercase: true,
+            require_uppercase: true,
+            require_digit: true,
+            require_symbol: true,
+            ..Default::default()
+        };
+
+        let violations = check_password(&config, "alice", Some("Alice"));
+
+        assert_eq!(
+            rules(&violations),
+            [
+                PasswordRule::MinLength,
+                PasswordRule::Uppercase,
+                PasswordRule::Digit,
+                PasswordRule::Symbol,
+                PasswordRule::ContainsUsername,
+            ]
+        );
+        assert!(check_password(&config, "Correct-Horse-9", Some("alice")).is_empty());
+    }
+
+    #[test]
+    fn counts_characters_not_bytes() {
+        let config = PasswordPolicyConfig {
+            min_length: 4,
+            max_length: 4,
+            ..Default::default()
+        };
+
+        assert!(check_password(&config, "ñøßé", None).is_empty());
+        assert_eq!(
+            rules(&check_password(&config, "ñøßéx", None)),
+            [PasswordRule::MaxLength]
+        );
+    }
+
+    #[test]
+    fn finds_passwords_in_range_files() {
+        let dir = std::env::temp_dir().join(format!("breaches-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        // SHA-1("password") = 5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8
+        std::fs::write(
+            dir.join("5BAA6.txt"),
+            "003D68EB55068C33ACE09247EE4C639306B:3\r\n\
+             1E4C9B93F3F0682250B6CF8331B7EE68FD8:9545824\r\n",
+        )
+        .unwrap();
+
+        let password = is_breached(&dir, "password").unwrap();
+        let other = is_breached(&dir, "correct horse battery staple").unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(password);
+        assert!(!other);
+    }
+}
@@ -0,0 +1,123 @@
+//! Username canonicalization, so "Alice", "alice " and "ａｌｉｃｅ" name the same account.
+
+use std::fmt::{self, Display};
+
+use unicode_normalization::UnicodeNormalization;
+
+const MIN_USERNAME_LEN: usize = 3;
+const MAX_USERNAME_LEN: usize = 32;
+
+/// Names that could pass for the service itself or its staff.
+const RESERVED_USERNAMES: &[&str] = &[
+    "admin",
+    "administrator",
+    "root",
+    "system",
+    "support",
+    "security",
+    "moderator",
+    "staff",
+    "postmaster",
+    "webmaster",
+    "noreply",
+    "api",
+    "auth",
+    "me",
+    "null",
+    "anonymous",
+];
+
+/// Why a username can't be registered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UsernameError {
+    Length,
+    /// Outside `a-z`, `0-9`, `.`, `_` and `-`, or not starting and ending with a letter or digit.
+    Charset,
+    Reserved,
+}
+
+impl Display for UsernameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UsernameError::Length => write!(
+                f,
+                "Username must be {} to {} characters long.",
+                MIN_USERNAME_LEN, MAX_USERNAME_LEN
+            ),
+            UsernameError::Charset => write!(
+                f,
+                "Username may only contain letters, digits, '.', '_' and '-', and must start \
+                 and end with a letter or digit."
+            ),
+            UsernameError::Reserved => write!(f, "Username is reserved."),
+        }
+    }
+}
+
+/// The form usernames are compared and indexed by: NFKC-normalized, lowercased and trimmed.
+///
+/// Lowercasing can undo NFKC for a few characters, so the result is normalized again.
+pub fn canonical_username(raw: &str) -> String {
+    let folded = raw.nfkc().collect::<String>().to_lowercase();
+
+    folded.nfkc().collect::<String>().trim().to_string()
+}
+
+/// Checks a username for registration and returns the form to display: NFKC-normalized and
+/// trimmed, with its case kept.
+pub fn validate_username(raw: &str) -> Result<String, UsernameError> {
+    let canonical = canonical_username(raw);
+
+    let length = canonical.chars().count();
+    if !(MIN_USERNAME_LEN..=MAX_USERNAME_LEN).contains(&length) {
+        return Err(UsernameError::Length);
+    }
+    let allowed = canonical
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '.' | '_' | '-'));
+    let edges = [canonical.chars().next(), canonical.chars().last()];
+    if !allowed || edges.iter().flatten().any(|c| !c.is_ascii_alphanumeric()) {
+        return Err(UsernameError::Charset);
+    }
+    if RESERVED_USERNAMES.contains(&canonical.as_str()) {
+        return Err(UsernameError::Reserved);
+    }
+
+    Ok(raw.nfkc().collect::<String>().trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_form_ignores_case_width_and_padding() {
+        for raw in ["alice", "Alice", " alice ", "ＡＬＩＣＥ", "\u{3000}alice"] {
+            assert_eq!(canonical_username(raw), "alice", "{:?}", raw);
+        }
+    }
+
+    #[test]
+    fn validation_keeps_the_display_case() {
+        assert_eq!(validate_username("  Alice.B ").as_deref(), Ok("Alice.B"));
+        assert_eq!(validate_username("Ａｌｉｃｅ").as_deref(), Ok("Alice"));
+    }
+
+    #[test]
+    fn validation_rejects_bad_names() {
+        assert_eq!(validate_username("al"), Err(UsernameError::Length));
+        assert_eq!(
+            validate_username(&"a".repeat(33)),
+            Err(UsernameError::Length)
+        );
+        assert_eq!(validate_username("bob smith"), Err(UsernameError::Charset));
+        assert_eq!(
+            validate_username("bob@example"),
+            Err(UsernameError::Charset)
+        );
+        assert_eq!(validate_username("_bob"), Err(UsernameError::Charset));
+        assert_eq!(validate_username("bøb"), Err(UsernameError::Charset));
+        assert_eq!(validate_username("Admin"), Err(UsernameError::Reserved));
+        assert_eq!(validate_username(" ROOT "), Err(UsernameError::Reserved));
+    }
+}
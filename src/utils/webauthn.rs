@@ -0,0 +1,565 @@
+use std::fmt::{self, Display};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use p256::ecdsa::signature::Verifier;
+use rsa::{BigUint, Pkcs1v15Sign, RsaPublicKey};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::config::WebauthnConfig;
+use crate::utils::cbor::{self, Cbor};
+
+/// COSE algorithm identifiers we accept, in order of preference.
+pub const ALG_ES256: i64 = -7;
+pub const ALG_EDDSA: i64 = -8;
+pub const ALG_RS256: i64 = -257;
+pub const SUPPORTED_ALGORITHMS: [i64; 3] = [ALG_ES256, ALG_EDDSA, ALG_RS256];
+
+/// Authenticator data flags.
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+/// Why a registration or authentication response was rejected.
+#[derive(Debug, PartialEq, Eq)]
+pub enum WebauthnError {
+    Malformed(&'static str),
+    WrongCeremony,
+    UntrustedOrigin(String),
+    WrongRelyingParty,
+    UserNotPresent,
+    UserNotVerified,
+    UnsupportedAttestation(String),
+    UnsupportedKey,
+    BadSignature,
+    /// The signature counter went backwards, a sign the authenticator was cloned.
+    CounterRegressed,
+}
+
+impl Display for WebauthnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebauthnError::Malformed(what) => write!(f, "Malformed credential: {}.", what),
+            WebauthnError::WrongCeremony => write!(f, "Client data is for another ceremony."),
+            WebauthnError::UntrustedOrigin(origin) => {
+                write!(f, "Origin {} is not allowed.", origin)
+            }
+            WebauthnError::WrongRelyingParty => {
+                write!(f, "Credential is scoped to another relying party.")
+            }
+            WebauthnError::UserNotPresent => write!(f, "User presence was not confirmed."),
+            WebauthnError::UserNotVerified => write!(f, "User verification is required."),
+            WebauthnError::UnsupportedAttestation(format) => {
+                write!(f, "Attestation format {} is not supported.", format)
+            }
+            WebauthnError::UnsupportedKey => write!(f, "Credential key type is not supported."),
+            WebauthnError::BadSignature => write!(f, "Signature does not verify."),
+            WebauthnError::CounterRegressed => {
+                write!(
+                    f,
+                    "Signature counter went backwards; the authenticator may be cloned."
+                )
+            }
+        }
+    }
+}
+
+/// The `clientDataJSON` the browser signs over.
+#[derive(Debug, Deserialize)]
+pub struct ClientData {
+    /// `webauthn.create` or `webauthn.get`.
+    #[serde(rename = "type")]
+    pub ceremony: String,
+    /// Base64url challenge the ceremony was started with.
+    pub challenge: String,
+    pub origin: String,
+    #[serde(rename = "crossOrigin", default)]
+    pub cross_origin: bool,
+}
+
+/// A credential that passed registration, ready to store.
+#[derive(Debug)]
+pub struct RegisteredCredential {
+    pub credential_id: Vec<u8>,
+    /// COSE-encoded public key.
+    pub public_key: Vec<u8>,
+    pub sign_count: u32,
+}
+
+/// Parsed authenticator data (WebAuthn §6.1).
+struct AuthenticatorData {
+    rp_id_hash: [u8; 32],
+    flags: u8,
+    sign_count: u32,
+    /// Credential ID and COSE key, present on registration.
+    attested: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+/// A credential public key, decoded from its COSE form.
+enum PublicKey {
+    Es256(p256::ecdsa::VerifyingKey),
+    Ed25519(ed25519_dalek::VerifyingKey),
+    Rs256(RsaPublicKey),
+}
+
+pub fn encode_base64url(bytes: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Decodes the unpadded base64url WebAuthn JSON uses, tolerating padding.
+pub fn decode_base64url(value: &str) -> Result<Vec<u8>, WebauthnError> {
+    URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|_| WebauthnError::Malformed("invalid base64url"))
+}
+
+pub fn parse_client_data(raw: &[u8]) -> Result<ClientData, WebauthnError> {
+    serde_json::from_slice(raw).map_err(|_| WebauthnError::Malformed("invalid clientDataJSON"))
+}
+
+fn check_client_data(
+    config: &WebauthnConfig,
+    client_data: &ClientData,
+    ceremony: &str,
+) -> Result<(), WebauthnError> {
+    if client_data.ceremony != ceremony {
+        return Err(WebauthnError::WrongCeremony);
+    }
+    if client_data.cross_origin || !config.origins.contains(&client_data.origin) {
+        return Err(WebauthnError::UntrustedOrigin(client_data.origin.clone()));
+    }
+    Ok(())
+}
+
+fn parse_authenticator_data(bytes: &[u8]) -> Result<AuthenticatorData, WebauthnError> {
+    const TRUNCATED: WebauthnError = WebauthnError::Malformed("truncated authenticator data");
+    if bytes.len() < 37 {
+        return Err(TRUNCATED);
+    }
+    let flags = bytes[32];
+    let sign_count = u32::from_be_bytes(bytes[33..37].try_into().unwrap());
+
+    let attested = if flags & FLAG_ATTESTED_CREDENTIAL != 0 {
+        // AAGUID (16 bytes), credential ID length (2), credential ID, then the COSE key.
+        let rest = bytes.get(37 + 16..).ok_or(TRUNCATED)?;
+        let (len, rest) = rest.split_at_checked(2).ok_or(TRUNCATED)?;
+        let len = usize::from(u16::from_be_bytes([len[0], len[1]]));
+        let (credential_id, rest) = rest.split_at_checked(len).ok_or(TRUNCATED)?;
+        let (_, after_key) = cbor::decode_prefix(rest)
+            .map_err(|_| WebauthnError::Malformed("invalid credential public key"))?;
+        let public_key = rest[..rest.len() - after_key.len()].to_vec();
+
+        Some((credential_id.to_vec(), public_key))
+    } else {
+        None
+    };
+
+    Ok(AuthenticatorData {
+        rp_id_hash: bytes[..32].try_into().unwrap(),
+        flags,
+        sign_count,
+        attested,
+    })
+}
+
+fn check_authenticator_data(
+    config: &WebauthnConfig,
+    data: &AuthenticatorData,
+) -> Result<(), WebauthnError> {
+    if data.rp_id_hash[..] != Sha256::digest(config.rp_id.as_bytes())[..] {
+        return Err(WebauthnError::WrongRelyingParty);
+    }
+    if data.flags & FLAG_USER_PRESENT == 0 {
+        return Err(WebauthnError::UserNotPresent);
+    }
+    if config.require_user_verification && data.flags & FLAG_USER_VERIFIED == 0 {
+        return Err(WebauthnError::UserNotVerified);
+    }
+    Ok(())
+}
+
+impl PublicKey {
+    fn from_cose(bytes: &[u8]) -> Result<Self, WebauthnError> {
+        let key = cbor::decode(bytes).map_err(|_| WebauthnError::UnsupportedKey)?;
+        let field = |label: i64| key.get_int(label);
+        let bytes_field = |label: i64| {
+            field(label)
+                .and_then(Cbor::as_bytes)
+                .ok_or(WebauthnError::UnsupportedKey)
+        };
+
+        // COSE key type (1), algorithm (3), and curve (-1).
+        match (
+            field(1).and_then(Cbor::as_integer),
+            field(3).and_then(Cbor::as_integer),
+        ) {
+            (Some(2), Some(ALG_ES256)) if field(-1).and_then(Cbor::as_integer) == Some(1) => {
+                let mut point = vec![0x04];
+                point.extend_from_slice(bytes_field(-2)?);
+                point.extend_from_slice(bytes_field(-3)?);
+                p256::ecdsa::VerifyingKey::from_sec1_bytes(&point)
+                    .map(PublicKey::Es256)
+                    .map_err(|_| WebauthnError::UnsupportedKey)
+            }
+            (Some(1), Some(ALG_EDDSA)) if field(-1).and_then(Cbor::as_integer) == Some(6) => {
+                let x: [u8; 32] = bytes_field(-2)?
+                    .try_into()
+                    .map_err(|_| WebauthnError::UnsupportedKey)?;
+                ed25519_dalek::VerifyingKey::from_bytes(&x)
+                    .map(PublicKey::Ed25519)
+                    .map_err(|_| WebauthnError::UnsupportedKey)
+            }
+            (Some(3), Some(ALG_RS256)) => RsaPublicKey::new(
+                BigUint::from_bytes_be(bytes_field(-1)?),
+                BigUint::from_bytes_be(bytes_field(-2)?),
+            )
+            .map(PublicKey::Rs256)
+            .map_err(|_| WebauthnError::UnsupportedKey),
+            _ => Err(WebauthnError::UnsupportedKey),
+        }
+    }
+
+    fn verify(&self, message: &[u8], signature: &[u8]) -> Result<(), WebauthnError> {
+        let verified = match self {
+            PublicKey::Es256(key) => {
+                p256::ecdsa::Signature::from_der(signature).is_ok_and(|signature| {
+                    let signature = signature.normalize_s().unwrap_or(signature);
+                    key.verify(message, &signature).is_ok()
+                })
+            }
+            PublicKey::Ed25519(key) => ed25519_dalek::Signature::from_slice(signature)
+                .is_ok_and(|signature| key.verify_strict(message, &signature).is_ok()),
+            PublicKey::Rs256(key) => key
+                .verify(
+                    Pkcs1v15Sign::new::<Sha256>(),
+                    &Sha256::digest(message),
+                    signature,
+                )
+                .is_ok(),
+        };
+
+        if verified {
+            Ok(())
+        } else {
+            Err(WebauthnError::BadSignature)
+        }
+    }
+}
+
+/// Checks a registration response (`navigator.credentials.create()`) whose challenge the
+/// caller has already matched. Only `none` attestation is accepted, which is what browsers
+/// send when the options ask for no attestation.
+pub fn verify_registration(
+    config: &WebauthnConfig,
+    client_data: &ClientData,
+    attestation_object: &[u8],
+) -> Result<RegisteredCredential, WebauthnError> {
+    check_client_data(config, client_data, "webauthn.create")?;
+
+    let attestation = cbor::decode(attestation_object)
+        .map_err(|_| WebauthnError::Malformed("invalid attestation object"))?;
+    let format = attestation
+        .get_text("fmt")
+        .and_then(Cbor::as_text)
+        .ok_or(WebauthnError::Malformed("attestation format missing"))?;
+    if format != "none" {
+        return Err(WebauthnError::UnsupportedAttestation(format.to_string()));
+    }
+    let auth_data = attestation
+        .get_text("authData")
+        .and_then(Cbor::as_bytes)
+        .ok_or(WebauthnError::Malformed("authenticator data missing"))?;
+
+    let data = parse_authenticator_data(auth_data)?;
+    check_authenticator_data(config, &data)?;
+    let Some((credential_id, public_key)) = data.attested else {
+        return Err(WebauthnError::Malformed("attested credential data missing"));
+    };
+    PublicKey::from_cose(&public_key)?;
+
+    Ok(RegisteredCredential {
+        credential_id,
+        public_key,
+        sign_count: data.sign_count,
+    })
+}
+
+/// Checks an authentication response (`navigator.credentials.get()`) against the stored
+/// credential, returning the new signature counter.
+///
+/// Authenticators that don't count always report `0`; otherwise the counter must grow.
+pub fn verify_assertion(
+    config: &WebauthnConfig,
+    client_data: &ClientData,
+    client_data_json: &[u8],
+    authenticator_data: &[u8],
+    signature: &[u8],
+    public_key: &[u8],
+    stored_sign_count: u32,
+) -> Result<u32, WebauthnError> {
+    check_client_data(config, client_data, "webauthn.get")?;
+
+    let data = parse_authenticator_data(authenticator_data)?;
+    check_authenticator_data(config, &data)?;
+
+    let mut message = authenticator_data.to_vec();
+    message.extend_from_slice(&Sha256::digest(client_data_json));
+    PublicKey::from_cose(public_key)?.verify(&message, signature)?;
+
+    if (data.sign_count != 0 || stored_sign_count != 0) && data.sign_count <= stored_sign_count {
+        return Err(WebauthnError::CounterRegressed);
+    }
+    Ok(data.sign_count)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use argon2::password_hash::rand_core::OsRng;
+    use p256::ecdsa::{SigningKey, signature::Signer};
+    use serde_json::{Value, json};
+
+    use crate::utils::cbor::tests::encode;
+
+    use super::*;
+
+    /// A software passkey: a P-256 key with a counter, producing the JSON browsers send.
+    pub(crate) struct SoftAuthenticator {
+        key: SigningKey,
+        pub credential_id: Vec<u8>,
+        pub sign_count: u32,
+        pub user_verified: bool,
+    }
+
+    impl SoftAuthenticator {
+        pub(crate) fn new() -> Self {
+            Self {
+                key: SigningKey::random(&mut OsRng),
+                credential_id: crate::utils::generate_opaque_token().into_bytes(),
+                sign_count: 0,
+                user_verified: true,
+            }
+        }
+
+        pub(crate) fn cose_key(&self) -> Vec<u8> {
+            let point = self.key.verifying_key().to_encoded_point(false);
+            encode(&Cbor::Map(vec![
+                (Cbor::Integer(1), Cbor::Integer(2)),
+                (Cbor::Integer(3), Cbor::Integer(ALG_ES256)),
+                (Cbor::Integer(-1), Cbor::Integer(1)),
+                (Cbor::Integer(-2), Cbor::Bytes(point.x().unwrap().to_vec())),
+                (Cbor::Integer(-3), Cbor::Bytes(point.y().unwrap().to_vec())),
+            ]))
+        }
+
+        fn client_data(ceremony: &str, challenge: &str, origin: &str) -> Vec<u8> {
+            json!({"type": ceremony, "challenge": challenge, "origin": origin})
+                .to_string()
+                .into_bytes()
+        }
+
+        fn authenticator_data(&mut self, rp_id: &str, attested: bool) -> Vec<u8> {
+            self.sign_count += 1;
+            let mut flags = FLAG_USER_PRESENT;
+            if self.user_verified {
+                flags |= FLAG_USER_VERIFIED;
+            }
+            if attested {
+                flags |= FLAG_ATTESTED_CREDENTIAL;
+            }
+
+            let mut data = Sha256::digest(rp_id.as_bytes()).to_vec();
+            data.push(flags);
+            data.extend(self.sign_count.to_be_bytes());
+            if attested {
+                data.extend([0u8; 16]);
+                data.extend((self.credential_id.len() as u16).to_be_bytes());
+                data.extend(&self.credential_id);
+                data.extend(self.cose_key());
+            }
+            data
+        }
+
+        /// `PublicKeyCredential.toJSON()` of a registration for `challenge`.
+        pub(crate) fn register(&mut self, config: &WebauthnConfig, challenge: &str) -> Value {
+            let client_data = Self::client_data("webauthn.create", challenge, &config.origins[0]);
+            let attestation = encode(&Cbor::Map(vec![
+                (Cbor::Text("fmt".into()), Cbor::Text("none".into())),
+                (Cbor::Text("attStmt".into()), Cbor::Map(vec![])),
+                (
+                    Cbor::Text("authData".into()),
+                    Cbor::Bytes(self.authenticator_data(&config.rp_id, true)),
+                ),
+            ]));
+
+            json!({
+                "id": encode_base64url(&self.credential_id),
+                "rawId": encode_base64url(&self.credential_id),
+                "type": "public-key",
+                "response": {
+                    "clientDataJSON": encode_base64url(&client_data),
+                    "attestationObject": encode_base64url(&attestation),
+                },
+            })
+        }
+
+        /// `PublicKeyCredential.toJSON()` of an authentication for `challenge`.
+        pub(crate) fn authenticate(&mut self, config: &WebauthnConfig, challenge: &str) -> Value {
+            let client_data = Self::client_data("webauthn.get", challenge, &config.origins[0]);
+            let auth_data = self.authenticator_data(&config.rp_id, false);
+            let mut message = auth_data.clone();
+            message.extend(Sha256::digest(&client_data));
+            let signature: p256::ecdsa::Signature = self.key.sign(&message);
+
+            json!({
+                "id": encode_base64url(&self.credential_id),
+                "rawId": encode_base64url(&self.credential_id),
+                "type": "public-key",
+                "response": {
+                    "clientDataJSON": encode_base64url(&client_data),
+                    "authenticatorData": encode_base64url(&auth_data),
+                    "signature": encode_base64url(signature.to_der().as_bytes()),
+                },
+            })
+        }
+    }
+
+    fn config() -> WebauthnConfig {
+        WebauthnConfig::default()
+    }
+
+    fn field(credential: &Value, name: &str) -> Vec<u8> {
+        decode_base64url(credential["response"][name].as_str().unwrap()).unwrap()
+    }
+
+    fn register(authenticator: &mut SoftAuthenticator) -> RegisteredCredential {
+        let response = authenticator.register(&config(), "challenge");
+        let client_data = parse_client_data(&field(&response, "clientDataJSON")).unwrap();
+
+        verify_registration(
+            &config(),
+            &client_data,
+            &field(&response, "attestationObject"),
+        )
+        .unwrap()
+    }
+
+    /// Signs for the default relying party and verifies against `config`.
+    fn authenticate(
+        authenticator: &mut SoftAuthenticator,
+        config: &WebauthnConfig,
+        stored: &RegisteredCredential,
+        stored_sign_count: u32,
+    ) -> Result<u32, WebauthnError> {
+        let response = authenticator.authenticate(&self::config(), "challenge");
+        let client_data_json = field(&response, "clientDataJSON");
+        let client_data = parse_client_data(&client_data_json).unwrap();
+
+        verify_assertion(
+            config,
+            &client_data,
+            &client_data_json,
+            &field(&response, "authenticatorData"),
+            &field(&response, "signature"),
+            &stored.public_key,
+            stored_sign_count,
+        )
+    }
+
+    #[test]
+    fn registration_extracts_the_credential() {
+        let mut authenticator = SoftAuthenticator::new();
+        let stored = register(&mut authenticator);
+
+        assert_eq!(stored.credential_id, authenticator.credential_id);
+        assert_eq!(stored.public_key, authenticator.cose_key());
+        assert_eq!(stored.sign_count, 1);
+    }
+
+    #[test]
+    fn registration_checks_origin_and_ceremony() {
+        let mut authenticator = SoftAuthenticator::new();
+        let response = authenticator.register(&config(), "challenge");
+        let attestation = field(&response, "attestationObject");
+
+        let mut client_data = parse_client_data(&field(&response, "clientDataJSON")).unwrap();
+        client_data.origin = "https://evil.example".into();
+        assert_eq!(
+            verify_registration(&config(), &client_data, &attestation).unwrap_err(),
+            WebauthnError::UntrustedOrigin("https://evil.example".into())
+        );
+
+        client_data.origin = config().origins[0].clone();
+        client_data.ceremony = "webauthn.get".into();
+        assert_eq!(
+            verify_registration(&config(), &client_data, &attestation).unwrap_err(),
+            WebauthnError::WrongCeremony
+        );
+    }
+
+    #[test]
+    fn assertions_verify_against_the_stored_key() {
+        let mut authenticator = SoftAuthenticator::new();
+        let stored = register(&mut authenticator);
+
+        assert_eq!(
+            authenticate(&mut authenticator, &config(), &stored, 1),
+            Ok(2)
+        );
+        // A counter that doesn't move past the stored one hints at a cloned key.
+        assert_eq!(
+            authenticate(&mut authenticator, &config(), &stored, 10),
+            Err(WebauthnError::CounterRegressed)
+        );
+        // Another key's signature fails.
+        let other = register(&mut SoftAuthenticator::new());
+        assert_eq!(
+            authenticate(&mut authenticator, &config(), &other, 0),
+            Err(WebauthnError::BadSignature)
+        );
+    }
+
+    #[test]
+    fn assertions_check_relying_party_and_user_verification() {
+        let mut authenticator = SoftAuthenticator::new();
+        let stored = register(&mut authenticator);
+
+        let mut other_rp = config();
+        other_rp.rp_id = "example.com".into();
+        assert_eq!(
+            authenticate(&mut authenticator, &other_rp, &stored, 0),
+            Err(WebauthnError::WrongRelyingParty)
+        );
+
+        authenticator.user_verified = false;
+        assert_eq!(
+            authenticate(&mut authenticator, &config(), &stored, 0),
+            Err(WebauthnError::UserNotVerified)
+        );
+        let mut lenient = config();
+        lenient.require_user_verification = false;
+        assert!(authenticate(&mut authenticator, &lenient, &stored, 0).is_ok());
+    }
+
+    #[test]
+    fn parses_ed25519_cose_keys() {
+        let key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+        let cose = encode(&Cbor::Map(vec![
+            (Cbor::Integer(1), Cbor::Integer(1)),
+            (Cbor::Integer(3), Cbor::Integer(ALG_EDDSA)),
+            (Cbor::Integer(-1), Cbor::Integer(6)),
+            (
+                Cbor::Integer(-2),
+                Cbor::Bytes(key.verifying_key().to_bytes().to_vec()),
+            ),
+        ]));
+        let signature = ed25519_dalek::Signer::sign(&key, b"message");
+
+        let public_key = PublicKey::from_cose(&cose).unwrap();
+        assert!(public_key.verify(b"message", &signature.to_bytes()).is_ok());
+        assert!(
+            public_key
+                .verify(b"tampered", &signature.to_bytes())
+                .is_err()
+        );
+    }
+}